@@ -0,0 +1,39 @@
+use std::{
+    process::Command,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+// exposes GIT_COMMIT and BUILD_TIMESTAMP (unix seconds) to the crate for /api/meta/version;
+// either can be preset in the environment, e.g. in a Docker build where .git isn't mounted
+fn main() {
+    let git_commit = std::env::var("GIT_COMMIT").ok().or_else(|| {
+        Command::new("git")
+            .args(["rev-parse", "--short=12", "HEAD"])
+            .output()
+            .ok()
+            .filter(|output| output.status.success())
+            .map(|output| String::from_utf8_lossy(&output.stdout).trim().to_owned())
+    });
+
+    let build_timestamp = std::env::var("BUILD_TIMESTAMP").unwrap_or_else(|_| {
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|elapsed| elapsed.as_secs())
+            .unwrap_or_default()
+            .to_string()
+    });
+
+    println!(
+        "cargo:rustc-env=GIT_COMMIT={}",
+        git_commit.as_deref().unwrap_or("unknown")
+    );
+    println!("cargo:rustc-env=BUILD_TIMESTAMP={}", build_timestamp);
+
+    println!("cargo:rerun-if-env-changed=GIT_COMMIT");
+    println!("cargo:rerun-if-env-changed=BUILD_TIMESTAMP");
+    println!("cargo:rerun-if-changed=.git/HEAD");
+    println!("cargo:rerun-if-changed=.git/refs");
+    println!("cargo:rerun-if-changed=src");
+    println!("cargo:rerun-if-changed=templates");
+    println!("cargo:rerun-if-changed=migrations");
+}
@@ -1,12 +1,45 @@
 #!/bin/bash
 
+# seconds to wait for the new server to report ready
+READY_TIMEOUT=60
+READY_URL=${READY_URL:-https://localhost/readyz}
+
 git pull
 cargo update
 cargo build --release
-sudo killall government-ai-project-backend
-sudo /home/cyh/cyhdev_back/target/release/cyhdev_back >> ./logs/$(date '+%Y-%m-%d_%H-%M-%S').log 2>&1 &
-if [ $? -eq 0 ]; then
-    echo "Server has successfully started"
-else
-    echo "Failed to start the server"
+
+# the old server drains HTTP for up to server.shutdown_drain_seconds, then gives background tasks as long
+# again; wait for both plus a margin for the trace flush before resorting to SIGKILL
+DRAIN_SECONDS=$(./target/release/cyhdev_back --print-config 2>/dev/null \
+    | sed -n 's/^shutdown_drain_seconds = "\([0-9]*\)".*/\1/p')
+STOP_TIMEOUT=$((2 * ${DRAIN_SECONDS:-30} + 15))
+
+# SIGTERM lets the running server finish in-flight requests and its current outbox batch
+if pgrep -x cyhdev_back > /dev/null; then
+    sudo pkill -TERM -x cyhdev_back
+    for _ in $(seq "$STOP_TIMEOUT"); do
+        pgrep -x cyhdev_back > /dev/null || break
+        sleep 1
+    done
+    if pgrep -x cyhdev_back > /dev/null; then
+        echo "Server did not stop within ${STOP_TIMEOUT}s; killing it"
+        sudo pkill -KILL -x cyhdev_back
+    fi
 fi
+
+# application logs go to logging.dir (LOG_DIR) with rotation and retention; this file only catches
+# what is written before the logger starts and panics
+mkdir -p ./logs
+sudo /home/cyh/cyhdev_back/target/release/cyhdev_back >> ./logs/console.log 2>&1 &
+
+# /readyz answers 200 once the database is reachable and migrated
+for _ in $(seq "$READY_TIMEOUT"); do
+    if curl -ksf -o /dev/null "$READY_URL"; then
+        echo "Server has successfully started"
+        exit 0
+    fi
+    sleep 1
+done
+
+echo "Server did not become ready within ${READY_TIMEOUT}s"
+exit 1
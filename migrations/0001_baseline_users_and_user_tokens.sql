@@ -0,0 +1,25 @@
+-- baseline: the users/user_tokens schema as it existed before migrations were tracked.
+-- IF NOT EXISTS lets an environment that was built by hand adopt the migration history.
+CREATE SCHEMA IF NOT EXISTS v1;
+
+CREATE TABLE IF NOT EXISTS v1.users (
+    user_id uuid PRIMARY KEY DEFAULT gen_random_uuid(),
+    user_screen_name varchar(64) NOT NULL UNIQUE,
+    user_email varchar(320) NOT NULL UNIQUE,
+    user_password_hash text NOT NULL,
+    user_created_at timestamptz NOT NULL,
+    user_recorded_to_db_at timestamptz NOT NULL,
+    user_updated_at timestamptz NOT NULL,
+    user_is_active boolean NOT NULL DEFAULT true,
+    user_email_verified boolean NOT NULL DEFAULT false
+);
+
+CREATE TABLE IF NOT EXISTS v1.user_tokens (
+    user_token_id uuid PRIMARY KEY DEFAULT gen_random_uuid(),
+    user_token_user_id uuid NOT NULL REFERENCES v1.users (user_id) ON DELETE CASCADE,
+    user_token_type varchar(64) NOT NULL,
+    user_token_value uuid NOT NULL,
+    user_token_created_at timestamptz NOT NULL,
+    user_token_expires_at timestamptz NOT NULL,
+    user_token_used boolean NOT NULL DEFAULT false
+);
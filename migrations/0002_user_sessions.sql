@@ -0,0 +1,11 @@
+CREATE TABLE IF NOT EXISTS v1.user_sessions (
+    user_session_id uuid PRIMARY KEY DEFAULT gen_random_uuid(),
+    user_session_user_id uuid NOT NULL REFERENCES v1.users (user_id) ON DELETE CASCADE,
+    user_session_token uuid NOT NULL UNIQUE,
+    user_session_created_at timestamptz NOT NULL,
+    user_session_last_used_at timestamptz NOT NULL,
+    user_session_expires_at timestamptz NOT NULL,
+    user_session_revoked boolean NOT NULL DEFAULT false,
+    user_session_user_agent text,
+    user_session_ip text
+);
@@ -0,0 +1,6 @@
+CREATE TABLE IF NOT EXISTS v1.user_roles (
+    user_role_user_id uuid NOT NULL REFERENCES v1.users (user_id) ON DELETE CASCADE,
+    user_role_name varchar(64) NOT NULL,
+    user_role_granted_at timestamptz NOT NULL DEFAULT now(),
+    PRIMARY KEY (user_role_user_id, user_role_name)
+);
@@ -0,0 +1,5 @@
+ALTER TABLE v1.user_tokens
+    ADD COLUMN IF NOT EXISTS user_token_session_id uuid REFERENCES v1.user_sessions (user_session_id) ON DELETE CASCADE;
+
+CREATE INDEX IF NOT EXISTS user_tokens_type_value_idx ON v1.user_tokens (user_token_type, user_token_value);
+CREATE INDEX IF NOT EXISTS user_tokens_session_id_idx ON v1.user_tokens (user_token_session_id);
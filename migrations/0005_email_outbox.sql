@@ -0,0 +1,15 @@
+CREATE TABLE IF NOT EXISTS v1.email_outbox (
+    email_outbox_id uuid PRIMARY KEY DEFAULT gen_random_uuid(),
+    email_outbox_to varchar NOT NULL,
+    email_outbox_subject varchar NOT NULL,
+    email_outbox_body text NOT NULL,
+    email_outbox_html_body text,
+    email_outbox_status varchar NOT NULL DEFAULT 'PENDING',
+    email_outbox_attempts integer NOT NULL DEFAULT 0,
+    email_outbox_next_attempt_at timestamptz NOT NULL DEFAULT now(),
+    email_outbox_last_error text,
+    email_outbox_created_at timestamptz NOT NULL DEFAULT now(),
+    email_outbox_sent_at timestamptz
+);
+
+CREATE INDEX IF NOT EXISTS email_outbox_status_next_attempt_idx ON v1.email_outbox (email_outbox_status, email_outbox_next_attempt_at);
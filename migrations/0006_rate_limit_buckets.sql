@@ -0,0 +1,8 @@
+CREATE TABLE IF NOT EXISTS v1.rate_limit_buckets (
+    rate_limit_bucket_key varchar PRIMARY KEY,
+    rate_limit_bucket_tokens double precision NOT NULL,
+    rate_limit_bucket_updated_at timestamptz NOT NULL,
+    rate_limit_bucket_full_at timestamptz NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS rate_limit_buckets_full_at_idx ON v1.rate_limit_buckets (rate_limit_bucket_full_at);
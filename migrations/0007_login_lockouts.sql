@@ -0,0 +1,23 @@
+CREATE TABLE IF NOT EXISTS v1.login_failures (
+    login_failure_key varchar PRIMARY KEY,
+    login_failure_count integer NOT NULL,
+    login_failure_last_at timestamptz NOT NULL,
+    login_failure_locked_until timestamptz
+);
+
+CREATE INDEX IF NOT EXISTS login_failures_last_at_idx ON v1.login_failures (login_failure_last_at);
+
+CREATE TABLE IF NOT EXISTS v1.login_lockouts (
+    login_lockout_id uuid PRIMARY KEY DEFAULT gen_random_uuid(),
+    login_lockout_key varchar NOT NULL,
+    login_lockout_user_id uuid REFERENCES v1.users (user_id) ON DELETE SET NULL,
+    login_lockout_ip text,
+    login_lockout_user_agent text,
+    login_lockout_failures integer NOT NULL,
+    login_lockout_locked_until timestamptz NOT NULL,
+    login_lockout_created_at timestamptz NOT NULL DEFAULT now(),
+    login_lockout_unlocked_at timestamptz
+);
+
+CREATE INDEX IF NOT EXISTS login_lockouts_created_at_idx ON v1.login_lockouts (login_lockout_created_at);
+CREATE INDEX IF NOT EXISTS login_lockouts_user_id_idx ON v1.login_lockouts (login_lockout_user_id);
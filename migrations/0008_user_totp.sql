@@ -0,0 +1,18 @@
+CREATE TABLE IF NOT EXISTS v1.user_totp (
+    user_totp_user_id uuid PRIMARY KEY REFERENCES v1.users (user_id) ON DELETE CASCADE,
+    user_totp_secret_encrypted bytea NOT NULL,
+    user_totp_created_at timestamptz NOT NULL,
+    user_totp_confirmed_at timestamptz,
+    user_totp_last_used_step bigint
+);
+
+CREATE TABLE IF NOT EXISTS v1.user_recovery_codes (
+    user_recovery_code_id uuid PRIMARY KEY DEFAULT gen_random_uuid(),
+    user_recovery_code_user_id uuid NOT NULL REFERENCES v1.users (user_id) ON DELETE CASCADE,
+    user_recovery_code_hash varchar NOT NULL,
+    user_recovery_code_selector varchar NOT NULL,
+    user_recovery_code_created_at timestamptz NOT NULL,
+    user_recovery_code_used_at timestamptz
+);
+
+CREATE INDEX IF NOT EXISTS user_recovery_codes_selector_idx ON v1.user_recovery_codes (user_recovery_code_user_id, user_recovery_code_selector);
@@ -0,0 +1,26 @@
+CREATE TABLE IF NOT EXISTS v1.passkeys (
+    passkey_id uuid PRIMARY KEY DEFAULT gen_random_uuid(),
+    passkey_user_id uuid NOT NULL REFERENCES v1.users (user_id) ON DELETE CASCADE,
+    passkey_credential_id bytea NOT NULL UNIQUE,
+    passkey_public_key bytea NOT NULL,
+    passkey_algorithm integer NOT NULL,
+    passkey_sign_count bigint NOT NULL,
+    passkey_backup_eligible boolean NOT NULL,
+    passkey_name varchar NOT NULL,
+    passkey_created_at timestamptz NOT NULL,
+    passkey_last_used_at timestamptz
+);
+
+CREATE INDEX IF NOT EXISTS passkeys_user_id_idx ON v1.passkeys (passkey_user_id);
+
+CREATE TABLE IF NOT EXISTS v1.passkey_challenges (
+    passkey_challenge_id uuid PRIMARY KEY DEFAULT gen_random_uuid(),
+    passkey_challenge_user_id uuid REFERENCES v1.users (user_id) ON DELETE CASCADE,
+    passkey_challenge_purpose varchar NOT NULL,
+    passkey_challenge_value bytea NOT NULL,
+    passkey_challenge_created_at timestamptz NOT NULL,
+    passkey_challenge_expires_at timestamptz NOT NULL,
+    passkey_challenge_used boolean NOT NULL DEFAULT false
+);
+
+CREATE INDEX IF NOT EXISTS passkey_challenges_expires_at_idx ON v1.passkey_challenges (passkey_challenge_expires_at);
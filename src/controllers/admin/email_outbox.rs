@@ -0,0 +1,117 @@
+use std::sync::Arc;
+
+use axum::{
+    extract::{Path, State},
+    response::IntoResponse,
+};
+use chrono::{DateTime, Utc};
+use serde_derive::Serialize;
+use uuid::Uuid;
+
+use crate::{
+    get_conn,
+    models::email_outbox::{EmailOutbox, EmailOutboxTruncated},
+    utils::{
+        errors::errors::AppError, gadgets::stopwatch::Stopwatch,
+        serde::serialize_to_response::serialize_to_response,
+        server_init::server_state_def::ServerState,
+    },
+};
+
+const STUCK_EMAILS_LIMIT: i64 = 100;
+
+// response
+#[derive(Serialize)]
+pub struct ListStuckEmailsResponse {
+    success: bool,
+    data: ListStuckEmailsResponseData,
+    meta: EmailOutboxResponseMeta,
+}
+
+#[derive(Serialize)]
+pub struct ListStuckEmailsResponseData {
+    emails: Vec<EmailOutboxTruncated>,
+}
+
+#[derive(Serialize)]
+pub struct RetryEmailResponse {
+    success: bool,
+    data: RetryEmailResponseData,
+    meta: EmailOutboxResponseMeta,
+}
+
+#[derive(Serialize)]
+pub struct RetryEmailResponseData {
+    message: String,
+    email_outbox_id: Uuid,
+}
+
+#[derive(Serialize)]
+pub struct EmailOutboxResponseMeta {
+    time_taken: String,
+    timestamp: DateTime<Utc>,
+}
+
+// GET /api/admin/email-outbox
+pub async fn list_stuck_emails(State(state): State<Arc<ServerState>>) -> impl IntoResponse {
+    let stopwatch: Stopwatch = Stopwatch::new("list_stuck_emails");
+    let conn = get_conn!(&state);
+
+    let emails = match EmailOutbox::get_stuck(&conn, STUCK_EMAILS_LIMIT).await {
+        Ok(emails) => emails,
+        Err(e) => {
+            return AppError::CouldNotGetEmailOutbox(
+                e.context("Could not get stuck EmailOutbox entries"),
+            )
+            .into_response();
+        }
+    };
+
+    let response = ListStuckEmailsResponse {
+        success: true,
+        data: ListStuckEmailsResponseData {
+            emails: emails.into_iter().map(EmailOutboxTruncated::from).collect(),
+        },
+        meta: EmailOutboxResponseMeta {
+            time_taken: format!("{:?}", stopwatch.get_original_start().elapsed()),
+            timestamp: Utc::now(),
+        },
+    };
+
+    serialize_to_response(&response)
+}
+
+// POST /api/admin/email-outbox/:email_outbox_id/retry
+pub async fn retry_email(
+    State(state): State<Arc<ServerState>>,
+    Path(email_outbox_id): Path<Uuid>,
+) -> impl IntoResponse {
+    let stopwatch: Stopwatch = Stopwatch::new("retry_email");
+    let conn = get_conn!(&state);
+
+    // only dead-lettered entries can be retried; pending ones are still owned by the worker
+    match EmailOutbox::requeue_dead(&conn, email_outbox_id).await {
+        Ok(true) => (),
+        Ok(false) => return AppError::EmailOutboxEntryNotFound.into_response(),
+        Err(e) => {
+            return AppError::CouldNotGetEmailOutbox(
+                e.context("Could not requeue EmailOutbox entry"),
+            )
+            .into_response();
+        }
+    }
+
+    let response = RetryEmailResponse {
+        success: true,
+        data: RetryEmailResponseData {
+            message: "Email requeued for delivery.".to_string(),
+            email_outbox_id,
+        },
+        meta: EmailOutboxResponseMeta {
+            time_taken: format!("{:?}", stopwatch.get_original_start().elapsed()),
+            timestamp: Utc::now(),
+        },
+    };
+
+    serialize_to_response(&response)
+}
@@ -0,0 +1,96 @@
+use std::sync::Arc;
+
+use axum::{
+    extract::{Path, Query, State},
+    response::{Html, IntoResponse},
+};
+use chrono::{DateTime, Utc};
+use serde_derive::{Deserialize, Serialize};
+
+use crate::utils::{
+    errors::errors::AppError,
+    gadgets::stopwatch::Stopwatch,
+    mail::templates::{render, EmailTemplate, RenderedEmail, DEFAULT_LOCALE},
+    serde::serialize_to_response::serialize_to_response,
+    server_init::server_state_def::ServerState,
+};
+
+// request
+#[derive(Deserialize)]
+pub struct PreviewEmailQuery {
+    locale: Option<String>,
+    /// "html" or "text" returns that part as-is instead of the usual response envelope
+    part: Option<String>,
+}
+
+// response
+#[derive(Serialize)]
+pub struct PreviewEmailResponse {
+    success: bool,
+    data: PreviewEmailResponseData,
+    meta: PreviewEmailResponseMeta,
+}
+
+#[derive(Serialize)]
+pub struct PreviewEmailResponseData {
+    template: String,
+    locale: String,
+    email: RenderedEmail,
+}
+
+#[derive(Serialize)]
+pub struct PreviewEmailResponseMeta {
+    time_taken: String,
+    timestamp: DateTime<Utc>,
+}
+
+// GET /api/admin/email-templates/:template/preview
+pub async fn preview_email_template(
+    State(state): State<Arc<ServerState>>,
+    Path(template): Path<String>,
+    Query(query): Query<PreviewEmailQuery>,
+) -> impl IntoResponse {
+    let stopwatch: Stopwatch = Stopwatch::new("preview_email_template");
+
+    let template = match template.parse::<EmailTemplate>() {
+        Ok(template) => template,
+        Err(_) => return AppError::EmailTemplateNotFound.into_response(),
+    };
+
+    let locale = query.locale.unwrap_or_else(|| DEFAULT_LOCALE.to_owned());
+
+    let rendered = match render(
+        template,
+        &locale,
+        &template.sample_vars(state.get_public_base_url()),
+    ) {
+        Ok(rendered) => rendered,
+        Err(e) => {
+            return AppError::CouldNotRenderEmailTemplate(
+                e.context("Could not render email template"),
+            )
+            .into_response();
+        }
+    };
+
+    match query.part.as_deref() {
+        Some("html") => return Html(rendered.html_body).into_response(),
+        Some("text") => return rendered.text_body.into_response(),
+        _ => (),
+    }
+
+    let response = PreviewEmailResponse {
+        success: true,
+        data: PreviewEmailResponseData {
+            template: template.name().to_owned(),
+            locale,
+            email: rendered,
+        },
+        meta: PreviewEmailResponseMeta {
+            time_taken: format!("{:?}", stopwatch.get_original_start().elapsed()),
+            timestamp: Utc::now(),
+        },
+    };
+
+    serialize_to_response(&response)
+}
@@ -0,0 +1,61 @@
+use std::sync::Arc;
+
+use axum::{extract::State, response::IntoResponse};
+use chrono::{DateTime, Utc};
+use serde_derive::Serialize;
+
+use crate::{
+    get_conn,
+    models::login_lockouts::LoginLockout,
+    utils::{
+        errors::errors::AppError, gadgets::stopwatch::Stopwatch,
+        serde::serialize_to_response::serialize_to_response,
+        server_init::server_state_def::ServerState,
+    },
+};
+
+const RECENT_LOCKOUTS_LIMIT: i64 = 100;
+
+// response
+#[derive(Serialize)]
+pub struct ListLoginLockoutsResponse {
+    success: bool,
+    data: ListLoginLockoutsResponseData,
+    meta: ListLoginLockoutsResponseMeta,
+}
+
+#[derive(Serialize)]
+pub struct ListLoginLockoutsResponseData {
+    lockouts: Vec<LoginLockout>,
+}
+
+#[derive(Serialize)]
+pub struct ListLoginLockoutsResponseMeta {
+    time_taken: String,
+    timestamp: DateTime<Utc>,
+}
+
+// GET /api/admin/login-lockouts
+pub async fn list_login_lockouts(State(state): State<Arc<ServerState>>) -> impl IntoResponse {
+    let stopwatch: Stopwatch = Stopwatch::new("list_login_lockouts");
+    let conn = get_conn!(&state);
+
+    let lockouts = match LoginLockout::get_recent(&conn, RECENT_LOCKOUTS_LIMIT).await {
+        Ok(lockouts) => lockouts,
+        Err(e) => {
+            return AppError::Database(e.context("Could not get recent LoginLockouts"))
+                .into_response();
+        }
+    };
+
+    let response = ListLoginLockoutsResponse {
+        success: true,
+        data: ListLoginLockoutsResponseData { lockouts },
+        meta: ListLoginLockoutsResponseMeta {
+            time_taken: format!("{:?}", stopwatch.get_original_start().elapsed()),
+            timestamp: Utc::now(),
+        },
+    };
+
+    serialize_to_response(&response)
+}
@@ -0,0 +1,352 @@
+use std::sync::Arc;
+
+use anyhow::Context;
+use axum::{extract::State, response::IntoResponse};
+use chrono::{DateTime, Utc};
+use deadpool_postgres::{Object, Transaction};
+use serde_derive::{Deserialize, Serialize};
+use tracing::{error, warn};
+use uuid::Uuid;
+
+use crate::{
+    controllers::middleware::client_ip::ClientIp,
+    get_conn, get_transaction,
+    models::{
+        email_outbox::EmailOutboxForm,
+        login_failures::LoginFailure,
+        login_lockouts::{LoginLockout, LoginLockoutForm},
+        user_tokens::{UserToken, UserTokenForm, ACCOUNT_UNLOCK},
+        users::User,
+    },
+    utils::{
+        errors::errors::AppError,
+        gadgets::stopwatch::Stopwatch,
+        mail::templates::{account_unlock_link, render, EmailTemplate},
+        serde::{payload::Payload, serialize_to_response::serialize_to_response},
+        server_init::{
+            server_init_funcs::initialize_metrics::LOGIN_LOCKOUTS_TOTAL,
+            server_state_def::ServerState,
+        },
+    },
+};
+
+// request
+#[derive(Deserialize)]
+pub struct UnlockAccountForm {
+    token_id: Uuid,
+}
+
+// response
+#[derive(Serialize)]
+pub struct UnlockAccountResponse {
+    success: bool,
+    data: UnlockAccountResponseData,
+    meta: UnlockAccountResponseMeta,
+}
+
+#[derive(Serialize)]
+pub struct UnlockAccountResponseData {
+    message: String,
+}
+
+#[derive(Serialize)]
+pub struct UnlockAccountResponseMeta {
+    time_taken: String,
+    timestamp: DateTime<Utc>,
+}
+
+/// the counters a login attempt is judged by: its client IP's and its account's, each counted once
+fn failure_keys(account_keys: &[String], client_ip: Option<ClientIp>) -> Vec<String> {
+    let mut keys: Vec<String> = client_ip
+        .map(|ClientIp(ip)| LoginFailure::ip_key(ip))
+        .into_iter()
+        .collect();
+    for key in account_keys {
+        if !keys.contains(key) {
+            keys.push(key.clone());
+        }
+    }
+    keys
+}
+
+/// refuses the attempt while its account or client IP is locked or backing off.
+/// Like the rate limiter, lets the attempt through if the counters can't be read
+pub async fn check_login_throttle(
+    state: &Arc<ServerState>,
+    conn: &Object,
+    account_keys: &[String],
+    client_ip: Option<ClientIp>,
+) -> Result<(), AppError> {
+    let throttle = state.get_login_throttle();
+    if !throttle.is_enabled() {
+        return Ok(());
+    }
+
+    match LoginFailure::get_by_keys(conn, &failure_keys(account_keys, client_ip)).await {
+        Ok(failures) => throttle.check(&failures),
+        Err(e) => {
+            error!("Could not get LoginFailures; not throttling: {:?}", e);
+            Ok(())
+        }
+    }
+}
+
+/// counts a failed attempt against its client IP and account, locking whichever reached its threshold.
+/// An existing account's keys are all counted, so every name it signs in with shares the limit, and only
+/// an existing account is mailed. Errors are only logged so the caller still answers with the same invalid
+/// credentials error
+pub async fn record_login_failure(
+    state: &Arc<ServerState>,
+    conn: &mut Object,
+    account_keys: &[String],
+    user: Option<&User>,
+    client_ip: Option<ClientIp>,
+    user_agent: Option<String>,
+    locale: &str,
+) {
+    let throttle = state.get_login_throttle();
+    if !throttle.is_enabled() {
+        return;
+    }
+    throttle.prune_if_due();
+
+    let transaction = match conn.transaction().await {
+        Ok(transaction) => transaction,
+        Err(e) => {
+            error!("Could not build transaction from connection: {:?}", e);
+            return;
+        }
+    };
+
+    let config = throttle.get_config();
+    let mut locked = Vec::new();
+    let mut account_locked = false;
+
+    for key in failure_keys(account_keys, client_ip) {
+        let is_account = key.starts_with("account:");
+        let (threshold, locked_user) = match is_account {
+            true => (config.account_threshold, user),
+            false => (config.ip_threshold, None),
+        };
+
+        let failure =
+            match LoginFailure::record(&transaction, &key, throttle.window_seconds()).await {
+                Ok(failure) => failure,
+                Err(e) => {
+                    error!("Could not record LoginFailure: {:?}", e);
+                    return;
+                }
+            };
+
+        if (failure.get_count() as u32) < threshold {
+            continue;
+        }
+
+        let locked_until = match failure
+            .lock(&transaction, throttle.duration_seconds())
+            .await
+        {
+            Ok(locked_until) => locked_until,
+            Err(e) => {
+                error!("Could not lock {}: {:?}", failure.get_key(), e);
+                return;
+            }
+        };
+
+        // an account's counters reach the threshold together; it is one lockout, recorded and mailed once
+        if is_account && account_locked {
+            continue;
+        }
+        account_locked |= is_account;
+
+        if let Err(e) = record_lockout(
+            state,
+            &transaction,
+            &failure,
+            locked_until,
+            locked_user,
+            client_ip,
+            user_agent.clone(),
+            locale,
+        )
+        .await
+        {
+            error!(
+                "Could not record the lockout of {}: {:?}",
+                failure.get_key(),
+                e
+            );
+            return;
+        }
+        locked.push(failure);
+    }
+
+    if let Err(e) = transaction.commit().await {
+        error!("Could not commit transaction: {:?}", e);
+        return;
+    }
+
+    for failure in locked {
+        let kind = match failure.get_key().starts_with("account:") {
+            true => "account",
+            false => "ip",
+        };
+        warn!(
+            key = failure.get_key(),
+            failures = failure.get_count(),
+            "Locked {} after repeated failed logins",
+            kind
+        );
+        metrics::counter!(LOGIN_LOCKOUTS_TOTAL, "kind" => kind).increment(1);
+    }
+}
+
+/// records the lockout for admins and, for an existing account, mails its owner an unlock link that stays
+/// valid for as long as the lock
+#[allow(clippy::too_many_arguments)]
+async fn record_lockout(
+    state: &Arc<ServerState>,
+    transaction: &Transaction<'_>,
+    failure: &LoginFailure,
+    locked_until: DateTime<Utc>,
+    user: Option<&User>,
+    client_ip: Option<ClientIp>,
+    user_agent: Option<String>,
+    locale: &str,
+) -> anyhow::Result<()> {
+    let throttle = state.get_login_throttle();
+
+    let lockout_form = LoginLockoutForm {
+        login_lockout_key: failure.get_key().to_owned(),
+        login_lockout_user_id: user.map(User::get_id),
+        login_lockout_ip: client_ip.map(|client_ip| client_ip.to_string()),
+        login_lockout_user_agent: user_agent,
+        login_lockout_failures: failure.get_count(),
+        login_lockout_locked_until: locked_until,
+    };
+    lockout_form
+        .insert(transaction)
+        .await
+        .context("Could not insert LoginLockout")?;
+
+    let Some(user) = user else {
+        return Ok(());
+    };
+
+    UserToken::invalidate_outstanding(transaction, user.get_id(), ACCOUNT_UNLOCK).await?;
+
+    let user_token_form = UserTokenForm {
+        user_token_user_id: user.get_id(),
+        user_token_type: ACCOUNT_UNLOCK.to_owned(),
+        user_token_value: Uuid::new_v4(),
+        user_token_expires_at: locked_until,
+        user_token_session_id: None,
+    };
+    let returned_token = user_token_form
+        .insert(transaction)
+        .await
+        .context("Could not insert account unlock UserToken")?;
+
+    let rendered = render(
+        EmailTemplate::AccountLocked,
+        locale,
+        &[
+            (
+                "link",
+                account_unlock_link(state.get_public_base_url(), returned_token.get_id()),
+            ),
+            (
+                "locked_minutes",
+                throttle.get_config().duration_minutes.to_string(),
+            ),
+        ],
+    )?;
+
+    EmailOutboxForm::from_rendered(user.get_email().to_owned(), rendered)
+        .insert(transaction)
+        .await
+        .context("Could not enqueue account locked email")?;
+
+    Ok(())
+}
+
+// POST /api/auth/unlock-account
+pub async fn unlock_account(
+    State(state): State<Arc<ServerState>>,
+    Payload(body): Payload<UnlockAccountForm>,
+) -> impl IntoResponse {
+    let stopwatch: Stopwatch = Stopwatch::new("unlock_account");
+
+    let mut conn = get_conn!(&state);
+
+    let token = match UserToken::get_by_id(&conn, body.token_id).await {
+        Ok(Some(tok)) if tok.get_type() == ACCOUNT_UNLOCK => tok,
+        Ok(_) => return AppError::UserTokenInvalid.into_response(),
+        Err(e) => {
+            error!("Could not get UserToken by ID: {:?}", e);
+            return AppError::UserTokenInvalid.into_response();
+        }
+    };
+
+    if token.is_used() {
+        return AppError::UserTokenUsed.into_response();
+    }
+
+    // the token expires with the lock, which has then lifted on its own
+    if token.is_expired() {
+        return AppError::UserTokenExpired.into_response();
+    }
+
+    let user = match User::get_by_id(&conn, token.get_user_id()).await {
+        Ok(Some(user)) => user,
+        Ok(None) => return AppError::UserTokenInvalid.into_response(),
+        Err(e) => {
+            return AppError::CouldNotGetUser(e.context("Could not get User by ID"))
+                .into_response();
+        }
+    };
+
+    let transaction = get_transaction!(conn);
+
+    match token.mark_used(&transaction).await {
+        Ok(true) => (),
+        Ok(false) => return AppError::UserTokenUsed.into_response(),
+        Err(e) => {
+            return AppError::CouldNotUpdateUserToken(
+                e.context("Could not mark account unlock UserToken as used"),
+            )
+            .into_response();
+        }
+    }
+
+    for key in LoginFailure::account_keys(&user) {
+        if let Err(e) = LoginFailure::clear(&transaction, &key).await {
+            return AppError::Database(e.context("Could not clear LoginFailure")).into_response();
+        }
+    }
+
+    if let Err(e) = LoginLockout::mark_unlocked(&transaction, token.get_user_id()).await {
+        return AppError::Database(e.context("Could not mark LoginLockout as unlocked"))
+            .into_response();
+    }
+
+    match transaction.commit().await {
+        Ok(_) => (),
+        Err(e) => {
+            return AppError::CouldNotCommitTransaction(e.into()).into_response();
+        }
+    }
+
+    let response = UnlockAccountResponse {
+        success: true,
+        data: UnlockAccountResponseData {
+            message: "Account unlocked. You can log in again.".to_string(),
+        },
+        meta: UnlockAccountResponseMeta {
+            time_taken: format!("{:?}", stopwatch.get_original_start().elapsed()),
+            timestamp: Utc::now(),
+        },
+    };
+
+    serialize_to_response(&response)
+}
@@ -0,0 +1,373 @@
+use std::sync::Arc;
+
+use axum::{
+    extract::State,
+    http::{header::SET_COOKIE, HeaderMap, HeaderValue},
+    response::{IntoResponse, Response},
+};
+use chrono::{DateTime, Utc};
+use deadpool_postgres::Transaction;
+use serde_derive::{Deserialize, Serialize};
+use tracing::{error, field::display, Span};
+use uuid::Uuid;
+
+use crate::{
+    controllers::{
+        auth::account_lockout::{check_login_throttle, record_login_failure},
+        middleware::{client_ip::ClientIp, request_response_info::get_user_agent},
+    },
+    get_conn, get_transaction,
+    models::{
+        jwt::Claims,
+        login_failures::LoginFailure,
+        passkeys::Passkey,
+        user_sessions::{UserSessionForm, SESSION_COOKIE_NAME, SESSION_DURATION_DAYS},
+        user_tokens::{UserTokenForm, LOGIN_2FA_CHALLENGE, LOGIN_2FA_CHALLENGE_DURATION_MINUTES},
+        user_totp::UserTotp,
+        users::{User, UserTruncated},
+    },
+    utils::{
+        errors::errors::AppError,
+        gadgets::{
+            argon::{dummy_password_hash, verify_password},
+            stopwatch::Stopwatch,
+        },
+        mail::templates::negotiate_locale,
+        serde::{payload::Payload, serialize_to_response::serialize_to_response},
+        server_init::server_state_def::ServerState,
+    },
+};
+
+// request
+#[derive(Deserialize)]
+pub struct LoginForm {
+    user_email_or_screen_name: String,
+    user_password: String,
+}
+
+// response
+#[derive(Serialize)]
+pub struct LoginResponse {
+    success: bool,
+    data: LoginResponseData,
+    meta: LoginResponseMeta,
+}
+
+#[derive(Serialize)]
+pub struct LoginResponseData {
+    two_factor_required: bool,
+    user: UserTruncated,
+    session_token: Uuid,
+    session_expires_at: DateTime<Utc>,
+    access_token: String,
+    access_token_expires_at: DateTime<Utc>,
+    refresh_token: Uuid,
+    refresh_token_expires_at: DateTime<Utc>,
+}
+
+/// sent instead of a session when the account has two-factor authentication
+#[derive(Serialize)]
+pub struct LoginChallengeResponse {
+    success: bool,
+    data: LoginChallengeResponseData,
+    meta: LoginResponseMeta,
+}
+
+#[derive(Serialize)]
+pub struct LoginChallengeResponseData {
+    two_factor_required: bool,
+    /// what /api/auth/login/2fa will take for this account: totp, recovery_code and, with a registered
+    /// passkey, passkey
+    two_factor_methods: Vec<&'static str>,
+    challenge_token: Uuid,
+    challenge_expires_at: DateTime<Utc>,
+}
+
+#[derive(Serialize)]
+pub struct LoginResponseMeta {
+    time_taken: String,
+    timestamp: DateTime<Utc>,
+}
+
+// POST /api/auth/login
+pub async fn login(
+    State(state): State<Arc<ServerState>>,
+    headers: HeaderMap,
+    client_ip: Option<ClientIp>,
+    Payload(body): Payload<LoginForm>,
+) -> impl IntoResponse {
+    let stopwatch: Stopwatch = Stopwatch::new("login");
+
+    // limited per identifier as submitted, before lookup, so guessing at unknown accounts is limited the same way
+    if let Err(e) = state
+        .get_rate_limiter()
+        .check_account(&body.user_email_or_screen_name)
+        .await
+    {
+        return e.into_response();
+    }
+
+    let mut conn = get_conn!(&state);
+
+    let user: Option<User> =
+        match User::get_by_email_or_screen_name(&conn, &body.user_email_or_screen_name).await {
+            Ok(user) => user,
+            Err(e) => {
+                return AppError::CouldNotGetUser(
+                    e.context("Could not get User by email or screen name"),
+                )
+                .into_response();
+            }
+        };
+
+    // judged before the password so a locked account can't be probed, even with the right one. The identifier
+    // as submitted is counted so one that matches no account backs off and locks just the same, and a real
+    // account's every name is counted so switching between them doesn't get around its limit
+    let mut account_keys = vec![LoginFailure::account_key(&body.user_email_or_screen_name)];
+    if let Some(user) = &user {
+        account_keys.extend(LoginFailure::account_keys(user));
+    }
+    if let Err(e) = check_login_throttle(&state, &conn, &account_keys, client_ip).await {
+        return e.into_response();
+    }
+
+    // an unknown user is checked against a dummy hash, spending the same argon2 work as a real account so
+    // response times don't give it away. argon2 is slow on purpose, so it runs on the blocking pool rather
+    // than on this worker
+    let password_hash = user
+        .as_ref()
+        .map(|user| user.get_password_hash().to_owned());
+    let password_matches = match tokio::task::spawn_blocking(move || match password_hash {
+        Some(password_hash) => verify_password(password_hash, body.user_password),
+        None => verify_password(dummy_password_hash(), body.user_password).map(|_| false),
+    })
+    .await
+    {
+        Ok(Ok(matches)) => matches,
+        Ok(Err(e)) => {
+            error!("Could not parse stored password hash: {:?}", e);
+            false
+        }
+        Err(e) => {
+            return AppError::CouldNotVerifyPassword(e.into()).into_response();
+        }
+    };
+
+    // unknown users and wrong passwords get the same error so accounts can't be enumerated
+    let user: User = match user {
+        Some(user) if password_matches => user,
+        _ => {
+            record_login_failure(
+                &state,
+                &mut conn,
+                &account_keys,
+                user.as_ref(),
+                client_ip,
+                get_user_agent(&headers),
+                negotiate_locale(&headers),
+            )
+            .await;
+            return AppError::InvalidCredentials.into_response();
+        }
+    };
+    Span::current().record("user.id", display(user.get_id()));
+
+    // account state is only revealed once the password checks out
+    if !user.is_email_verified() {
+        return AppError::UserEmailNotVerified.into_response();
+    }
+
+    if !user.is_active() {
+        return AppError::UserInactive.into_response();
+    }
+
+    let totp = match UserTotp::get_by_user_id(&conn, user.get_id()).await {
+        Ok(totp) => totp,
+        Err(e) => {
+            return AppError::Database(e.context("Could not get UserTotp")).into_response();
+        }
+    };
+
+    // with a confirmed authenticator the password only earns a challenge, redeemed at /api/auth/login/2fa
+    if totp.is_some_and(|totp| totp.is_confirmed()) {
+        let mut two_factor_methods = vec!["totp", "recovery_code"];
+        match Passkey::get_by_user_id(&conn, user.get_id()).await {
+            Ok(passkeys) if passkeys.is_empty() => (),
+            Ok(_) => two_factor_methods.push("passkey"),
+            Err(e) => {
+                return AppError::Database(e.context("Could not get Passkeys by user ID"))
+                    .into_response();
+            }
+        }
+
+        let transaction = get_transaction!(conn);
+
+        let challenge_form = UserTokenForm {
+            user_token_user_id: user.get_id(),
+            user_token_type: LOGIN_2FA_CHALLENGE.to_owned(),
+            user_token_value: Uuid::new_v4(),
+            user_token_expires_at: Utc::now()
+                + chrono::Duration::minutes(LOGIN_2FA_CHALLENGE_DURATION_MINUTES),
+            user_token_session_id: None,
+        };
+
+        let challenge = match challenge_form.insert(&transaction).await {
+            Ok(challenge) => challenge,
+            Err(e) => {
+                return AppError::CouldNotInsertUserToken(
+                    e.context("Could not insert 2FA challenge UserToken"),
+                )
+                .into_response();
+            }
+        };
+
+        match transaction.commit().await {
+            Ok(_) => (),
+            Err(e) => {
+                return AppError::CouldNotCommitTransaction(e.into()).into_response();
+            }
+        }
+
+        let response = LoginChallengeResponse {
+            success: true,
+            data: LoginChallengeResponseData {
+                two_factor_required: true,
+                two_factor_methods,
+                challenge_token: challenge.get_value(),
+                challenge_expires_at: challenge.get_expired_time(),
+            },
+            meta: LoginResponseMeta {
+                time_taken: format!("{:?}", stopwatch.get_original_start().elapsed()),
+                timestamp: Utc::now(),
+            },
+        };
+
+        return serialize_to_response(&response);
+    }
+
+    let roles = match user.get_roles(&conn).await {
+        Ok(roles) => roles,
+        Err(e) => {
+            return AppError::CouldNotGetUser(e.context("Could not get roles for User"))
+                .into_response();
+        }
+    };
+
+    let transaction = get_transaction!(conn);
+
+    issue_session(
+        &state,
+        transaction,
+        user,
+        roles,
+        &headers,
+        client_ip,
+        stopwatch,
+    )
+    .await
+}
+
+/// opens a session for a user who has passed every login step and commits the caller's transaction along
+/// with it, so whatever the caller spent on the way (a 2FA challenge, a recovery code) is only spent if the
+/// session is issued
+pub async fn issue_session(
+    state: &Arc<ServerState>,
+    transaction: Transaction<'_>,
+    user: User,
+    roles: Vec<String>,
+    headers: &HeaderMap,
+    client_ip: Option<ClientIp>,
+    stopwatch: Stopwatch,
+) -> Response {
+    let session_form = UserSessionForm {
+        user_session_user_id: user.get_id(),
+        user_session_expires_at: Utc::now() + chrono::Duration::days(SESSION_DURATION_DAYS),
+        user_session_user_agent: get_user_agent(headers),
+        user_session_ip: client_ip.map(|client_ip| client_ip.to_string()),
+    };
+
+    let session = match session_form.insert(&transaction).await {
+        Ok(session) => session,
+        Err(e) => {
+            return AppError::CouldNotInsertUserSession(e.context("Could not insert UserSession"))
+                .into_response();
+        }
+    };
+
+    let refresh_token = match UserTokenForm::new_refresh_token(&session)
+        .insert(&transaction)
+        .await
+    {
+        Ok(token) => token,
+        Err(e) => {
+            return AppError::CouldNotInsertUserToken(
+                e.context("Could not insert refresh UserToken"),
+            )
+            .into_response();
+        }
+    };
+
+    // a successful login forgets the account's failures; the client IP's are kept so one valid account
+    // can't be used to reset a guessing run against others
+    for key in LoginFailure::account_keys(&user) {
+        if let Err(e) = LoginFailure::clear(&transaction, &key).await {
+            return AppError::Database(e.context("Could not clear LoginFailure")).into_response();
+        }
+    }
+
+    // sign before committing so a signing failure doesn't leave an orphaned session behind
+    let claims = Claims::new(
+        user.get_id(),
+        user.get_screen_name().to_owned(),
+        roles,
+        session.get_id(),
+    );
+
+    let access_token = match state.get_jwt().sign(&claims) {
+        Ok(token) => token,
+        Err(e) => {
+            return AppError::CouldNotSignJwt(e.context("Could not sign JWT")).into_response();
+        }
+    };
+
+    match transaction.commit().await {
+        Ok(_) => (),
+        Err(e) => {
+            return AppError::CouldNotCommitTransaction(e.into()).into_response();
+        }
+    }
+
+    let session_cookie = format!(
+        "{}={}; Path=/; Max-Age={}; HttpOnly; Secure; SameSite=Lax",
+        SESSION_COOKIE_NAME,
+        session.get_token(),
+        chrono::Duration::days(SESSION_DURATION_DAYS).num_seconds()
+    );
+
+    let response = LoginResponse {
+        success: true,
+        data: LoginResponseData {
+            two_factor_required: false,
+            user: UserTruncated::from(user),
+            session_token: session.get_token(),
+            session_expires_at: session.get_expired_time(),
+            access_token,
+            access_token_expires_at: claims.get_expired_time(),
+            refresh_token: refresh_token.get_value(),
+            refresh_token_expires_at: refresh_token.get_expired_time(),
+        },
+        meta: LoginResponseMeta {
+            time_taken: format!("{:?}", stopwatch.get_original_start().elapsed()),
+            timestamp: Utc::now(),
+        },
+    };
+
+    let mut response = serialize_to_response(&response);
+    if response.status().is_success() {
+        if let Ok(cookie) = HeaderValue::from_str(&session_cookie) {
+            response.headers_mut().insert(SET_COOKIE, cookie);
+        }
+    }
+
+    response
+}
@@ -0,0 +1,248 @@
+use std::sync::Arc;
+
+use axum::{extract::State, http::HeaderMap, response::IntoResponse};
+use chrono::Utc;
+use deadpool_postgres::Object;
+use serde_derive::Deserialize;
+use tracing::{error, field::display, Span};
+use uuid::Uuid;
+
+use crate::{
+    controllers::{
+        auth::{
+            account_lockout::{check_login_throttle, record_login_failure},
+            login::issue_session,
+            passkey_login::{check_passkey_assertion, PasskeyAssertionForm, VerifiedAssertion},
+        },
+        middleware::{client_ip::ClientIp, request_response_info::get_user_agent},
+    },
+    get_conn, get_transaction,
+    models::{
+        login_failures::LoginFailure,
+        passkey_challenges::PASSKEY_SECOND_FACTOR,
+        user_recovery_codes::UserRecoveryCode,
+        user_tokens::{UserToken, LOGIN_2FA_CHALLENGE},
+        user_totp::UserTotp,
+        users::User,
+    },
+    utils::{
+        errors::errors::AppError,
+        gadgets::{argon::verify_password, stopwatch::Stopwatch},
+        mail::templates::negotiate_locale,
+        serde::payload::Payload,
+        server_init::server_state_def::ServerState,
+        two_factor::{
+            recovery_codes::{normalize_recovery_code, recovery_code_selector},
+            totp::verify,
+        },
+        webauthn::relying_party::UserVerification,
+    },
+};
+
+// request
+/// the challenge from /api/auth/login with a code from the authenticator app, a recovery code or a passkey
+/// assertion started at /api/auth/passkeys/2fa/start
+#[derive(Deserialize)]
+pub struct LoginTwoFactorForm {
+    challenge_token: Uuid,
+    code: Option<String>,
+    recovery_code: Option<String>,
+    passkey: Option<PasskeyAssertionForm>,
+}
+
+/// what a submitted factor turned out to be, to be spent along with the session
+enum SecondFactor {
+    Totp(UserTotp, i64),
+    RecoveryCode(UserRecoveryCode),
+    Passkey(VerifiedAssertion),
+}
+
+// POST /api/auth/login/2fa; answers like /api/auth/login does without two-factor authentication
+pub async fn login_two_factor(
+    State(state): State<Arc<ServerState>>,
+    headers: HeaderMap,
+    client_ip: Option<ClientIp>,
+    Payload(body): Payload<LoginTwoFactorForm>,
+) -> impl IntoResponse {
+    let stopwatch: Stopwatch = Stopwatch::new("login_two_factor");
+
+    let mut conn = get_conn!(&state);
+
+    let challenge =
+        match UserToken::get_by_value(&conn, LOGIN_2FA_CHALLENGE, body.challenge_token).await {
+            Ok(Some(challenge)) => challenge,
+            Ok(None) => return AppError::UserTokenInvalid.into_response(),
+            Err(e) => {
+                error!("Could not get UserToken by value: {:?}", e);
+                return AppError::UserTokenInvalid.into_response();
+            }
+        };
+
+    if challenge.is_used() {
+        return AppError::UserTokenUsed.into_response();
+    }
+
+    if challenge.is_expired() {
+        return AppError::UserTokenExpired.into_response();
+    }
+
+    let user = match User::get_by_id(&conn, challenge.get_user_id()).await {
+        Ok(Some(user)) => user,
+        Ok(None) => return AppError::UserTokenInvalid.into_response(),
+        Err(e) => {
+            return AppError::CouldNotGetUser(e.context("Could not get User by ID"))
+                .into_response();
+        }
+    };
+    Span::current().record("user.id", display(user.get_id()));
+
+    if !user.is_active() {
+        return AppError::UserInactive.into_response();
+    }
+
+    // wrong codes count as failed logins, so guessing at codes runs into the same back-off and lockout
+    if let Err(e) =
+        check_login_throttle(&state, &conn, &LoginFailure::account_keys(&user), client_ip).await
+    {
+        return e.into_response();
+    }
+
+    let second_factor = match check_second_factor(&state, &conn, &user, &body).await {
+        Ok(Some(second_factor)) => second_factor,
+        Ok(None) => {
+            record_login_failure(
+                &state,
+                &mut conn,
+                &LoginFailure::account_keys(&user),
+                Some(&user),
+                client_ip,
+                get_user_agent(&headers),
+                negotiate_locale(&headers),
+            )
+            .await;
+            return AppError::InvalidTwoFactorCode.into_response();
+        }
+        Err(e) => return e.into_response(),
+    };
+
+    let roles = match user.get_roles(&conn).await {
+        Ok(roles) => roles,
+        Err(e) => {
+            return AppError::CouldNotGetUser(e.context("Could not get roles for User"))
+                .into_response();
+        }
+    };
+
+    let transaction = get_transaction!(conn);
+
+    match challenge.mark_used(&transaction).await {
+        Ok(true) => (),
+        Ok(false) => return AppError::UserTokenUsed.into_response(),
+        Err(e) => {
+            return AppError::CouldNotUpdateUserToken(
+                e.context("Could not mark 2FA challenge UserToken as used"),
+            )
+            .into_response();
+        }
+    }
+
+    // a concurrent login got to the same code first
+    let spent = match &second_factor {
+        SecondFactor::Totp(totp, step) => totp.mark_step_used(&transaction, *step).await,
+        SecondFactor::RecoveryCode(recovery_code) => recovery_code.mark_used(&transaction).await,
+        SecondFactor::Passkey(assertion) => match assertion.spend(&transaction).await {
+            Ok(()) => Ok(true),
+            Err(e) => return e.into_response(),
+        },
+    };
+    match spent {
+        Ok(true) => (),
+        Ok(false) => return AppError::InvalidTwoFactorCode.into_response(),
+        Err(e) => {
+            return AppError::Database(e.context("Could not spend second factor")).into_response();
+        }
+    }
+
+    issue_session(
+        &state,
+        transaction,
+        user,
+        roles,
+        &headers,
+        client_ip,
+        stopwatch,
+    )
+    .await
+}
+
+/// the enrolled secret's time step, the recovery code or the passkey the submission matches, None if it
+/// matches none of them
+async fn check_second_factor(
+    state: &Arc<ServerState>,
+    conn: &Object,
+    user: &User,
+    body: &LoginTwoFactorForm,
+) -> Result<Option<SecondFactor>, AppError> {
+    if let Some(passkey) = body.passkey.as_ref() {
+        return Ok(check_passkey_assertion(
+            state,
+            conn,
+            passkey,
+            PASSKEY_SECOND_FACTOR,
+            Some(user.get_id()),
+            UserVerification::Discouraged,
+        )
+        .await?
+        .map(SecondFactor::Passkey));
+    }
+
+    if let Some(code) = body.code.as_deref() {
+        let totp = match UserTotp::get_by_user_id(conn, user.get_id()).await {
+            Ok(Some(totp)) if totp.is_confirmed() => totp,
+            Ok(_) => return Ok(None),
+            Err(e) => return Err(AppError::Database(e.context("Could not get UserTotp"))),
+        };
+
+        let secret = state
+            .get_secret_cipher()
+            .decrypt(user.get_id(), totp.get_secret_encrypted())
+            .map_err(AppError::CouldNotVerifyTwoFactor)?;
+
+        return Ok(verify(
+            &secret,
+            code,
+            Utc::now().timestamp(),
+            totp.get_last_used_step(),
+        )
+        .map(|step| SecondFactor::Totp(totp, step)));
+    }
+
+    let Some(recovery_code) = body.recovery_code.as_deref() else {
+        return Ok(None);
+    };
+
+    let normalized = normalize_recovery_code(recovery_code);
+    let candidates = UserRecoveryCode::get_unused_by_selector(
+        conn,
+        user.get_id(),
+        recovery_code_selector(&normalized),
+    )
+    .await
+    .map_err(|e| AppError::Database(e.context("Could not get UserRecoveryCodes")))?;
+
+    // argon2 is slow on purpose, so it runs on the blocking pool rather than on this worker
+    let recovery_code = tokio::task::spawn_blocking(move || {
+        candidates.into_iter().find(|candidate| {
+            verify_password(candidate.get_hash().to_owned(), normalized.clone()).unwrap_or_else(
+                |e| {
+                    error!("Could not parse stored recovery code hash: {:?}", e);
+                    false
+                },
+            )
+        })
+    })
+    .await
+    .map_err(|e| AppError::CouldNotVerifyTwoFactor(e.into()))?;
+
+    Ok(recovery_code.map(SecondFactor::RecoveryCode))
+}
@@ -0,0 +1,151 @@
+use std::sync::Arc;
+
+use axum::{
+    extract::State,
+    http::{header::SET_COOKIE, HeaderValue},
+    response::IntoResponse,
+};
+use chrono::{DateTime, Utc};
+use serde_derive::Serialize;
+
+use crate::{
+    controllers::middleware::auth::AuthUser,
+    get_conn, get_transaction,
+    models::{
+        user_sessions::{UserSession, SESSION_COOKIE_NAME},
+        user_tokens::UserToken,
+    },
+    utils::{
+        errors::errors::AppError, gadgets::stopwatch::Stopwatch,
+        serde::serialize_to_response::serialize_to_response,
+        server_init::server_state_def::ServerState,
+    },
+};
+
+// response
+#[derive(Serialize)]
+pub struct LogoutResponse {
+    success: bool,
+    data: LogoutResponseData,
+    meta: LogoutResponseMeta,
+}
+
+#[derive(Serialize)]
+pub struct LogoutResponseData {
+    message: String,
+    revoked_sessions: u64,
+}
+
+#[derive(Serialize)]
+pub struct LogoutResponseMeta {
+    time_taken: String,
+    timestamp: DateTime<Utc>,
+}
+
+fn clear_session_cookie(mut response: axum::response::Response) -> axum::response::Response {
+    if response.status().is_success() {
+        if let Ok(cookie) = HeaderValue::from_str(&format!(
+            "{}=; Path=/; Max-Age=0; HttpOnly; Secure; SameSite=Lax",
+            SESSION_COOKIE_NAME
+        )) {
+            response.headers_mut().insert(SET_COOKIE, cookie);
+        }
+    }
+
+    response
+}
+
+// POST /api/auth/logout
+pub async fn logout(
+    State(state): State<Arc<ServerState>>,
+    auth_user: AuthUser,
+) -> impl IntoResponse {
+    let stopwatch: Stopwatch = Stopwatch::new("logout");
+    let mut conn = get_conn!(&state);
+    let transaction = get_transaction!(conn);
+
+    let revoked_sessions = match UserSession::revoke(&transaction, auth_user.session_id).await {
+        Ok(count) => count,
+        Err(e) => {
+            return AppError::CouldNotRevokeUserSession(e.context("Could not revoke UserSession"))
+                .into_response();
+        }
+    };
+
+    if let Err(e) = UserToken::revoke_session_family(&transaction, auth_user.session_id).await {
+        return AppError::CouldNotRevokeUserSession(
+            e.context("Could not revoke refresh token family"),
+        )
+        .into_response();
+    }
+
+    match transaction.commit().await {
+        Ok(_) => (),
+        Err(e) => {
+            return AppError::CouldNotCommitTransaction(e.into()).into_response();
+        }
+    }
+
+    let response = LogoutResponse {
+        success: true,
+        data: LogoutResponseData {
+            message: "Logged out.".to_string(),
+            revoked_sessions,
+        },
+        meta: LogoutResponseMeta {
+            time_taken: format!("{:?}", stopwatch.get_original_start().elapsed()),
+            timestamp: Utc::now(),
+        },
+    };
+
+    clear_session_cookie(serialize_to_response(&response))
+}
+
+// POST /api/auth/logout-all
+pub async fn logout_all(
+    State(state): State<Arc<ServerState>>,
+    auth_user: AuthUser,
+) -> impl IntoResponse {
+    let stopwatch: Stopwatch = Stopwatch::new("logout_all");
+    let mut conn = get_conn!(&state);
+    let transaction = get_transaction!(conn);
+
+    let revoked_sessions =
+        match UserSession::revoke_all_for_user(&transaction, auth_user.user.get_id()).await {
+            Ok(count) => count,
+            Err(e) => {
+                return AppError::CouldNotRevokeUserSession(
+                    e.context("Could not revoke UserSessions"),
+                )
+                .into_response();
+            }
+        };
+
+    if let Err(e) =
+        UserToken::revoke_all_refresh_for_user(&transaction, auth_user.user.get_id()).await
+    {
+        return AppError::CouldNotRevokeUserSession(e.context("Could not revoke refresh tokens"))
+            .into_response();
+    }
+
+    match transaction.commit().await {
+        Ok(_) => (),
+        Err(e) => {
+            return AppError::CouldNotCommitTransaction(e.into()).into_response();
+        }
+    }
+
+    let response = LogoutResponse {
+        success: true,
+        data: LogoutResponseData {
+            message: "Logged out of every session.".to_string(),
+            revoked_sessions,
+        },
+        meta: LogoutResponseMeta {
+            time_taken: format!("{:?}", stopwatch.get_original_start().elapsed()),
+            timestamp: Utc::now(),
+        },
+    };
+
+    clear_session_cookie(serialize_to_response(&response))
+}
@@ -0,0 +1,51 @@
+use axum::response::IntoResponse;
+use chrono::{DateTime, Utc};
+use serde_derive::Serialize;
+use uuid::Uuid;
+
+use crate::{
+    controllers::middleware::auth::AuthUser,
+    models::users::UserTruncated,
+    utils::{gadgets::stopwatch::Stopwatch, serde::serialize_to_response::serialize_to_response},
+};
+
+// response
+#[derive(Serialize)]
+pub struct MeResponse {
+    success: bool,
+    data: MeResponseData,
+    meta: MeResponseMeta,
+}
+
+#[derive(Serialize)]
+pub struct MeResponseData {
+    user: UserTruncated,
+    roles: Vec<String>,
+    session_id: Uuid,
+}
+
+#[derive(Serialize)]
+pub struct MeResponseMeta {
+    time_taken: String,
+    timestamp: DateTime<Utc>,
+}
+
+// GET /api/auth/me
+pub async fn me(auth_user: AuthUser) -> impl IntoResponse {
+    let stopwatch: Stopwatch = Stopwatch::new("me");
+
+    let response = MeResponse {
+        success: true,
+        data: MeResponseData {
+            user: UserTruncated::from(auth_user.user),
+            roles: auth_user.roles,
+            session_id: auth_user.session_id,
+        },
+        meta: MeResponseMeta {
+            time_taken: format!("{:?}", stopwatch.get_original_start().elapsed()),
+            timestamp: Utc::now(),
+        },
+    };
+
+    serialize_to_response(&response)
+}
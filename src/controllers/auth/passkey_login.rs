@@ -0,0 +1,380 @@
+use std::sync::Arc;
+
+use axum::{extract::State, http::HeaderMap, response::IntoResponse};
+use chrono::{DateTime, Utc};
+use deadpool_postgres::{Object, Transaction};
+use serde_derive::{Deserialize, Serialize};
+use tracing::{error, field::display, warn, Span};
+use uuid::Uuid;
+
+use crate::{
+    controllers::{
+        auth::{
+            account_lockout::{check_login_throttle, record_login_failure},
+            login::issue_session,
+            passkeys::{get_redeemable_challenge, issue_passkey_challenge},
+        },
+        middleware::{client_ip::ClientIp, request_response_info::get_user_agent},
+    },
+    get_conn, get_transaction,
+    models::{
+        login_failures::LoginFailure,
+        passkey_challenges::{PasskeyChallenge, PASSKEY_LOGIN, PASSKEY_SECOND_FACTOR},
+        passkeys::Passkey,
+        user_tokens::{UserToken, LOGIN_2FA_CHALLENGE},
+        users::User,
+    },
+    utils::{
+        errors::errors::AppError,
+        gadgets::stopwatch::Stopwatch,
+        mail::templates::negotiate_locale,
+        serde::{payload::Payload, serialize_to_response::serialize_to_response},
+        server_init::server_state_def::ServerState,
+        webauthn::relying_party::{
+            AuthenticationCredential, CredentialDescriptor, RequestOptions, UserVerification,
+        },
+    },
+};
+
+// request
+/// a credential from navigator.credentials.get() and the challenge it answers
+#[derive(Deserialize)]
+pub struct PasskeyAssertionForm {
+    challenge_id: Uuid,
+    credential: AuthenticationCredential,
+}
+
+#[derive(Deserialize)]
+pub struct StartPasskeySecondFactorForm {
+    challenge_token: Uuid,
+}
+
+// response
+#[derive(Serialize)]
+pub struct StartPasskeyAssertionResponse {
+    success: bool,
+    data: StartPasskeyAssertionResponseData,
+    meta: StartPasskeyAssertionResponseMeta,
+}
+
+#[derive(Serialize)]
+pub struct StartPasskeyAssertionResponseData {
+    challenge_id: Uuid,
+    /// for navigator.credentials.get(), after PublicKeyCredential.parseRequestOptionsFromJSON()
+    options: RequestOptions,
+}
+
+#[derive(Serialize)]
+pub struct StartPasskeyAssertionResponseMeta {
+    time_taken: String,
+    timestamp: DateTime<Utc>,
+}
+
+/// an assertion whose signature checked out, to be spent along with the session
+pub struct VerifiedAssertion {
+    challenge: PasskeyChallenge,
+    passkey: Passkey,
+    sign_count: u32,
+}
+
+impl VerifiedAssertion {
+    pub fn get_user_id(&self) -> Uuid {
+        self.passkey.get_user_id()
+    }
+
+    /// answers the challenge and records the new sign counter; fails if a concurrent request answered the
+    /// challenge first or the counter shows the credential was cloned
+    pub async fn spend(&self, transaction: &Transaction<'_>) -> Result<(), AppError> {
+        match self.challenge.mark_used(transaction).await {
+            Ok(true) => (),
+            Ok(false) => return Err(AppError::PasskeyChallengeInvalid),
+            Err(e) => {
+                return Err(AppError::Database(
+                    e.context("Could not mark PasskeyChallenge as used"),
+                ))
+            }
+        }
+
+        match self
+            .passkey
+            .update_sign_count(transaction, i64::from(self.sign_count))
+            .await
+        {
+            Ok(true) => Ok(()),
+            Ok(false) => {
+                warn!(
+                    passkey.id = %self.passkey.get_id(),
+                    sign_count = self.sign_count,
+                    "Passkey sign counter did not advance; the credential may have been cloned"
+                );
+                Err(AppError::PasskeyRejected)
+            }
+            Err(e) => Err(AppError::Database(
+                e.context("Could not update Passkey sign count"),
+            )),
+        }
+    }
+}
+
+/// checks an assertion against its challenge and the stored passkey it names. Ok(None) means the credential is
+/// unknown, belongs to someone else or its signature does not verify; the reason is only logged
+pub async fn check_passkey_assertion(
+    state: &Arc<ServerState>,
+    conn: &Object,
+    form: &PasskeyAssertionForm,
+    purpose: &str,
+    user_id: Option<Uuid>,
+    user_verification: UserVerification,
+) -> Result<Option<VerifiedAssertion>, AppError> {
+    let challenge = get_redeemable_challenge(conn, form.challenge_id, purpose, user_id).await?;
+
+    let credential_id = match form.credential.credential_id() {
+        Ok(credential_id) => credential_id,
+        Err(e) => {
+            warn!("Passkey assertion rejected: {:#}", e);
+            return Ok(None);
+        }
+    };
+
+    let passkey = match Passkey::get_by_credential_id(conn, &credential_id).await {
+        Ok(Some(passkey)) => passkey,
+        Ok(None) => {
+            warn!("Passkey assertion rejected: unknown credential");
+            return Ok(None);
+        }
+        Err(e) => {
+            return Err(AppError::Database(
+                e.context("Could not get Passkey by credential ID"),
+            ))
+        }
+    };
+
+    if user_id.is_some_and(|user_id| user_id != passkey.get_user_id()) {
+        warn!("Passkey assertion rejected: credential belongs to another user");
+        return Ok(None);
+    }
+
+    // a discoverable credential names the account it was created for, which has to be the passkey's
+    match form.credential.user_handle() {
+        Ok(Some(user_handle)) if user_handle != passkey.get_user_id().as_bytes() => {
+            warn!("Passkey assertion rejected: user handle does not match the credential");
+            return Ok(None);
+        }
+        Ok(_) => (),
+        Err(e) => {
+            warn!("Passkey assertion rejected: {:#}", e);
+            return Ok(None);
+        }
+    }
+
+    match state.get_relying_party().verify_assertion(
+        challenge.get_value(),
+        passkey.get_public_key(),
+        &form.credential,
+        user_verification,
+    ) {
+        Ok(sign_count) => Ok(Some(VerifiedAssertion {
+            challenge,
+            passkey,
+            sign_count,
+        })),
+        Err(e) => {
+            warn!(passkey.id = %passkey.get_id(), "Passkey assertion rejected: {:#}", e);
+            Ok(None)
+        }
+    }
+}
+
+// POST /api/auth/passkeys/login/start; usernameless, so the browser offers whichever passkey it holds for the site
+pub async fn start_passkey_login(State(state): State<Arc<ServerState>>) -> impl IntoResponse {
+    let stopwatch: Stopwatch = Stopwatch::new("start_passkey_login");
+    let mut conn = get_conn!(&state);
+
+    let challenge = match issue_passkey_challenge(&mut conn, None, PASSKEY_LOGIN).await {
+        Ok(challenge) => challenge,
+        Err(e) => return e.into_response(),
+    };
+
+    let options = state.get_relying_party().request_options(
+        challenge.get_value(),
+        Vec::new(),
+        UserVerification::Required,
+    );
+
+    let response = StartPasskeyAssertionResponse {
+        success: true,
+        data: StartPasskeyAssertionResponseData {
+            challenge_id: challenge.get_id(),
+            options,
+        },
+        meta: StartPasskeyAssertionResponseMeta {
+            time_taken: format!("{:?}", stopwatch.get_original_start().elapsed()),
+            timestamp: Utc::now(),
+        },
+    };
+
+    serialize_to_response(&response)
+}
+
+// POST /api/auth/passkeys/login/finish; answers like /api/auth/login. A user-verified passkey is already two
+// factors, so no TOTP challenge follows
+pub async fn finish_passkey_login(
+    State(state): State<Arc<ServerState>>,
+    headers: HeaderMap,
+    client_ip: Option<ClientIp>,
+    Payload(body): Payload<PasskeyAssertionForm>,
+) -> impl IntoResponse {
+    let stopwatch: Stopwatch = Stopwatch::new("finish_passkey_login");
+
+    let mut conn = get_conn!(&state);
+
+    let assertion = match check_passkey_assertion(
+        &state,
+        &conn,
+        &body,
+        PASSKEY_LOGIN,
+        None,
+        UserVerification::Required,
+    )
+    .await
+    {
+        Ok(Some(assertion)) => assertion,
+        // only the client IP is charged: a credential ID is not secret, so charging its account would let
+        // anyone lock the owner out with junk signatures
+        Ok(None) => {
+            record_login_failure(
+                &state,
+                &mut conn,
+                &[],
+                None,
+                client_ip,
+                get_user_agent(&headers),
+                negotiate_locale(&headers),
+            )
+            .await;
+            return AppError::InvalidCredentials.into_response();
+        }
+        Err(e) => return e.into_response(),
+    };
+
+    let user = match User::get_by_id(&conn, assertion.get_user_id()).await {
+        Ok(Some(user)) => user,
+        Ok(None) => return AppError::InvalidCredentials.into_response(),
+        Err(e) => {
+            return AppError::CouldNotGetUser(e.context("Could not get User by ID"))
+                .into_response();
+        }
+    };
+    Span::current().record("user.id", display(user.get_id()));
+
+    // a locked account stays locked whichever way its owner signs in
+    if let Err(e) =
+        check_login_throttle(&state, &conn, &LoginFailure::account_keys(&user), client_ip).await
+    {
+        return e.into_response();
+    }
+
+    if !user.is_email_verified() {
+        return AppError::UserEmailNotVerified.into_response();
+    }
+
+    if !user.is_active() {
+        return AppError::UserInactive.into_response();
+    }
+
+    let roles = match user.get_roles(&conn).await {
+        Ok(roles) => roles,
+        Err(e) => {
+            return AppError::CouldNotGetUser(e.context("Could not get roles for User"))
+                .into_response();
+        }
+    };
+
+    let transaction = get_transaction!(conn);
+
+    if let Err(e) = assertion.spend(&transaction).await {
+        return e.into_response();
+    }
+
+    issue_session(
+        &state,
+        transaction,
+        user,
+        roles,
+        &headers,
+        client_ip,
+        stopwatch,
+    )
+    .await
+}
+
+// POST /api/auth/passkeys/2fa/start; the answer goes to /api/auth/login/2fa as `passkey`
+pub async fn start_passkey_second_factor(
+    State(state): State<Arc<ServerState>>,
+    Payload(body): Payload<StartPasskeySecondFactorForm>,
+) -> impl IntoResponse {
+    let stopwatch: Stopwatch = Stopwatch::new("start_passkey_second_factor");
+    let mut conn = get_conn!(&state);
+
+    let login_challenge =
+        match UserToken::get_by_value(&conn, LOGIN_2FA_CHALLENGE, body.challenge_token).await {
+            Ok(Some(challenge)) => challenge,
+            Ok(None) => return AppError::UserTokenInvalid.into_response(),
+            Err(e) => {
+                error!("Could not get UserToken by value: {:?}", e);
+                return AppError::UserTokenInvalid.into_response();
+            }
+        };
+
+    if login_challenge.is_used() {
+        return AppError::UserTokenUsed.into_response();
+    }
+
+    if login_challenge.is_expired() {
+        return AppError::UserTokenExpired.into_response();
+    }
+
+    let passkeys = match Passkey::get_by_user_id(&conn, login_challenge.get_user_id()).await {
+        Ok(passkeys) if passkeys.is_empty() => return AppError::PasskeyNotFound.into_response(),
+        Ok(passkeys) => passkeys,
+        Err(e) => {
+            return AppError::Database(e.context("Could not get Passkeys by user ID"))
+                .into_response();
+        }
+    };
+
+    let challenge = match issue_passkey_challenge(
+        &mut conn,
+        Some(login_challenge.get_user_id()),
+        PASSKEY_SECOND_FACTOR,
+    )
+    .await
+    {
+        Ok(challenge) => challenge,
+        Err(e) => return e.into_response(),
+    };
+
+    // the password already identified the user, so a touch is enough
+    let options = state.get_relying_party().request_options(
+        challenge.get_value(),
+        passkeys
+            .iter()
+            .map(|passkey| CredentialDescriptor::new(passkey.get_credential_id()))
+            .collect(),
+        UserVerification::Discouraged,
+    );
+
+    let response = StartPasskeyAssertionResponse {
+        success: true,
+        data: StartPasskeyAssertionResponseData {
+            challenge_id: challenge.get_id(),
+            options,
+        },
+        meta: StartPasskeyAssertionResponseMeta {
+            time_taken: format!("{:?}", stopwatch.get_original_start().elapsed()),
+            timestamp: Utc::now(),
+        },
+    };
+
+    serialize_to_response(&response)
+}
@@ -0,0 +1,377 @@
+use std::sync::Arc;
+
+use axum::{
+    extract::{Path, State},
+    response::IntoResponse,
+};
+use chrono::{DateTime, Utc};
+use deadpool_postgres::Object;
+use serde_derive::{Deserialize, Serialize};
+use tracing::warn;
+use uuid::Uuid;
+
+use crate::{
+    controllers::middleware::auth::AuthUser,
+    get_conn, get_transaction,
+    models::{
+        passkey_challenges::{PasskeyChallenge, PasskeyChallengeForm, PASSKEY_REGISTRATION},
+        passkeys::{Passkey, PasskeyForm, PasskeyTruncated},
+    },
+    utils::{
+        errors::errors::AppError,
+        gadgets::stopwatch::Stopwatch,
+        serde::{payload::Payload, serialize_to_response::serialize_to_response},
+        server_init::server_state_def::ServerState,
+        webauthn::relying_party::{
+            CreationOptions, CredentialDescriptor, RegistrationCredential, RelyingParty,
+            CEREMONY_TIMEOUT_MINUTES,
+        },
+    },
+};
+
+/// longer names are cut short rather than refused
+const PASSKEY_NAME_MAX_CHARS: usize = 64;
+
+// request
+#[derive(Deserialize)]
+pub struct FinishPasskeyRegistrationForm {
+    challenge_id: Uuid,
+    /// a label to tell passkeys apart, e.g. "work laptop"
+    name: Option<String>,
+    credential: RegistrationCredential,
+}
+
+// response
+#[derive(Serialize)]
+pub struct StartPasskeyRegistrationResponse {
+    success: bool,
+    data: StartPasskeyRegistrationResponseData,
+    meta: PasskeysResponseMeta,
+}
+
+#[derive(Serialize)]
+pub struct StartPasskeyRegistrationResponseData {
+    challenge_id: Uuid,
+    /// for navigator.credentials.create(), after PublicKeyCredential.parseCreationOptionsFromJSON()
+    options: CreationOptions,
+}
+
+#[derive(Serialize)]
+pub struct FinishPasskeyRegistrationResponse {
+    success: bool,
+    data: FinishPasskeyRegistrationResponseData,
+    meta: PasskeysResponseMeta,
+}
+
+#[derive(Serialize)]
+pub struct FinishPasskeyRegistrationResponseData {
+    message: String,
+    passkey: PasskeyTruncated,
+}
+
+#[derive(Serialize)]
+pub struct ListPasskeysResponse {
+    success: bool,
+    data: ListPasskeysResponseData,
+    meta: PasskeysResponseMeta,
+}
+
+#[derive(Serialize)]
+pub struct ListPasskeysResponseData {
+    passkeys: Vec<PasskeyTruncated>,
+}
+
+#[derive(Serialize)]
+pub struct DeletePasskeyResponse {
+    success: bool,
+    data: DeletePasskeyResponseData,
+    meta: PasskeysResponseMeta,
+}
+
+#[derive(Serialize)]
+pub struct DeletePasskeyResponseData {
+    message: String,
+    deleted_passkey_id: Uuid,
+}
+
+#[derive(Serialize)]
+pub struct PasskeysResponseMeta {
+    time_taken: String,
+    timestamp: DateTime<Utc>,
+}
+
+/// stores a fresh challenge for one ceremony, clearing out ones whose ceremonies have timed out
+pub async fn issue_passkey_challenge(
+    conn: &mut Object,
+    user_id: Option<Uuid>,
+    purpose: &str,
+) -> Result<PasskeyChallenge, AppError> {
+    let value =
+        RelyingParty::generate_challenge().map_err(AppError::CouldNotStartPasskeyCeremony)?;
+
+    let transaction = conn
+        .transaction()
+        .await
+        .map_err(|e| AppError::CouldNotBuildTransactionFromConn(e.into()))?;
+
+    PasskeyChallenge::delete_expired(&transaction)
+        .await
+        .map_err(|e| AppError::Database(e.context("Could not delete expired PasskeyChallenges")))?;
+
+    let challenge = PasskeyChallengeForm {
+        passkey_challenge_user_id: user_id,
+        passkey_challenge_purpose: purpose.to_owned(),
+        passkey_challenge_value: value,
+        passkey_challenge_expires_at: Utc::now()
+            + chrono::Duration::minutes(CEREMONY_TIMEOUT_MINUTES),
+    }
+    .insert(&transaction)
+    .await
+    .map_err(|e| AppError::Database(e.context("Could not insert PasskeyChallenge")))?;
+
+    transaction
+        .commit()
+        .await
+        .map_err(|e| AppError::CouldNotCommitTransaction(e.into()))?;
+
+    Ok(challenge)
+}
+
+/// the challenge, if it was issued for this purpose (and user, where one was named) and is still unanswered
+pub async fn get_redeemable_challenge(
+    conn: &Object,
+    challenge_id: Uuid,
+    purpose: &str,
+    user_id: Option<Uuid>,
+) -> Result<PasskeyChallenge, AppError> {
+    let challenge = match PasskeyChallenge::get_by_id(conn, challenge_id).await {
+        Ok(Some(challenge)) => challenge,
+        Ok(None) => return Err(AppError::PasskeyChallengeInvalid),
+        Err(e) => {
+            return Err(AppError::Database(
+                e.context("Could not get PasskeyChallenge by ID"),
+            ))
+        }
+    };
+
+    if challenge.get_purpose() != purpose
+        || challenge.get_user_id() != user_id
+        || challenge.is_used()
+        || challenge.is_expired()
+    {
+        return Err(AppError::PasskeyChallengeInvalid);
+    }
+
+    Ok(challenge)
+}
+
+// POST /api/auth/passkeys/register/start
+pub async fn start_passkey_registration(
+    State(state): State<Arc<ServerState>>,
+    auth_user: AuthUser,
+) -> impl IntoResponse {
+    let stopwatch: Stopwatch = Stopwatch::new("start_passkey_registration");
+    let user = auth_user.user;
+
+    let mut conn = get_conn!(&state);
+
+    let passkeys = match Passkey::get_by_user_id(&conn, user.get_id()).await {
+        Ok(passkeys) => passkeys,
+        Err(e) => {
+            return AppError::Database(e.context("Could not get Passkeys by user ID"))
+                .into_response();
+        }
+    };
+
+    let challenge =
+        match issue_passkey_challenge(&mut conn, Some(user.get_id()), PASSKEY_REGISTRATION).await {
+            Ok(challenge) => challenge,
+            Err(e) => return e.into_response(),
+        };
+
+    let options = state.get_relying_party().creation_options(
+        challenge.get_value(),
+        user.get_id(),
+        user.get_email(),
+        user.get_screen_name(),
+        passkeys
+            .iter()
+            .map(|passkey| CredentialDescriptor::new(passkey.get_credential_id()))
+            .collect(),
+    );
+
+    let response = StartPasskeyRegistrationResponse {
+        success: true,
+        data: StartPasskeyRegistrationResponseData {
+            challenge_id: challenge.get_id(),
+            options,
+        },
+        meta: PasskeysResponseMeta {
+            time_taken: format!("{:?}", stopwatch.get_original_start().elapsed()),
+            timestamp: Utc::now(),
+        },
+    };
+
+    serialize_to_response(&response)
+}
+
+// POST /api/auth/passkeys/register/finish
+pub async fn finish_passkey_registration(
+    State(state): State<Arc<ServerState>>,
+    auth_user: AuthUser,
+    Payload(body): Payload<FinishPasskeyRegistrationForm>,
+) -> impl IntoResponse {
+    let stopwatch: Stopwatch = Stopwatch::new("finish_passkey_registration");
+    let user = auth_user.user;
+
+    let mut conn = get_conn!(&state);
+
+    let challenge = match get_redeemable_challenge(
+        &conn,
+        body.challenge_id,
+        PASSKEY_REGISTRATION,
+        Some(user.get_id()),
+    )
+    .await
+    {
+        Ok(challenge) => challenge,
+        Err(e) => return e.into_response(),
+    };
+
+    let registered = match state
+        .get_relying_party()
+        .verify_registration(challenge.get_value(), &body.credential)
+    {
+        Ok(registered) => registered,
+        Err(e) => {
+            warn!("Passkey registration rejected: {:#}", e);
+            return AppError::PasskeyRejected.into_response();
+        }
+    };
+
+    let name = match body.name.as_deref().map(str::trim) {
+        Some(name) if !name.is_empty() => name.chars().take(PASSKEY_NAME_MAX_CHARS).collect(),
+        _ => format!("Passkey added {}", Utc::now().format("%Y-%m-%d")),
+    };
+
+    let transaction = get_transaction!(conn);
+
+    match challenge.mark_used(&transaction).await {
+        Ok(true) => (),
+        Ok(false) => return AppError::PasskeyChallengeInvalid.into_response(),
+        Err(e) => {
+            return AppError::Database(e.context("Could not mark PasskeyChallenge as used"))
+                .into_response();
+        }
+    }
+
+    let passkey_form = PasskeyForm {
+        passkey_user_id: user.get_id(),
+        passkey_credential_id: registered.credential_id,
+        passkey_public_key: registered.public_key,
+        passkey_algorithm: registered.algorithm as i32,
+        passkey_sign_count: i64::from(registered.sign_count),
+        passkey_backup_eligible: registered.backup_eligible,
+        passkey_name: name,
+    };
+    let passkey = match passkey_form.insert(&transaction).await {
+        Ok(Some(passkey)) => passkey,
+        Ok(None) => return AppError::PasskeyAlreadyRegistered.into_response(),
+        Err(e) => {
+            return AppError::Database(e.context("Could not insert Passkey")).into_response();
+        }
+    };
+
+    match transaction.commit().await {
+        Ok(_) => (),
+        Err(e) => {
+            return AppError::CouldNotCommitTransaction(e.into()).into_response();
+        }
+    }
+
+    let response = FinishPasskeyRegistrationResponse {
+        success: true,
+        data: FinishPasskeyRegistrationResponseData {
+            message: "Passkey registered.".to_string(),
+            passkey: PasskeyTruncated::from(passkey),
+        },
+        meta: PasskeysResponseMeta {
+            time_taken: format!("{:?}", stopwatch.get_original_start().elapsed()),
+            timestamp: Utc::now(),
+        },
+    };
+
+    serialize_to_response(&response)
+}
+
+// GET /api/auth/passkeys
+pub async fn list_passkeys(
+    State(state): State<Arc<ServerState>>,
+    auth_user: AuthUser,
+) -> impl IntoResponse {
+    let stopwatch: Stopwatch = Stopwatch::new("list_passkeys");
+    let conn = get_conn!(&state);
+
+    let passkeys = match Passkey::get_by_user_id(&conn, auth_user.user.get_id()).await {
+        Ok(passkeys) => passkeys,
+        Err(e) => {
+            return AppError::Database(e.context("Could not get Passkeys by user ID"))
+                .into_response();
+        }
+    };
+
+    let response = ListPasskeysResponse {
+        success: true,
+        data: ListPasskeysResponseData {
+            passkeys: passkeys.into_iter().map(PasskeyTruncated::from).collect(),
+        },
+        meta: PasskeysResponseMeta {
+            time_taken: format!("{:?}", stopwatch.get_original_start().elapsed()),
+            timestamp: Utc::now(),
+        },
+    };
+
+    serialize_to_response(&response)
+}
+
+// DELETE /api/auth/passkeys/:passkey_id
+pub async fn delete_passkey(
+    State(state): State<Arc<ServerState>>,
+    auth_user: AuthUser,
+    Path(passkey_id): Path<Uuid>,
+) -> impl IntoResponse {
+    let stopwatch: Stopwatch = Stopwatch::new("delete_passkey");
+    let mut conn = get_conn!(&state);
+
+    let transaction = get_transaction!(conn);
+
+    // someone else's passkey is reported as missing rather than forbidden
+    match Passkey::delete_for_user(&transaction, auth_user.user.get_id(), passkey_id).await {
+        Ok(true) => (),
+        Ok(false) => return AppError::PasskeyNotFound.into_response(),
+        Err(e) => {
+            return AppError::Database(e.context("Could not delete Passkey")).into_response();
+        }
+    }
+
+    match transaction.commit().await {
+        Ok(_) => (),
+        Err(e) => {
+            return AppError::CouldNotCommitTransaction(e.into()).into_response();
+        }
+    }
+
+    let response = DeletePasskeyResponse {
+        success: true,
+        data: DeletePasskeyResponseData {
+            message: "Passkey deleted.".to_string(),
+            deleted_passkey_id: passkey_id,
+        },
+        meta: PasskeysResponseMeta {
+            time_taken: format!("{:?}", stopwatch.get_original_start().elapsed()),
+            timestamp: Utc::now(),
+        },
+    };
+
+    serialize_to_response(&response)
+}
@@ -0,0 +1,274 @@
+use std::sync::Arc;
+
+use axum::{extract::State, http::HeaderMap, response::IntoResponse};
+use chrono::{DateTime, Utc};
+use serde_derive::{Deserialize, Serialize};
+use tracing::error;
+use uuid::Uuid;
+
+use crate::{
+    get_conn, get_transaction,
+    models::{
+        email_outbox::EmailOutboxForm,
+        user_sessions::UserSession,
+        user_tokens::{UserToken, UserTokenForm, PASSWORD_RESET, PASSWORD_RESET_DURATION_MINUTES},
+        users::{User, UserUpdateForm},
+    },
+    utils::{
+        errors::errors::AppError,
+        gadgets::{argon::try_hash_password, regex::pw_regex_custom, stopwatch::Stopwatch},
+        mail::templates::{negotiate_locale, password_reset_link, render, EmailTemplate},
+        serde::{payload::Payload, serialize_to_response::serialize_to_response},
+        server_init::server_state_def::ServerState,
+    },
+};
+
+// request
+#[derive(Deserialize)]
+pub struct ForgotPasswordForm {
+    user_email: String,
+}
+
+#[derive(Deserialize)]
+pub struct ResetPasswordForm {
+    token_id: Uuid,
+    new_password: String,
+}
+
+// response
+#[derive(Serialize)]
+pub struct PasswordResetResponse {
+    success: bool,
+    data: PasswordResetResponseData,
+    meta: PasswordResetResponseMeta,
+}
+
+#[derive(Serialize)]
+pub struct PasswordResetResponseData {
+    message: String,
+}
+
+#[derive(Serialize)]
+pub struct PasswordResetResponseMeta {
+    time_taken: String,
+    timestamp: DateTime<Utc>,
+}
+
+/// issues a reset token and mails it; every failure is only logged so the caller can't tell whether the account exists
+async fn issue_password_reset(state: &Arc<ServerState>, locale: &str, user_email: &str) {
+    let mut conn = match state.get_conn().await {
+        Ok(conn) => conn,
+        Err(e) => {
+            error!("Could not get connection from pool: {:?}", e);
+            return;
+        }
+    };
+
+    let user = match User::get_by_email(&conn, user_email).await {
+        Ok(Some(user)) if user.is_active() => user,
+        Ok(_) => return,
+        Err(e) => {
+            error!("Could not get User by email: {:?}", e);
+            return;
+        }
+    };
+
+    let transaction = match conn.transaction().await {
+        Ok(transaction) => transaction,
+        Err(e) => {
+            error!("Could not build transaction from connection: {:?}", e);
+            return;
+        }
+    };
+
+    let user_token_form = UserTokenForm {
+        user_token_user_id: user.get_id(),
+        user_token_type: PASSWORD_RESET.to_owned(),
+        user_token_value: Uuid::new_v4(),
+        user_token_expires_at: Utc::now()
+            + chrono::Duration::minutes(PASSWORD_RESET_DURATION_MINUTES),
+        user_token_session_id: None,
+    };
+
+    let returned_token = match user_token_form.insert(&transaction).await {
+        Ok(token) => token,
+        Err(e) => {
+            error!("Could not insert password reset UserToken: {:?}", e);
+            return;
+        }
+    };
+
+    let rendered = match render(
+        EmailTemplate::PasswordReset,
+        locale,
+        &[
+            (
+                "link",
+                password_reset_link(state.get_public_base_url(), returned_token.get_id()),
+            ),
+            (
+                "expires_minutes",
+                PASSWORD_RESET_DURATION_MINUTES.to_string(),
+            ),
+        ],
+    ) {
+        Ok(rendered) => rendered,
+        Err(e) => {
+            error!("Could not render password reset email: {:?}", e);
+            return;
+        }
+    };
+
+    let email_outbox_form = EmailOutboxForm::from_rendered(user.get_email().to_owned(), rendered);
+
+    if let Err(e) = email_outbox_form.insert(&transaction).await {
+        error!("Could not enqueue password reset email: {:?}", e);
+        return;
+    }
+
+    if let Err(e) = transaction.commit().await {
+        error!("Could not commit transaction: {:?}", e);
+    }
+}
+
+// POST /api/auth/forgot-password
+pub async fn forgot_password(
+    State(state): State<Arc<ServerState>>,
+    headers: HeaderMap,
+    Payload(body): Payload<ForgotPasswordForm>,
+) -> impl IntoResponse {
+    let stopwatch: Stopwatch = Stopwatch::new("forgot_password");
+
+    if !state.email_regex().is_match(&body.user_email) {
+        return AppError::WrongEmailFormat.into_response();
+    }
+
+    if let Err(e) = state
+        .get_rate_limiter()
+        .check_account(&body.user_email)
+        .await
+    {
+        return e.into_response();
+    }
+
+    issue_password_reset(&state, negotiate_locale(&headers), &body.user_email).await;
+
+    let response = PasswordResetResponse {
+        success: true,
+        data: PasswordResetResponseData {
+            message: "If an account exists for this email, a password reset link has been sent."
+                .to_string(),
+        },
+        meta: PasswordResetResponseMeta {
+            time_taken: format!("{:?}", stopwatch.get_original_start().elapsed()),
+            timestamp: Utc::now(),
+        },
+    };
+
+    serialize_to_response(&response)
+}
+
+// POST /api/auth/reset-password
+pub async fn reset_password(
+    State(state): State<Arc<ServerState>>,
+    Payload(body): Payload<ResetPasswordForm>,
+) -> impl IntoResponse {
+    let stopwatch: Stopwatch = Stopwatch::new("reset_password");
+
+    // check if password is valid form
+    if !pw_regex_custom(&body.new_password) {
+        return AppError::WrongPwFormat.into_response();
+    }
+
+    let mut conn = get_conn!(&state);
+
+    let token = match UserToken::get_by_id(&conn, body.token_id).await {
+        Ok(Some(tok)) if tok.get_type() == PASSWORD_RESET => tok,
+        Ok(_) => return AppError::UserTokenInvalid.into_response(),
+        Err(e) => {
+            error!("Could not get UserToken by ID: {:?}", e);
+            return AppError::UserTokenInvalid.into_response();
+        }
+    };
+
+    if token.is_used() {
+        return AppError::UserTokenUsed.into_response();
+    }
+
+    if token.is_expired() {
+        return AppError::UserTokenExpired.into_response();
+    }
+
+    // argon2 is slow on purpose, so it runs on the blocking pool, and before the transaction so no locks are
+    // held while it does
+    let password_hash =
+        match tokio::task::spawn_blocking(move || try_hash_password(&body.new_password)).await {
+            Ok(Ok(password_hash)) => password_hash,
+            Ok(Err(e)) => return AppError::CouldNotHashPassword(e).into_response(),
+            Err(e) => return AppError::CouldNotHashPassword(e.into()).into_response(),
+        };
+
+    let transaction = get_transaction!(conn);
+
+    match token.mark_used(&transaction).await {
+        Ok(true) => (),
+        Ok(false) => return AppError::UserTokenUsed.into_response(),
+        Err(e) => {
+            return AppError::CouldNotUpdateUserToken(
+                e.context("Could not mark password reset UserToken as used"),
+            )
+            .into_response();
+        }
+    }
+
+    let user_update_form = UserUpdateForm {
+        user_screen_name: None,
+        user_email: None,
+        user_password_hash: Some(password_hash),
+        user_is_active: None,
+    };
+
+    match user_update_form
+        .update_db(&transaction, token.get_user_id())
+        .await
+    {
+        Ok(Some(_)) => (),
+        Ok(None) => return AppError::UserTokenInvalid.into_response(),
+        Err(e) => {
+            return AppError::CouldNotUpdateUser(e.context("Could not update User password"))
+                .into_response();
+        }
+    }
+
+    // whoever held the old password loses every session along with it
+    if let Err(e) = UserSession::revoke_all_for_user(&transaction, token.get_user_id()).await {
+        return AppError::CouldNotRevokeUserSession(e.context("Could not revoke UserSessions"))
+            .into_response();
+    }
+
+    if let Err(e) = UserToken::revoke_all_refresh_for_user(&transaction, token.get_user_id()).await
+    {
+        return AppError::CouldNotRevokeUserSession(e.context("Could not revoke refresh tokens"))
+            .into_response();
+    }
+
+    match transaction.commit().await {
+        Ok(_) => (),
+        Err(e) => {
+            return AppError::CouldNotCommitTransaction(e.into()).into_response();
+        }
+    }
+
+    let response = PasswordResetResponse {
+        success: true,
+        data: PasswordResetResponseData {
+            message: "Password has been reset. Please log in again.".to_string(),
+        },
+        meta: PasswordResetResponseMeta {
+            time_taken: format!("{:?}", stopwatch.get_original_start().elapsed()),
+            timestamp: Utc::now(),
+        },
+    };
+
+    serialize_to_response(&response)
+}
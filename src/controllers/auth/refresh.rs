@@ -0,0 +1,219 @@
+use std::sync::Arc;
+
+use axum::{extract::State, response::IntoResponse};
+use chrono::{DateTime, Utc};
+use serde_derive::{Deserialize, Serialize};
+use tracing::{error, warn};
+use uuid::Uuid;
+
+use crate::{
+    get_conn, get_transaction,
+    models::{
+        jwt::Claims,
+        user_sessions::UserSession,
+        user_tokens::{UserToken, UserTokenForm, USER_REFRESH_TOKEN},
+        users::User,
+    },
+    utils::{
+        errors::errors::AppError,
+        gadgets::stopwatch::Stopwatch,
+        serde::{payload::Payload, serialize_to_response::serialize_to_response},
+        server_init::server_state_def::ServerState,
+    },
+};
+
+// request
+#[derive(Deserialize)]
+pub struct RefreshForm {
+    refresh_token: Uuid,
+}
+
+// response
+#[derive(Serialize)]
+pub struct RefreshResponse {
+    success: bool,
+    data: RefreshResponseData,
+    meta: RefreshResponseMeta,
+}
+
+#[derive(Serialize)]
+pub struct RefreshResponseData {
+    access_token: String,
+    access_token_expires_at: DateTime<Utc>,
+    refresh_token: Uuid,
+    refresh_token_expires_at: DateTime<Utc>,
+}
+
+#[derive(Serialize)]
+pub struct RefreshResponseMeta {
+    time_taken: String,
+    timestamp: DateTime<Utc>,
+}
+
+// POST /api/auth/refresh
+pub async fn refresh(
+    State(state): State<Arc<ServerState>>,
+    Payload(body): Payload<RefreshForm>,
+) -> impl IntoResponse {
+    let stopwatch: Stopwatch = Stopwatch::new("refresh");
+    let mut conn = get_conn!(&state);
+
+    let token = match UserToken::get_by_value(&conn, USER_REFRESH_TOKEN, body.refresh_token).await {
+        Ok(Some(tok)) => tok,
+        Ok(None) => return AppError::UserTokenInvalid.into_response(),
+        Err(e) => {
+            error!("Could not get refresh UserToken by value: {:?}", e);
+            return AppError::UserTokenInvalid.into_response();
+        }
+    };
+
+    let session_id = match token.get_session_id() {
+        Some(session_id) => session_id,
+        None => return AppError::UserTokenInvalid.into_response(),
+    };
+
+    let session = match UserSession::get_by_id(&conn, session_id).await {
+        Ok(Some(session)) => session,
+        Ok(None) => return AppError::UserSessionInvalid.into_response(),
+        Err(e) => {
+            error!("Could not get UserSession by ID: {:?}", e);
+            return AppError::UserSessionInvalid.into_response();
+        }
+    };
+
+    let user = match User::get_by_id(&conn, token.get_user_id()).await {
+        Ok(Some(user)) => user,
+        Ok(None) => return AppError::UserSessionInvalid.into_response(),
+        Err(e) => {
+            return AppError::CouldNotGetUser(e.context("Could not get User by ID"))
+                .into_response();
+        }
+    };
+
+    let roles = match user.get_roles(&conn).await {
+        Ok(roles) => roles,
+        Err(e) => {
+            return AppError::CouldNotGetUser(e.context("Could not get roles for User"))
+                .into_response();
+        }
+    };
+
+    // after a logout or an earlier reuse the family is already dead; a straggling token is no new alarm
+    if token.is_used() && !session.is_valid() {
+        return AppError::UserSessionInvalid.into_response();
+    }
+
+    let transaction = get_transaction!(conn);
+
+    // a used token coming back means it was copied, and whoever copied it may hold others; every session and
+    // refresh token the user has is revoked so no copy keeps working
+    let redeemed = if token.is_used() {
+        false
+    } else {
+        match token.mark_used(&transaction).await {
+            Ok(redeemed) => redeemed,
+            Err(e) => {
+                return AppError::CouldNotUpdateUserToken(
+                    e.context("Could not mark refresh UserToken as used"),
+                )
+                .into_response();
+            }
+        }
+    };
+
+    if !redeemed {
+        warn!(
+            "Refresh token reuse detected for user {} on session {}; revoking all of their sessions",
+            token.get_user_id(),
+            session_id
+        );
+
+        if let Err(e) = UserSession::revoke_all_for_user(&transaction, token.get_user_id()).await {
+            return AppError::CouldNotRevokeUserSession(
+                e.context("Could not revoke UserSessions for user"),
+            )
+            .into_response();
+        }
+
+        if let Err(e) =
+            UserToken::revoke_all_refresh_for_user(&transaction, token.get_user_id()).await
+        {
+            return AppError::CouldNotRevokeUserSession(
+                e.context("Could not revoke refresh tokens for user"),
+            )
+            .into_response();
+        }
+
+        if let Err(e) = transaction.commit().await {
+            return AppError::CouldNotCommitTransaction(e.into()).into_response();
+        }
+
+        return AppError::RefreshTokenReused.into_response();
+    }
+
+    if token.is_expired() {
+        return AppError::UserTokenExpired.into_response();
+    }
+
+    if !session.is_valid() {
+        return AppError::UserSessionInvalid.into_response();
+    }
+
+    if !user.is_active() {
+        return AppError::UserInactive.into_response();
+    }
+
+    let new_refresh_token = match UserTokenForm::new_refresh_token(&session)
+        .insert(&transaction)
+        .await
+    {
+        Ok(token) => token,
+        Err(e) => {
+            return AppError::CouldNotInsertUserToken(
+                e.context("Could not insert refresh UserToken"),
+            )
+            .into_response();
+        }
+    };
+
+    let claims = Claims::new(
+        user.get_id(),
+        user.get_screen_name().to_owned(),
+        roles,
+        session.get_id(),
+    );
+
+    let access_token = match state.get_jwt().sign(&claims) {
+        Ok(token) => token,
+        Err(e) => {
+            return AppError::CouldNotSignJwt(e.context("Could not sign JWT")).into_response();
+        }
+    };
+
+    match transaction.commit().await {
+        Ok(_) => (),
+        Err(e) => {
+            return AppError::CouldNotCommitTransaction(e.into()).into_response();
+        }
+    }
+
+    if let Err(e) = session.touch(&conn).await {
+        error!("Could not update UserSession last used time: {:?}", e);
+    }
+
+    let response = RefreshResponse {
+        success: true,
+        data: RefreshResponseData {
+            access_token,
+            access_token_expires_at: claims.get_expired_time(),
+            refresh_token: new_refresh_token.get_value(),
+            refresh_token_expires_at: new_refresh_token.get_expired_time(),
+        },
+        meta: RefreshResponseMeta {
+            time_taken: format!("{:?}", stopwatch.get_original_start().elapsed()),
+            timestamp: Utc::now(),
+        },
+    };
+
+    serialize_to_response(&response)
+}
@@ -0,0 +1,191 @@
+use std::sync::Arc;
+
+use axum::{extract::State, http::HeaderMap, response::IntoResponse};
+use chrono::{DateTime, Utc};
+use serde_derive::{Deserialize, Serialize};
+use tracing::info;
+use uuid::Uuid;
+
+use crate::{
+    controllers::auth::signup::enqueue_verification_email,
+    get_conn, get_transaction,
+    models::{
+        user_tokens::{
+            UserToken, UserTokenForm, SIGNUP_EMAIL_VALIDATE, VERIFICATION_DAILY_CAP,
+            VERIFICATION_DURATION_HOURS, VERIFICATION_RESEND_COOLDOWN_SECONDS,
+        },
+        users::User,
+    },
+    utils::{
+        errors::errors::AppError,
+        gadgets::stopwatch::Stopwatch,
+        mail::templates::negotiate_locale,
+        serde::{payload::Payload, serialize_to_response::serialize_to_response},
+        server_init::server_state_def::ServerState,
+    },
+};
+
+// request
+#[derive(Deserialize)]
+pub struct ResendVerificationForm {
+    user_email: String,
+}
+
+// response
+#[derive(Serialize)]
+pub struct ResendVerificationResponse {
+    success: bool,
+    data: ResendVerificationResponseData,
+    meta: ResendVerificationResponseMeta,
+}
+
+#[derive(Serialize)]
+pub struct ResendVerificationResponseData {
+    message: String,
+}
+
+#[derive(Serialize)]
+pub struct ResendVerificationResponseMeta {
+    time_taken: String,
+    timestamp: DateTime<Utc>,
+}
+
+fn generic_success(stopwatch: &Stopwatch) -> axum::response::Response {
+    let response = ResendVerificationResponse {
+        success: true,
+        data: ResendVerificationResponseData {
+            message: "If an unverified account exists for this email, a new verification link has been sent."
+                .to_string(),
+        },
+        meta: ResendVerificationResponseMeta {
+            time_taken: format!("{:?}", stopwatch.get_original_start().elapsed()),
+            timestamp: Utc::now(),
+        },
+    };
+
+    serialize_to_response(&response)
+}
+
+// POST /api/auth/resend-verification
+pub async fn resend_verification(
+    State(state): State<Arc<ServerState>>,
+    headers: HeaderMap,
+    Payload(body): Payload<ResendVerificationForm>,
+) -> impl IntoResponse {
+    let stopwatch: Stopwatch = Stopwatch::new("resend_verification");
+
+    if !state.email_regex().is_match(&body.user_email) {
+        return AppError::WrongEmailFormat.into_response();
+    }
+
+    if let Err(e) = state
+        .get_rate_limiter()
+        .check_account(&body.user_email)
+        .await
+    {
+        return e.into_response();
+    }
+
+    let mut conn = get_conn!(&state);
+
+    // unknown and already verified accounts look the same as a successful resend
+    let user = match User::get_by_email(&conn, &body.user_email).await {
+        Ok(Some(user)) if !user.is_email_verified() && user.is_active() => user,
+        Ok(_) => return generic_success(&stopwatch),
+        Err(e) => {
+            return AppError::CouldNotGetUser(e.context("Could not get User by email"))
+                .into_response();
+        }
+    };
+
+    let transaction = get_transaction!(conn);
+
+    // serialize concurrent resends for the same user so the limits below hold
+    match User::lock_by_id(&transaction, user.get_id()).await {
+        Ok(true) => (),
+        Ok(false) => return generic_success(&stopwatch),
+        Err(e) => {
+            return AppError::CouldNotGetUser(e.context("Could not lock User row")).into_response();
+        }
+    }
+
+    let now = Utc::now();
+
+    let (issued_today, last_issued_at) = match UserToken::get_issuance_since(
+        &transaction,
+        user.get_id(),
+        SIGNUP_EMAIL_VALIDATE,
+        now - chrono::Duration::days(1),
+    )
+    .await
+    {
+        Ok(stats) => stats,
+        Err(e) => {
+            return AppError::Database(e.context("Could not count verification UserTokens"))
+                .into_response();
+        }
+    };
+
+    // only unverified accounts get this far, so hitting a limit has to look like a send as well; callers
+    // are throttled visibly by the per-IP and per-account rate limits instead
+    if issued_today >= VERIFICATION_DAILY_CAP {
+        info!(user.id = %user.get_id(), "Verification resend skipped: daily cap reached");
+        return generic_success(&stopwatch);
+    }
+
+    if last_issued_at.is_some_and(|last_issued_at| {
+        last_issued_at + chrono::Duration::seconds(VERIFICATION_RESEND_COOLDOWN_SECONDS) > now
+    }) {
+        info!(user.id = %user.get_id(), "Verification resend skipped: still cooling down");
+        return generic_success(&stopwatch);
+    }
+
+    if let Err(e) =
+        UserToken::invalidate_outstanding(&transaction, user.get_id(), SIGNUP_EMAIL_VALIDATE).await
+    {
+        return AppError::CouldNotUpdateUserToken(
+            e.context("Could not invalidate outstanding verification UserTokens"),
+        )
+        .into_response();
+    }
+
+    let user_token_form = UserTokenForm {
+        user_token_user_id: user.get_id(),
+        user_token_type: SIGNUP_EMAIL_VALIDATE.to_owned(),
+        user_token_value: Uuid::new_v4(),
+        user_token_expires_at: now + chrono::Duration::hours(VERIFICATION_DURATION_HOURS),
+        user_token_session_id: None,
+    };
+
+    let returned_token = match user_token_form.insert(&transaction).await {
+        Ok(token) => token,
+        Err(e) => {
+            return AppError::CouldNotInsertUserToken(
+                e.context("Could not insert verification UserToken"),
+            )
+            .into_response();
+        }
+    };
+
+    if let Err(e) = enqueue_verification_email(
+        &transaction,
+        &state,
+        negotiate_locale(&headers),
+        user.get_email().to_owned(),
+        returned_token.get_id(),
+    )
+    .await
+    {
+        return AppError::CouldNotEnqueueEmail(e.context("Could not enqueue verification email"))
+            .into_response();
+    }
+
+    match transaction.commit().await {
+        Ok(_) => (),
+        Err(e) => {
+            return AppError::CouldNotCommitTransaction(e.into()).into_response();
+        }
+    }
+
+    generic_success(&stopwatch)
+}
@@ -0,0 +1,147 @@
+use std::sync::Arc;
+
+use axum::{
+    extract::{Path, State},
+    response::IntoResponse,
+};
+use chrono::{DateTime, Utc};
+use serde_derive::Serialize;
+use uuid::Uuid;
+
+use crate::{
+    controllers::middleware::auth::AuthUser,
+    get_conn, get_transaction,
+    models::{
+        user_sessions::{UserSession, UserSessionTruncated},
+        user_tokens::UserToken,
+    },
+    utils::{
+        errors::errors::AppError, gadgets::stopwatch::Stopwatch,
+        serde::serialize_to_response::serialize_to_response,
+        server_init::server_state_def::ServerState,
+    },
+};
+
+// response
+#[derive(Serialize)]
+pub struct ListSessionsResponse {
+    success: bool,
+    data: ListSessionsResponseData,
+    meta: SessionsResponseMeta,
+}
+
+#[derive(Serialize)]
+pub struct ListSessionsResponseData {
+    current_session_id: Uuid,
+    sessions: Vec<UserSessionTruncated>,
+}
+
+#[derive(Serialize)]
+pub struct RevokeSessionResponse {
+    success: bool,
+    data: RevokeSessionResponseData,
+    meta: SessionsResponseMeta,
+}
+
+#[derive(Serialize)]
+pub struct RevokeSessionResponseData {
+    message: String,
+    revoked_session_id: Uuid,
+}
+
+#[derive(Serialize)]
+pub struct SessionsResponseMeta {
+    time_taken: String,
+    timestamp: DateTime<Utc>,
+}
+
+// GET /api/auth/sessions
+pub async fn list_sessions(
+    State(state): State<Arc<ServerState>>,
+    auth_user: AuthUser,
+) -> impl IntoResponse {
+    let stopwatch: Stopwatch = Stopwatch::new("list_sessions");
+    let conn = get_conn!(&state);
+
+    let sessions = match UserSession::get_active_by_user_id(&conn, auth_user.user.get_id()).await {
+        Ok(sessions) => sessions,
+        Err(e) => {
+            return AppError::CouldNotGetUserSessions(
+                e.context("Could not get UserSessions by user ID"),
+            )
+            .into_response();
+        }
+    };
+
+    let response = ListSessionsResponse {
+        success: true,
+        data: ListSessionsResponseData {
+            current_session_id: auth_user.session_id,
+            sessions: sessions
+                .into_iter()
+                .map(UserSessionTruncated::from)
+                .collect(),
+        },
+        meta: SessionsResponseMeta {
+            time_taken: format!("{:?}", stopwatch.get_original_start().elapsed()),
+            timestamp: Utc::now(),
+        },
+    };
+
+    serialize_to_response(&response)
+}
+
+// DELETE /api/auth/sessions/:session_id
+pub async fn revoke_session(
+    State(state): State<Arc<ServerState>>,
+    auth_user: AuthUser,
+    Path(session_id): Path<Uuid>,
+) -> impl IntoResponse {
+    let stopwatch: Stopwatch = Stopwatch::new("revoke_session");
+    let mut conn = get_conn!(&state);
+
+    // someone else's session is reported as missing rather than forbidden
+    match UserSession::get_by_id(&conn, session_id).await {
+        Ok(Some(session)) if session.get_user_id() == auth_user.user.get_id() => (),
+        Ok(_) => return AppError::UserSessionNotFound.into_response(),
+        Err(e) => {
+            return AppError::CouldNotGetUserSessions(e.context("Could not get UserSession by ID"))
+                .into_response();
+        }
+    };
+
+    let transaction = get_transaction!(conn);
+
+    if let Err(e) = UserSession::revoke(&transaction, session_id).await {
+        return AppError::CouldNotRevokeUserSession(e.context("Could not revoke UserSession"))
+            .into_response();
+    }
+
+    if let Err(e) = UserToken::revoke_session_family(&transaction, session_id).await {
+        return AppError::CouldNotRevokeUserSession(
+            e.context("Could not revoke refresh token family"),
+        )
+        .into_response();
+    }
+
+    match transaction.commit().await {
+        Ok(_) => (),
+        Err(e) => {
+            return AppError::CouldNotCommitTransaction(e.into()).into_response();
+        }
+    }
+
+    let response = RevokeSessionResponse {
+        success: true,
+        data: RevokeSessionResponseData {
+            message: "Session revoked.".to_string(),
+            revoked_session_id: session_id,
+        },
+        meta: SessionsResponseMeta {
+            time_taken: format!("{:?}", stopwatch.get_original_start().elapsed()),
+            timestamp: Utc::now(),
+        },
+    };
+
+    serialize_to_response(&response)
+}
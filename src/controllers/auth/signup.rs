@@ -1,25 +1,26 @@
 use std::sync::Arc;
 
-use anyhow::anyhow;
-use axum::{extract::State, response::IntoResponse, Json};
+use axum::{extract::State, http::HeaderMap, response::IntoResponse};
 use chrono::{DateTime, Utc};
-use lettre::{message::Mailbox, AsyncTransport, Message};
+use deadpool_postgres::Transaction;
 use serde_derive::Serialize;
 use tokio_postgres::error::SqlState;
-use tracing::error;
 use uuid::Uuid;
 
 use crate::{
     get_conn, get_transaction,
     models::{
-        consts::SMTP_EMAIL,
-        user_tokens::{UserToken, UserTokenForm, SIGNUP_EMAIL_VALIDATE},
+        email_outbox::{EmailOutbox, EmailOutboxForm},
+        user_tokens::{
+            UserToken, UserTokenForm, SIGNUP_EMAIL_VALIDATE, VERIFICATION_DURATION_HOURS,
+        },
         users::{UserForm, UserTruncated},
     },
     utils::{
-        errors::errors::{ErrResp, ErrRespDat},
+        errors::errors::AppError,
         gadgets::{regex::pw_regex_custom, stopwatch::Stopwatch},
-        serde::serialize_to_response::serialize_to_response,
+        mail::templates::{negotiate_locale, render, verify_email_link, EmailTemplate},
+        serde::{payload::Payload, serialize_to_response::serialize_to_response},
         server_init::server_state_def::ServerState,
     },
 };
@@ -50,28 +51,53 @@ pub struct SignupResponseMeta {
     timestamp: DateTime<Utc>,
 }
 
+/// enqueues the email verification link for the given SIGNUP_EMAIL_VALIDATE token
+pub async fn enqueue_verification_email(
+    transaction: &Transaction<'_>,
+    state: &ServerState,
+    locale: &str,
+    user_email: String,
+    user_token_id: Uuid,
+) -> anyhow::Result<EmailOutbox> {
+    let rendered = render(
+        EmailTemplate::VerifyEmail,
+        locale,
+        &[
+            (
+                "link",
+                verify_email_link(state.get_public_base_url(), user_token_id),
+            ),
+            ("expires_hours", VERIFICATION_DURATION_HOURS.to_string()),
+        ],
+    )?;
+
+    EmailOutboxForm::from_rendered(user_email, rendered)
+        .insert(transaction)
+        .await
+}
+
 // POST /api/auth/signup
 pub async fn signup(
     State(state): State<Arc<ServerState>>,
-    Json(body): Json<UserForm>,
+    headers: HeaderMap,
+    Payload(body): Payload<UserForm>,
 ) -> impl IntoResponse {
     // time measurement
     let stopwatch: Stopwatch = Stopwatch::new("");
 
     // check if email is valid form
     if !state.email_regex().is_match(&body.user_email) {
-        return ErrResp::from(ErrRespDat::WRONG_EMAIL_FORMAT, &stopwatch, anyhow!(""))
-            .into_response();
+        return AppError::WrongEmailFormat.into_response();
     }
 
     // check if password is valid form (At least 8 characters and includes uppercase, lowercase, number, and special characters among: [@, $, !, %, *, ?, &, #])
     if !pw_regex_custom(&body.user_password) {
-        return ErrResp::from(ErrRespDat::WRONG_PW_FORMAT, &stopwatch, anyhow!("")).into_response();
+        return AppError::WrongPwFormat.into_response();
     }
 
     // get database connection and transaction objects
-    let mut conn = get_conn!(&state, &stopwatch);
-    let transaction = get_transaction!(conn, &stopwatch);
+    let mut conn = get_conn!(&state);
+    let transaction = get_transaction!(conn);
 
     // insert new user into DB
     let returned_user: UserTruncated = match body.insert(&transaction).await {
@@ -79,21 +105,12 @@ pub async fn signup(
         Err(e) => match e.as_db_error() {
             Some(db_error) => match *db_error.code() {
                 SqlState::UNIQUE_VIOLATION => {
-                    return ErrResp::from(
-                        ErrRespDat::USER_ALREADY_EXISTS,
-                        &stopwatch,
-                        anyhow!("User already exists! Please use another email and screen name."),
-                    )
-                    .into_response();
-                }
-                _ => {
-                    return ErrResp::from(ErrRespDat::COULD_NOT_INSERT_USER, &stopwatch, anyhow!(e))
-                        .into_response()
+                    return AppError::UserAlreadyExists.into_response();
                 }
+                _ => return AppError::CouldNotInsertUser(e.into()).into_response(),
             },
             None => {
-                return ErrResp::from(ErrRespDat::COULD_NOT_INSERT_USER, &stopwatch, anyhow!(e))
-                    .into_response();
+                return AppError::CouldNotInsertUser(e.into()).into_response();
             }
         },
     };
@@ -106,66 +123,40 @@ pub async fn signup(
         user_token_user_id: returned_user.get_id(),
         user_token_type: SIGNUP_EMAIL_VALIDATE.to_owned(),
         user_token_value: user_token_id,
-        user_token_expires_at: returned_user.get_created_at() + chrono::Duration::days(1),
+        user_token_expires_at: returned_user.get_created_at()
+            + chrono::Duration::hours(VERIFICATION_DURATION_HOURS),
+        user_token_session_id: None,
     };
 
     // insert into DB and get token (email_validation)
     let returned_token: UserToken = match user_token_form.insert(&transaction).await {
         Ok(token) => token,
         Err(e) => {
-            return ErrResp::from(
-                ErrRespDat::COULD_NOT_INSERT_USER_TOKEN,
-                &stopwatch,
-                anyhow!(e),
-            )
-            .into_response()
+            return AppError::CouldNotInsertUserToken(e.context("Could not insert UserToken"))
+                .into_response()
         }
     };
 
     let returned_token_id = returned_token.get_id();
     drop(returned_token);
 
+    // enqueue verification email in the same transaction; the outbox worker delivers it after commit
+    if let Err(e) = enqueue_verification_email(
+        &transaction,
+        &state,
+        negotiate_locale(&headers),
+        body.user_email.clone(),
+        returned_token_id,
+    )
+    .await
+    {
+        return AppError::CouldNotEnqueueEmail(e.context("Could not enqueue verification email"))
+            .into_response();
+    }
+
     // commit transaction
     match transaction.commit().await {
         Ok(_) => {
-            tokio::spawn({
-                // if successfully committed onto DB, then send email in separate thread
-                let state = Arc::clone(&state);
-                let body_user_email = body.user_email.clone();
-
-                // construct email
-                async move {
-                    let email: Message = match Message::builder()
-                        .from(unsafe {SMTP_EMAIL.parse().unwrap_unchecked()})
-                        .to(match body_user_email.parse::<Mailbox>() {
-                            Ok(mb) => mb,
-                            Err(e) => {
-                                error!("Could not parse body email: {:?}", e);
-                                return;
-                            },
-                        })
-                        .subject("Email Verification for cyhdev.com forums!")
-                        .body(format!(
-                            "Please verify your email by clicking on the following link: https://www.cyhdev.com/auth/verify_email?email_token={}",
-                            returned_token_id
-                        )) {
-                            Ok(email) => email,
-                            Err(e) => {
-                                error!("Could not construct email: {:?}", e);
-                                return;
-                            },
-                        };
-
-                    // send email on shared email client
-                    match state.get_mailer().send(email).await {
-                        Ok(_) => (),
-                        Err(e) => {
-                            error!("Could not send mail: {:?}", e);
-                        }
-                    };
-                }
-            });
-
             // serialize user w. truncated password hash for return
             let signup_response = SignupResponse {
                 success: true,
@@ -178,17 +169,9 @@ pub async fn signup(
                 },
             };
 
-            serialize_to_response(&signup_response, &stopwatch)
+            serialize_to_response(&signup_response)
         }
 
-        Err(e) => {
-            error!("Could not commit transaction: {:?}", e);
-            ErrResp::from(
-                ErrRespDat::COULD_NOT_COMMIT_TRANSACTION,
-                &stopwatch,
-                anyhow!(e),
-            )
-            .into_response()
-        }
+        Err(e) => AppError::CouldNotCommitTransaction(e.into()).into_response(),
     }
 }
@@ -0,0 +1,270 @@
+use std::sync::Arc;
+
+use anyhow::Context;
+use axum::{extract::State, response::IntoResponse};
+use chrono::{DateTime, Utc};
+use data_encoding::BASE64;
+use deadpool_postgres::Transaction;
+use serde_derive::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::{
+    controllers::middleware::auth::AuthUser,
+    get_conn, get_transaction,
+    models::{
+        user_recovery_codes::{UserRecoveryCode, UserRecoveryCodeForm},
+        user_totp::{UserTotp, UserTotpForm},
+    },
+    utils::{
+        errors::errors::AppError,
+        gadgets::{argon::hash_password, stopwatch::Stopwatch},
+        serde::{payload::Payload, serialize_to_response::serialize_to_response},
+        server_init::server_state_def::ServerState,
+        two_factor::{
+            qr_code::render_qr_png,
+            recovery_codes::{
+                generate_recovery_code, normalize_recovery_code, recovery_code_selector,
+                RECOVERY_CODE_COUNT,
+            },
+            totp::{encode_secret, generate_secret, otpauth_uri, verify},
+        },
+    },
+};
+
+// request
+#[derive(Deserialize)]
+pub struct ConfirmTwoFactorForm {
+    code: String,
+}
+
+// response
+#[derive(Serialize)]
+pub struct EnrollTwoFactorResponse {
+    success: bool,
+    data: EnrollTwoFactorResponseData,
+    meta: TwoFactorResponseMeta,
+}
+
+#[derive(Serialize)]
+pub struct EnrollTwoFactorResponseData {
+    /// base32, for typing into an authenticator app by hand
+    secret: String,
+    otpauth_uri: String,
+    /// base64 PNG of otpauth_uri as a QR code
+    qr_code_png: String,
+}
+
+#[derive(Serialize)]
+pub struct ConfirmTwoFactorResponse {
+    success: bool,
+    data: ConfirmTwoFactorResponseData,
+    meta: TwoFactorResponseMeta,
+}
+
+#[derive(Serialize)]
+pub struct ConfirmTwoFactorResponseData {
+    message: String,
+    /// shown once; only their hashes are kept
+    recovery_codes: Vec<String>,
+}
+
+#[derive(Serialize)]
+pub struct TwoFactorResponseMeta {
+    time_taken: String,
+    timestamp: DateTime<Utc>,
+}
+
+// POST /api/auth/2fa/enroll
+pub async fn enroll_two_factor(
+    State(state): State<Arc<ServerState>>,
+    auth_user: AuthUser,
+) -> impl IntoResponse {
+    let stopwatch: Stopwatch = Stopwatch::new("enroll_two_factor");
+    let user = auth_user.user;
+
+    let secret = match generate_secret() {
+        Ok(secret) => secret,
+        Err(e) => return AppError::CouldNotSetUpTwoFactor(e).into_response(),
+    };
+
+    let secret_encrypted = match state.get_secret_cipher().encrypt(user.get_id(), &secret) {
+        Ok(sealed) => sealed,
+        Err(e) => return AppError::CouldNotSetUpTwoFactor(e).into_response(),
+    };
+
+    let uri = otpauth_uri(state.get_two_factor_issuer(), user.get_email(), &secret);
+    let qr_code_png = match render_qr_png(&uri) {
+        Ok(png) => BASE64.encode(&png),
+        Err(e) => {
+            return AppError::CouldNotSetUpTwoFactor(e.context("Could not render QR code"))
+                .into_response();
+        }
+    };
+
+    let mut conn = get_conn!(&state);
+    let transaction = get_transaction!(conn);
+
+    // a pending secret is replaced, so a user who lost the QR code can simply enroll again
+    let totp_form = UserTotpForm {
+        user_totp_user_id: user.get_id(),
+        user_totp_secret_encrypted: secret_encrypted,
+    };
+    match totp_form.insert(&transaction).await {
+        Ok(Some(_)) => (),
+        Ok(None) => return AppError::TwoFactorAlreadyEnabled.into_response(),
+        Err(e) => {
+            return AppError::Database(e.context("Could not insert UserTotp")).into_response();
+        }
+    }
+
+    match transaction.commit().await {
+        Ok(_) => (),
+        Err(e) => {
+            return AppError::CouldNotCommitTransaction(e.into()).into_response();
+        }
+    }
+
+    let response = EnrollTwoFactorResponse {
+        success: true,
+        data: EnrollTwoFactorResponseData {
+            secret: encode_secret(&secret),
+            otpauth_uri: uri,
+            qr_code_png,
+        },
+        meta: TwoFactorResponseMeta {
+            time_taken: format!("{:?}", stopwatch.get_original_start().elapsed()),
+            timestamp: Utc::now(),
+        },
+    };
+
+    serialize_to_response(&response)
+}
+
+// POST /api/auth/2fa/confirm
+pub async fn confirm_two_factor(
+    State(state): State<Arc<ServerState>>,
+    auth_user: AuthUser,
+    Payload(body): Payload<ConfirmTwoFactorForm>,
+) -> impl IntoResponse {
+    let stopwatch: Stopwatch = Stopwatch::new("confirm_two_factor");
+    let user = auth_user.user;
+
+    let mut conn = get_conn!(&state);
+
+    let totp = match UserTotp::get_by_user_id(&conn, user.get_id()).await {
+        Ok(Some(totp)) if totp.is_confirmed() => {
+            return AppError::TwoFactorAlreadyEnabled.into_response();
+        }
+        Ok(Some(totp)) => totp,
+        Ok(None) => return AppError::TwoFactorNotEnrolled.into_response(),
+        Err(e) => {
+            return AppError::Database(e.context("Could not get UserTotp")).into_response();
+        }
+    };
+
+    let secret = match state
+        .get_secret_cipher()
+        .decrypt(user.get_id(), totp.get_secret_encrypted())
+    {
+        Ok(secret) => secret,
+        Err(e) => return AppError::CouldNotVerifyTwoFactor(e).into_response(),
+    };
+
+    let step = match verify(&secret, &body.code, Utc::now().timestamp(), None) {
+        Some(step) => step,
+        None => return AppError::InvalidTwoFactorCode.into_response(),
+    };
+
+    let recovery_codes = match (0..RECOVERY_CODE_COUNT)
+        .map(|_| generate_recovery_code())
+        .collect::<anyhow::Result<Vec<String>>>()
+    {
+        Ok(codes) => codes,
+        Err(e) => return AppError::CouldNotSetUpTwoFactor(e).into_response(),
+    };
+
+    let recovery_code_forms = match hash_recovery_codes(user.get_id(), &recovery_codes).await {
+        Ok(forms) => forms,
+        Err(e) => return AppError::CouldNotSetUpTwoFactor(e).into_response(),
+    };
+
+    let transaction = get_transaction!(conn);
+
+    // a re-enrollment in the meantime replaced the secret this code was checked against
+    match totp.confirm(&transaction, step).await {
+        Ok(true) => (),
+        Ok(false) => return AppError::InvalidTwoFactorCode.into_response(),
+        Err(e) => {
+            return AppError::Database(e.context("Could not confirm UserTotp")).into_response();
+        }
+    }
+
+    if let Err(e) = store_recovery_codes(&transaction, user.get_id(), recovery_code_forms).await {
+        return AppError::Database(e).into_response();
+    }
+
+    match transaction.commit().await {
+        Ok(_) => (),
+        Err(e) => {
+            return AppError::CouldNotCommitTransaction(e.into()).into_response();
+        }
+    }
+
+    let response = ConfirmTwoFactorResponse {
+        success: true,
+        data: ConfirmTwoFactorResponseData {
+            message: "Two-factor authentication is enabled. Keep these recovery codes somewhere safe; each works once.".to_string(),
+            recovery_codes,
+        },
+        meta: TwoFactorResponseMeta {
+            time_taken: format!("{:?}", stopwatch.get_original_start().elapsed()),
+            timestamp: Utc::now(),
+        },
+    };
+
+    serialize_to_response(&response)
+}
+
+/// the codes as stored: each one's selector and argon2 hash. Ten hashes take a while, so they are worked out
+/// on the blocking pool rather than holding up other requests on this worker
+async fn hash_recovery_codes(
+    user_id: Uuid,
+    recovery_codes: &[String],
+) -> anyhow::Result<Vec<UserRecoveryCodeForm>> {
+    let normalized: Vec<String> = recovery_codes
+        .iter()
+        .map(|code| normalize_recovery_code(code))
+        .collect();
+
+    tokio::task::spawn_blocking(move || {
+        normalized
+            .iter()
+            .map(|code| UserRecoveryCodeForm {
+                user_recovery_code_user_id: user_id,
+                user_recovery_code_hash: hash_password(code),
+                user_recovery_code_selector: recovery_code_selector(code).to_owned(),
+            })
+            .collect()
+    })
+    .await
+    .context("Could not hash recovery codes")
+}
+
+/// replaces the user's recovery codes with these
+async fn store_recovery_codes(
+    transaction: &Transaction<'_>,
+    user_id: Uuid,
+    recovery_code_forms: Vec<UserRecoveryCodeForm>,
+) -> anyhow::Result<()> {
+    UserRecoveryCode::delete_by_user_id(transaction, user_id)
+        .await
+        .context("Could not delete UserRecoveryCodes")?;
+
+    for form in recovery_code_forms {
+        form.insert(transaction)
+            .await
+            .context("Could not insert UserRecoveryCode")?;
+    }
+
+    Ok(())
+}
@@ -1,7 +1,6 @@
 use std::sync::Arc;
 
-use anyhow::anyhow;
-use axum::{extract::State, response::IntoResponse, Json};
+use axum::{extract::State, response::IntoResponse};
 use chrono::{DateTime, Utc};
 use serde_derive::{Deserialize, Serialize};
 use tracing::error;
@@ -9,11 +8,11 @@ use uuid::Uuid;
 
 use crate::{
     get_conn, get_transaction,
-    models::user_tokens::UserToken,
+    models::user_tokens::{UserToken, SIGNUP_EMAIL_VALIDATE},
     utils::{
-        errors::errors::{ErrResp, ErrRespDat},
+        errors::errors::AppError,
         gadgets::stopwatch::Stopwatch,
-        serde::serialize_to_response::serialize_to_response,
+        serde::{payload::Payload, serialize_to_response::serialize_to_response},
         server_init::server_state_def::ServerState,
     },
 };
@@ -46,75 +45,42 @@ pub struct VerifyEmailResponseMeta {
 // POST /api/auth/validate-email
 pub async fn verify_email(
     State(state): State<Arc<ServerState>>,
-    Json(body): Json<VerifyEmailForm>,
+    Payload(body): Payload<VerifyEmailForm>,
 ) -> impl IntoResponse {
     let stopwatch: Stopwatch = Stopwatch::new("verify_email");
-    let mut conn = get_conn!(&state, stopwatch);
+    let mut conn = get_conn!(&state);
 
     let token = match UserToken::get_by_id(&conn, body.token_id).await {
-        Ok(Some(tok)) => tok,
-        Ok(None) => {
-            return ErrResp::from(
-                ErrRespDat::USER_TOKEN_INVALID,
-                &stopwatch,
-                anyhow!("Invalid user token!"),
-            )
-            .into_response()
-        }
+        Ok(Some(tok)) if tok.get_type() == SIGNUP_EMAIL_VALIDATE => tok,
+        Ok(_) => return AppError::UserTokenInvalid.into_response(),
         Err(e) => {
             error!("Could not get UserToken by ID: {:?}", e);
-            return ErrResp::from(
-                ErrRespDat::USER_TOKEN_INVALID,
-                &stopwatch,
-                anyhow!("Invalid user token!"),
-            )
-            .into_response();
+            return AppError::UserTokenInvalid.into_response();
         }
     };
 
     if token.is_used() {
-        return ErrResp::from(
-            ErrRespDat::USER_TOKEN_USED,
-            &stopwatch,
-            anyhow!("User token already used!"),
-        )
-        .into_response();
+        return AppError::UserTokenUsed.into_response();
     }
 
     if token.is_expired() {
-        return ErrResp::from(
-            ErrRespDat::USER_TOKEN_EXPIRED,
-            &stopwatch,
-            anyhow!("User token expired at: {}", token.get_expired_time()),
-        )
-        .into_response();
+        return AppError::UserTokenExpired.into_response();
     }
 
-    let transaction = get_transaction!(conn, stopwatch);
+    let transaction = get_transaction!(conn);
 
     match token.validate_user_email(&transaction).await {
         Ok(_) => (),
         Err(e) => {
             error!("Could not execute SQL or user was already verified: {}", e);
-            return ErrResp::from(
-                ErrRespDat::USER_ALREADY_VERIFIED,
-                &stopwatch,
-                anyhow!("User already verified with this token!"),
-            )
-            .into_response();
+            return AppError::UserAlreadyVerified.into_response();
         }
     };
 
     match transaction.commit().await {
         Ok(_) => (),
         Err(e) => {
-            error!("Could not commit transaction: {}", e);
-            return ErrResp::from(
-                ErrRespDat::COULD_NOT_COMMIT_TRANSACTION,
-                &stopwatch,
-                anyhow!("Failed to commit transaction!"),
-            )
-            .into_response();
+            return AppError::CouldNotCommitTransaction(e.into()).into_response();
         }
     }
 
@@ -129,5 +95,5 @@ pub async fn verify_email(
         },
     };
 
-    serialize_to_response(&response, &stopwatch)
+    serialize_to_response(&response)
 }
@@ -1,15 +1,10 @@
 #[macro_export]
 macro_rules! get_conn {
-    ($state:expr, $stopwatch:expr) => {{
+    ($state:expr) => {{
         match $state.get_conn().await {
             Ok(conn) => conn,
             Err(e) => {
-                return $crate::utils::errors::errors::ErrResp::from(
-                    $crate::utils::errors::errors::ErrRespDat::COULD_NOT_GET_CONN_FROM_POOL,
-                    &$stopwatch,
-                    e,
-                )
-                .into_response();
+                return $crate::utils::errors::errors::AppError::from(e).into_response();
             }
         }
     }};
@@ -17,14 +12,12 @@ macro_rules! get_conn {
 
 #[macro_export]
 macro_rules! get_transaction {
-    ($conn:expr, $stopwatch:expr) => {{
+    ($conn:expr) => {{
         match $conn.transaction().await {
             Ok(tran) => tran,
             Err(e) => {
-                return ErrResp::from(
-                    ErrRespDat::COULD_NOT_BUILD_TRANSACTION_FROM_CONN,
-                    &$stopwatch,
-                    anyhow::Error::from(e),
+                return $crate::utils::errors::errors::AppError::CouldNotBuildTransactionFromConn(
+                    e.into(),
                 )
                 .into_response()
             }
@@ -0,0 +1,156 @@
+use std::{future::Future, sync::Arc, time::Duration};
+
+use axum::{extract::State, http::StatusCode, response::IntoResponse};
+use chrono::{DateTime, Utc};
+use serde_derive::Serialize;
+use tracing::warn;
+
+use crate::utils::{
+    db::migrations::unapplied_migrations, gadgets::stopwatch::Stopwatch,
+    serde::serialize_to_response::serialize_to_response,
+    server_init::server_state_def::ServerState,
+};
+
+const DATABASE_CHECK_TIMEOUT: Duration = Duration::from_secs(2);
+const MAIL_CHECK_TIMEOUT: Duration = Duration::from_secs(5);
+
+// response
+#[derive(Serialize)]
+pub struct HealthResponse {
+    success: bool,
+    data: HealthResponseData,
+    meta: HealthResponseMeta,
+}
+
+#[derive(Serialize)]
+pub struct HealthResponseData {
+    status: String,
+}
+
+#[derive(Serialize)]
+pub struct HealthResponseMeta {
+    time_taken: String,
+    timestamp: DateTime<Utc>,
+}
+
+#[derive(Serialize)]
+pub struct ReadinessResponse {
+    success: bool,
+    data: ReadinessResponseData,
+    meta: HealthResponseMeta,
+}
+
+#[derive(Serialize)]
+pub struct ReadinessResponseData {
+    ready: bool,
+    checks: Vec<ReadinessCheck>,
+}
+
+#[derive(Serialize)]
+pub struct ReadinessCheck {
+    name: String,
+    ok: bool,
+    /// a short public reason when the check failed; the underlying error only goes to the log
+    message: Option<String>,
+    time_taken: String,
+}
+
+// GET /healthz
+// the process is up and serving requests; says nothing about its dependencies
+pub async fn healthz() -> impl IntoResponse {
+    let stopwatch: Stopwatch = Stopwatch::new("healthz");
+
+    serialize_to_response(&HealthResponse {
+        success: true,
+        data: HealthResponseData {
+            status: "ok".to_owned(),
+        },
+        meta: HealthResponseMeta {
+            time_taken: format!("{:?}", stopwatch.get_original_start().elapsed()),
+            timestamp: Utc::now(),
+        },
+    })
+}
+
+// GET /readyz
+// 200 when the database answers and its schema is current (and, if configured, mail is reachable); 503 otherwise
+pub async fn readyz(State(state): State<Arc<ServerState>>) -> impl IntoResponse {
+    let stopwatch: Stopwatch = Stopwatch::new("readyz");
+
+    let mut checks = vec![
+        run_check("database", DATABASE_CHECK_TIMEOUT, check_database(&state)).await,
+        run_check(
+            "migrations",
+            DATABASE_CHECK_TIMEOUT,
+            check_migrations(&state),
+        )
+        .await,
+    ];
+
+    if state.get_check_mail_when_ready() {
+        checks.push(run_check("mail", MAIL_CHECK_TIMEOUT, state.get_mailer().check()).await);
+    }
+
+    let ready = checks.iter().all(|check| check.ok);
+
+    let response = serialize_to_response(&ReadinessResponse {
+        success: ready,
+        data: ReadinessResponseData { ready, checks },
+        meta: HealthResponseMeta {
+            time_taken: format!("{:?}", stopwatch.get_original_start().elapsed()),
+            timestamp: Utc::now(),
+        },
+    });
+
+    match ready {
+        true => (StatusCode::OK, response),
+        false => (StatusCode::SERVICE_UNAVAILABLE, response),
+    }
+}
+
+async fn run_check(
+    name: &str,
+    timeout: Duration,
+    check: impl Future<Output = anyhow::Result<()>>,
+) -> ReadinessCheck {
+    let stopwatch: Stopwatch = Stopwatch::new(name);
+
+    let message = match tokio::time::timeout(timeout, check).await {
+        Ok(Ok(())) => None,
+        Ok(Err(e)) => {
+            warn!("Readiness check {} failed: {:?}", name, e);
+            Some("unavailable".to_owned())
+        }
+        Err(_) => {
+            warn!("Readiness check {} timed out after {:?}", name, timeout);
+            Some("timed out".to_owned())
+        }
+    };
+
+    ReadinessCheck {
+        name: name.to_owned(),
+        ok: message.is_none(),
+        message,
+        time_taken: format!("{:?}", stopwatch.get_original_start().elapsed()),
+    }
+}
+
+async fn check_database(state: &ServerState) -> anyhow::Result<()> {
+    let conn = state.get_conn().await?;
+    conn.query_one("SELECT 1", &[]).await?;
+    Ok(())
+}
+
+async fn check_migrations(state: &ServerState) -> anyhow::Result<()> {
+    let conn = state.get_conn().await?;
+    let unapplied = unapplied_migrations(&conn).await?;
+
+    if !unapplied.is_empty() {
+        return Err(anyhow::anyhow!(
+            "Migrations not applied yet: {:?}",
+            unapplied
+        ));
+    }
+
+    Ok(())
+}
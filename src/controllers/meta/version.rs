@@ -0,0 +1,68 @@
+use std::sync::Arc;
+
+use axum::{extract::State, response::IntoResponse};
+use chrono::{DateTime, Utc};
+use serde_derive::Serialize;
+
+use crate::utils::{
+    gadgets::stopwatch::Stopwatch, serde::serialize_to_response::serialize_to_response,
+    server_init::server_state_def::ServerState,
+};
+
+/// commit the binary was built from, or "unknown"; set by build.rs
+pub const GIT_COMMIT: &str = env!("GIT_COMMIT");
+/// unix seconds; set by build.rs
+const BUILD_TIMESTAMP: &str = env!("BUILD_TIMESTAMP");
+
+// response
+#[derive(Serialize)]
+pub struct VersionResponse {
+    success: bool,
+    data: VersionResponseData,
+    meta: VersionResponseMeta,
+}
+
+#[derive(Serialize)]
+pub struct VersionResponseData {
+    name: String,
+    version: String,
+    git_commit: String,
+    build_time: Option<DateTime<Utc>>,
+    server_start_time: DateTime<Utc>,
+    uptime_seconds: i64,
+}
+
+#[derive(Serialize)]
+pub struct VersionResponseMeta {
+    time_taken: String,
+    timestamp: DateTime<Utc>,
+}
+
+pub fn build_time() -> Option<DateTime<Utc>> {
+    BUILD_TIMESTAMP
+        .parse::<i64>()
+        .ok()
+        .and_then(|seconds| DateTime::from_timestamp(seconds, 0))
+}
+
+// GET /api/meta/version
+pub async fn version(State(state): State<Arc<ServerState>>) -> impl IntoResponse {
+    let stopwatch: Stopwatch = Stopwatch::new("version");
+    let now = Utc::now();
+
+    serialize_to_response(&VersionResponse {
+        success: true,
+        data: VersionResponseData {
+            name: env!("CARGO_PKG_NAME").to_owned(),
+            version: env!("CARGO_PKG_VERSION").to_owned(),
+            git_commit: GIT_COMMIT.to_owned(),
+            build_time: build_time(),
+            server_start_time: state.get_server_start_time(),
+            uptime_seconds: (now - state.get_server_start_time()).num_seconds(),
+        },
+        meta: VersionResponseMeta {
+            time_taken: format!("{:?}", stopwatch.get_original_start().elapsed()),
+            timestamp: now,
+        },
+    })
+}
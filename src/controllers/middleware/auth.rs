@@ -0,0 +1,221 @@
+use std::sync::Arc;
+
+use axum::{
+    async_trait,
+    extract::{FromRequestParts, Request, State},
+    http::{
+        header::{AUTHORIZATION, COOKIE},
+        request::Parts,
+        HeaderMap,
+    },
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use tracing::{error, field::display, Span};
+use uuid::Uuid;
+
+use crate::{
+    models::{
+        user_sessions::{UserSession, SESSION_COOKIE_NAME},
+        users::{User, ROLE_ADMIN},
+    },
+    utils::{errors::errors::AppError, server_init::server_state_def::ServerState},
+};
+
+/// the authenticated caller; rejects the request when no valid credential is presented
+#[derive(Clone)]
+pub struct AuthUser {
+    pub user: User,
+    pub session_id: Uuid,
+    pub roles: Vec<String>,
+}
+
+/// like AuthUser, but lets guests through as None; a credential that is presented but invalid is still rejected
+#[derive(Clone)]
+pub struct MaybeAuthUser(pub Option<AuthUser>);
+
+enum Credential {
+    Bearer(String),
+    SessionCookie(String),
+}
+
+fn read_credential(headers: &HeaderMap) -> Option<Credential> {
+    let bearer = headers
+        .get(AUTHORIZATION)
+        .and_then(|header_value| header_value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "))
+        .map(|token| Credential::Bearer(token.trim().to_owned()));
+
+    bearer.or_else(|| {
+        headers
+            .get_all(COOKIE)
+            .iter()
+            .filter_map(|header_value| header_value.to_str().ok())
+            .flat_map(|cookies| cookies.split(';'))
+            .filter_map(|cookie| cookie.trim().split_once('='))
+            .find(|(name, _)| *name == SESSION_COOKIE_NAME)
+            .map(|(_, value)| Credential::SessionCookie(value.to_owned()))
+    })
+}
+
+/// resolves the caller from a bearer access token or a session cookie, in that order
+async fn authenticate(
+    state: &Arc<ServerState>,
+    headers: &HeaderMap,
+) -> Result<Option<AuthUser>, AppError> {
+    let credential = match read_credential(headers) {
+        Some(credential) => credential,
+        None => return Ok(None),
+    };
+
+    let conn = state.get_conn().await?;
+
+    let (session, claimed_roles) = match credential {
+        Credential::Bearer(token) => {
+            let claims = state.get_jwt().verify(&token)?;
+
+            match UserSession::get_by_id(&conn, claims.sid).await {
+                Ok(Some(session)) if session.get_user_id() == claims.sub => {
+                    (session, Some(claims.roles))
+                }
+                Ok(_) => return Err(AppError::UserSessionInvalid),
+                Err(e) => {
+                    error!("Could not get UserSession by ID: {:?}", e);
+                    return Err(AppError::UserSessionInvalid);
+                }
+            }
+        }
+        Credential::SessionCookie(value) => {
+            let token = match Uuid::parse_str(&value) {
+                Ok(token) => token,
+                Err(_) => return Err(AppError::UserSessionInvalid),
+            };
+
+            match UserSession::get_by_token(&conn, token).await {
+                Ok(Some(session)) => (session, None),
+                Ok(None) => return Err(AppError::UserSessionInvalid),
+                Err(e) => {
+                    error!("Could not get UserSession by token: {:?}", e);
+                    return Err(AppError::UserSessionInvalid);
+                }
+            }
+        }
+    };
+
+    if !session.is_valid() {
+        return Err(AppError::UserSessionInvalid);
+    }
+
+    let user = match User::get_by_id(&conn, session.get_user_id()).await {
+        Ok(Some(user)) => user,
+        Ok(None) => return Err(AppError::UserSessionInvalid),
+        Err(e) => {
+            return Err(AppError::CouldNotGetUser(
+                e.context("Could not get User by ID"),
+            ));
+        }
+    };
+
+    if !user.is_active() {
+        return Err(AppError::UserInactive);
+    }
+
+    // access tokens carry their roles; session cookies have to look them up
+    let roles = match claimed_roles {
+        Some(roles) => roles,
+        None => {
+            if let Err(e) = session.touch(&conn).await {
+                error!("Could not update UserSession last used time: {:?}", e);
+            }
+
+            match user.get_roles(&conn).await {
+                Ok(roles) => roles,
+                Err(e) => {
+                    return Err(AppError::CouldNotGetUser(
+                        e.context("Could not get roles for User"),
+                    ));
+                }
+            }
+        }
+    };
+
+    // tags the request span opened by trace_request
+    Span::current().record("user.id", display(user.get_id()));
+
+    Ok(Some(AuthUser {
+        user,
+        session_id: session.get_id(),
+        roles,
+    }))
+}
+
+#[async_trait]
+impl FromRequestParts<Arc<ServerState>> for AuthUser {
+    type Rejection = AppError;
+
+    async fn from_request_parts(
+        parts: &mut Parts,
+        state: &Arc<ServerState>,
+    ) -> Result<Self, Self::Rejection> {
+        // already resolved by require_auth
+        if let Some(auth_user) = parts.extensions.get::<AuthUser>() {
+            return Ok(auth_user.clone());
+        }
+
+        match authenticate(state, &parts.headers).await? {
+            Some(auth_user) => Ok(auth_user),
+            None => Err(AppError::AuthRequired),
+        }
+    }
+}
+
+#[async_trait]
+impl FromRequestParts<Arc<ServerState>> for MaybeAuthUser {
+    type Rejection = AppError;
+
+    async fn from_request_parts(
+        parts: &mut Parts,
+        state: &Arc<ServerState>,
+    ) -> Result<Self, Self::Rejection> {
+        if let Some(auth_user) = parts.extensions.get::<AuthUser>() {
+            return Ok(MaybeAuthUser(Some(auth_user.clone())));
+        }
+
+        Ok(MaybeAuthUser(authenticate(state, &parts.headers).await?))
+    }
+}
+
+/// admin route guard; like require_auth, but the caller must also hold ROLE_ADMIN
+pub async fn require_admin(
+    State(state): State<Arc<ServerState>>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let (mut parts, body) = request.into_parts();
+
+    match AuthUser::from_request_parts(&mut parts, &state).await {
+        Ok(auth_user) if auth_user.roles.iter().any(|role| role == ROLE_ADMIN) => {
+            parts.extensions.insert(auth_user);
+            next.run(Request::from_parts(parts, body)).await
+        }
+        Ok(_) => AppError::AdminRequired.into_response(),
+        Err(rejection) => rejection.into_response(),
+    }
+}
+
+/// route guard; wrap a group of routes with `route_layer(from_fn_with_state(state, require_auth))`
+pub async fn require_auth(
+    State(state): State<Arc<ServerState>>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let (mut parts, body) = request.into_parts();
+
+    match AuthUser::from_request_parts(&mut parts, &state).await {
+        Ok(auth_user) => {
+            parts.extensions.insert(auth_user);
+            next.run(Request::from_parts(parts, body)).await
+        }
+        Err(rejection) => rejection.into_response(),
+    }
+}
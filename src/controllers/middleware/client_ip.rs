@@ -0,0 +1,76 @@
+use std::{
+    fmt,
+    net::{IpAddr, SocketAddr},
+    sync::Arc,
+};
+
+use axum::{
+    async_trait,
+    extract::{ConnectInfo, FromRequestParts, Request, State},
+    http::{request::Parts, Extensions, StatusCode},
+    middleware::Next,
+    response::Response,
+};
+use tracing::{field::display, Span};
+
+use crate::utils::{net::proxy_protocol::ProxiedPeer, server_init::server_state_def::ServerState};
+
+/// the client's address, resolved once per request by resolve_client_ip from the socket peer and whatever
+/// trusted proxies report. Missing only when the server runs without ConnectInfo, so take it as
+/// `Option<ClientIp>` in handlers.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ClientIp(pub IpAddr);
+
+impl ClientIp {
+    /// the resolved address as text, or "unknown"; for logs and rate limit keys
+    pub fn describe(extensions: &Extensions) -> String {
+        match extensions.get::<ClientIp>() {
+            Some(client_ip) => client_ip.to_string(),
+            None => "unknown".to_owned(),
+        }
+    }
+}
+
+impl fmt::Display for ClientIp {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+#[async_trait]
+impl<S: Send + Sync> FromRequestParts<S> for ClientIp {
+    type Rejection = StatusCode;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        parts
+            .extensions
+            .get::<ClientIp>()
+            .copied()
+            .ok_or(StatusCode::INTERNAL_SERVER_ERROR)
+    }
+}
+
+/// resolves the client address against server.trusted_proxies and stores it as a ClientIp extension
+/// for logging, rate limiting and session records; also recorded as `client.address` on the request span
+pub async fn resolve_client_ip(
+    State(state): State<Arc<ServerState>>,
+    connect_info: Option<ConnectInfo<SocketAddr>>,
+    mut request: Request,
+    next: Next,
+) -> Response {
+    if let Some(ConnectInfo(peer)) = connect_info {
+        let proxied = request
+            .extensions()
+            .get::<ProxiedPeer>()
+            .and_then(|proxied_peer| proxied_peer.0)
+            .map(|source| source.ip());
+        let client_ip = state
+            .get_trusted_proxies()
+            .resolve(peer.ip(), proxied, request.headers());
+
+        Span::current().record("client.address", display(client_ip));
+        request.extensions_mut().insert(ClientIp(client_ip));
+    }
+
+    next.run(request).await
+}
@@ -0,0 +1,19 @@
+use axum::{
+    extract::Request,
+    http::{header::VARY, HeaderValue},
+    middleware::Next,
+    response::Response,
+};
+
+use crate::utils::serde::content_format::{ContentFormat, RESPONSE_FORMAT};
+
+/// picks the response format from the Accept header and makes it visible to serialize_to_response and ErrResp
+pub async fn negotiate_content_format(request: Request, next: Next) -> Response {
+    let format = ContentFormat::from_accept(request.headers());
+
+    let mut response = RESPONSE_FORMAT.scope(format, next.run(request)).await;
+    response
+        .headers_mut()
+        .append(VARY, HeaderValue::from_static("accept"));
+    response
+}
@@ -0,0 +1,44 @@
+use axum::{
+    extract::{MatchedPath, Request},
+    http::Method,
+    middleware::Next,
+    response::Response,
+};
+
+use crate::utils::server_init::server_init_funcs::initialize_metrics::{
+    HTTP_REQUESTS_TOTAL, HTTP_REQUEST_DURATION_SECONDS,
+};
+
+/// counts requests and records their latency, labelled by route template rather than raw URI
+/// so path parameters and unknown paths don't each get a series of their own
+pub async fn record_request_metrics(request: Request, next: Next) -> Response {
+    let start = tokio::time::Instant::now();
+
+    let path = request
+        .extensions()
+        .get::<MatchedPath>()
+        .map(|matched_path| matched_path.as_str().to_owned())
+        .unwrap_or_else(|| "unmatched".to_owned());
+    let method = match *request.method() {
+        Method::GET
+        | Method::POST
+        | Method::PUT
+        | Method::PATCH
+        | Method::DELETE
+        | Method::HEAD
+        | Method::OPTIONS => request.method().as_str().to_owned(),
+        _ => "OTHER".to_owned(),
+    };
+
+    let response = next.run(request).await;
+
+    let labels = [
+        ("method", method),
+        ("path", path),
+        ("status", response.status().as_u16().to_string()),
+    ];
+    metrics::counter!(HTTP_REQUESTS_TOTAL, &labels).increment(1);
+    metrics::histogram!(HTTP_REQUEST_DURATION_SECONDS, &labels).record(start.elapsed());
+
+    response
+}
@@ -0,0 +1,38 @@
+use std::sync::Arc;
+
+use axum::{
+    extract::{Request, State},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+
+use crate::utils::{
+    rate_limit::rate_limit_store::RateLimitGroup, server_init::server_state_def::ServerState,
+};
+
+use super::client_ip::ClientIp;
+
+/// takes a token from the client IP's bucket in `group` and answers 429 once it is empty;
+/// allowed responses carry RateLimit-* headers for the strictest bucket the request drew from
+pub async fn rate_limit(
+    State((state, group)): State<(Arc<ServerState>, RateLimitGroup)>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let client_ip = ClientIp::describe(request.extensions());
+
+    match state
+        .get_rate_limiter()
+        .check(group, &format!("ip:{}", client_ip))
+        .await
+    {
+        Ok(decision) => {
+            let mut response = next.run(request).await;
+            if let Some(decision) = decision {
+                decision.apply_headers(response.headers_mut());
+            }
+            response
+        }
+        Err(e) => e.into_response(),
+    }
+}
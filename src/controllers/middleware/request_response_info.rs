@@ -1,6 +1,33 @@
-use axum::{extract::Request, middleware::Next, response::Response};
+use axum::{
+    extract::Request,
+    http::{header::USER_AGENT, HeaderMap},
+    middleware::Next,
+    response::Response,
+};
 use tracing::info;
 
+use super::client_ip::ClientIp;
+
+tokio::task_local! {
+    /// when the current request reached print_request_info
+    static REQUEST_STARTED_AT: tokio::time::Instant;
+}
+
+/// time spent on the current request so far; zero outside of a request
+pub fn request_elapsed() -> std::time::Duration {
+    REQUEST_STARTED_AT
+        .try_with(|started_at| started_at.elapsed())
+        .unwrap_or_default()
+}
+
+/// reads the client's user agent string, if any
+pub fn get_user_agent(headers: &HeaderMap) -> Option<String> {
+    headers
+        .get(USER_AGENT)
+        .and_then(|header_value| header_value.to_str().ok())
+        .map(|ua| ua.to_owned())
+}
+
 pub async fn print_request_info(request: Request, next: Next) -> Response {
     let start = tokio::time::Instant::now();
     let (method, uri, version) = (
@@ -9,23 +36,11 @@ pub async fn print_request_info(request: Request, next: Next) -> Response {
         request.version(),
     );
 
-    let ip_str = request
-        .headers()
-        .get("x-forwarded-for")
-        .and_then(|header_value| header_value.to_str().ok())
-        .map(|ip| ip.to_owned())
-        .or_else(|| {
-            request
-                .headers()
-                .get("x-real-ip")
-                .and_then(|header_value| header_value.to_str().ok())
-                .map(|ip| ip.to_owned())
-        })
-        .unwrap_or_else(|| "unknown".to_owned());
+    let ip_str = ClientIp::describe(request.extensions());
 
     info!("{} {} {:?} from {}", method, uri, version, ip_str);
 
-    let response = next.run(request).await;
+    let response = REQUEST_STARTED_AT.scope(start, next.run(request)).await;
 
     info!(
         "{} {} {:?}: {} in {:?}",
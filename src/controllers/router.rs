@@ -1,20 +1,143 @@
 use std::sync::Arc;
 
-use axum::{middleware::from_fn, routing::post};
+use axum::{
+    middleware::{from_fn, from_fn_with_state},
+    routing::{delete, get, post},
+};
 use tower_http::compression::CompressionLayer;
 
-use crate::utils::server_init::server_state_def::ServerState;
+use crate::utils::{
+    rate_limit::rate_limit_store::RateLimitGroup, server_init::server_state_def::ServerState,
+};
 
 use super::{
-    auth::{signup::signup, verify_email::verify_email},
-    middleware::request_response_info::print_request_info,
+    admin::{
+        email_outbox::{list_stuck_emails, retry_email},
+        email_templates::preview_email_template,
+        login_lockouts::list_login_lockouts,
+    },
+    auth::{
+        account_lockout::unlock_account,
+        login::login,
+        login_two_factor::login_two_factor,
+        logout::{logout, logout_all},
+        me::me,
+        passkey_login::{finish_passkey_login, start_passkey_login, start_passkey_second_factor},
+        passkeys::{
+            delete_passkey, finish_passkey_registration, list_passkeys, start_passkey_registration,
+        },
+        password_reset::{forgot_password, reset_password},
+        refresh::refresh,
+        resend_verification::resend_verification,
+        sessions::{list_sessions, revoke_session},
+        signup::signup,
+        two_factor::{confirm_two_factor, enroll_two_factor},
+        verify_email::verify_email,
+    },
+    meta::{
+        health::{healthz, readyz},
+        version::version,
+    },
+    middleware::{
+        auth::{require_admin, require_auth},
+        client_ip::resolve_client_ip,
+        content_negotiation::negotiate_content_format,
+        metrics::record_request_metrics,
+        rate_limit::rate_limit,
+        request_response_info::print_request_info,
+        request_span::trace_request,
+    },
 };
 
 pub fn generate_router(state: &Arc<ServerState>) -> axum::Router {
-    axum::Router::new()
+    let limited =
+        |group: RateLimitGroup| from_fn_with_state((Arc::clone(state), group), rate_limit);
+
+    // routes that check a password, recovery code or passkey
+    let credentials = axum::Router::new()
         .route("/api/auth/signup", post(signup))
+        .route("/api/auth/login", post(login))
+        .route("/api/auth/login/2fa", post(login_two_factor))
+        .route("/api/auth/passkeys/login/start", post(start_passkey_login))
+        .route(
+            "/api/auth/passkeys/login/finish",
+            post(finish_passkey_login),
+        )
+        .route(
+            "/api/auth/passkeys/2fa/start",
+            post(start_passkey_second_factor),
+        )
+        .route_layer(limited(RateLimitGroup::Credentials));
+
+    // routes that redeem an emailed or refresh token
+    let tokens = axum::Router::new()
+        .route("/api/auth/refresh", post(refresh))
+        .route("/api/auth/reset-password", post(reset_password))
         .route("/api/auth/validate-email", post(verify_email))
+        .route("/api/auth/unlock-account", post(unlock_account))
+        .route_layer(limited(RateLimitGroup::Tokens));
+
+    // routes that send email
+    let email = axum::Router::new()
+        .route("/api/auth/forgot-password", post(forgot_password))
+        .route("/api/auth/resend-verification", post(resend_verification))
+        .route_layer(limited(RateLimitGroup::Email));
+
+    // routes that require an authenticated user
+    let protected = axum::Router::new()
+        .route("/api/auth/me", get(me))
+        .route("/api/auth/logout", post(logout))
+        .route("/api/auth/logout-all", post(logout_all))
+        .route("/api/auth/sessions", get(list_sessions))
+        .route("/api/auth/sessions/:session_id", delete(revoke_session))
+        .route("/api/auth/2fa/enroll", post(enroll_two_factor))
+        .route("/api/auth/2fa/confirm", post(confirm_two_factor))
+        .route("/api/auth/passkeys", get(list_passkeys))
+        .route("/api/auth/passkeys/:passkey_id", delete(delete_passkey))
+        .route(
+            "/api/auth/passkeys/register/start",
+            post(start_passkey_registration),
+        )
+        .route(
+            "/api/auth/passkeys/register/finish",
+            post(finish_passkey_registration),
+        )
+        .route_layer(from_fn_with_state(Arc::clone(state), require_auth));
+
+    // routes that require an administrator
+    let admin = axum::Router::new()
+        .route("/api/admin/email-outbox", get(list_stuck_emails))
+        .route(
+            "/api/admin/email-outbox/:email_outbox_id/retry",
+            post(retry_email),
+        )
+        .route(
+            "/api/admin/email-templates/:template/preview",
+            get(preview_email_template),
+        )
+        .route("/api/admin/login-lockouts", get(list_login_lockouts))
+        .route_layer(from_fn_with_state(Arc::clone(state), require_admin));
+
+    // every API route also draws from the client's default bucket, before authentication
+    let api = axum::Router::new()
+        .merge(credentials)
+        .merge(tokens)
+        .merge(email)
+        .merge(protected)
+        .merge(admin)
+        .route_layer(limited(RateLimitGroup::Default));
+
+    // probes and build info are never rate limited
+    axum::Router::new()
+        .route("/healthz", get(healthz))
+        .route("/readyz", get(readyz))
+        .route("/api/meta/version", get(version))
+        .merge(api)
         .layer(CompressionLayer::new())
+        .layer(from_fn(record_request_metrics))
         .layer(from_fn(print_request_info))
+        .layer(from_fn_with_state(Arc::clone(state), resolve_client_ip))
+        .layer(from_fn(negotiate_content_format))
+        .layer(from_fn(trace_request))
         .with_state(Arc::clone(state))
 }
@@ -1,29 +1,97 @@
 pub mod models {
     pub mod common_traits;
     pub mod consts;
+    pub mod email_outbox;
     pub mod jwt;
+    pub mod login_failures;
+    pub mod login_lockouts;
+    pub mod passkey_challenges;
+    pub mod passkeys;
+    pub mod rate_limit_buckets;
+    pub mod user_recovery_codes;
+    pub mod user_sessions;
     pub mod user_tokens;
+    pub mod user_totp;
     pub mod users;
 }
 
 pub mod controllers {
     pub mod middleware {
+        pub mod auth;
+        pub mod client_ip;
+        pub mod content_negotiation;
+        pub mod metrics;
+        pub mod rate_limit;
         pub mod request_response_info;
+        pub mod request_span;
+    }
+    pub mod admin {
+        pub mod email_outbox;
+        pub mod email_templates;
+        pub mod login_lockouts;
     }
     pub mod auth {
+        pub mod account_lockout;
         pub mod login;
+        pub mod login_two_factor;
+        pub mod logout;
+        pub mod me;
+        pub mod passkey_login;
+        pub mod passkeys;
+        pub mod password_reset;
+        pub mod refresh;
+        pub mod resend_verification;
+        pub mod sessions;
         pub mod signup;
+        pub mod two_factor;
         pub mod verify_email;
     }
+    pub mod meta {
+        pub mod health;
+        pub mod version;
+    }
     pub mod macros;
     pub mod router;
 }
 
 pub mod utils {
+    pub mod cli {
+        pub mod cli_args;
+        pub mod preview_email;
+        pub mod print_config;
+    }
+    pub mod config {
+        pub mod app_config;
+    }
+    pub mod db {
+        pub mod migrations;
+    }
     pub mod errors {
+        #[allow(clippy::module_inception)]
         pub mod errors;
     }
+    pub mod lockout {
+        pub mod login_throttle;
+    }
+    pub mod mail {
+        pub mod file_transport;
+        pub mod mail_transport;
+        pub mod memory_transport;
+        pub mod smtp_transport;
+        pub mod templates;
+    }
+    pub mod net {
+        pub mod proxy_protocol;
+        pub mod trusted_proxies;
+    }
+    pub mod rate_limit {
+        pub mod memory_store;
+        pub mod postgres_store;
+        pub mod rate_limit_store;
+    }
     pub mod serde {
+        pub mod content_format;
+        pub mod payload;
         pub mod serialize_to_response;
     }
     pub mod gadgets {
@@ -34,42 +102,156 @@ pub mod utils {
     pub mod server_init {
         pub mod cache_load_funcs {}
         pub mod server_init_funcs {
+            pub mod admin_server;
             pub mod https_redirector;
             pub mod initialize_crypto;
             pub mod initialize_db_conn_pool;
             pub mod initialize_logger;
             pub mod initialize_mailer;
+            pub mod initialize_metrics;
+            pub mod initialize_rate_limiter;
+            pub mod initialize_trace_export;
             pub mod load_cert_config;
             pub mod load_env_vars;
+            pub mod load_jwt_keys;
+            pub mod shutdown_signal;
         }
         pub mod initialize_server;
         pub mod server_state_def;
     }
+    pub mod two_factor {
+        pub mod qr_code;
+        pub mod recovery_codes;
+        pub mod secret_cipher;
+        pub mod totp;
+    }
+    pub mod webauthn {
+        pub mod authenticator_data;
+        pub mod cose_key;
+        pub mod relying_party;
+    }
+    pub mod workers {
+        pub mod email_outbox_worker;
+    }
 }
 
 use chrono::{DateTime, Utc};
 use utils::{
+    cli::{cli_args::CliArgs, preview_email::print_email_preview, print_config::print_config},
+    config::app_config::AppConfig,
+    db::migrations::{run_migrations, MigrationMode},
     gadgets::stopwatch::Stopwatch,
     server_init::{
         initialize_server::init_server,
-        server_init_funcs::{initialize_logger::init_logger, load_env_vars::load_env},
+        server_init_funcs::{
+            initialize_db_conn_pool::init_db_conn_pool,
+            initialize_logger::{init_logger, LOG_FILE_NAME},
+            initialize_trace_export::init_trace_export,
+            load_env_vars::load_env,
+        },
     },
 };
 
 #[tokio::main(flavor = "multi_thread")]
 async fn main() -> anyhow::Result<()> {
+    let cli_args = CliArgs::from_env()?;
+
+    // one-off commands that don't start the server
+    if let Some(template) = cli_args.preview_email.as_deref() {
+        let _ = load_env();
+        return print_email_preview(
+            template,
+            cli_args.locale.as_deref(),
+            cli_args.config.as_deref(),
+        );
+    }
+
+    if cli_args.print_config {
+        load_env()?;
+        return print_config(cli_args.config.as_deref());
+    }
+
     let server_start_time: DateTime<Utc> = Utc::now();
+
+    // configuration comes first since it decides how and where to log; errors go straight to stderr
+    let env_path = load_env()?;
+    let config = AppConfig::load(cli_args.config.as_deref())?;
+    let tracer_provider = init_trace_export(&config.telemetry)?;
+
+    // initialize logger; the guard flushes queued lines when main returns
+    let (log_filter, _log_guard) = init_logger(&config.logging, tracer_provider.as_ref())?;
     let mut stopwatch: Stopwatch = Stopwatch::new("cyhdev.com backend server starting...");
+    match config.logging.dir.as_deref() {
+        Some(dir) => stopwatch.click(&format!("logging to {:?}", dir.join(LOG_FILE_NAME))),
+        None => stopwatch.click("logging to stdout"),
+    }
+
+    match env_path {
+        Some(env_path) => {
+            stopwatch.click(&format!("environment variables loaded from {:?}", env_path))
+        }
+        None => stopwatch.click("no .env file found"),
+    }
+    match config.telemetry.otlp_endpoint.as_deref() {
+        Some(endpoint) => stopwatch.click(&format!(
+            "configuration loaded; exporting traces to {}",
+            endpoint
+        )),
+        None => stopwatch.click("configuration loaded"),
+    }
 
-    // initialize logger
-    init_logger()?;
-    stopwatch.click("logging inititalized");
+    if cli_args.migrate_only {
+        let mode = if cli_args.dry_run {
+            MigrationMode::DryRun
+        } else {
+            MigrationMode::Apply
+        };
+        let conn = init_db_conn_pool(&config.database)?.get().await?;
+        let migrations = run_migrations(conn, mode).await?;
+        match mode {
+            MigrationMode::Apply => {
+                stopwatch.total(&format!("{} migration(s) applied in", migrations.len()))
+            }
+            MigrationMode::DryRun => stopwatch.total(&format!(
+                "dry run: {} pending migration(s) ran cleanly and were rolled back in",
+                migrations.len()
+            )),
+        }
+        return Ok(());
+    }
 
-    // load .env files
-    let env_path = load_env()?;
-    stopwatch.click(&format!("environment variables loaded from {:?}", env_path));
+    let served = init_server(&mut stopwatch, server_start_time, config, log_filter).await;
 
-    init_server(&mut stopwatch, server_start_time).await?;
+    // flush spans still buffered by the batch exporter; this blocks on the export
+    if let Some(provider) = tracer_provider {
+        match tokio::task::spawn_blocking(move || provider.shutdown()).await {
+            Ok(Ok(())) => stopwatch.click("trace exporter flushed"),
+            Ok(Err(e)) => tracing::warn!("Could not flush trace exporter: {:?}", e),
+            Err(e) => tracing::warn!("Could not flush trace exporter: {:?}", e),
+        }
+    }
+
+    served
+}
 
-    Ok(())
+#[cfg(test)]
+pub mod tests {
+    mod auth_flow;
+    mod client_ip;
+    mod email_outbox;
+    pub mod harness;
+    mod lockout;
+    mod logging;
+    mod meta;
+    mod metrics;
+    mod passkeys;
+    mod password_reset;
+    mod rate_limit;
+    mod refresh;
+    mod resend_verification;
+    mod sessions;
+    mod shutdown;
+    pub mod software_authenticator;
+    mod trace_context;
+    mod two_factor;
 }
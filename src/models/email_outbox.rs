@@ -0,0 +1,259 @@
+use chrono::{DateTime, Utc};
+use deadpool_postgres::{Object, Transaction};
+use serde_derive::{Deserialize, Serialize};
+use tracing::instrument;
+use uuid::Uuid;
+
+use crate::utils::mail::templates::RenderedEmail;
+
+use super::common_traits::{FromRow, FromRows, ToInsertStmt};
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct EmailOutbox {
+    email_outbox_id: Uuid,                       // Outbox entry's primary key.
+    email_outbox_to: String,                     // Recipient address.
+    email_outbox_subject: String,                // Subject line.
+    email_outbox_body: String,                   // Plaintext body.
+    email_outbox_html_body: Option<String>,      // HTML alternative, if any.
+    email_outbox_status: String,                 // PENDING, SENT or DEAD.
+    email_outbox_attempts: i32,                  // Number of delivery attempts made so far.
+    email_outbox_next_attempt_at: DateTime<Utc>, // Earliest time the worker may try again.
+    email_outbox_last_error: Option<String>,     // Error from the most recent failed attempt.
+    email_outbox_created_at: DateTime<Utc>,      // The time when the entry was enqueued.
+    email_outbox_sent_at: Option<DateTime<Utc>>, // The time when the email was handed to the transport.
+}
+
+impl FromRow for EmailOutbox {
+    fn from_row(row: tokio_postgres::Row) -> EmailOutbox {
+        EmailOutbox {
+            email_outbox_id: row.get::<&str, Uuid>("email_outbox_id"),
+            email_outbox_to: row.get::<&str, String>("email_outbox_to"),
+            email_outbox_subject: row.get::<&str, String>("email_outbox_subject"),
+            email_outbox_body: row.get::<&str, String>("email_outbox_body"),
+            email_outbox_html_body: row.get::<&str, Option<String>>("email_outbox_html_body"),
+            email_outbox_status: row.get::<&str, String>("email_outbox_status"),
+            email_outbox_attempts: row.get::<&str, i32>("email_outbox_attempts"),
+            email_outbox_next_attempt_at: row
+                .get::<&str, DateTime<Utc>>("email_outbox_next_attempt_at"),
+            email_outbox_last_error: row.get::<&str, Option<String>>("email_outbox_last_error"),
+            email_outbox_created_at: row.get::<&str, DateTime<Utc>>("email_outbox_created_at"),
+            email_outbox_sent_at: row.get::<&str, Option<DateTime<Utc>>>("email_outbox_sent_at"),
+        }
+    }
+}
+
+impl FromRows for EmailOutbox {
+    fn from_rows(rows: Vec<tokio_postgres::Row>) -> Vec<Self> {
+        rows.into_iter().map(EmailOutbox::from_row).collect()
+    }
+}
+
+impl EmailOutbox {
+    /// leases a batch of due entries by pushing their next attempt past the lease, so other instances skip
+    /// them while they are being sent and pick them up again only if this one never records a result
+    #[instrument(name = "EmailOutbox::claim_due", skip_all)]
+    pub async fn claim_due(
+        conn: &Transaction<'_>,
+        limit: i64,
+        lease: chrono::Duration,
+    ) -> anyhow::Result<Vec<Self>> {
+        let rows = conn
+            .query(
+                "UPDATE v1.email_outbox SET email_outbox_next_attempt_at = NOW() + make_interval(secs => $3) WHERE email_outbox_id IN (SELECT email_outbox_id FROM v1.email_outbox WHERE email_outbox_status = $1 AND email_outbox_next_attempt_at <= NOW() ORDER BY email_outbox_next_attempt_at LIMIT $2 FOR UPDATE SKIP LOCKED) RETURNING *",
+                &[&EMAIL_OUTBOX_PENDING, &limit, &(lease.num_seconds() as f64)],
+            )
+            .await?;
+        Ok(EmailOutbox::from_rows(rows))
+    }
+
+    /// dead-lettered entries and pending ones that have already failed or are long overdue
+    #[instrument(name = "EmailOutbox::get_stuck", skip_all)]
+    pub async fn get_stuck(conn: &Object, limit: i64) -> anyhow::Result<Vec<Self>> {
+        let rows = conn
+            .query(
+                "SELECT * FROM v1.email_outbox WHERE email_outbox_status = $1 OR (email_outbox_status = $2 AND (email_outbox_attempts > 0 OR email_outbox_next_attempt_at < NOW() - INTERVAL '10 minutes')) ORDER BY email_outbox_created_at DESC LIMIT $3",
+                &[&EMAIL_OUTBOX_DEAD, &EMAIL_OUTBOX_PENDING, &limit],
+            )
+            .await?;
+        Ok(EmailOutbox::from_rows(rows))
+    }
+
+    /// returns false, changing nothing, if another instance has claimed the entry since its lease ran out
+    #[instrument(name = "EmailOutbox::mark_sent", skip_all)]
+    pub async fn mark_sent(&self, conn: &Transaction<'_>) -> anyhow::Result<bool> {
+        match conn
+            .execute(
+                "UPDATE v1.email_outbox SET email_outbox_status = $1, email_outbox_attempts = email_outbox_attempts + 1, email_outbox_sent_at = NOW(), email_outbox_last_error = NULL WHERE email_outbox_id = $2 AND email_outbox_status = $3 AND email_outbox_next_attempt_at = $4",
+                &[
+                    &EMAIL_OUTBOX_SENT,
+                    &self.email_outbox_id,
+                    &EMAIL_OUTBOX_PENDING,
+                    &self.email_outbox_next_attempt_at,
+                ],
+            )
+            .await
+        {
+            Ok(count) => Ok(count == 1),
+            Err(e) => Err(anyhow::Error::from(e)),
+        }
+    }
+
+    /// records a failed attempt; the entry is dead-lettered once it runs out of attempts.
+    /// Like mark_sent, returns false if another instance has claimed it since
+    #[instrument(name = "EmailOutbox::mark_failed", skip_all)]
+    pub async fn mark_failed(
+        &self,
+        conn: &Transaction<'_>,
+        error: &str,
+        next_attempt_at: Option<DateTime<Utc>>,
+    ) -> anyhow::Result<bool> {
+        let (status, next_attempt_at) = match next_attempt_at {
+            Some(next_attempt_at) => (EMAIL_OUTBOX_PENDING, next_attempt_at),
+            None => (EMAIL_OUTBOX_DEAD, self.email_outbox_next_attempt_at),
+        };
+
+        match conn
+            .execute(
+                "UPDATE v1.email_outbox SET email_outbox_status = $1, email_outbox_attempts = email_outbox_attempts + 1, email_outbox_next_attempt_at = $2, email_outbox_last_error = $3 WHERE email_outbox_id = $4 AND email_outbox_status = $5 AND email_outbox_next_attempt_at = $6",
+                &[
+                    &status,
+                    &next_attempt_at,
+                    &error,
+                    &self.email_outbox_id,
+                    &EMAIL_OUTBOX_PENDING,
+                    &self.email_outbox_next_attempt_at,
+                ],
+            )
+            .await
+        {
+            Ok(count) => Ok(count == 1),
+            Err(e) => Err(anyhow::Error::from(e)),
+        }
+    }
+
+    /// puts a dead-lettered entry back in the queue with a fresh set of attempts
+    #[instrument(name = "EmailOutbox::requeue_dead", skip_all)]
+    pub async fn requeue_dead(conn: &Object, email_outbox_id: Uuid) -> anyhow::Result<bool> {
+        match conn
+            .execute(
+                "UPDATE v1.email_outbox SET email_outbox_status = $1, email_outbox_attempts = 0, email_outbox_next_attempt_at = NOW() WHERE email_outbox_id = $2 AND email_outbox_status = $3",
+                &[&EMAIL_OUTBOX_PENDING, &email_outbox_id, &EMAIL_OUTBOX_DEAD],
+            )
+            .await
+        {
+            Ok(count) => Ok(count == 1),
+            Err(e) => Err(anyhow::Error::from(e)),
+        }
+    }
+
+    pub fn get_id(&self) -> Uuid {
+        self.email_outbox_id
+    }
+
+    pub fn get_to(&self) -> &str {
+        &self.email_outbox_to
+    }
+
+    pub fn get_subject(&self) -> &str {
+        &self.email_outbox_subject
+    }
+
+    pub fn get_body(&self) -> &str {
+        &self.email_outbox_body
+    }
+
+    pub fn get_html_body(&self) -> Option<&str> {
+        self.email_outbox_html_body.as_deref()
+    }
+
+    pub fn get_attempts(&self) -> i32 {
+        self.email_outbox_attempts
+    }
+}
+
+/// outbox entry as shown to admins; the body is left out since it carries single-use links
+#[derive(Serialize, Deserialize, Debug)]
+pub struct EmailOutboxTruncated {
+    email_outbox_id: Uuid,
+    email_outbox_to: String,
+    email_outbox_subject: String,
+    email_outbox_status: String,
+    email_outbox_attempts: i32,
+    email_outbox_next_attempt_at: DateTime<Utc>,
+    email_outbox_last_error: Option<String>,
+    email_outbox_created_at: DateTime<Utc>,
+}
+
+impl From<EmailOutbox> for EmailOutboxTruncated {
+    fn from(entry: EmailOutbox) -> Self {
+        EmailOutboxTruncated {
+            email_outbox_id: entry.email_outbox_id,
+            email_outbox_to: entry.email_outbox_to,
+            email_outbox_subject: entry.email_outbox_subject,
+            email_outbox_status: entry.email_outbox_status,
+            email_outbox_attempts: entry.email_outbox_attempts,
+            email_outbox_next_attempt_at: entry.email_outbox_next_attempt_at,
+            email_outbox_last_error: entry.email_outbox_last_error,
+            email_outbox_created_at: entry.email_outbox_created_at,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct EmailOutboxForm {
+    pub email_outbox_to: String,
+    pub email_outbox_subject: String,
+    pub email_outbox_body: String,
+    pub email_outbox_html_body: Option<String>,
+}
+
+impl ToInsertStmt for EmailOutboxForm {
+    fn to_insert_stmt() -> String {
+        String::from(
+            "INSERT INTO v1.email_outbox (email_outbox_to, email_outbox_subject, email_outbox_body, email_outbox_html_body, email_outbox_status, email_outbox_attempts, email_outbox_next_attempt_at, email_outbox_created_at) VALUES ($1, $2, $3, $4, $5, $6, $7, $8) RETURNING *",
+        )
+    }
+}
+
+impl EmailOutboxForm {
+    pub fn from_rendered(email_outbox_to: String, rendered: RenderedEmail) -> Self {
+        EmailOutboxForm {
+            email_outbox_to,
+            email_outbox_subject: rendered.subject,
+            email_outbox_body: rendered.text_body,
+            email_outbox_html_body: Some(rendered.html_body),
+        }
+    }
+
+    /// enqueues inside the caller's transaction so the email exists iff the rows it refers to do
+    #[instrument(name = "EmailOutboxForm::insert", skip_all)]
+    pub async fn insert(&self, conn: &Transaction<'_>) -> anyhow::Result<EmailOutbox> {
+        let now = Utc::now();
+        let attempts: i32 = 0;
+        match conn
+            .query_one(
+                &EmailOutboxForm::to_insert_stmt(),
+                &[
+                    &self.email_outbox_to,
+                    &self.email_outbox_subject,
+                    &self.email_outbox_body,
+                    &self.email_outbox_html_body,
+                    &EMAIL_OUTBOX_PENDING,
+                    &attempts,
+                    &now,
+                    &now,
+                ],
+            )
+            .await
+        {
+            Ok(row) => Ok(EmailOutbox::from_row(row)),
+            Err(e) => Err(anyhow::Error::from(e)),
+        }
+    }
+}
+
+pub const EMAIL_OUTBOX_PENDING: &str = "PENDING";
+pub const EMAIL_OUTBOX_SENT: &str = "SENT";
+pub const EMAIL_OUTBOX_DEAD: &str = "DEAD";
+
+pub const EMAIL_OUTBOX_MAX_ATTEMPTS: i32 = 8;
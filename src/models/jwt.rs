@@ -1 +1,234 @@
-pub struct JWT {}
+use std::collections::HashMap;
+
+use anyhow::anyhow;
+use chrono::{DateTime, Utc};
+use jsonwebtoken::{
+    decode, decode_header, encode, errors::ErrorKind, Algorithm, DecodingKey, EncodingKey, Header,
+    Validation,
+};
+use serde_derive::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::utils::errors::errors::AppError;
+
+pub const ACCESS_TOKEN_DURATION_MINUTES: i64 = 15;
+pub const JWT_MIN_SECRET_LEN: usize = 32;
+
+/// claims carried by an access token
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct Claims {
+    pub sub: Uuid,          // User's PKEY.
+    pub name: String,       // User's screen name at the time of issuance.
+    pub roles: Vec<String>, // Roles granted to the user.
+    pub sid: Uuid,          // The session this token was issued for.
+    pub iat: i64,           // Issued at, seconds since epoch.
+    pub exp: i64,           // Expires at, seconds since epoch.
+    pub jti: Uuid,          // Token's unique ID.
+}
+
+impl Claims {
+    pub fn new(user_id: Uuid, screen_name: String, roles: Vec<String>, session_id: Uuid) -> Self {
+        let now = Utc::now();
+        Claims {
+            sub: user_id,
+            name: screen_name,
+            roles,
+            sid: session_id,
+            iat: now.timestamp(),
+            exp: (now + chrono::Duration::minutes(ACCESS_TOKEN_DURATION_MINUTES)).timestamp(),
+            jti: Uuid::new_v4(),
+        }
+    }
+
+    pub fn get_expired_time(&self) -> DateTime<Utc> {
+        DateTime::from_timestamp(self.exp, 0).unwrap_or_default()
+    }
+
+    pub fn has_role(&self, role: &str) -> bool {
+        self.roles.iter().any(|r| r == role)
+    }
+}
+
+/// reasons an access token can be rejected
+#[derive(Debug, PartialEq, Eq)]
+pub enum JwtError {
+    Expired,
+    BadSignature,
+    Malformed,
+}
+
+impl From<JwtError> for AppError {
+    fn from(e: JwtError) -> Self {
+        match e {
+            JwtError::Expired => AppError::JwtExpired,
+            JwtError::BadSignature => AppError::JwtInvalidSignature,
+            JwtError::Malformed => AppError::JwtMalformed,
+        }
+    }
+}
+
+/// HS256 key ring; signs with the active key and verifies with any key still listed, selected by the `kid` header
+#[derive(Clone)]
+pub struct JWT {
+    active_kid: String,
+    encoding_key: EncodingKey,
+    decoding_keys: HashMap<String, DecodingKey>,
+}
+
+impl JWT {
+    pub fn from_secrets(active_kid: &str, secrets: &[(String, String)]) -> anyhow::Result<Self> {
+        let mut decoding_keys = HashMap::new();
+        let mut encoding_key = None;
+
+        for (kid, secret) in secrets {
+            if secret.len() < JWT_MIN_SECRET_LEN {
+                return Err(anyhow!(
+                    "JWT secret for kid {} must be at least {} bytes",
+                    kid,
+                    JWT_MIN_SECRET_LEN
+                ));
+            }
+            if decoding_keys
+                .insert(kid.clone(), DecodingKey::from_secret(secret.as_bytes()))
+                .is_some()
+            {
+                return Err(anyhow!("Duplicate JWT kid: {}", kid));
+            }
+            if kid == active_kid {
+                encoding_key = Some(EncodingKey::from_secret(secret.as_bytes()));
+            }
+        }
+
+        match encoding_key {
+            Some(encoding_key) => Ok(JWT {
+                active_kid: active_kid.to_owned(),
+                encoding_key,
+                decoding_keys,
+            }),
+            None => Err(anyhow!("No JWT secret found for active kid {}", active_kid)),
+        }
+    }
+
+    pub fn sign(&self, claims: &Claims) -> anyhow::Result<String> {
+        let mut header = Header::new(Algorithm::HS256);
+        header.kid = Some(self.active_kid.clone());
+
+        encode(&header, claims, &self.encoding_key).map_err(anyhow::Error::from)
+    }
+
+    pub fn verify(&self, token: &str) -> Result<Claims, JwtError> {
+        let header = decode_header(token).map_err(|_| JwtError::Malformed)?;
+
+        // tokens signed with a key that has been rotated out can no longer be verified
+        let key = match header.kid {
+            Some(ref kid) => self.decoding_keys.get(kid).ok_or(JwtError::BadSignature)?,
+            None => return Err(JwtError::Malformed),
+        };
+
+        let mut validation = Validation::new(Algorithm::HS256);
+        validation.leeway = 0;
+
+        match decode::<Claims>(token, key, &validation) {
+            Ok(data) => Ok(data.claims),
+            Err(e) => match e.kind() {
+                ErrorKind::ExpiredSignature => Err(JwtError::Expired),
+                ErrorKind::InvalidSignature => Err(JwtError::BadSignature),
+                _ => Err(JwtError::Malformed),
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SECRET_A: &str = "aaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaa";
+    const SECRET_B: &str = "bbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbb";
+
+    fn claims() -> Claims {
+        Claims::new(
+            Uuid::new_v4(),
+            "tester".to_owned(),
+            vec!["user".to_owned()],
+            Uuid::new_v4(),
+        )
+    }
+
+    fn key_ring(active_kid: &str) -> JWT {
+        JWT::from_secrets(
+            active_kid,
+            &[
+                ("a".to_owned(), SECRET_A.to_owned()),
+                ("b".to_owned(), SECRET_B.to_owned()),
+            ],
+        )
+        .expect("Failed to build key ring")
+    }
+
+    #[test]
+    fn test_sign_and_verify_roundtrip() {
+        let jwt = key_ring("a");
+        let claims = claims();
+
+        let token = jwt.sign(&claims).expect("Failed to sign");
+        let verified = jwt.verify(&token).expect("Failed to verify");
+
+        assert_eq!(verified.sub, claims.sub);
+        assert_eq!(verified.sid, claims.sid);
+        assert_eq!(verified.jti, claims.jti);
+        assert!(verified.has_role("user"));
+    }
+
+    /// tokens signed before a rotation still verify as long as the old key is listed
+    #[test]
+    fn test_rotation_keeps_old_kid_valid() {
+        let token = key_ring("a").sign(&claims()).expect("Failed to sign");
+
+        assert!(key_ring("b").verify(&token).is_ok());
+
+        let rotated_out = JWT::from_secrets("b", &[("b".to_owned(), SECRET_B.to_owned())])
+            .expect("Failed to build key ring");
+        assert_eq!(
+            rotated_out.verify(&token).unwrap_err(),
+            JwtError::BadSignature
+        );
+    }
+
+    #[test]
+    fn test_expired_token_is_rejected() {
+        let jwt = key_ring("a");
+        let mut claims = claims();
+        claims.iat -= 3600;
+        claims.exp = Utc::now().timestamp() - 60;
+
+        let token = jwt.sign(&claims).expect("Failed to sign");
+        assert_eq!(jwt.verify(&token).unwrap_err(), JwtError::Expired);
+    }
+
+    #[test]
+    fn test_bad_signature_is_rejected() {
+        let forged = JWT::from_secrets(
+            "a",
+            &[(
+                "a".to_owned(),
+                "cccccccccccccccccccccccccccccccccccccccc".to_owned(),
+            )],
+        )
+        .expect("Failed to build key ring")
+        .sign(&claims())
+        .expect("Failed to sign");
+
+        assert_eq!(
+            key_ring("a").verify(&forged).unwrap_err(),
+            JwtError::BadSignature
+        );
+    }
+
+    #[test]
+    fn test_malformed_token_is_rejected() {
+        let jwt = key_ring("a");
+        assert_eq!(jwt.verify("not-a-token").unwrap_err(), JwtError::Malformed);
+        assert_eq!(jwt.verify("").unwrap_err(), JwtError::Malformed);
+    }
+}
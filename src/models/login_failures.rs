@@ -0,0 +1,172 @@
+use std::net::IpAddr;
+
+use chrono::{DateTime, Utc};
+use deadpool_postgres::{Object, Transaction};
+use serde_derive::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use tracing::instrument;
+
+use super::{
+    common_traits::{FromRow, FromRows},
+    users::User,
+};
+
+/// recent failed logins against one account or from one client IP; timestamps come from the database clock
+/// so every instance agrees on how long ago the last failure was
+#[derive(Serialize, Deserialize, Debug)]
+pub struct LoginFailure {
+    login_failure_key: String,            // account:<sha256> or ip:<address>.
+    login_failure_count: i32,             // Failures since the counter was reset.
+    login_failure_last_at: DateTime<Utc>, // The time of the most recent failure.
+    login_failure_locked_until: Option<DateTime<Utc>>, // Logins are refused until then.
+    login_failure_elapsed_seconds: f64,   // Seconds since the last failure.
+    login_failure_locked_seconds: f64,    // Seconds left on the lock, 0 if none.
+}
+
+/// computed columns every query returns alongside the row
+const TIMING_COLUMNS: &str = "GREATEST(EXTRACT(EPOCH FROM NOW() - login_failure_last_at), 0)::float8 AS login_failure_elapsed_seconds, COALESCE(GREATEST(EXTRACT(EPOCH FROM login_failure_locked_until - NOW()), 0), 0)::float8 AS login_failure_locked_seconds";
+
+impl FromRow for LoginFailure {
+    fn from_row(row: tokio_postgres::Row) -> LoginFailure {
+        LoginFailure {
+            login_failure_key: row.get::<&str, String>("login_failure_key"),
+            login_failure_count: row.get::<&str, i32>("login_failure_count"),
+            login_failure_last_at: row.get::<&str, DateTime<Utc>>("login_failure_last_at"),
+            login_failure_locked_until: row
+                .get::<&str, Option<DateTime<Utc>>>("login_failure_locked_until"),
+            login_failure_elapsed_seconds: row.get::<&str, f64>("login_failure_elapsed_seconds"),
+            login_failure_locked_seconds: row.get::<&str, f64>("login_failure_locked_seconds"),
+        }
+    }
+}
+
+impl FromRows for LoginFailure {
+    fn from_rows(rows: Vec<tokio_postgres::Row>) -> Vec<Self> {
+        rows.into_iter().map(LoginFailure::from_row).collect()
+    }
+}
+
+impl LoginFailure {
+    /// keyed on the email or screen name as submitted, hashed so the table doesn't collect guesses at them.
+    /// Identifiers that match no account are counted and locked all the same, so a lock gives nothing away
+    pub fn account_key(identifier: &str) -> String {
+        let digest: String = Sha256::digest(identifier.trim().to_lowercase().as_bytes())
+            .iter()
+            .map(|byte| format!("{:02x}", byte))
+            .collect();
+        format!("account:{}", digest)
+    }
+
+    /// the counters of both identifiers a user can sign in with
+    pub fn account_keys(user: &User) -> Vec<String> {
+        vec![
+            LoginFailure::account_key(user.get_email()),
+            LoginFailure::account_key(user.get_screen_name()),
+        ]
+    }
+
+    pub fn ip_key(ip: IpAddr) -> String {
+        format!("ip:{}", ip)
+    }
+
+    #[instrument(name = "LoginFailure::get_by_keys", skip_all)]
+    pub async fn get_by_keys(conn: &Object, keys: &[String]) -> anyhow::Result<Vec<Self>> {
+        let rows = conn
+            .query(
+                &format!(
+                    "SELECT *, {} FROM v1.login_failures WHERE login_failure_key = ANY($1)",
+                    TIMING_COLUMNS
+                ),
+                &[&keys],
+            )
+            .await?;
+        Ok(LoginFailure::from_rows(rows))
+    }
+
+    /// counts one more failure; a counter idle for longer than the window starts over.
+    /// Holds the row lock until the transaction ends, so concurrent failures are counted one after another
+    #[instrument(name = "LoginFailure::record", skip_all)]
+    pub async fn record(
+        conn: &Transaction<'_>,
+        key: &str,
+        window_seconds: f64,
+    ) -> anyhow::Result<Self> {
+        let row = conn
+            .query_one(
+                &format!(
+                    "INSERT INTO v1.login_failures AS f (login_failure_key, login_failure_count, login_failure_last_at) VALUES ($1, 1, NOW()) ON CONFLICT (login_failure_key) DO UPDATE SET login_failure_count = CASE WHEN f.login_failure_last_at < NOW() - make_interval(secs => $2) THEN 1 ELSE f.login_failure_count + 1 END, login_failure_last_at = NOW() RETURNING *, {}",
+                    TIMING_COLUMNS
+                ),
+                &[&key, &window_seconds],
+            )
+            .await?;
+        Ok(LoginFailure::from_row(row))
+    }
+
+    /// refuses logins for the given time; the counter starts over so the lock isn't renewed by the next failure
+    #[instrument(name = "LoginFailure::lock", skip_all)]
+    pub async fn lock(
+        &self,
+        conn: &Transaction<'_>,
+        duration_seconds: f64,
+    ) -> anyhow::Result<DateTime<Utc>> {
+        let row = conn
+            .query_one(
+                "UPDATE v1.login_failures SET login_failure_count = 0, login_failure_locked_until = NOW() + make_interval(secs => $1) WHERE login_failure_key = $2 RETURNING login_failure_locked_until",
+                &[&duration_seconds, &self.login_failure_key],
+            )
+            .await?;
+        Ok(row.get::<&str, DateTime<Utc>>("login_failure_locked_until"))
+    }
+
+    /// forgets the failures and any lock, e.g. after a successful login or an unlock
+    #[instrument(name = "LoginFailure::clear", skip_all)]
+    pub async fn clear(conn: &Transaction<'_>, key: &str) -> anyhow::Result<()> {
+        match conn
+            .execute(
+                "DELETE FROM v1.login_failures WHERE login_failure_key = $1",
+                &[&key],
+            )
+            .await
+        {
+            Ok(_) => Ok(()),
+            Err(e) => Err(anyhow::Error::from(e)),
+        }
+    }
+
+    /// counters idle for longer than the window would start over anyway, so those rows can go once unlocked
+    #[instrument(name = "LoginFailure::delete_stale", skip_all)]
+    pub async fn delete_stale(conn: &Object, window_seconds: f64) -> anyhow::Result<u64> {
+        match conn
+            .execute(
+                "DELETE FROM v1.login_failures WHERE login_failure_last_at < NOW() - make_interval(secs => $1) AND (login_failure_locked_until IS NULL OR login_failure_locked_until <= NOW())",
+                &[&window_seconds],
+            )
+            .await
+        {
+            Ok(deleted) => Ok(deleted),
+            Err(e) => Err(anyhow::Error::from(e)),
+        }
+    }
+
+    pub fn get_key(&self) -> &str {
+        &self.login_failure_key
+    }
+
+    pub fn get_count(&self) -> i32 {
+        self.login_failure_count
+    }
+
+    pub fn get_elapsed_seconds(&self) -> f64 {
+        self.login_failure_elapsed_seconds
+    }
+
+    /// seconds left on the lock; 0 when the key isn't locked
+    pub fn get_locked_seconds(&self) -> f64 {
+        self.login_failure_locked_seconds
+    }
+
+    pub fn is_locked(&self) -> bool {
+        self.login_failure_locked_seconds > 0.0
+    }
+}
@@ -0,0 +1,117 @@
+use chrono::{DateTime, Utc};
+use deadpool_postgres::{Object, Transaction};
+use serde_derive::{Deserialize, Serialize};
+use tracing::instrument;
+use uuid::Uuid;
+
+use super::common_traits::{FromRow, FromRows, ToInsertStmt};
+
+/// audit record of one lockout, kept after the lock ends so admins can see attack patterns
+#[derive(Serialize, Deserialize, Debug)]
+pub struct LoginLockout {
+    login_lockout_id: Uuid,                           // Lockout's primary key.
+    login_lockout_key: String,                        // account:<sha256> or ip:<address>.
+    login_lockout_user_id: Option<Uuid>, // The locked account; None for an IP or unknown name.
+    login_lockout_ip: Option<String>,    // Client IP of the failure that locked it.
+    login_lockout_user_agent: Option<String>, // User agent of that failure.
+    login_lockout_failures: i32,         // Failures counted within the window.
+    login_lockout_locked_until: DateTime<Utc>, // The time when the lock ends on its own.
+    login_lockout_created_at: DateTime<Utc>, // The time when the lock was imposed.
+    login_lockout_unlocked_at: Option<DateTime<Utc>>, // The time the owner lifted it early.
+}
+
+impl FromRow for LoginLockout {
+    fn from_row(row: tokio_postgres::Row) -> LoginLockout {
+        LoginLockout {
+            login_lockout_id: row.get::<&str, Uuid>("login_lockout_id"),
+            login_lockout_key: row.get::<&str, String>("login_lockout_key"),
+            login_lockout_user_id: row.get::<&str, Option<Uuid>>("login_lockout_user_id"),
+            login_lockout_ip: row.get::<&str, Option<String>>("login_lockout_ip"),
+            login_lockout_user_agent: row.get::<&str, Option<String>>("login_lockout_user_agent"),
+            login_lockout_failures: row.get::<&str, i32>("login_lockout_failures"),
+            login_lockout_locked_until: row
+                .get::<&str, DateTime<Utc>>("login_lockout_locked_until"),
+            login_lockout_created_at: row.get::<&str, DateTime<Utc>>("login_lockout_created_at"),
+            login_lockout_unlocked_at: row
+                .get::<&str, Option<DateTime<Utc>>>("login_lockout_unlocked_at"),
+        }
+    }
+}
+
+impl FromRows for LoginLockout {
+    fn from_rows(rows: Vec<tokio_postgres::Row>) -> Vec<Self> {
+        rows.into_iter().map(LoginLockout::from_row).collect()
+    }
+}
+
+impl LoginLockout {
+    /// most recent first
+    #[instrument(name = "LoginLockout::get_recent", skip_all)]
+    pub async fn get_recent(conn: &Object, limit: i64) -> anyhow::Result<Vec<Self>> {
+        let rows = conn
+            .query(
+                "SELECT * FROM v1.login_lockouts ORDER BY login_lockout_created_at DESC LIMIT $1",
+                &[&limit],
+            )
+            .await?;
+        Ok(LoginLockout::from_rows(rows))
+    }
+
+    /// records that the account's owner lifted its current locks
+    #[instrument(name = "LoginLockout::mark_unlocked", skip_all)]
+    pub async fn mark_unlocked(conn: &Transaction<'_>, user_id: Uuid) -> anyhow::Result<u64> {
+        match conn
+            .execute(
+                "UPDATE v1.login_lockouts SET login_lockout_unlocked_at = NOW() WHERE login_lockout_user_id = $1 AND login_lockout_unlocked_at IS NULL AND login_lockout_locked_until > NOW()",
+                &[&user_id],
+            )
+            .await
+        {
+            Ok(count) => Ok(count),
+            Err(e) => Err(anyhow::Error::from(e)),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct LoginLockoutForm {
+    pub login_lockout_key: String,
+    pub login_lockout_user_id: Option<Uuid>,
+    pub login_lockout_ip: Option<String>,
+    pub login_lockout_user_agent: Option<String>,
+    pub login_lockout_failures: i32,
+    pub login_lockout_locked_until: DateTime<Utc>,
+}
+
+impl ToInsertStmt for LoginLockoutForm {
+    fn to_insert_stmt() -> String {
+        String::from(
+            "INSERT INTO v1.login_lockouts (login_lockout_key, login_lockout_user_id, login_lockout_ip, login_lockout_user_agent, login_lockout_failures, login_lockout_locked_until, login_lockout_created_at) VALUES ($1, $2, $3, $4, $5, $6, $7) RETURNING *",
+        )
+    }
+}
+
+impl LoginLockoutForm {
+    #[instrument(name = "LoginLockoutForm::insert", skip_all)]
+    pub async fn insert(&self, conn: &Transaction<'_>) -> anyhow::Result<LoginLockout> {
+        let now = Utc::now();
+        match conn
+            .query_one(
+                &LoginLockoutForm::to_insert_stmt(),
+                &[
+                    &self.login_lockout_key,
+                    &self.login_lockout_user_id,
+                    &self.login_lockout_ip,
+                    &self.login_lockout_user_agent,
+                    &self.login_lockout_failures,
+                    &self.login_lockout_locked_until,
+                    &now,
+                ],
+            )
+            .await
+        {
+            Ok(row) => Ok(LoginLockout::from_row(row)),
+            Err(e) => Err(anyhow::Error::from(e)),
+        }
+    }
+}
@@ -0,0 +1,152 @@
+use chrono::{DateTime, Utc};
+use deadpool_postgres::{Object, Transaction};
+use serde_derive::{Deserialize, Serialize};
+use tracing::instrument;
+use uuid::Uuid;
+
+use super::common_traits::{FromRow, ToInsertStmt};
+
+/// random bytes a WebAuthn ceremony has to sign over, redeemable once for the purpose it was issued for
+#[derive(Serialize, Deserialize)]
+pub struct PasskeyChallenge {
+    passkey_challenge_id: Uuid,                  // Challenge's primary key.
+    passkey_challenge_user_id: Option<Uuid>, // The user it was issued to; None for passkey login.
+    passkey_challenge_purpose: String,       // The ceremony it was issued for.
+    passkey_challenge_value: Vec<u8>,        // The challenge bytes themselves.
+    passkey_challenge_created_at: DateTime<Utc>, // The time when it was issued.
+    passkey_challenge_expires_at: DateTime<Utc>, // The time when the ceremony times out.
+    passkey_challenge_used: bool,            // Whether a response was accepted for it.
+}
+
+impl FromRow for PasskeyChallenge {
+    fn from_row(row: tokio_postgres::Row) -> PasskeyChallenge {
+        PasskeyChallenge {
+            passkey_challenge_id: row.get::<&str, Uuid>("passkey_challenge_id"),
+            passkey_challenge_user_id: row.get::<&str, Option<Uuid>>("passkey_challenge_user_id"),
+            passkey_challenge_purpose: row.get::<&str, String>("passkey_challenge_purpose"),
+            passkey_challenge_value: row.get::<&str, Vec<u8>>("passkey_challenge_value"),
+            passkey_challenge_created_at: row
+                .get::<&str, DateTime<Utc>>("passkey_challenge_created_at"),
+            passkey_challenge_expires_at: row
+                .get::<&str, DateTime<Utc>>("passkey_challenge_expires_at"),
+            passkey_challenge_used: row.get::<&str, bool>("passkey_challenge_used"),
+        }
+    }
+}
+
+impl PasskeyChallenge {
+    #[instrument(name = "PasskeyChallenge::get_by_id", skip_all)]
+    pub async fn get_by_id(
+        conn: &Object,
+        passkey_challenge_id: Uuid,
+    ) -> anyhow::Result<Option<Self>> {
+        match conn
+            .query_opt(
+                "SELECT * FROM v1.passkey_challenges WHERE passkey_challenge_id = $1",
+                &[&passkey_challenge_id],
+            )
+            .await
+        {
+            Ok(Some(row)) => Ok(Some(PasskeyChallenge::from_row(row))),
+            Ok(None) => Ok(None),
+            Err(e) => Err(anyhow::Error::from(e)),
+        }
+    }
+
+    /// marks the challenge as answered; returns false if another response got to it first
+    #[instrument(name = "PasskeyChallenge::mark_used", skip_all)]
+    pub async fn mark_used(&self, conn: &Transaction<'_>) -> anyhow::Result<bool> {
+        match conn
+            .execute(
+                "UPDATE v1.passkey_challenges SET passkey_challenge_used = true WHERE passkey_challenge_id = $1 AND passkey_challenge_used = false",
+                &[&self.passkey_challenge_id],
+            )
+            .await
+        {
+            Ok(count) => Ok(count == 1),
+            Err(e) => Err(anyhow::Error::from(e)),
+        }
+    }
+
+    /// challenges are useless once their ceremony times out, so they are cleared as new ones are issued
+    #[instrument(name = "PasskeyChallenge::delete_expired", skip_all)]
+    pub async fn delete_expired(conn: &Transaction<'_>) -> anyhow::Result<u64> {
+        match conn
+            .execute(
+                "DELETE FROM v1.passkey_challenges WHERE passkey_challenge_expires_at < NOW()",
+                &[],
+            )
+            .await
+        {
+            Ok(count) => Ok(count),
+            Err(e) => Err(anyhow::Error::from(e)),
+        }
+    }
+
+    pub fn get_id(&self) -> Uuid {
+        self.passkey_challenge_id
+    }
+
+    pub fn get_user_id(&self) -> Option<Uuid> {
+        self.passkey_challenge_user_id
+    }
+
+    pub fn get_purpose(&self) -> &str {
+        &self.passkey_challenge_purpose
+    }
+
+    pub fn get_value(&self) -> &[u8] {
+        &self.passkey_challenge_value
+    }
+
+    pub fn is_used(&self) -> bool {
+        self.passkey_challenge_used
+    }
+
+    pub fn is_expired(&self) -> bool {
+        self.passkey_challenge_expires_at < Utc::now()
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct PasskeyChallengeForm {
+    pub passkey_challenge_user_id: Option<Uuid>,
+    pub passkey_challenge_purpose: String,
+    pub passkey_challenge_value: Vec<u8>,
+    pub passkey_challenge_expires_at: DateTime<Utc>,
+}
+
+impl ToInsertStmt for PasskeyChallengeForm {
+    fn to_insert_stmt() -> String {
+        String::from(
+            "INSERT INTO v1.passkey_challenges (passkey_challenge_user_id, passkey_challenge_purpose, passkey_challenge_value, passkey_challenge_created_at, passkey_challenge_expires_at) VALUES ($1, $2, $3, $4, $5) RETURNING *",
+        )
+    }
+}
+
+impl PasskeyChallengeForm {
+    #[instrument(name = "PasskeyChallengeForm::insert", skip_all)]
+    pub async fn insert(&self, conn: &Transaction<'_>) -> anyhow::Result<PasskeyChallenge> {
+        let now = Utc::now();
+        match conn
+            .query_one(
+                &PasskeyChallengeForm::to_insert_stmt(),
+                &[
+                    &self.passkey_challenge_user_id,
+                    &self.passkey_challenge_purpose,
+                    &self.passkey_challenge_value,
+                    &now,
+                    &self.passkey_challenge_expires_at,
+                ],
+            )
+            .await
+        {
+            Ok(row) => Ok(PasskeyChallenge::from_row(row)),
+            Err(e) => Err(anyhow::Error::from(e)),
+        }
+    }
+}
+
+pub const PASSKEY_REGISTRATION: &str = "PASSKEY_REGISTRATION";
+pub const PASSKEY_LOGIN: &str = "PASSKEY_LOGIN";
+pub const PASSKEY_SECOND_FACTOR: &str = "PASSKEY_SECOND_FACTOR";
@@ -0,0 +1,200 @@
+use chrono::{DateTime, Utc};
+use deadpool_postgres::{Object, Transaction};
+use serde_derive::{Deserialize, Serialize};
+use tracing::instrument;
+use uuid::Uuid;
+
+use super::common_traits::{FromRow, FromRows, ToInsertStmt};
+
+/// a WebAuthn credential registered to a user, usable as a primary login or a second factor
+#[derive(Serialize, Deserialize, Debug)]
+pub struct Passkey {
+    passkey_id: Uuid,                            // Passkey's primary key.
+    passkey_user_id: Uuid,                       // The user the passkey signs in as.
+    passkey_credential_id: Vec<u8>,              // Credential ID chosen by the authenticator.
+    passkey_public_key: Vec<u8>,                 // COSE_Key the assertions are checked with.
+    passkey_algorithm: i32,                      // COSE algorithm of the key.
+    passkey_sign_count: i64,                     // Highest signature counter seen.
+    passkey_backup_eligible: bool,               // Whether it syncs between devices.
+    passkey_name: String,                        // Label the user gave it.
+    passkey_created_at: DateTime<Utc>,           // The time when it was registered.
+    passkey_last_used_at: Option<DateTime<Utc>>, // The time it last signed in.
+}
+
+impl FromRow for Passkey {
+    fn from_row(row: tokio_postgres::Row) -> Passkey {
+        Passkey {
+            passkey_id: row.get::<&str, Uuid>("passkey_id"),
+            passkey_user_id: row.get::<&str, Uuid>("passkey_user_id"),
+            passkey_credential_id: row.get::<&str, Vec<u8>>("passkey_credential_id"),
+            passkey_public_key: row.get::<&str, Vec<u8>>("passkey_public_key"),
+            passkey_algorithm: row.get::<&str, i32>("passkey_algorithm"),
+            passkey_sign_count: row.get::<&str, i64>("passkey_sign_count"),
+            passkey_backup_eligible: row.get::<&str, bool>("passkey_backup_eligible"),
+            passkey_name: row.get::<&str, String>("passkey_name"),
+            passkey_created_at: row.get::<&str, DateTime<Utc>>("passkey_created_at"),
+            passkey_last_used_at: row.get::<&str, Option<DateTime<Utc>>>("passkey_last_used_at"),
+        }
+    }
+}
+
+impl FromRows for Passkey {
+    fn from_rows(rows: Vec<tokio_postgres::Row>) -> Vec<Self> {
+        rows.into_iter().map(Passkey::from_row).collect()
+    }
+}
+
+impl Passkey {
+    #[instrument(name = "Passkey::get_by_user_id", skip_all)]
+    pub async fn get_by_user_id(conn: &Object, user_id: Uuid) -> anyhow::Result<Vec<Self>> {
+        let rows = conn
+            .query(
+                "SELECT * FROM v1.passkeys WHERE passkey_user_id = $1 ORDER BY passkey_created_at",
+                &[&user_id],
+            )
+            .await?;
+        Ok(Passkey::from_rows(rows))
+    }
+
+    #[instrument(name = "Passkey::get_by_credential_id", skip_all)]
+    pub async fn get_by_credential_id(
+        conn: &Object,
+        credential_id: &[u8],
+    ) -> anyhow::Result<Option<Self>> {
+        match conn
+            .query_opt(
+                "SELECT * FROM v1.passkeys WHERE passkey_credential_id = $1",
+                &[&credential_id],
+            )
+            .await
+        {
+            Ok(Some(row)) => Ok(Some(Passkey::from_row(row))),
+            Ok(None) => Ok(None),
+            Err(e) => Err(anyhow::Error::from(e)),
+        }
+    }
+
+    /// records a sign-in with the authenticator's new counter. Returns false if the counter did not go up,
+    /// which means the credential was cloned; authenticators that keep no counter always report 0
+    #[instrument(name = "Passkey::update_sign_count", skip_all)]
+    pub async fn update_sign_count(
+        &self,
+        conn: &Transaction<'_>,
+        sign_count: i64,
+    ) -> anyhow::Result<bool> {
+        match conn
+            .execute(
+                "UPDATE v1.passkeys SET passkey_sign_count = $2, passkey_last_used_at = NOW() WHERE passkey_id = $1 AND (passkey_sign_count < $2 OR (passkey_sign_count = 0 AND $2 = 0))",
+                &[&self.passkey_id, &sign_count],
+            )
+            .await
+        {
+            Ok(count) => Ok(count == 1),
+            Err(e) => Err(anyhow::Error::from(e)),
+        }
+    }
+
+    /// only deletes the user's own passkey; returns false if they have none with this ID
+    #[instrument(name = "Passkey::delete_for_user", skip_all)]
+    pub async fn delete_for_user(
+        conn: &Transaction<'_>,
+        user_id: Uuid,
+        passkey_id: Uuid,
+    ) -> anyhow::Result<bool> {
+        match conn
+            .execute(
+                "DELETE FROM v1.passkeys WHERE passkey_id = $1 AND passkey_user_id = $2",
+                &[&passkey_id, &user_id],
+            )
+            .await
+        {
+            Ok(count) => Ok(count == 1),
+            Err(e) => Err(anyhow::Error::from(e)),
+        }
+    }
+
+    pub fn get_id(&self) -> Uuid {
+        self.passkey_id
+    }
+
+    pub fn get_user_id(&self) -> Uuid {
+        self.passkey_user_id
+    }
+
+    pub fn get_credential_id(&self) -> &[u8] {
+        &self.passkey_credential_id
+    }
+
+    pub fn get_public_key(&self) -> &[u8] {
+        &self.passkey_public_key
+    }
+}
+
+/// what a user sees of their passkeys
+#[derive(Serialize, Deserialize)]
+pub struct PasskeyTruncated {
+    passkey_id: Uuid,
+    passkey_name: String,
+    passkey_backup_eligible: bool,
+    passkey_created_at: DateTime<Utc>,
+    passkey_last_used_at: Option<DateTime<Utc>>,
+}
+
+impl From<Passkey> for PasskeyTruncated {
+    fn from(passkey: Passkey) -> Self {
+        PasskeyTruncated {
+            passkey_id: passkey.passkey_id,
+            passkey_name: passkey.passkey_name,
+            passkey_backup_eligible: passkey.passkey_backup_eligible,
+            passkey_created_at: passkey.passkey_created_at,
+            passkey_last_used_at: passkey.passkey_last_used_at,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct PasskeyForm {
+    pub passkey_user_id: Uuid,
+    pub passkey_credential_id: Vec<u8>,
+    pub passkey_public_key: Vec<u8>,
+    pub passkey_algorithm: i32,
+    pub passkey_sign_count: i64,
+    pub passkey_backup_eligible: bool,
+    pub passkey_name: String,
+}
+
+impl ToInsertStmt for PasskeyForm {
+    /// a credential ID can only be registered once, to whichever account got it first
+    fn to_insert_stmt() -> String {
+        String::from(
+            "INSERT INTO v1.passkeys (passkey_user_id, passkey_credential_id, passkey_public_key, passkey_algorithm, passkey_sign_count, passkey_backup_eligible, passkey_name, passkey_created_at) VALUES ($1, $2, $3, $4, $5, $6, $7, $8) ON CONFLICT (passkey_credential_id) DO NOTHING RETURNING *",
+        )
+    }
+}
+
+impl PasskeyForm {
+    /// None when the credential is already registered
+    #[instrument(name = "PasskeyForm::insert", skip_all)]
+    pub async fn insert(&self, conn: &Transaction<'_>) -> anyhow::Result<Option<Passkey>> {
+        let now = Utc::now();
+        match conn
+            .query_opt(
+                &PasskeyForm::to_insert_stmt(),
+                &[
+                    &self.passkey_user_id,
+                    &self.passkey_credential_id,
+                    &self.passkey_public_key,
+                    &self.passkey_algorithm,
+                    &self.passkey_sign_count,
+                    &self.passkey_backup_eligible,
+                    &self.passkey_name,
+                    &now,
+                ],
+            )
+            .await
+        {
+            Ok(row) => Ok(row.map(Passkey::from_row)),
+            Err(e) => Err(anyhow::Error::from(e)),
+        }
+    }
+}
@@ -0,0 +1,104 @@
+use chrono::{DateTime, Utc};
+use deadpool_postgres::{Object, Transaction};
+use serde_derive::{Deserialize, Serialize};
+use tracing::instrument;
+
+use super::common_traits::{FromRow, FromRows};
+
+/// a token bucket of the shared rate limit store; timestamps come from the database clock so every instance agrees
+#[derive(Serialize, Deserialize, Debug)]
+pub struct RateLimitBucket {
+    rate_limit_bucket_key: String, // Group and subject, e.g. email:ip:203.0.113.7.
+    rate_limit_bucket_tokens: f64, // Tokens left as of the last update.
+    rate_limit_bucket_updated_at: DateTime<Utc>, // The time when a token was last taken.
+    rate_limit_bucket_full_at: DateTime<Utc>, // The time when the bucket is full again.
+    rate_limit_bucket_elapsed_seconds: f64, // Seconds since the last update, as of the query.
+}
+
+impl FromRow for RateLimitBucket {
+    fn from_row(row: tokio_postgres::Row) -> RateLimitBucket {
+        RateLimitBucket {
+            rate_limit_bucket_key: row.get::<&str, String>("rate_limit_bucket_key"),
+            rate_limit_bucket_tokens: row.get::<&str, f64>("rate_limit_bucket_tokens"),
+            rate_limit_bucket_updated_at: row
+                .get::<&str, DateTime<Utc>>("rate_limit_bucket_updated_at"),
+            rate_limit_bucket_full_at: row.get::<&str, DateTime<Utc>>("rate_limit_bucket_full_at"),
+            rate_limit_bucket_elapsed_seconds: row
+                .get::<&str, f64>("rate_limit_bucket_elapsed_seconds"),
+        }
+    }
+}
+
+impl FromRows for RateLimitBucket {
+    fn from_rows(rows: Vec<tokio_postgres::Row>) -> Vec<Self> {
+        rows.into_iter().map(RateLimitBucket::from_row).collect()
+    }
+}
+
+impl RateLimitBucket {
+    /// creates a full bucket for a new key, then locks the row until the transaction ends
+    #[instrument(name = "RateLimitBucket::lock_or_create", skip_all)]
+    pub async fn lock_or_create(
+        conn: &Transaction<'_>,
+        key: &str,
+        burst: f64,
+    ) -> anyhow::Result<Self> {
+        conn.execute(
+            "INSERT INTO v1.rate_limit_buckets (rate_limit_bucket_key, rate_limit_bucket_tokens, rate_limit_bucket_updated_at, rate_limit_bucket_full_at) VALUES ($1, $2, clock_timestamp(), clock_timestamp()) ON CONFLICT (rate_limit_bucket_key) DO NOTHING",
+            &[&key, &burst],
+        )
+        .await?;
+
+        // clock_timestamp() rather than NOW(), which would be the time this transaction started, before any wait for the lock
+        let row = conn
+            .query_one(
+                "SELECT *, GREATEST(EXTRACT(EPOCH FROM clock_timestamp() - rate_limit_bucket_updated_at), 0)::float8 AS rate_limit_bucket_elapsed_seconds FROM v1.rate_limit_buckets WHERE rate_limit_bucket_key = $1 FOR UPDATE",
+                &[&key],
+            )
+            .await?;
+        Ok(RateLimitBucket::from_row(row))
+    }
+
+    /// stores what is left after taking a token; `full_in_seconds` is how long until the bucket refills
+    #[instrument(name = "RateLimitBucket::save", skip_all)]
+    pub async fn save(
+        &self,
+        conn: &Transaction<'_>,
+        tokens: f64,
+        full_in_seconds: f64,
+    ) -> anyhow::Result<()> {
+        match conn
+            .execute(
+                "UPDATE v1.rate_limit_buckets SET rate_limit_bucket_tokens = $1, rate_limit_bucket_updated_at = clock_timestamp(), rate_limit_bucket_full_at = clock_timestamp() + make_interval(secs => $2) WHERE rate_limit_bucket_key = $3",
+                &[&tokens, &full_in_seconds, &self.rate_limit_bucket_key],
+            )
+            .await
+        {
+            Ok(_) => Ok(()),
+            Err(e) => Err(anyhow::Error::from(e)),
+        }
+    }
+
+    /// a full bucket is the same as no bucket, so those rows can go
+    #[instrument(name = "RateLimitBucket::delete_full", skip_all)]
+    pub async fn delete_full(conn: &Object) -> anyhow::Result<u64> {
+        match conn
+            .execute(
+                "DELETE FROM v1.rate_limit_buckets WHERE rate_limit_bucket_full_at <= clock_timestamp()",
+                &[],
+            )
+            .await
+        {
+            Ok(deleted) => Ok(deleted),
+            Err(e) => Err(anyhow::Error::from(e)),
+        }
+    }
+
+    pub fn get_tokens(&self) -> f64 {
+        self.rate_limit_bucket_tokens
+    }
+
+    pub fn get_elapsed_seconds(&self) -> f64 {
+        self.rate_limit_bucket_elapsed_seconds
+    }
+}
@@ -0,0 +1,128 @@
+use chrono::{DateTime, Utc};
+use deadpool_postgres::{Object, Transaction};
+use serde_derive::{Deserialize, Serialize};
+use tracing::instrument;
+use uuid::Uuid;
+
+use super::common_traits::{FromRow, FromRows, ToInsertStmt};
+
+/// a one-time code that stands in for a TOTP code when the authenticator is lost; only its argon2 hash is kept
+#[derive(Serialize, Deserialize)]
+pub struct UserRecoveryCode {
+    user_recovery_code_id: Uuid,                       // Code's primary key.
+    user_recovery_code_user_id: Uuid,                  // The user the code was issued to.
+    user_recovery_code_hash: String,                   // Argon2 hash of the normalized code.
+    user_recovery_code_selector: String,               // Its first letters, kept in the clear.
+    user_recovery_code_created_at: DateTime<Utc>,      // The time when the code was issued.
+    user_recovery_code_used_at: Option<DateTime<Utc>>, // The time it was spent on a login.
+}
+
+impl FromRow for UserRecoveryCode {
+    fn from_row(row: tokio_postgres::Row) -> UserRecoveryCode {
+        UserRecoveryCode {
+            user_recovery_code_id: row.get::<&str, Uuid>("user_recovery_code_id"),
+            user_recovery_code_user_id: row.get::<&str, Uuid>("user_recovery_code_user_id"),
+            user_recovery_code_hash: row.get::<&str, String>("user_recovery_code_hash"),
+            user_recovery_code_selector: row.get::<&str, String>("user_recovery_code_selector"),
+            user_recovery_code_created_at: row
+                .get::<&str, DateTime<Utc>>("user_recovery_code_created_at"),
+            user_recovery_code_used_at: row
+                .get::<&str, Option<DateTime<Utc>>>("user_recovery_code_used_at"),
+        }
+    }
+}
+
+impl FromRows for UserRecoveryCode {
+    fn from_rows(rows: Vec<tokio_postgres::Row>) -> Vec<Self> {
+        rows.into_iter().map(UserRecoveryCode::from_row).collect()
+    }
+}
+
+impl UserRecoveryCode {
+    /// the user's unused codes a submission could be: almost always just the one with its selector
+    #[instrument(name = "UserRecoveryCode::get_unused_by_selector", skip_all)]
+    pub async fn get_unused_by_selector(
+        conn: &Object,
+        user_id: Uuid,
+        selector: &str,
+    ) -> anyhow::Result<Vec<Self>> {
+        let rows = conn
+            .query(
+                "SELECT * FROM v1.user_recovery_codes WHERE user_recovery_code_user_id = $1 AND user_recovery_code_used_at IS NULL AND user_recovery_code_selector = $2 ORDER BY user_recovery_code_created_at",
+                &[&user_id, &selector],
+            )
+            .await?;
+        Ok(UserRecoveryCode::from_rows(rows))
+    }
+
+    /// spends the code; returns false if a concurrent login spent it first
+    #[instrument(name = "UserRecoveryCode::mark_used", skip_all)]
+    pub async fn mark_used(&self, conn: &Transaction<'_>) -> anyhow::Result<bool> {
+        match conn
+            .execute(
+                "UPDATE v1.user_recovery_codes SET user_recovery_code_used_at = NOW() WHERE user_recovery_code_id = $1 AND user_recovery_code_used_at IS NULL",
+                &[&self.user_recovery_code_id],
+            )
+            .await
+        {
+            Ok(count) => Ok(count == 1),
+            Err(e) => Err(anyhow::Error::from(e)),
+        }
+    }
+
+    /// drops the user's previous set, spent or not, before a new one is issued
+    #[instrument(name = "UserRecoveryCode::delete_by_user_id", skip_all)]
+    pub async fn delete_by_user_id(conn: &Transaction<'_>, user_id: Uuid) -> anyhow::Result<u64> {
+        match conn
+            .execute(
+                "DELETE FROM v1.user_recovery_codes WHERE user_recovery_code_user_id = $1",
+                &[&user_id],
+            )
+            .await
+        {
+            Ok(count) => Ok(count),
+            Err(e) => Err(anyhow::Error::from(e)),
+        }
+    }
+
+    pub fn get_hash(&self) -> &str {
+        &self.user_recovery_code_hash
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct UserRecoveryCodeForm {
+    pub user_recovery_code_user_id: Uuid,
+    pub user_recovery_code_hash: String,
+    pub user_recovery_code_selector: String,
+}
+
+impl ToInsertStmt for UserRecoveryCodeForm {
+    fn to_insert_stmt() -> String {
+        String::from(
+            "INSERT INTO v1.user_recovery_codes (user_recovery_code_user_id, user_recovery_code_hash, user_recovery_code_selector, user_recovery_code_created_at) VALUES ($1, $2, $3, $4) RETURNING *",
+        )
+    }
+}
+
+impl UserRecoveryCodeForm {
+    #[instrument(name = "UserRecoveryCodeForm::insert", skip_all)]
+    pub async fn insert(&self, conn: &Transaction<'_>) -> anyhow::Result<UserRecoveryCode> {
+        let now = Utc::now();
+        match conn
+            .query_one(
+                &UserRecoveryCodeForm::to_insert_stmt(),
+                &[
+                    &self.user_recovery_code_user_id,
+                    &self.user_recovery_code_hash,
+                    &self.user_recovery_code_selector,
+                    &now,
+                ],
+            )
+            .await
+        {
+            Ok(row) => Ok(UserRecoveryCode::from_row(row)),
+            Err(e) => Err(anyhow::Error::from(e)),
+        }
+    }
+}
@@ -0,0 +1,231 @@
+use chrono::{DateTime, Utc};
+use deadpool_postgres::{Object, Transaction};
+use serde_derive::{Deserialize, Serialize};
+use tracing::instrument;
+use uuid::Uuid;
+
+use super::common_traits::{FromRow, FromRows, ToInsertStmt};
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct UserSession {
+    user_session_id: Uuid,                    // Session's primary key.
+    user_session_user_id: Uuid,               // Reference to the user this session belongs to.
+    user_session_token: Uuid, // The secret handed to the client as the session credential.
+    user_session_created_at: DateTime<Utc>, // The time when the session was issued.
+    user_session_last_used_at: DateTime<Utc>, // The last time the session was used to authenticate.
+    user_session_expires_at: DateTime<Utc>, // The time when the session will expire and become invalid.
+    user_session_revoked: bool, // Indicates whether the session was revoked (logout etc.).
+    user_session_user_agent: Option<String>, // User agent of the client that logged in.
+    user_session_ip: Option<String>, // IP address of the client that logged in.
+}
+
+impl FromRow for UserSession {
+    fn from_row(row: tokio_postgres::Row) -> UserSession {
+        UserSession {
+            user_session_id: row.get::<&str, Uuid>("user_session_id"),
+            user_session_user_id: row.get::<&str, Uuid>("user_session_user_id"),
+            user_session_token: row.get::<&str, Uuid>("user_session_token"),
+            user_session_created_at: row.get::<&str, DateTime<Utc>>("user_session_created_at"),
+            user_session_last_used_at: row.get::<&str, DateTime<Utc>>("user_session_last_used_at"),
+            user_session_expires_at: row.get::<&str, DateTime<Utc>>("user_session_expires_at"),
+            user_session_revoked: row.get::<&str, bool>("user_session_revoked"),
+            user_session_user_agent: row.get::<&str, Option<String>>("user_session_user_agent"),
+            user_session_ip: row.get::<&str, Option<String>>("user_session_ip"),
+        }
+    }
+}
+
+impl FromRows for UserSession {
+    fn from_rows(rows: Vec<tokio_postgres::Row>) -> Vec<Self> {
+        rows.into_iter().map(UserSession::from_row).collect()
+    }
+}
+
+impl UserSession {
+    #[instrument(name = "UserSession::get_by_token", skip_all)]
+    pub async fn get_by_token(
+        conn: &Object,
+        user_session_token: Uuid,
+    ) -> anyhow::Result<Option<Self>> {
+        match conn
+            .query_opt(
+                "SELECT * FROM v1.user_sessions WHERE user_session_token = $1",
+                &[&user_session_token],
+            )
+            .await
+        {
+            Ok(Some(row)) => Ok(Some(UserSession::from_row(row))),
+            Ok(None) => Ok(None),
+            Err(e) => Err(anyhow::Error::from(e)),
+        }
+    }
+
+    #[instrument(name = "UserSession::get_by_id", skip_all)]
+    pub async fn get_by_id(conn: &Object, user_session_id: Uuid) -> anyhow::Result<Option<Self>> {
+        match conn
+            .query_opt(
+                "SELECT * FROM v1.user_sessions WHERE user_session_id = $1",
+                &[&user_session_id],
+            )
+            .await
+        {
+            Ok(Some(row)) => Ok(Some(UserSession::from_row(row))),
+            Ok(None) => Ok(None),
+            Err(e) => Err(anyhow::Error::from(e)),
+        }
+    }
+
+    /// sessions that can still authenticate, most recently used first
+    #[instrument(name = "UserSession::get_active_by_user_id", skip_all)]
+    pub async fn get_active_by_user_id(conn: &Object, user_id: Uuid) -> anyhow::Result<Vec<Self>> {
+        let rows = conn
+            .query(
+                "SELECT * FROM v1.user_sessions WHERE user_session_user_id = $1 AND user_session_revoked = false AND user_session_expires_at > NOW() ORDER BY user_session_last_used_at DESC",
+                &[&user_id],
+            )
+            .await?;
+        Ok(UserSession::from_rows(rows))
+    }
+
+    #[instrument(name = "UserSession::touch", skip_all)]
+    pub async fn touch(&self, conn: &Object) -> anyhow::Result<()> {
+        match conn
+            .execute(
+                "UPDATE v1.user_sessions SET user_session_last_used_at = NOW() WHERE user_session_id = $1",
+                &[&self.user_session_id],
+            )
+            .await
+        {
+            Ok(_) => Ok(()),
+            Err(e) => Err(anyhow::Error::from(e)),
+        }
+    }
+
+    #[instrument(name = "UserSession::revoke", skip_all)]
+    pub async fn revoke(conn: &Transaction<'_>, user_session_id: Uuid) -> anyhow::Result<u64> {
+        match conn
+            .execute(
+                "UPDATE v1.user_sessions SET user_session_revoked = true WHERE user_session_id = $1 AND user_session_revoked = false",
+                &[&user_session_id],
+            )
+            .await
+        {
+            Ok(count) => Ok(count),
+            Err(e) => Err(anyhow::Error::from(e)),
+        }
+    }
+
+    #[instrument(name = "UserSession::revoke_all_for_user", skip_all)]
+    pub async fn revoke_all_for_user(conn: &Transaction<'_>, user_id: Uuid) -> anyhow::Result<u64> {
+        match conn
+            .execute(
+                "UPDATE v1.user_sessions SET user_session_revoked = true WHERE user_session_user_id = $1 AND user_session_revoked = false",
+                &[&user_id],
+            )
+            .await
+        {
+            Ok(count) => Ok(count),
+            Err(e) => Err(anyhow::Error::from(e)),
+        }
+    }
+
+    /// a session can authenticate requests only while it is neither revoked nor expired
+    pub fn is_valid(&self) -> bool {
+        !self.is_revoked() && !self.is_expired()
+    }
+
+    pub fn get_id(&self) -> Uuid {
+        self.user_session_id
+    }
+
+    pub fn get_user_id(&self) -> Uuid {
+        self.user_session_user_id
+    }
+
+    pub fn get_token(&self) -> Uuid {
+        self.user_session_token
+    }
+
+    pub fn get_expired_time(&self) -> DateTime<Utc> {
+        self.user_session_expires_at
+    }
+
+    pub fn is_revoked(&self) -> bool {
+        self.user_session_revoked
+    }
+
+    pub fn is_expired(&self) -> bool {
+        self.user_session_expires_at < Utc::now()
+    }
+}
+
+/// session as shown to its owner; never carries the session token
+#[derive(Serialize, Deserialize, Debug)]
+pub struct UserSessionTruncated {
+    user_session_id: Uuid,
+    user_session_created_at: DateTime<Utc>,
+    user_session_last_used_at: DateTime<Utc>,
+    user_session_expires_at: DateTime<Utc>,
+    user_session_user_agent: Option<String>,
+    user_session_ip: Option<String>,
+}
+
+impl From<UserSession> for UserSessionTruncated {
+    fn from(session: UserSession) -> Self {
+        UserSessionTruncated {
+            user_session_id: session.user_session_id,
+            user_session_created_at: session.user_session_created_at,
+            user_session_last_used_at: session.user_session_last_used_at,
+            user_session_expires_at: session.user_session_expires_at,
+            user_session_user_agent: session.user_session_user_agent,
+            user_session_ip: session.user_session_ip,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct UserSessionForm {
+    pub user_session_user_id: Uuid,
+    pub user_session_expires_at: DateTime<Utc>,
+    pub user_session_user_agent: Option<String>,
+    pub user_session_ip: Option<String>,
+}
+
+impl ToInsertStmt for UserSessionForm {
+    fn to_insert_stmt() -> String {
+        String::from(
+            "INSERT INTO v1.user_sessions (user_session_user_id, user_session_token, user_session_created_at, user_session_last_used_at, user_session_expires_at, user_session_revoked, user_session_user_agent, user_session_ip) VALUES ($1, $2, $3, $4, $5, $6, $7, $8) RETURNING *",
+        )
+    }
+}
+
+impl UserSessionForm {
+    #[instrument(name = "UserSessionForm::insert", skip_all)]
+    pub async fn insert(&self, conn: &Transaction<'_>) -> anyhow::Result<UserSession> {
+        let now = Utc::now();
+        let user_session_token = Uuid::new_v4();
+        let user_session_revoked = false;
+        match conn
+            .query_one(
+                &UserSessionForm::to_insert_stmt(),
+                &[
+                    &self.user_session_user_id,
+                    &user_session_token,
+                    &now,
+                    &now,
+                    &self.user_session_expires_at,
+                    &user_session_revoked,
+                    &self.user_session_user_agent,
+                    &self.user_session_ip,
+                ],
+            )
+            .await
+        {
+            Ok(row) => Ok(UserSession::from_row(row)),
+            Err(e) => Err(anyhow::Error::from(e)),
+        }
+    }
+}
+
+pub const SESSION_DURATION_DAYS: i64 = 30;
+pub const SESSION_COOKIE_NAME: &str = "session";
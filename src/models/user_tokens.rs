@@ -2,9 +2,13 @@ use anyhow::anyhow;
 use chrono::{DateTime, Utc};
 use deadpool_postgres::{Object, Transaction};
 use serde_derive::{Deserialize, Serialize};
+use tracing::instrument;
 use uuid::Uuid;
 
-use super::common_traits::{FromRow, FromRows, ToInsertStmt};
+use super::{
+    common_traits::{FromRow, FromRows, ToInsertStmt},
+    user_sessions::UserSession,
+};
 
 #[derive(Serialize, Deserialize)]
 pub struct UserToken {
@@ -15,6 +19,7 @@ pub struct UserToken {
     user_token_created_at: DateTime<Utc>, // The time when the token was generated.
     user_token_expires_at: DateTime<Utc>, // The time when the token will expire and become invalid.
     user_token_used: bool,                // Indicates whether the token has been used or redeemed.
+    user_token_session_id: Option<Uuid>,  // The session (token family) a refresh token belongs to.
 }
 
 impl FromRow for UserToken {
@@ -27,6 +32,7 @@ impl FromRow for UserToken {
             user_token_created_at: row.get::<&str, DateTime<Utc>>("user_token_created_at"),
             user_token_expires_at: row.get::<&str, DateTime<Utc>>("user_token_expires_at"),
             user_token_used: row.get::<&str, bool>("user_token_used"),
+            user_token_session_id: row.get::<&str, Option<Uuid>>("user_token_session_id"),
         }
     }
 }
@@ -38,6 +44,7 @@ impl FromRows for UserToken {
 }
 
 impl UserToken {
+    #[instrument(name = "UserToken::get_by_id", skip_all)]
     pub async fn get_by_id(conn: &Object, user_token_id: Uuid) -> anyhow::Result<Option<Self>> {
         match conn
             .query_opt(
@@ -52,6 +59,119 @@ impl UserToken {
         }
     }
 
+    #[instrument(name = "UserToken::get_by_value", skip_all)]
+    pub async fn get_by_value(
+        conn: &Object,
+        user_token_type: &str,
+        user_token_value: Uuid,
+    ) -> anyhow::Result<Option<Self>> {
+        match conn
+            .query_opt(
+                "SELECT * FROM v1.user_tokens WHERE user_token_type = $1 AND user_token_value = $2",
+                &[&user_token_type, &user_token_value],
+            )
+            .await
+        {
+            Ok(Some(row)) => Ok(Some(UserToken::from_row(row))),
+            Ok(None) => Ok(None),
+            Err(e) => Err(anyhow::Error::from(e)),
+        }
+    }
+
+    /// marks the token as redeemed; returns false if someone else redeemed it first
+    #[instrument(name = "UserToken::mark_used", skip_all)]
+    pub async fn mark_used(&self, conn: &Transaction<'_>) -> anyhow::Result<bool> {
+        match conn
+            .execute(
+                "UPDATE v1.user_tokens SET user_token_used = true WHERE user_token_id = $1 AND user_token_used = false",
+                &[&self.user_token_id],
+            )
+            .await
+        {
+            Ok(count) => Ok(count == 1),
+            Err(e) => Err(anyhow::Error::from(e)),
+        }
+    }
+
+    /// burns every outstanding refresh token issued for the session
+    #[instrument(name = "UserToken::revoke_session_family", skip_all)]
+    pub async fn revoke_session_family(
+        conn: &Transaction<'_>,
+        user_session_id: Uuid,
+    ) -> anyhow::Result<u64> {
+        match conn
+            .execute(
+                "UPDATE v1.user_tokens SET user_token_used = true WHERE user_token_session_id = $1 AND user_token_type = $2 AND user_token_used = false",
+                &[&user_session_id, &USER_REFRESH_TOKEN],
+            )
+            .await
+        {
+            Ok(count) => Ok(count),
+            Err(e) => Err(anyhow::Error::from(e)),
+        }
+    }
+
+    /// counts tokens of a type issued to the user since the given time, along with the most recent issuance
+    #[instrument(name = "UserToken::get_issuance_since", skip_all)]
+    pub async fn get_issuance_since(
+        conn: &Transaction<'_>,
+        user_id: Uuid,
+        user_token_type: &str,
+        since: DateTime<Utc>,
+    ) -> anyhow::Result<(i64, Option<DateTime<Utc>>)> {
+        match conn
+            .query_one(
+                "SELECT COUNT(*) AS issued, MAX(user_token_created_at) AS last_issued_at FROM v1.user_tokens WHERE user_token_user_id = $1 AND user_token_type = $2 AND user_token_created_at > $3",
+                &[&user_id, &user_token_type, &since],
+            )
+            .await
+        {
+            Ok(row) => Ok((
+                row.get::<&str, i64>("issued"),
+                row.get::<&str, Option<DateTime<Utc>>>("last_issued_at"),
+            )),
+            Err(e) => Err(anyhow::Error::from(e)),
+        }
+    }
+
+    /// supersedes every unredeemed token of a type so only a freshly issued one stays valid
+    #[instrument(name = "UserToken::invalidate_outstanding", skip_all)]
+    pub async fn invalidate_outstanding(
+        conn: &Transaction<'_>,
+        user_id: Uuid,
+        user_token_type: &str,
+    ) -> anyhow::Result<u64> {
+        match conn
+            .execute(
+                "UPDATE v1.user_tokens SET user_token_used = true WHERE user_token_user_id = $1 AND user_token_type = $2 AND user_token_used = false",
+                &[&user_id, &user_token_type],
+            )
+            .await
+        {
+            Ok(count) => Ok(count),
+            Err(e) => Err(anyhow::Error::from(e)),
+        }
+    }
+
+    /// burns every outstanding refresh token the user holds, across all sessions
+    #[instrument(name = "UserToken::revoke_all_refresh_for_user", skip_all)]
+    pub async fn revoke_all_refresh_for_user(
+        conn: &Transaction<'_>,
+        user_id: Uuid,
+    ) -> anyhow::Result<u64> {
+        match conn
+            .execute(
+                "UPDATE v1.user_tokens SET user_token_used = true WHERE user_token_user_id = $1 AND user_token_type = $2 AND user_token_used = false",
+                &[&user_id, &USER_REFRESH_TOKEN],
+            )
+            .await
+        {
+            Ok(count) => Ok(count),
+            Err(e) => Err(anyhow::Error::from(e)),
+        }
+    }
+
+    #[instrument(name = "UserToken::delete_by_id", skip_all)]
     pub async fn delete_by_id(conn: &Transaction<'_>, user_token_id: Uuid) -> anyhow::Result<u64> {
         let query = "DELETE FROM v1.user_tokens WHERE user_token_id = $1";
         let result = conn.execute(query, &[&user_token_id]).await;
@@ -70,6 +190,7 @@ impl UserToken {
         }
     }
 
+    #[instrument(name = "UserToken::validate_user_email", skip_all)]
     pub async fn validate_user_email(&self, conn: &Transaction<'_>) -> anyhow::Result<()> {
         match conn.execute(
             "UPDATE v1.users SET user_email_verified = true WHERE user_id = $1 AND user_email_verified = false",
@@ -101,6 +222,22 @@ impl UserToken {
         self.user_token_id
     }
 
+    pub fn get_user_id(&self) -> Uuid {
+        self.user_token_user_id
+    }
+
+    pub fn get_type(&self) -> &str {
+        &self.user_token_type
+    }
+
+    pub fn get_value(&self) -> Uuid {
+        self.user_token_value
+    }
+
+    pub fn get_session_id(&self) -> Option<Uuid> {
+        self.user_token_session_id
+    }
+
     pub fn is_used(&self) -> bool {
         self.user_token_used
     }
@@ -120,17 +257,33 @@ pub struct UserTokenForm {
     pub user_token_type: String,
     pub user_token_value: Uuid,
     pub user_token_expires_at: DateTime<Utc>,
+    pub user_token_session_id: Option<Uuid>,
 }
 
 impl ToInsertStmt for UserTokenForm {
     fn to_insert_stmt() -> String {
         String::from(
-            "INSERT INTO v1.user_tokens (user_token_user_id, user_token_type, user_token_value, user_token_created_at, user_token_expires_at, user_token_used) VALUES ($1, $2, $3, $4, $5, $6) RETURNING *",
+            "INSERT INTO v1.user_tokens (user_token_user_id, user_token_type, user_token_value, user_token_created_at, user_token_expires_at, user_token_used, user_token_session_id) VALUES ($1, $2, $3, $4, $5, $6, $7) RETURNING *",
         )
     }
 }
 
 impl UserTokenForm {
+    /// a refresh token never outlives the session it belongs to
+    pub fn new_refresh_token(session: &UserSession) -> Self {
+        UserTokenForm {
+            user_token_user_id: session.get_user_id(),
+            user_token_type: USER_REFRESH_TOKEN.to_owned(),
+            user_token_value: Uuid::new_v4(),
+            user_token_expires_at: std::cmp::min(
+                Utc::now() + chrono::Duration::days(REFRESH_TOKEN_DURATION_DAYS),
+                session.get_expired_time(),
+            ),
+            user_token_session_id: Some(session.get_id()),
+        }
+    }
+
+    #[instrument(name = "UserTokenForm::insert", skip_all)]
     pub async fn insert(&self, conn: &Transaction<'_>) -> anyhow::Result<UserToken> {
         let now = Utc::now();
         let user_token_used = false;
@@ -144,6 +297,7 @@ impl UserTokenForm {
                     &now,
                     &self.user_token_expires_at,
                     &user_token_used,
+                    &self.user_token_session_id,
                 ],
             )
             .await
@@ -162,6 +316,7 @@ pub struct UserTokenUpdateForm {
 }
 
 impl UserTokenUpdateForm {
+    #[instrument(name = "UserTokenUpdateForm::update_db", skip_all)]
     pub async fn update_db(
         &self,
         conn: &Transaction<'_>,
@@ -214,3 +369,14 @@ impl UserTokenUpdateForm {
 }
 
 pub const SIGNUP_EMAIL_VALIDATE: &str = "SIGNUP_EMAIL_VALIDATE";
+pub const USER_REFRESH_TOKEN: &str = "USER_REFRESH_TOKEN";
+pub const PASSWORD_RESET: &str = "PASSWORD_RESET";
+pub const ACCOUNT_UNLOCK: &str = "ACCOUNT_UNLOCK";
+pub const LOGIN_2FA_CHALLENGE: &str = "LOGIN_2FA_CHALLENGE";
+
+pub const REFRESH_TOKEN_DURATION_DAYS: i64 = 14;
+pub const PASSWORD_RESET_DURATION_MINUTES: i64 = 30;
+pub const LOGIN_2FA_CHALLENGE_DURATION_MINUTES: i64 = 5;
+pub const VERIFICATION_DURATION_HOURS: i64 = 24;
+pub const VERIFICATION_RESEND_COOLDOWN_SECONDS: i64 = 60;
+pub const VERIFICATION_DAILY_CAP: i64 = 5;
@@ -0,0 +1,126 @@
+use chrono::{DateTime, Utc};
+use deadpool_postgres::{Object, Transaction};
+use tracing::instrument;
+use uuid::Uuid;
+
+use super::common_traits::{FromRow, ToInsertStmt};
+
+/// a user's TOTP enrollment; logins only ask for a code once it is confirmed.
+/// Deliberately not Serialize: the secret never leaves the server after enrollment
+pub struct UserTotp {
+    user_totp_user_id: Uuid,             // The enrolled user; the primary key.
+    user_totp_secret_encrypted: Vec<u8>, // Nonce, ciphertext and tag.
+    user_totp_created_at: DateTime<Utc>, // The time the secret was generated.
+    user_totp_confirmed_at: Option<DateTime<Utc>>, // The time a code first checked out.
+    user_totp_last_used_step: Option<i64>, // Newest step used; refuses replays.
+}
+
+impl FromRow for UserTotp {
+    fn from_row(row: tokio_postgres::Row) -> UserTotp {
+        UserTotp {
+            user_totp_user_id: row.get::<&str, Uuid>("user_totp_user_id"),
+            user_totp_secret_encrypted: row.get::<&str, Vec<u8>>("user_totp_secret_encrypted"),
+            user_totp_created_at: row.get::<&str, DateTime<Utc>>("user_totp_created_at"),
+            user_totp_confirmed_at: row
+                .get::<&str, Option<DateTime<Utc>>>("user_totp_confirmed_at"),
+            user_totp_last_used_step: row.get::<&str, Option<i64>>("user_totp_last_used_step"),
+        }
+    }
+}
+
+impl UserTotp {
+    #[instrument(name = "UserTotp::get_by_user_id", skip_all)]
+    pub async fn get_by_user_id(conn: &Object, user_id: Uuid) -> anyhow::Result<Option<Self>> {
+        match conn
+            .query_opt(
+                "SELECT * FROM v1.user_totp WHERE user_totp_user_id = $1",
+                &[&user_id],
+            )
+            .await
+        {
+            Ok(Some(row)) => Ok(Some(UserTotp::from_row(row))),
+            Ok(None) => Ok(None),
+            Err(e) => Err(anyhow::Error::from(e)),
+        }
+    }
+
+    /// confirms this pending secret with the step of the code that proved it; returns false if it was
+    /// confirmed or replaced by a new enrollment in the meantime
+    #[instrument(name = "UserTotp::confirm", skip_all)]
+    pub async fn confirm(&self, conn: &Transaction<'_>, step: i64) -> anyhow::Result<bool> {
+        match conn
+            .execute(
+                "UPDATE v1.user_totp SET user_totp_confirmed_at = NOW(), user_totp_last_used_step = $2 WHERE user_totp_user_id = $1 AND user_totp_created_at = $3 AND user_totp_confirmed_at IS NULL",
+                &[&self.user_totp_user_id, &step, &self.user_totp_created_at],
+            )
+            .await
+        {
+            Ok(count) => Ok(count == 1),
+            Err(e) => Err(anyhow::Error::from(e)),
+        }
+    }
+
+    /// records the step a login code was accepted for; returns false if that step or a later one was
+    /// already used, i.e. the code is being replayed
+    #[instrument(name = "UserTotp::mark_step_used", skip_all)]
+    pub async fn mark_step_used(&self, conn: &Transaction<'_>, step: i64) -> anyhow::Result<bool> {
+        match conn
+            .execute(
+                "UPDATE v1.user_totp SET user_totp_last_used_step = $2 WHERE user_totp_user_id = $1 AND (user_totp_last_used_step IS NULL OR user_totp_last_used_step < $2)",
+                &[&self.user_totp_user_id, &step],
+            )
+            .await
+        {
+            Ok(count) => Ok(count == 1),
+            Err(e) => Err(anyhow::Error::from(e)),
+        }
+    }
+
+    pub fn get_secret_encrypted(&self) -> &[u8] {
+        &self.user_totp_secret_encrypted
+    }
+
+    pub fn get_last_used_step(&self) -> Option<i64> {
+        self.user_totp_last_used_step
+    }
+
+    pub fn is_confirmed(&self) -> bool {
+        self.user_totp_confirmed_at.is_some()
+    }
+}
+
+pub struct UserTotpForm {
+    pub user_totp_user_id: Uuid,
+    pub user_totp_secret_encrypted: Vec<u8>,
+}
+
+impl ToInsertStmt for UserTotpForm {
+    /// enrolling again before confirming replaces the pending secret; a confirmed one is left alone
+    fn to_insert_stmt() -> String {
+        String::from(
+            "INSERT INTO v1.user_totp AS t (user_totp_user_id, user_totp_secret_encrypted, user_totp_created_at) VALUES ($1, $2, $3) ON CONFLICT (user_totp_user_id) DO UPDATE SET user_totp_secret_encrypted = EXCLUDED.user_totp_secret_encrypted, user_totp_created_at = EXCLUDED.user_totp_created_at, user_totp_last_used_step = NULL WHERE t.user_totp_confirmed_at IS NULL RETURNING *",
+        )
+    }
+}
+
+impl UserTotpForm {
+    /// None when the user already has a confirmed secret
+    #[instrument(name = "UserTotpForm::insert", skip_all)]
+    pub async fn insert(&self, conn: &Transaction<'_>) -> anyhow::Result<Option<UserTotp>> {
+        let now = Utc::now();
+        match conn
+            .query_opt(
+                &UserTotpForm::to_insert_stmt(),
+                &[
+                    &self.user_totp_user_id,
+                    &self.user_totp_secret_encrypted,
+                    &now,
+                ],
+            )
+            .await
+        {
+            Ok(row) => Ok(row.map(UserTotp::from_row)),
+            Err(e) => Err(anyhow::Error::from(e)),
+        }
+    }
+}
@@ -2,13 +2,14 @@ use chrono::{DateTime, Utc};
 use deadpool_postgres::{Object, Transaction};
 use serde_derive::{Deserialize, Serialize};
 use tokio_postgres::types::Type;
+use tracing::instrument;
 use uuid::Uuid;
 
 use crate::utils::gadgets::argon::hash_password;
 
 use super::common_traits::{FromRow, FromRows, ToBatchInsertStmt, ToInsertStmt};
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Clone, Debug)]
 pub struct User {
     user_id: uuid::Uuid,                   // User's PKEY.
     user_screen_name: String,              // User's screen name. Unique.
@@ -44,6 +45,7 @@ impl FromRows for User {
 }
 
 impl User {
+    #[instrument(name = "User::get_by_id", skip_all)]
     pub async fn get_by_id(conn: &Object, user_id: Uuid) -> anyhow::Result<Option<Self>> {
         match conn
             .query_opt("SELECT * FROM v1.users WHERE user_id = $1", &[&user_id])
@@ -55,6 +57,7 @@ impl User {
         }
     }
 
+    #[instrument(name = "User::get_by_ids", skip_all)]
     pub async fn get_by_ids(conn: &Object, user_ids: Vec<Uuid>) -> anyhow::Result<Vec<Self>> {
         let rows = conn
             .query(
@@ -65,6 +68,60 @@ impl User {
         Ok(User::from_rows(rows))
     }
 
+    #[instrument(name = "User::get_by_email", skip_all)]
+    pub async fn get_by_email(conn: &Object, user_email: &str) -> anyhow::Result<Option<Self>> {
+        match conn
+            .query_opt(
+                "SELECT * FROM v1.users WHERE user_email = $1",
+                &[&user_email],
+            )
+            .await
+        {
+            Ok(Some(row)) => Ok(Some(User::from_row(row))),
+            Ok(None) => Ok(None),
+            Err(e) => Err(anyhow::Error::from(e)),
+        }
+    }
+
+    #[instrument(name = "User::get_by_email_or_screen_name", skip_all)]
+    pub async fn get_by_email_or_screen_name(
+        conn: &Object,
+        email_or_screen_name: &str,
+    ) -> anyhow::Result<Option<Self>> {
+        // an exact email match takes precedence over a screen name that happens to look like an email
+        match conn
+            .query_opt(
+                "SELECT * FROM v1.users WHERE user_email = $1 OR user_screen_name = $1 ORDER BY (user_email = $1) DESC LIMIT 1",
+                &[&email_or_screen_name],
+            )
+            .await
+        {
+            Ok(Some(row)) => Ok(Some(User::from_row(row))),
+            Ok(None) => Ok(None),
+            Err(e) => Err(anyhow::Error::from(e)),
+        }
+    }
+
+    /// every user implicitly holds ROLE_USER; anything else is granted through v1.user_roles
+    #[instrument(name = "User::get_roles", skip_all)]
+    pub async fn get_roles(&self, conn: &Object) -> anyhow::Result<Vec<String>> {
+        let rows = conn
+            .query(
+                "SELECT user_role_name FROM v1.user_roles WHERE user_role_user_id = $1 ORDER BY user_role_name",
+                &[&self.user_id],
+            )
+            .await?;
+
+        let mut roles = vec![ROLE_USER.to_owned()];
+        roles.extend(
+            rows.into_iter()
+                .map(|row| row.get::<&str, String>("user_role_name"))
+                .filter(|role| role != ROLE_USER),
+        );
+
+        Ok(roles)
+    }
+
     pub fn get_id(&self) -> Uuid {
         self.user_id
     }
@@ -72,6 +129,26 @@ impl User {
     pub fn get_created_at(&self) -> DateTime<Utc> {
         self.user_created_at
     }
+
+    pub fn get_screen_name(&self) -> &str {
+        &self.user_screen_name
+    }
+
+    pub fn get_email(&self) -> &str {
+        &self.user_email
+    }
+
+    pub fn get_password_hash(&self) -> &str {
+        &self.user_password_hash
+    }
+
+    pub fn is_active(&self) -> bool {
+        self.user_is_active
+    }
+
+    pub fn is_email_verified(&self) -> bool {
+        self.user_email_verified
+    }
 }
 
 #[derive(Serialize, Deserialize, Debug)]
@@ -155,6 +232,7 @@ impl ToBatchInsertStmt for UserForm {
 }
 
 impl UserForm {
+    #[instrument(name = "UserForm::insert", skip_all)]
     pub async fn insert(
         &self,
         conn: &Transaction<'_>,
@@ -179,6 +257,7 @@ impl UserForm {
         }
     }
 
+    #[instrument(name = "UserForm::batch_insert", skip_all)]
     pub async fn batch_insert(
         batch: Vec<Self>,
         conn: &Transaction<'_>,
@@ -226,29 +305,43 @@ impl UserForm {
 pub struct UserUpdateForm {
     pub user_screen_name: Option<String>,
     pub user_email: Option<String>,
-    pub user_password: Option<String>,
+    /// already hashed, so the caller can do the slow part off the executor and outside its transaction
+    pub user_password_hash: Option<String>,
     pub user_is_active: Option<bool>,
 }
 
 impl UserUpdateForm {
+    #[instrument(name = "UserUpdateForm::update_db", skip_all)]
     pub async fn update_db(
         &self,
         conn: &Transaction<'_>,
         user_id: Uuid,
     ) -> anyhow::Result<Option<User>> {
-        let set_clauses: Vec<_> = [
-            self.user_screen_name
-                .as_ref()
-                .map(|_| "user_screen_name = $1"),
-            self.user_email.as_ref().map(|_| "user_email = $2"),
-            self.user_password
-                .as_ref()
-                .map(|_| "user_password_hash = $3"),
-            self.user_is_active.map(|_| "user_is_active = $4"),
-        ]
-        .iter()
-        .filter_map(|&x| x)
-        .collect();
+        // placeholders are numbered by what is actually set; postgres can't infer types for unreferenced params
+        let mut set_clauses = Vec::new();
+        let mut params: Vec<&(dyn tokio_postgres::types::ToSql + Sync)> = Vec::new();
+        let mut idx = 1;
+
+        if let Some(ref screen_name) = self.user_screen_name {
+            set_clauses.push(format!("user_screen_name = ${}", idx));
+            params.push(screen_name);
+            idx += 1;
+        }
+        if let Some(ref email) = self.user_email {
+            set_clauses.push(format!("user_email = ${}", idx));
+            params.push(email);
+            idx += 1;
+        }
+        if let Some(ref password_hash) = self.user_password_hash {
+            set_clauses.push(format!("user_password_hash = ${}", idx));
+            params.push(password_hash);
+            idx += 1;
+        }
+        if let Some(ref is_active) = self.user_is_active {
+            set_clauses.push(format!("user_is_active = ${}", idx));
+            params.push(is_active);
+            idx += 1;
+        }
 
         if set_clauses.is_empty() {
             return Ok(None); // Nothing to update
@@ -257,22 +350,12 @@ impl UserUpdateForm {
         let set_clause = set_clauses.join(", ");
 
         let query = format!(
-            "UPDATE v1.users SET {}, user_updated_at = NOW() WHERE user_id = $5 RETURNING *",
-            set_clause
+            "UPDATE v1.users SET {}, user_updated_at = NOW() WHERE user_id = ${} RETURNING *",
+            set_clause, idx
         );
+        params.push(&user_id);
 
-        let result = conn
-            .query_opt(
-                &query,
-                &[
-                    &self.user_screen_name,
-                    &self.user_email,
-                    &self.user_password.as_ref().map(|pwd| hash_password(pwd)),
-                    &self.user_is_active,
-                    &user_id,
-                ],
-            )
-            .await;
+        let result = conn.query_opt(&query, &params).await;
 
         match result {
             Ok(opt_row) => Ok(opt_row.map(User::from_row)),
@@ -282,6 +365,22 @@ impl UserUpdateForm {
 }
 
 impl User {
+    /// row-locks the user for the rest of the transaction so per-user checks can't race
+    #[instrument(name = "User::lock_by_id", skip_all)]
+    pub async fn lock_by_id(conn: &Transaction<'_>, user_id: Uuid) -> anyhow::Result<bool> {
+        match conn
+            .query_opt(
+                "SELECT user_id FROM v1.users WHERE user_id = $1 FOR UPDATE",
+                &[&user_id],
+            )
+            .await
+        {
+            Ok(row) => Ok(row.is_some()),
+            Err(e) => Err(anyhow::Error::from(e)),
+        }
+    }
+
+    #[instrument(name = "User::delete_by_id", skip_all)]
     pub async fn delete_by_id(conn: &Transaction<'_>, user_id: Uuid) -> anyhow::Result<u64> {
         let query = "DELETE FROM v1.users WHERE user_id = $1";
         let result = conn.execute(query, &[&user_id]).await;
@@ -300,3 +399,6 @@ impl User {
         }
     }
 }
+
+pub const ROLE_USER: &str = "user";
+pub const ROLE_ADMIN: &str = "admin";
@@ -0,0 +1,93 @@
+use axum::http::StatusCode;
+use serde_json::json;
+
+use crate::models::users::User;
+
+use super::harness::{uuid_param, TestApp};
+
+const EMAIL: &str = "new.user@example.com";
+const PASSWORD: &str = "Sup3r$ecret";
+
+fn signup_body() -> serde_json::Value {
+    json!({
+        "user_screen_name": "new_user",
+        "user_email": EMAIL,
+        "user_password": PASSWORD,
+    })
+}
+
+fn login_body() -> serde_json::Value {
+    json!({
+        "user_email_or_screen_name": EMAIL,
+        "user_password": PASSWORD,
+    })
+}
+
+#[tokio::test]
+async fn test_signup_then_verify_email() {
+    let app = TestApp::spawn().await;
+
+    let response = app.post("/api/auth/signup", signup_body()).await;
+    assert_eq!(response.status, StatusCode::OK, "{}", response.body);
+    assert_eq!(response.body["data"]["user"]["user_email"], EMAIL);
+
+    // unverified accounts can't log in yet
+    let response = app.post("/api/auth/login", login_body()).await;
+    assert_eq!(response.body["data"]["code"], "USER_EMAIL_NOT_VERIFIED");
+
+    // the verification email goes out through the outbox
+    assert!(app.mailer.sent().is_empty());
+    assert_eq!(app.deliver_emails().await, 1);
+    let sent = app.mailer.sent_to(EMAIL);
+    assert_eq!(sent.len(), 1);
+    let token_id = uuid_param(&sent[0].text_body, "email_token");
+    assert!(sent[0]
+        .html_body
+        .as_deref()
+        .unwrap()
+        .contains(&token_id.to_string()));
+
+    let response = app
+        .post("/api/auth/validate-email", json!({ "token_id": token_id }))
+        .await;
+    assert_eq!(response.status, StatusCode::OK, "{}", response.body);
+
+    let conn = app.state.get_conn().await.unwrap();
+    let user = User::get_by_email(&conn, EMAIL).await.unwrap().unwrap();
+    assert!(user.is_email_verified());
+    drop(conn);
+
+    // tokens are single use
+    let response = app
+        .post("/api/auth/validate-email", json!({ "token_id": token_id }))
+        .await;
+    assert_eq!(response.body["data"]["code"], "USER_TOKEN_USED");
+
+    let response = app.post("/api/auth/login", login_body()).await;
+    assert_eq!(response.status, StatusCode::OK, "{}", response.body);
+}
+
+#[tokio::test]
+async fn test_signup_rejects_duplicates_and_bad_input() {
+    let app = TestApp::spawn().await;
+
+    let response = app.post("/api/auth/signup", signup_body()).await;
+    assert_eq!(response.status, StatusCode::OK, "{}", response.body);
+
+    let response = app.post("/api/auth/signup", signup_body()).await;
+    assert_eq!(response.body["data"]["code"], "USER_ALREADY_EXISTS");
+
+    let mut body = signup_body();
+    body["user_email"] = json!("not-an-email");
+    let response = app.post("/api/auth/signup", body).await;
+    assert_eq!(response.body["data"]["code"], "WRONG_EMAIL_FORMAT");
+
+    let mut body = signup_body();
+    body["user_email"] = json!("other@example.com");
+    body["user_password"] = json!("short");
+    let response = app.post("/api/auth/signup", body).await;
+    assert_eq!(response.body["data"]["code"], "WRONG_PW_FORMAT");
+
+    // only the first signup queued an email
+    assert_eq!(app.deliver_emails().await, 1);
+}
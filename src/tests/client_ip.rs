@@ -0,0 +1,68 @@
+use std::{collections::HashSet, net::SocketAddr};
+
+use axum::{
+    body::Body,
+    extract::ConnectInfo,
+    http::{
+        header::{ACCEPT, CONTENT_TYPE},
+        Method, Request, StatusCode,
+    },
+};
+use serde_json::json;
+
+use super::harness::{TestApp, TestResponse};
+
+const EMAIL: &str = "proxied.user@example.com";
+const PASSWORD: &str = "Sup3r$ecret";
+
+async fn login(app: &TestApp, peer: Option<&str>, forwarded_for: &str) -> TestResponse {
+    let mut request = Request::builder()
+        .method(Method::POST)
+        .uri("/api/auth/login")
+        .header(ACCEPT, "application/json")
+        .header(CONTENT_TYPE, "application/json")
+        .header("x-forwarded-for", forwarded_for)
+        .body(Body::from(
+            json!({ "user_email_or_screen_name": EMAIL, "user_password": PASSWORD }).to_string(),
+        ))
+        .unwrap();
+    if let Some(peer) = peer {
+        request
+            .extensions_mut()
+            .insert(ConnectInfo(peer.parse::<SocketAddr>().unwrap()));
+    }
+
+    app.router_request(request).await
+}
+
+#[tokio::test]
+async fn test_sessions_record_the_resolved_client_ip() {
+    let app = TestApp::spawn().await;
+
+    app.create_verified_user(EMAIL, "proxied_user", PASSWORD)
+        .await;
+
+    // through the trusted test peer: the rightmost hop it didn't vouch for is the client
+    let response = login(&app, None, "1.1.1.1, 198.51.100.7").await;
+    assert_eq!(response.status, StatusCode::OK, "{}", response.body);
+    let access_token = response.body["data"]["access_token"]
+        .as_str()
+        .unwrap()
+        .to_owned();
+
+    // straight from an untrusted peer: the header is a spoof and is ignored
+    let response = login(&app, Some("203.0.113.9:5555"), "198.51.100.8").await;
+    assert_eq!(response.status, StatusCode::OK, "{}", response.body);
+
+    let response = app
+        .request_as(Method::GET, &access_token, "/api/auth/sessions", json!({}))
+        .await;
+    assert_eq!(response.status, StatusCode::OK, "{}", response.body);
+    let ips: HashSet<&str> = response.body["data"]["sessions"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|session| session["user_session_ip"].as_str().unwrap())
+        .collect();
+    assert_eq!(ips, HashSet::from(["198.51.100.7", "203.0.113.9"]));
+}
@@ -0,0 +1,119 @@
+use axum::http::StatusCode;
+use serde_json::json;
+
+use crate::{
+    models::email_outbox::{EmailOutbox, EMAIL_OUTBOX_SENT},
+    utils::workers::email_outbox_worker::EMAIL_OUTBOX_LEASE,
+};
+
+use super::harness::TestApp;
+
+const EMAIL: &str = "outbox.user@example.com";
+
+#[tokio::test]
+async fn test_claimed_emails_are_leased_and_each_result_is_kept() {
+    let app = TestApp::spawn().await;
+    let response = app
+        .post(
+            "/api/auth/signup",
+            json!({
+                "user_screen_name": "outbox_user",
+                "user_email": EMAIL,
+                "user_password": "Sup3r$ecret",
+            }),
+        )
+        .await;
+    assert_eq!(response.status, StatusCode::OK, "{}", response.body);
+
+    // another instance claims the email and then goes quiet
+    let mut conn = app.state.get_conn().await.unwrap();
+    let transaction = conn.transaction().await.unwrap();
+    let claimed = EmailOutbox::claim_due(&transaction, 10, EMAIL_OUTBOX_LEASE)
+        .await
+        .unwrap();
+    transaction.commit().await.unwrap();
+    assert_eq!(claimed.len(), 1);
+
+    // nobody else touches it while the lease lasts, and nothing is held locked meanwhile
+    assert_eq!(app.deliver_emails().await, 0);
+    assert!(app.mailer.sent().is_empty());
+
+    // once the lease runs out without a result, it is sent exactly once
+    conn.execute(
+        "UPDATE v1.email_outbox SET email_outbox_next_attempt_at = NOW()",
+        &[],
+    )
+    .await
+    .unwrap();
+    assert_eq!(app.deliver_emails().await, 1);
+    assert_eq!(app.deliver_emails().await, 0);
+    assert_eq!(app.mailer.sent_to(EMAIL).len(), 1);
+
+    let status: String = conn
+        .query_one("SELECT email_outbox_status FROM v1.email_outbox", &[])
+        .await
+        .unwrap()
+        .get(0);
+    assert_eq!(status, EMAIL_OUTBOX_SENT);
+}
+
+#[tokio::test]
+async fn test_a_result_only_counts_while_its_lease_is_held() {
+    let app = TestApp::spawn().await;
+    let response = app
+        .post(
+            "/api/auth/signup",
+            json!({
+                "user_screen_name": "outbox_user",
+                "user_email": EMAIL,
+                "user_password": "Sup3r$ecret",
+            }),
+        )
+        .await;
+    assert_eq!(response.status, StatusCode::OK, "{}", response.body);
+
+    let mut conn = app.state.get_conn().await.unwrap();
+    let transaction = conn.transaction().await.unwrap();
+    let stale = EmailOutbox::claim_due(&transaction, 1, EMAIL_OUTBOX_LEASE)
+        .await
+        .unwrap()
+        .pop()
+        .unwrap();
+    transaction.commit().await.unwrap();
+
+    // the first lease runs out and another instance claims the entry
+    conn.execute(
+        "UPDATE v1.email_outbox SET email_outbox_next_attempt_at = NOW()",
+        &[],
+    )
+    .await
+    .unwrap();
+    let transaction = conn.transaction().await.unwrap();
+    let current = EmailOutbox::claim_due(&transaction, 1, EMAIL_OUTBOX_LEASE)
+        .await
+        .unwrap()
+        .pop()
+        .unwrap();
+    transaction.commit().await.unwrap();
+
+    // the late result of the first claim changes nothing; the current holder's is kept
+    let transaction = conn.transaction().await.unwrap();
+    assert!(!stale
+        .mark_failed(&transaction, "timed out", None)
+        .await
+        .unwrap());
+    assert!(!stale.mark_sent(&transaction).await.unwrap());
+    assert!(current.mark_sent(&transaction).await.unwrap());
+    transaction.commit().await.unwrap();
+
+    let (status, attempts): (String, i32) = conn
+        .query_one(
+            "SELECT email_outbox_status, email_outbox_attempts FROM v1.email_outbox",
+            &[],
+        )
+        .await
+        .map(|row| (row.get(0), row.get(1)))
+        .unwrap();
+    assert_eq!(status, EMAIL_OUTBOX_SENT);
+    assert_eq!(attempts, 1);
+}
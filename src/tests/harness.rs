@@ -0,0 +1,446 @@
+use std::{
+    net::SocketAddr,
+    path::{Path, PathBuf},
+    process::{Child, Command, Stdio},
+    sync::Arc,
+    time::Duration,
+};
+
+use anyhow::{anyhow, Context};
+use axum::{
+    body::Body,
+    extract::connect_info::MockConnectInfo,
+    http::{
+        header::{ACCEPT, AUTHORIZATION, CONTENT_TYPE},
+        HeaderMap, Method, Request, StatusCode,
+    },
+    Router,
+};
+use deadpool_postgres::{Manager, Pool};
+use serde_json::json;
+use tokio_postgres::NoTls;
+use tower::ServiceExt;
+use tracing_subscriber::{layer::SubscriberExt, reload};
+use uuid::Uuid;
+
+use crate::{
+    controllers::router::generate_router,
+    utils::{
+        config::app_config::{LockoutConfig, RateLimitConfig, RateLimitStoreKind},
+        db::migrations::{run_migrations, MigrationMode},
+        lockout::login_throttle::LoginThrottle,
+        mail::memory_transport::InMemoryMailTransport,
+        server_init::{
+            server_init_funcs::{
+                initialize_logger::{build_log_filter, LogFilterHandle},
+                initialize_rate_limiter::init_rate_limiter,
+            },
+            server_state_def::ServerState,
+        },
+        workers::email_outbox_worker::drain_once,
+    },
+};
+
+/// a throwaway database that is removed when dropped: a whole cluster started from the local postgres
+/// binaries, or, when TEST_DATABASE_URL is set, a fresh database on that server
+enum TestDatabase {
+    Local {
+        process: Child,
+        data_dir: PathBuf,
+    },
+    Server {
+        admin_config: Box<tokio_postgres::Config>,
+        dbname: String,
+    },
+}
+
+impl TestDatabase {
+    /// the database and the config to connect to it. Having neither is an error rather than a skip, so a run
+    /// without a database can't pass
+    async fn create() -> anyhow::Result<(TestDatabase, tokio_postgres::Config)> {
+        if let Ok(url) = std::env::var("TEST_DATABASE_URL") {
+            let admin_config: tokio_postgres::Config =
+                url.parse().context("Could not parse TEST_DATABASE_URL")?;
+            let dbname = format!("cyhdev_test_{}", Uuid::new_v4().simple());
+
+            let admin = connect(&admin_config).await?;
+            admin
+                .batch_execute(&format!("CREATE DATABASE {}", dbname))
+                .await?;
+
+            let mut config = admin_config.clone();
+            config.dbname(&dbname);
+            return Ok((
+                TestDatabase::Server {
+                    admin_config: Box::new(admin_config),
+                    dbname,
+                },
+                config,
+            ));
+        }
+
+        TestDatabase::start_local()
+            .await
+            .context("set TEST_DATABASE_URL, or install postgres and run as a non-root user")
+    }
+
+    async fn start_local() -> anyhow::Result<(TestDatabase, tokio_postgres::Config)> {
+        let bin_dir = postgres_bin_dir();
+        let data_dir =
+            std::env::temp_dir().join(format!("cyhdev_test_{}", Uuid::new_v4().simple()));
+
+        let initdb = Command::new(bin_dir.join("initdb"))
+            .arg("--pgdata")
+            .arg(&data_dir)
+            .args([
+                "--username",
+                "postgres",
+                "--auth",
+                "trust",
+                "--encoding",
+                "UTF8",
+                "--no-sync",
+            ])
+            .stdout(Stdio::null())
+            .stderr(Stdio::piped())
+            .output()
+            .context("Could not run initdb")?;
+        if !initdb.status.success() {
+            let _ = std::fs::remove_dir_all(&data_dir);
+            return Err(anyhow!(
+                "initdb failed: {}",
+                String::from_utf8_lossy(&initdb.stderr).trim()
+            ));
+        }
+
+        // unix socket only, inside the data directory, so concurrent tests never share a port
+        let process = Command::new(bin_dir.join("postgres"))
+            .arg("-D")
+            .arg(&data_dir)
+            .arg("-k")
+            .arg(&data_dir)
+            .args(["-c", "listen_addresses=", "-c", "fsync=off"])
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .spawn()
+            .context("Could not start postgres")?;
+
+        let mut config = tokio_postgres::Config::new();
+        config
+            .host_path(&data_dir)
+            .port(5432)
+            .user("postgres")
+            .dbname("postgres");
+
+        let database = TestDatabase::Local { process, data_dir };
+
+        // postgres takes a moment to accept connections
+        for _ in 0..100 {
+            if connect(&config).await.is_ok() {
+                return Ok((database, config));
+            }
+            tokio::time::sleep(Duration::from_millis(100)).await;
+        }
+
+        Err(anyhow!("postgres did not accept connections in time"))
+    }
+}
+
+impl Drop for TestDatabase {
+    fn drop(&mut self) {
+        match self {
+            TestDatabase::Local { process, data_dir } => {
+                let _ = process.kill();
+                let _ = process.wait();
+                let _ = std::fs::remove_dir_all(data_dir);
+            }
+            TestDatabase::Server {
+                admin_config,
+                dbname,
+            } => {
+                // Drop can't await, so the cleanup runs on a runtime of its own
+                let admin_config = admin_config.as_ref().clone();
+                let statement = format!("DROP DATABASE IF EXISTS {} WITH (FORCE)", dbname);
+                let dropped = std::thread::spawn(move || {
+                    tokio::runtime::Builder::new_current_thread()
+                        .enable_all()
+                        .build()?
+                        .block_on(async {
+                            connect(&admin_config)
+                                .await?
+                                .batch_execute(&statement)
+                                .await
+                                .map_err(anyhow::Error::from)
+                        })
+                })
+                .join();
+
+                if !matches!(dropped, Ok(Ok(()))) {
+                    eprintln!("could not drop test database {}", dbname);
+                }
+            }
+        }
+    }
+}
+
+/// the postgres binaries from `pg_config --bindir`, or whatever is on PATH
+fn postgres_bin_dir() -> PathBuf {
+    Command::new("pg_config")
+        .arg("--bindir")
+        .output()
+        .ok()
+        .filter(|output| output.status.success())
+        .map(|output| PathBuf::from(String::from_utf8_lossy(&output.stdout).trim()))
+        .filter(|bin_dir| bin_dir.join("initdb").exists())
+        .unwrap_or_else(|| Path::new("").to_path_buf())
+}
+
+async fn connect(config: &tokio_postgres::Config) -> anyhow::Result<tokio_postgres::Client> {
+    let (client, connection) = config.connect(NoTls).await?;
+    tokio::spawn(connection);
+    Ok(client)
+}
+
+pub struct TestResponse {
+    pub status: StatusCode,
+    pub headers: HeaderMap,
+    pub body: serde_json::Value,
+}
+
+/// the socket address every test request comes from; a trusted proxy in the test state.
+/// Insert a ConnectInfo extension into a request to connect from elsewhere.
+pub const TEST_PEER: SocketAddr =
+    SocketAddr::new(std::net::IpAddr::V4(std::net::Ipv4Addr::LOCALHOST), 40000);
+
+/// the full router over a migrated throwaway database, with an in-memory mailer
+pub struct TestApp {
+    pub state: Arc<ServerState>,
+    pub mailer: Arc<InMemoryMailTransport>,
+    router: Router,
+    // declared last so the pool is gone before the database is removed
+    _database: TestDatabase,
+}
+
+impl TestApp {
+    /// panics when no database is available. Rate limiting is off and the login lockout has its default
+    /// settings.
+    pub async fn spawn() -> TestApp {
+        TestApp::spawn_with(&test_rate_limit_config(false), &test_lockout_config()).await
+    }
+
+    /// like spawn, with the given rate limits
+    pub async fn spawn_with_rate_limits(rate_limits: &RateLimitConfig) -> TestApp {
+        TestApp::spawn_with(rate_limits, &test_lockout_config()).await
+    }
+
+    /// like spawn, with the given login lockout settings
+    pub async fn spawn_with_lockout(lockout: &LockoutConfig) -> TestApp {
+        TestApp::spawn_with(&test_rate_limit_config(false), lockout).await
+    }
+
+    async fn spawn_with(rate_limits: &RateLimitConfig, lockout: &LockoutConfig) -> TestApp {
+        let (database, config) = TestDatabase::create()
+            .await
+            .expect("could not create test database");
+
+        let pool = Pool::builder(Manager::new(config, NoTls))
+            .max_size(8)
+            .build()
+            .expect("could not build test pool");
+
+        run_migrations(
+            pool.get()
+                .await
+                .expect("could not connect to test database"),
+            MigrationMode::Apply,
+        )
+        .await
+        .expect("could not migrate test database");
+
+        let mailer = Arc::new(InMemoryMailTransport::new());
+        let rate_limiter = init_rate_limiter(rate_limits, &pool);
+        let login_throttle = LoginThrottle::new(lockout.clone(), pool.clone());
+        let state = Arc::new(
+            ServerState::for_tests(pool, Arc::clone(&mailer) as _, rate_limiter, login_throttle)
+                .expect("could not build test state"),
+        );
+
+        TestApp {
+            router: generate_router(&state).layer(MockConnectInfo(TEST_PEER)),
+            state,
+            mailer,
+            _database: database,
+        }
+    }
+
+    /// sends a JSON request through the router and reads the JSON response
+    pub async fn request(
+        &self,
+        method: Method,
+        uri: &str,
+        body: Option<serde_json::Value>,
+    ) -> TestResponse {
+        let request = Request::builder()
+            .method(method)
+            .uri(uri)
+            .header(ACCEPT, "application/json")
+            .header(CONTENT_TYPE, "application/json")
+            .body(match body {
+                Some(body) => Body::from(body.to_string()),
+                None => Body::empty(),
+            })
+            .unwrap();
+
+        self.router_request(request).await
+    }
+
+    /// sends a prepared request through the router and reads the JSON response
+    pub async fn router_request(&self, request: Request<Body>) -> TestResponse {
+        let response = self.router.clone().oneshot(request).await.unwrap();
+        let status = response.status();
+        let headers = response.headers().clone();
+        let bytes = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+
+        TestResponse {
+            status,
+            headers,
+            body: serde_json::from_slice(&bytes).unwrap_or(serde_json::Value::Null),
+        }
+    }
+
+    pub async fn post(&self, uri: &str, body: serde_json::Value) -> TestResponse {
+        self.request(Method::POST, uri, Some(body)).await
+    }
+
+    /// like request, with the access token as a bearer token
+    pub async fn request_as(
+        &self,
+        method: Method,
+        access_token: &str,
+        uri: &str,
+        body: serde_json::Value,
+    ) -> TestResponse {
+        self.router_request(
+            Request::builder()
+                .method(method)
+                .uri(uri)
+                .header(ACCEPT, "application/json")
+                .header(CONTENT_TYPE, "application/json")
+                .header(AUTHORIZATION, format!("Bearer {}", access_token))
+                .body(Body::from(body.to_string()))
+                .unwrap(),
+        )
+        .await
+    }
+
+    pub async fn login(&self, account: &str, password: &str) -> TestResponse {
+        self.post(
+            "/api/auth/login",
+            json!({ "user_email_or_screen_name": account, "user_password": password }),
+        )
+        .await
+    }
+
+    /// like login, forwarded for the given client IP by TEST_PEER
+    pub async fn login_from(&self, ip: &str, account: &str, password: &str) -> TestResponse {
+        self.router_request(
+            Request::builder()
+                .method(Method::POST)
+                .uri("/api/auth/login")
+                .header(ACCEPT, "application/json")
+                .header(CONTENT_TYPE, "application/json")
+                .header("x-forwarded-for", ip)
+                .body(Body::from(
+                    json!({ "user_email_or_screen_name": account, "user_password": password })
+                        .to_string(),
+                ))
+                .unwrap(),
+        )
+        .await
+    }
+
+    /// signs up and verifies an account through the API, leaving the mailer empty
+    pub async fn create_verified_user(&self, email: &str, screen_name: &str, password: &str) {
+        let response = self
+            .post(
+                "/api/auth/signup",
+                json!({
+                    "user_screen_name": screen_name,
+                    "user_email": email,
+                    "user_password": password,
+                }),
+            )
+            .await;
+        assert_eq!(response.status, StatusCode::OK, "{}", response.body);
+
+        self.deliver_emails().await;
+        let token_id = uuid_param(&self.mailer.sent_to(email)[0].text_body, "email_token");
+        let response = self
+            .post("/api/auth/validate-email", json!({ "token_id": token_id }))
+            .await;
+        assert_eq!(response.status, StatusCode::OK, "{}", response.body);
+        self.mailer.clear();
+    }
+
+    /// logs in with a password alone and returns the access token
+    pub async fn access_token(&self, account: &str, password: &str) -> String {
+        let response = self.login(account, password).await;
+        assert_eq!(response.status, StatusCode::OK, "{}", response.body);
+        assert_eq!(response.body["data"]["two_factor_required"], false);
+        response.body["data"]["access_token"]
+            .as_str()
+            .unwrap()
+            .to_owned()
+    }
+
+    /// runs the outbox worker once so queued emails land in the in-memory mailer
+    pub async fn deliver_emails(&self) -> usize {
+        drain_once(&self.state).await.unwrap()
+    }
+}
+
+/// the default limits with an in-memory store; tests override the group they exercise
+pub fn test_rate_limit_config(enabled: bool) -> RateLimitConfig {
+    RateLimitConfig {
+        enabled,
+        store: RateLimitStoreKind::Memory,
+        default: "300/min".parse().unwrap(),
+        credentials: "10/min".parse().unwrap(),
+        tokens: "30/min".parse().unwrap(),
+        email: "5/min".parse().unwrap(),
+        account: "20/hour".parse().unwrap(),
+    }
+}
+
+/// the default lockout settings; tests lower the thresholds they exercise
+pub fn test_lockout_config() -> LockoutConfig {
+    LockoutConfig {
+        enabled: true,
+        account_threshold: 10,
+        ip_threshold: 50,
+        free_attempts: 3,
+        max_delay_seconds: 60,
+        window_minutes: 15,
+        duration_minutes: 30,
+    }
+}
+
+/// a log filter handle like the one init_logger returns, driving a subscriber that is this thread's default
+/// until the guard is dropped
+pub fn test_log_filter() -> (LogFilterHandle, tracing::subscriber::DefaultGuard) {
+    let (filter, handle) = reload::Layer::new(build_log_filter("info").unwrap());
+    let guard = tracing::subscriber::set_default(tracing_subscriber::registry().with(filter));
+    (LogFilterHandle::new(handle), guard)
+}
+
+/// pulls a UUID query parameter out of a link in an email body
+pub fn uuid_param(text: &str, name: &str) -> Uuid {
+    let start = text
+        .find(&format!("{}=", name))
+        .unwrap_or_else(|| panic!("no {} in {:?}", name, text))
+        + name.len()
+        + 1;
+    Uuid::parse_str(&text[start..start + 36]).unwrap()
+}
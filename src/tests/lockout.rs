@@ -0,0 +1,222 @@
+use axum::http::{header::RETRY_AFTER, StatusCode};
+use serde_json::json;
+
+use crate::models::login_lockouts::LoginLockout;
+
+use super::harness::{test_lockout_config, uuid_param, TestApp, TestResponse};
+
+const EMAIL: &str = "locked.user@example.com";
+const PASSWORD: &str = "Sup3r$ecret";
+
+fn retry_after(response: &TestResponse) -> u64 {
+    response.headers[RETRY_AFTER]
+        .to_str()
+        .unwrap()
+        .parse()
+        .unwrap()
+}
+
+#[tokio::test]
+async fn test_account_locks_and_unlocks_by_email() {
+    let mut lockout = test_lockout_config();
+    lockout.account_threshold = 3;
+    lockout.max_delay_seconds = 0;
+    let app = TestApp::spawn_with_lockout(&lockout).await;
+    app.create_verified_user(EMAIL, "locked_user", PASSWORD)
+        .await;
+
+    // failures from different IPs all count against the account
+    for ip in ["203.0.113.1", "203.0.113.2", "203.0.113.3"] {
+        let response = app.login_from(ip, EMAIL, "Wr0ng$password").await;
+        assert_eq!(response.body["data"]["code"], "INVALID_CREDENTIALS");
+    }
+
+    // locked even for the right password
+    let response = app.login_from("198.51.100.1", EMAIL, PASSWORD).await;
+    assert_eq!(response.status, StatusCode::LOCKED);
+    assert_eq!(response.body["data"]["code"], "ACCOUNT_LOCKED");
+    assert!((1..=30 * 60).contains(&retry_after(&response)));
+
+    // the lockout is on record for admins
+    let conn = app.state.get_conn().await.unwrap();
+    let lockouts =
+        serde_json::to_value(LoginLockout::get_recent(&conn, 10).await.unwrap()).unwrap();
+    assert_eq!(lockouts.as_array().unwrap().len(), 1);
+    assert_eq!(lockouts[0]["login_lockout_ip"], "203.0.113.3");
+    assert_eq!(lockouts[0]["login_lockout_failures"], 3);
+    assert!(lockouts[0]["login_lockout_unlocked_at"].is_null());
+
+    // the owner is mailed an unlock link
+    assert_eq!(app.deliver_emails().await, 1);
+    let sent = app.mailer.sent_to(EMAIL);
+    let token_id = uuid_param(&sent[0].text_body, "unlock_token");
+
+    let response = app
+        .post("/api/auth/unlock-account", json!({ "token_id": token_id }))
+        .await;
+    assert_eq!(response.status, StatusCode::OK, "{}", response.body);
+
+    let response = app.login_from("198.51.100.1", EMAIL, PASSWORD).await;
+    assert_eq!(response.status, StatusCode::OK, "{}", response.body);
+
+    let response = app
+        .post("/api/auth/unlock-account", json!({ "token_id": token_id }))
+        .await;
+    assert_eq!(response.body["data"]["code"], "USER_TOKEN_USED");
+
+    let lockouts =
+        serde_json::to_value(LoginLockout::get_recent(&conn, 10).await.unwrap()).unwrap();
+    assert!(!lockouts[0]["login_lockout_unlocked_at"].is_null());
+}
+
+#[tokio::test]
+async fn test_email_and_screen_name_share_the_account_counter() {
+    let mut lockout = test_lockout_config();
+    lockout.account_threshold = 3;
+    lockout.max_delay_seconds = 0;
+    let app = TestApp::spawn_with_lockout(&lockout).await;
+    app.create_verified_user(EMAIL, "locked_user", PASSWORD)
+        .await;
+
+    // failures under either name add up to one lockout and one email
+    for account in [EMAIL, "locked_user", EMAIL] {
+        let response = app
+            .login_from("203.0.113.1", account, "Wr0ng$password")
+            .await;
+        assert_eq!(response.body["data"]["code"], "INVALID_CREDENTIALS");
+    }
+
+    for account in [EMAIL, "locked_user", "Locked_User"] {
+        let response = app.login_from("198.51.100.1", account, PASSWORD).await;
+        assert_eq!(response.body["data"]["code"], "ACCOUNT_LOCKED");
+    }
+
+    let conn = app.state.get_conn().await.unwrap();
+    let lockouts =
+        serde_json::to_value(LoginLockout::get_recent(&conn, 10).await.unwrap()).unwrap();
+    assert_eq!(lockouts.as_array().unwrap().len(), 1);
+    assert_eq!(app.deliver_emails().await, 1);
+
+    // and the unlock link lifts it under both names
+    let token_id = uuid_param(&app.mailer.sent_to(EMAIL)[0].text_body, "unlock_token");
+    let response = app
+        .post("/api/auth/unlock-account", json!({ "token_id": token_id }))
+        .await;
+    assert_eq!(response.status, StatusCode::OK, "{}", response.body);
+    let response = app
+        .login_from("198.51.100.1", "locked_user", PASSWORD)
+        .await;
+    assert_eq!(response.status, StatusCode::OK, "{}", response.body);
+}
+
+#[tokio::test]
+async fn test_successful_login_resets_the_account_counter() {
+    let mut lockout = test_lockout_config();
+    lockout.account_threshold = 2;
+    lockout.max_delay_seconds = 0;
+    let app = TestApp::spawn_with_lockout(&lockout).await;
+    app.create_verified_user(EMAIL, "locked_user", PASSWORD)
+        .await;
+
+    for _ in 0..3 {
+        let response = app.login_from("203.0.113.1", EMAIL, "Wr0ng$password").await;
+        assert_eq!(response.body["data"]["code"], "INVALID_CREDENTIALS");
+        let response = app.login_from("203.0.113.1", EMAIL, PASSWORD).await;
+        assert_eq!(response.status, StatusCode::OK, "{}", response.body);
+    }
+}
+
+#[tokio::test]
+async fn test_failures_back_off_per_ip() {
+    let mut lockout = test_lockout_config();
+    lockout.free_attempts = 1;
+    let app = TestApp::spawn_with_lockout(&lockout).await;
+
+    // unknown accounts still count against the client IP
+    let response = app
+        .login_from("203.0.113.7", "nobody@example.com", PASSWORD)
+        .await;
+    assert_eq!(response.body["data"]["code"], "INVALID_CREDENTIALS");
+
+    let response = app
+        .login_from("203.0.113.7", "someone@example.com", PASSWORD)
+        .await;
+    assert_eq!(response.status, StatusCode::TOO_MANY_REQUESTS);
+    assert_eq!(response.body["data"]["code"], "LOGIN_BACKOFF");
+    assert_eq!(retry_after(&response), 1);
+
+    // and the unknown account backs off from anywhere, like a real one
+    let response = app
+        .login_from("198.51.100.1", "nobody@example.com", PASSWORD)
+        .await;
+    assert_eq!(response.body["data"]["code"], "LOGIN_BACKOFF");
+    let response = app
+        .login_from("198.51.100.1", "anybody@example.com", PASSWORD)
+        .await;
+    assert_eq!(response.body["data"]["code"], "INVALID_CREDENTIALS");
+}
+
+#[tokio::test]
+async fn test_unknown_accounts_lock_like_real_ones() {
+    let mut lockout = test_lockout_config();
+    lockout.account_threshold = 3;
+    lockout.max_delay_seconds = 0;
+    let app = TestApp::spawn_with_lockout(&lockout).await;
+    app.create_verified_user(EMAIL, "locked_user", PASSWORD)
+        .await;
+
+    for account in [EMAIL, "nobody@example.com"] {
+        for ip in ["203.0.113.1", "203.0.113.2", "203.0.113.3"] {
+            let response = app.login_from(ip, account, "Wr0ng$password").await;
+            assert_eq!(response.body["data"]["code"], "INVALID_CREDENTIALS");
+        }
+    }
+
+    // the same answer whether or not the account exists, however the name is written
+    for account in [EMAIL, "nobody@example.com", " Nobody@Example.com"] {
+        let response = app.login_from("198.51.100.1", account, PASSWORD).await;
+        assert_eq!(response.status, StatusCode::LOCKED);
+        assert_eq!(response.body["data"]["code"], "ACCOUNT_LOCKED");
+        assert!((1..=30 * 60).contains(&retry_after(&response)));
+    }
+
+    // only a real owner is mailed, and the lockout record names no user for the unknown one
+    assert_eq!(app.deliver_emails().await, 1);
+    assert_eq!(app.mailer.sent_to(EMAIL).len(), 1);
+    let conn = app.state.get_conn().await.unwrap();
+    let lockouts =
+        serde_json::to_value(LoginLockout::get_recent(&conn, 10).await.unwrap()).unwrap();
+    assert_eq!(lockouts.as_array().unwrap().len(), 2);
+    assert!(lockouts[0]["login_lockout_user_id"].is_null());
+    assert!(!lockouts[1]["login_lockout_user_id"].is_null());
+}
+
+#[tokio::test]
+async fn test_ip_locks_without_an_email() {
+    let mut lockout = test_lockout_config();
+    lockout.ip_threshold = 2;
+    lockout.max_delay_seconds = 0;
+    let app = TestApp::spawn_with_lockout(&lockout).await;
+    app.create_verified_user(EMAIL, "locked_user", PASSWORD)
+        .await;
+
+    for account in ["nobody@example.com", "someone@example.com"] {
+        let response = app.login_from("203.0.113.7", account, PASSWORD).await;
+        assert_eq!(response.body["data"]["code"], "INVALID_CREDENTIALS");
+    }
+
+    // the IP is refused whatever account it tries; other IPs are not
+    let response = app.login_from("203.0.113.7", EMAIL, PASSWORD).await;
+    assert_eq!(response.body["data"]["code"], "LOGIN_BACKOFF");
+    assert!(retry_after(&response) > 60);
+
+    let response = app.login_from("198.51.100.1", EMAIL, PASSWORD).await;
+    assert_eq!(response.status, StatusCode::OK, "{}", response.body);
+
+    let conn = app.state.get_conn().await.unwrap();
+    let lockouts =
+        serde_json::to_value(LoginLockout::get_recent(&conn, 10).await.unwrap()).unwrap();
+    assert_eq!(lockouts[0]["login_lockout_key"], "ip:203.0.113.7");
+    assert!(lockouts[0]["login_lockout_user_id"].is_null());
+    assert_eq!(app.deliver_emails().await, 0);
+}
@@ -0,0 +1,88 @@
+use std::sync::Arc;
+
+use axum::{
+    body::Body,
+    http::{header::CONTENT_TYPE, Method, Request, StatusCode},
+    Router,
+};
+use serde_json::json;
+use tower::ServiceExt;
+use tracing::Level;
+
+use crate::utils::server_init::server_init_funcs::{
+    admin_server::admin_router, initialize_metrics::init_metrics,
+};
+
+use super::harness::{test_log_filter, TestApp};
+
+/// the comma-separated directives of a filter as reported by /log-level
+fn directives(body: &serde_json::Value) -> Vec<String> {
+    body["filter"]
+        .as_str()
+        .unwrap()
+        .split(',')
+        .map(str::to_owned)
+        .collect()
+}
+
+async fn send(
+    router: &Router,
+    method: Method,
+    body: Option<serde_json::Value>,
+) -> (StatusCode, serde_json::Value) {
+    let response = router
+        .clone()
+        .oneshot(
+            Request::builder()
+                .method(method)
+                .uri("/log-level")
+                .header(CONTENT_TYPE, "application/json")
+                .body(match body {
+                    Some(body) => Body::from(body.to_string()),
+                    None => Body::empty(),
+                })
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+    let status = response.status();
+    let bytes = axum::body::to_bytes(response.into_body(), usize::MAX)
+        .await
+        .unwrap();
+    (status, serde_json::from_slice(&bytes).unwrap())
+}
+
+#[tokio::test]
+async fn test_log_level_can_be_changed_at_runtime() {
+    let app = TestApp::spawn().await;
+    let (log_filter, _log_guard) = test_log_filter();
+    let router = admin_router(Arc::clone(&app.state), init_metrics().unwrap(), log_filter);
+
+    let (status, body) = send(&router, Method::GET, None).await;
+    assert_eq!(status, StatusCode::OK);
+    assert!(directives(&body).contains(&"info".to_owned()), "{}", body);
+    assert!(!tracing::enabled!(Level::DEBUG));
+
+    let (status, body) = send(&router, Method::PUT, Some(json!({ "filter": "debug" }))).await;
+    assert_eq!(status, StatusCode::OK, "{}", body);
+    assert!(directives(&body).contains(&"debug".to_owned()), "{}", body);
+    assert!(tracing::enabled!(Level::DEBUG));
+    // the exclusions for noisy crates survive a change
+    assert!(
+        directives(&body).contains(&"rustls=off".to_owned()),
+        "{}",
+        body
+    );
+
+    // a bad filter is rejected and the current one stays
+    let (status, body) = send(
+        &router,
+        Method::PUT,
+        Some(json!({ "filter": "cyhdev_back=loud" })),
+    )
+    .await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert!(body["error"].is_string());
+    let (_, body) = send(&router, Method::GET, None).await;
+    assert!(directives(&body).contains(&"debug".to_owned()), "{}", body);
+}
@@ -0,0 +1,43 @@
+use axum::http::{Method, StatusCode};
+
+use super::harness::TestApp;
+
+#[tokio::test]
+async fn test_health_readiness_and_version() {
+    let app = TestApp::spawn().await;
+
+    let response = app.request(Method::GET, "/healthz", None).await;
+    assert_eq!(response.status, StatusCode::OK);
+    assert_eq!(response.body["data"]["status"], "ok");
+
+    let response = app.request(Method::GET, "/readyz", None).await;
+    assert_eq!(response.status, StatusCode::OK, "{}", response.body);
+    assert_eq!(response.body["data"]["ready"], true);
+    let checks = response.body["data"]["checks"].as_array().unwrap();
+    assert_eq!(checks.len(), 2);
+    assert!(checks.iter().all(|check| check["ok"] == true));
+
+    let response = app.request(Method::GET, "/api/meta/version", None).await;
+    assert_eq!(response.status, StatusCode::OK);
+    assert_eq!(response.body["data"]["name"], env!("CARGO_PKG_NAME"));
+    assert_eq!(response.body["data"]["version"], env!("CARGO_PKG_VERSION"));
+    assert!(response.body["data"]["git_commit"].is_string());
+    assert!(response.body["data"]["uptime_seconds"].as_i64().unwrap() >= 0);
+}
+
+#[tokio::test]
+async fn test_not_ready_without_a_database() {
+    let app = TestApp::spawn().await;
+
+    app.state.close_pool();
+
+    // liveness doesn't depend on the database
+    let response = app.request(Method::GET, "/healthz", None).await;
+    assert_eq!(response.status, StatusCode::OK);
+
+    let response = app.request(Method::GET, "/readyz", None).await;
+    assert_eq!(response.status, StatusCode::SERVICE_UNAVAILABLE);
+    assert_eq!(response.body["data"]["ready"], false);
+    assert_eq!(response.body["data"]["checks"][0]["name"], "database");
+    assert_eq!(response.body["data"]["checks"][0]["ok"], false);
+}
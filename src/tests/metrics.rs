@@ -0,0 +1,72 @@
+use std::sync::Arc;
+
+use axum::{
+    body::Body,
+    http::{header::CONTENT_TYPE, Method, Request, StatusCode},
+};
+use serde_json::json;
+use tower::ServiceExt;
+
+use crate::utils::server_init::server_init_funcs::{
+    admin_server::admin_router, initialize_metrics::init_metrics,
+};
+
+use super::harness::{test_log_filter, TestApp};
+
+#[tokio::test]
+async fn test_metrics_are_labelled_by_route_and_served_on_the_admin_router() {
+    let app = TestApp::spawn().await;
+    let handle = init_metrics().unwrap();
+    let (log_filter, _log_guard) = test_log_filter();
+
+    let response = app.request(Method::GET, "/healthz", None).await;
+    assert_eq!(response.status, StatusCode::OK);
+    let response = app.request(Method::GET, "/no/such/route/12345", None).await;
+    assert_eq!(response.status, StatusCode::NOT_FOUND);
+    let response = app
+        .post(
+            "/api/auth/signup",
+            json!({
+                "user_screen_name": "metrics_user",
+                "user_email": "metrics.user@example.com",
+                "user_password": "Sup3r$ecret",
+            }),
+        )
+        .await;
+    assert_eq!(response.status, StatusCode::OK, "{}", response.body);
+    assert_eq!(app.deliver_emails().await, 1);
+
+    // the public router doesn't expose it
+    let response = app.request(Method::GET, "/metrics", None).await;
+    assert_eq!(response.status, StatusCode::NOT_FOUND);
+
+    let response = admin_router(Arc::clone(&app.state), handle, log_filter)
+        .oneshot(
+            Request::builder()
+                .uri("/metrics")
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    assert!(response.headers()[CONTENT_TYPE]
+        .to_str()
+        .unwrap()
+        .starts_with("text/plain"));
+    let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+        .await
+        .unwrap();
+    let body = String::from_utf8(body.to_vec()).unwrap();
+
+    assert!(body.contains(r#"http_requests_total{method="GET",path="/healthz",status="200"}"#));
+    assert!(body.contains(
+        r#"http_request_duration_seconds_bucket{method="GET",path="/healthz",status="200",le="#
+    ));
+    assert!(body.contains(r#"http_requests_total{method="GET",path="unmatched",status="404"}"#));
+    assert!(!body.contains("/no/such/route"));
+    assert!(body.contains("db_pool_max_size 8"));
+    assert!(body.contains("db_pool_available"));
+    assert!(body.contains("emails_sent_total"));
+    assert!(body.contains(r#"password_hash_duration_seconds_bucket{operation="hash",le="#));
+}
@@ -0,0 +1,218 @@
+use axum::http::{Method, StatusCode};
+use chrono::Utc;
+use data_encoding::BASE32_NOPAD;
+use serde_json::json;
+
+use crate::utils::{
+    server_init::server_state_def::TEST_PUBLIC_BASE_URL,
+    two_factor::totp::{code_at, step_at},
+};
+
+use super::{
+    harness::{TestApp, TestResponse},
+    software_authenticator::SoftwareAuthenticator,
+};
+
+const EMAIL: &str = "passkey.user@example.com";
+const PASSWORD: &str = "Sup3r$ecret";
+
+/// runs the registration ceremony with the authenticator and returns the finish response
+async fn register(
+    app: &TestApp,
+    access_token: &str,
+    authenticator: &mut SoftwareAuthenticator,
+) -> TestResponse {
+    let response = app
+        .request_as(
+            Method::POST,
+            access_token,
+            "/api/auth/passkeys/register/start",
+            json!({}),
+        )
+        .await;
+    assert_eq!(response.status, StatusCode::OK, "{}", response.body);
+    let data = &response.body["data"];
+
+    app.request_as(
+        Method::POST,
+        access_token,
+        "/api/auth/passkeys/register/finish",
+        json!({
+            "challenge_id": data["challenge_id"],
+            "name": "laptop",
+            "credential": authenticator.register(&data["options"]),
+        }),
+    )
+    .await
+}
+
+/// starts a usernameless login and returns the finish body the authenticator answers it with
+async fn passkey_assertion(
+    app: &TestApp,
+    authenticator: &mut SoftwareAuthenticator,
+) -> serde_json::Value {
+    let response = app.post("/api/auth/passkeys/login/start", json!({})).await;
+    assert_eq!(response.status, StatusCode::OK, "{}", response.body);
+    let data = &response.body["data"];
+    assert_eq!(data["options"]["userVerification"], "required");
+
+    json!({
+        "challenge_id": data["challenge_id"],
+        "credential": authenticator.authenticate(&data["options"]),
+    })
+}
+
+#[tokio::test]
+async fn test_register_and_log_in_with_a_passkey() {
+    let app = TestApp::spawn().await;
+    app.create_verified_user(EMAIL, "passkey_user", PASSWORD)
+        .await;
+    let access_token = app.access_token(EMAIL, PASSWORD).await;
+    let mut authenticator = SoftwareAuthenticator::new("localhost", TEST_PUBLIC_BASE_URL);
+
+    let response = register(&app, &access_token, &mut authenticator).await;
+    assert_eq!(response.status, StatusCode::OK, "{}", response.body);
+    assert_eq!(response.body["data"]["passkey"]["passkey_name"], "laptop");
+    let passkey_id = response.body["data"]["passkey"]["passkey_id"]
+        .as_str()
+        .unwrap()
+        .to_owned();
+
+    // the registered credential is excluded from further ceremonies and can't be added twice
+    let response = register(&app, &access_token, &mut authenticator).await;
+    assert_eq!(response.status, StatusCode::CONFLICT);
+    assert_eq!(response.body["data"]["code"], "PASSKEY_ALREADY_REGISTERED");
+
+    let response = app
+        .request_as(Method::GET, &access_token, "/api/auth/passkeys", json!({}))
+        .await;
+    assert_eq!(
+        response.body["data"]["passkeys"].as_array().unwrap().len(),
+        1
+    );
+
+    // a passkey signs in on its own, without the password
+    let finish = passkey_assertion(&app, &mut authenticator).await;
+    let response = app
+        .post("/api/auth/passkeys/login/finish", finish.clone())
+        .await;
+    assert_eq!(response.status, StatusCode::OK, "{}", response.body);
+    assert_eq!(response.body["data"]["two_factor_required"], false);
+    assert_eq!(
+        response.body["data"]["user"]["user_screen_name"],
+        "passkey_user"
+    );
+    assert!(response.body["data"]["access_token"].is_string());
+
+    let response = app.post("/api/auth/passkeys/login/finish", finish).await;
+    assert_eq!(response.status, StatusCode::UNAUTHORIZED);
+    assert_eq!(response.body["data"]["code"], "PASSKEY_CHALLENGE_INVALID");
+
+    // a counter that went backwards gives away a cloned authenticator
+    authenticator.sign_count = 0;
+    let finish = passkey_assertion(&app, &mut authenticator).await;
+    let response = app.post("/api/auth/passkeys/login/finish", finish).await;
+    assert_eq!(response.body["data"]["code"], "PASSKEY_REJECTED");
+
+    let response = app
+        .request_as(
+            Method::DELETE,
+            &access_token,
+            &format!("/api/auth/passkeys/{}", passkey_id),
+            json!({}),
+        )
+        .await;
+    assert_eq!(response.status, StatusCode::OK, "{}", response.body);
+
+    authenticator.sign_count = 100;
+    let finish = passkey_assertion(&app, &mut authenticator).await;
+    let response = app.post("/api/auth/passkeys/login/finish", finish).await;
+    assert_eq!(response.body["data"]["code"], "INVALID_CREDENTIALS");
+}
+
+#[tokio::test]
+async fn test_passkey_answers_the_two_factor_challenge() {
+    let app = TestApp::spawn().await;
+    app.create_verified_user(EMAIL, "passkey_user", PASSWORD)
+        .await;
+    let access_token = app.access_token(EMAIL, PASSWORD).await;
+
+    // a security key that only checks for a touch
+    let mut security_key = SoftwareAuthenticator::new("localhost", TEST_PUBLIC_BASE_URL);
+    security_key.user_verified = false;
+    let response = register(&app, &access_token, &mut security_key).await;
+    assert_eq!(response.status, StatusCode::OK, "{}", response.body);
+
+    // which isn't enough to stand in for the password
+    let finish = passkey_assertion(&app, &mut security_key).await;
+    let response = app.post("/api/auth/passkeys/login/finish", finish).await;
+    assert_eq!(response.body["data"]["code"], "INVALID_CREDENTIALS");
+
+    let response = app
+        .request_as(
+            Method::POST,
+            &access_token,
+            "/api/auth/2fa/enroll",
+            json!({}),
+        )
+        .await;
+    let secret = BASE32_NOPAD
+        .decode(response.body["data"]["secret"].as_str().unwrap().as_bytes())
+        .unwrap();
+    let response = app
+        .request_as(
+            Method::POST,
+            &access_token,
+            "/api/auth/2fa/confirm",
+            json!({ "code": code_at(&secret, step_at(Utc::now().timestamp())) }),
+        )
+        .await;
+    assert_eq!(response.status, StatusCode::OK, "{}", response.body);
+
+    let response = app.login(EMAIL, PASSWORD).await;
+    assert_eq!(response.body["data"]["two_factor_required"], true);
+    assert_eq!(
+        response.body["data"]["two_factor_methods"],
+        json!(["totp", "recovery_code", "passkey"])
+    );
+    let challenge_token = response.body["data"]["challenge_token"].clone();
+
+    let response = app
+        .post(
+            "/api/auth/passkeys/2fa/start",
+            json!({ "challenge_token": challenge_token }),
+        )
+        .await;
+    assert_eq!(response.status, StatusCode::OK, "{}", response.body);
+    let data = &response.body["data"];
+    assert_eq!(
+        data["options"]["allowCredentials"]
+            .as_array()
+            .unwrap()
+            .len(),
+        1
+    );
+    let passkey = json!({
+        "challenge_id": data["challenge_id"],
+        "credential": security_key.authenticate(&data["options"]),
+    });
+
+    let response = app
+        .post(
+            "/api/auth/login/2fa",
+            json!({ "challenge_token": challenge_token, "passkey": passkey }),
+        )
+        .await;
+    assert_eq!(response.status, StatusCode::OK, "{}", response.body);
+    assert!(response.body["data"]["session_token"].is_string());
+
+    // the passkey challenge is spent along with the login challenge
+    let challenge_token = app.login(EMAIL, PASSWORD).await.body["data"]["challenge_token"].clone();
+    let response = app
+        .post(
+            "/api/auth/login/2fa",
+            json!({ "challenge_token": challenge_token, "passkey": passkey }),
+        )
+        .await;
+    assert_eq!(response.body["data"]["code"], "PASSKEY_CHALLENGE_INVALID");
+}
@@ -0,0 +1,81 @@
+use axum::http::{Method, StatusCode};
+use serde_json::json;
+
+use super::harness::{uuid_param, TestApp};
+
+const EMAIL: &str = "forgetful.user@example.com";
+const PASSWORD: &str = "Sup3r$ecret";
+const NEW_PASSWORD: &str = "N3w$ecret!";
+
+#[tokio::test]
+async fn test_reset_password_by_email_revokes_every_session() {
+    let app = TestApp::spawn().await;
+    app.create_verified_user(EMAIL, "forgetful_user", PASSWORD)
+        .await;
+    let session = app.login(EMAIL, PASSWORD).await.body["data"].clone();
+
+    // unknown addresses get the same answer and no email
+    let response = app
+        .post(
+            "/api/auth/forgot-password",
+            json!({ "user_email": "nobody@example.com" }),
+        )
+        .await;
+    assert_eq!(response.status, StatusCode::OK, "{}", response.body);
+    let unknown_message = response.body["data"]["message"].clone();
+    assert_eq!(app.deliver_emails().await, 0);
+
+    let response = app
+        .post("/api/auth/forgot-password", json!({ "user_email": EMAIL }))
+        .await;
+    assert_eq!(response.status, StatusCode::OK, "{}", response.body);
+    assert_eq!(response.body["data"]["message"], unknown_message);
+    assert_eq!(app.deliver_emails().await, 1);
+    let token_id = uuid_param(&app.mailer.sent_to(EMAIL)[0].text_body, "reset_token");
+
+    let response = app
+        .post(
+            "/api/auth/reset-password",
+            json!({ "token_id": token_id, "new_password": "short" }),
+        )
+        .await;
+    assert_eq!(response.body["data"]["code"], "WRONG_PW_FORMAT");
+
+    let response = app
+        .post(
+            "/api/auth/reset-password",
+            json!({ "token_id": token_id, "new_password": NEW_PASSWORD }),
+        )
+        .await;
+    assert_eq!(response.status, StatusCode::OK, "{}", response.body);
+
+    // whoever held the old password is signed out, and the link works once
+    let response = app
+        .request_as(
+            Method::GET,
+            session["access_token"].as_str().unwrap(),
+            "/api/auth/me",
+            json!({}),
+        )
+        .await;
+    assert_eq!(response.body["data"]["code"], "USER_SESSION_INVALID");
+    let response = app
+        .post(
+            "/api/auth/refresh",
+            json!({ "refresh_token": session["refresh_token"] }),
+        )
+        .await;
+    assert_eq!(response.body["data"]["code"], "USER_SESSION_INVALID");
+
+    let response = app
+        .post(
+            "/api/auth/reset-password",
+            json!({ "token_id": token_id, "new_password": PASSWORD }),
+        )
+        .await;
+    assert_eq!(response.body["data"]["code"], "USER_TOKEN_USED");
+
+    let response = app.login(EMAIL, PASSWORD).await;
+    assert_eq!(response.body["data"]["code"], "INVALID_CREDENTIALS");
+    app.access_token(EMAIL, NEW_PASSWORD).await;
+}
@@ -0,0 +1,112 @@
+use axum::http::{header::RETRY_AFTER, Method, StatusCode};
+use serde_json::json;
+
+use crate::utils::config::app_config::RateLimitStoreKind;
+
+use super::harness::{test_rate_limit_config, TestApp, TestResponse};
+
+const WRONG_PASSWORD: &str = "Wr0ng$password";
+
+fn assert_rate_limited(response: &TestResponse) {
+    assert_eq!(response.status, StatusCode::TOO_MANY_REQUESTS);
+    assert_eq!(response.body["success"], false);
+    assert_eq!(response.body["data"]["code"], "RATE_LIMITED");
+    assert_eq!(response.body["data"]["status_code"], 429);
+    let retry_after: u64 = response.headers[RETRY_AFTER]
+        .to_str()
+        .unwrap()
+        .parse()
+        .unwrap();
+    assert!(retry_after >= 1);
+    assert_eq!(response.headers["ratelimit-remaining"], "0");
+    assert_eq!(
+        response.headers["ratelimit-reset"],
+        response.headers[RETRY_AFTER]
+    );
+}
+
+#[tokio::test]
+async fn test_credentials_are_limited_per_ip() {
+    let mut limits = test_rate_limit_config(true);
+    limits.credentials = "2/min".parse().unwrap();
+    let app = TestApp::spawn_with_rate_limits(&limits).await;
+
+    let response = app
+        .login_from("203.0.113.7", "nobody@example.com", WRONG_PASSWORD)
+        .await;
+    assert_eq!(response.status, StatusCode::UNAUTHORIZED);
+    // the credentials bucket is stricter than the default one, so its numbers are reported
+    assert_eq!(response.headers["ratelimit-limit"], "2");
+    assert_eq!(response.headers["ratelimit-remaining"], "1");
+
+    let response = app
+        .login_from("203.0.113.7", "nobody@example.com", WRONG_PASSWORD)
+        .await;
+    assert_eq!(response.status, StatusCode::UNAUTHORIZED);
+
+    let response = app
+        .login_from("203.0.113.7", "nobody@example.com", WRONG_PASSWORD)
+        .await;
+    assert_rate_limited(&response);
+    assert_eq!(response.headers["ratelimit-limit"], "2");
+
+    // other clients and unlimited routes are unaffected
+    let response = app
+        .login_from("198.51.100.1", "nobody@example.com", WRONG_PASSWORD)
+        .await;
+    assert_eq!(response.status, StatusCode::UNAUTHORIZED);
+    let response = app.request(Method::GET, "/healthz", None).await;
+    assert_eq!(response.status, StatusCode::OK);
+    assert!(!response.headers.contains_key("ratelimit-limit"));
+}
+
+#[tokio::test]
+async fn test_logins_are_limited_per_account_across_ips() {
+    let mut limits = test_rate_limit_config(true);
+    limits.account = "2/hour".parse().unwrap();
+    let app = TestApp::spawn_with_rate_limits(&limits).await;
+
+    let response = app
+        .login_from("203.0.113.1", "Victim@Example.com", WRONG_PASSWORD)
+        .await;
+    assert_eq!(response.status, StatusCode::UNAUTHORIZED);
+    let response = app
+        .login_from("203.0.113.2", "victim@example.com", WRONG_PASSWORD)
+        .await;
+    assert_eq!(response.status, StatusCode::UNAUTHORIZED);
+
+    let response = app
+        .login_from("203.0.113.3", "victim@example.com ", WRONG_PASSWORD)
+        .await;
+    assert_rate_limited(&response);
+
+    let response = app
+        .login_from("203.0.113.3", "someone.else@example.com", WRONG_PASSWORD)
+        .await;
+    assert_eq!(response.status, StatusCode::UNAUTHORIZED);
+}
+
+#[tokio::test]
+async fn test_postgres_store_keeps_buckets_in_the_database() {
+    let mut limits = test_rate_limit_config(true);
+    limits.store = RateLimitStoreKind::Postgres;
+    limits.email = "1/min".parse().unwrap();
+    let app = TestApp::spawn_with_rate_limits(&limits).await;
+    assert_eq!(app.state.get_rate_limiter().store_name(), "postgres");
+
+    let body = json!({ "user_email": "someone@example.com" });
+    let response = app.post("/api/auth/forgot-password", body.clone()).await;
+    assert_eq!(response.status, StatusCode::OK, "{}", response.body);
+    let response = app.post("/api/auth/forgot-password", body).await;
+    assert_rate_limited(&response);
+
+    let conn = app.state.get_conn().await.unwrap();
+    let row = conn
+        .query_one(
+            "SELECT rate_limit_bucket_tokens FROM v1.rate_limit_buckets WHERE rate_limit_bucket_key = 'email:ip:127.0.0.1'",
+            &[],
+        )
+        .await
+        .unwrap();
+    assert!(row.get::<_, f64>(0) < 1.0);
+}
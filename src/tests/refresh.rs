@@ -0,0 +1,84 @@
+use axum::http::{Method, StatusCode};
+use serde_json::json;
+use uuid::Uuid;
+
+use super::harness::TestApp;
+
+const EMAIL: &str = "refresh.user@example.com";
+const PASSWORD: &str = "Sup3r$ecret";
+
+#[tokio::test]
+async fn test_refresh_tokens_rotate_and_reuse_revokes_every_session() {
+    let app = TestApp::spawn().await;
+    app.create_verified_user(EMAIL, "refresh_user", PASSWORD)
+        .await;
+
+    let phone = app.login(EMAIL, PASSWORD).await.body["data"].clone();
+    let laptop = app.login(EMAIL, PASSWORD).await.body["data"].clone();
+
+    // each refresh hands out a new token and a new access token for the same session
+    let response = app
+        .post(
+            "/api/auth/refresh",
+            json!({ "refresh_token": phone["refresh_token"] }),
+        )
+        .await;
+    assert_eq!(response.status, StatusCode::OK, "{}", response.body);
+    let rotated = response.body["data"]["refresh_token"].clone();
+    assert_ne!(rotated, phone["refresh_token"]);
+    let access_token = response.body["data"]["access_token"].as_str().unwrap();
+    let response = app
+        .request_as(Method::GET, access_token, "/api/auth/me", json!({}))
+        .await;
+    assert_eq!(response.status, StatusCode::OK, "{}", response.body);
+
+    // the spent token coming back means it was copied
+    let response = app
+        .post(
+            "/api/auth/refresh",
+            json!({ "refresh_token": phone["refresh_token"] }),
+        )
+        .await;
+    assert_eq!(response.status, StatusCode::UNAUTHORIZED);
+    assert_eq!(response.body["data"]["code"], "REFRESH_TOKEN_REUSED");
+
+    // neither the rotated token nor the user's other sessions survive
+    let response = app
+        .post("/api/auth/refresh", json!({ "refresh_token": rotated }))
+        .await;
+    assert_eq!(response.body["data"]["code"], "USER_SESSION_INVALID");
+    let response = app
+        .post(
+            "/api/auth/refresh",
+            json!({ "refresh_token": laptop["refresh_token"] }),
+        )
+        .await;
+    assert_eq!(response.body["data"]["code"], "USER_SESSION_INVALID");
+    let response = app
+        .request_as(
+            Method::GET,
+            laptop["access_token"].as_str().unwrap(),
+            "/api/auth/me",
+            json!({}),
+        )
+        .await;
+    assert_eq!(response.body["data"]["code"], "USER_SESSION_INVALID");
+
+    // a fresh login starts a new family
+    let response = app.login(EMAIL, PASSWORD).await;
+    let response = app
+        .post(
+            "/api/auth/refresh",
+            json!({ "refresh_token": response.body["data"]["refresh_token"] }),
+        )
+        .await;
+    assert_eq!(response.status, StatusCode::OK, "{}", response.body);
+
+    let response = app
+        .post(
+            "/api/auth/refresh",
+            json!({ "refresh_token": Uuid::new_v4() }),
+        )
+        .await;
+    assert_eq!(response.body["data"]["code"], "USER_TOKEN_INVALID");
+}
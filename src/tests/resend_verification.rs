@@ -0,0 +1,109 @@
+use axum::http::StatusCode;
+use serde_json::json;
+
+use crate::models::user_tokens::VERIFICATION_DAILY_CAP;
+
+use super::harness::{uuid_param, TestApp};
+
+const EMAIL: &str = "unverified.user@example.com";
+const PASSWORD: &str = "Sup3r$ecret";
+
+/// moves every verification token back past the resend cooldown, but not out of the daily window
+async fn end_cooldown(app: &TestApp) {
+    let conn = app.state.get_conn().await.unwrap();
+    conn.execute(
+        "UPDATE v1.user_tokens SET user_token_created_at = user_token_created_at - interval '2 minutes'",
+        &[],
+    )
+    .await
+    .unwrap();
+}
+
+#[tokio::test]
+async fn test_resend_verification_never_reveals_an_account() {
+    let app = TestApp::spawn().await;
+    let response = app
+        .post(
+            "/api/auth/signup",
+            json!({
+                "user_screen_name": "unverified_user",
+                "user_email": EMAIL,
+                "user_password": PASSWORD,
+            }),
+        )
+        .await;
+    assert_eq!(response.status, StatusCode::OK, "{}", response.body);
+    assert_eq!(app.deliver_emails().await, 1);
+    let first_token = uuid_param(&app.mailer.sent_to(EMAIL)[0].text_body, "email_token");
+    app.create_verified_user("verified.user@example.com", "verified_user", PASSWORD)
+        .await;
+
+    let response = app
+        .post(
+            "/api/auth/resend-verification",
+            json!({ "user_email": "nobody@example.com" }),
+        )
+        .await;
+    assert_eq!(response.status, StatusCode::OK, "{}", response.body);
+    let generic = response.body["data"].clone();
+
+    // a verified account, and one still cooling down from signup, answer the same and get no email
+    for email in ["verified.user@example.com", EMAIL] {
+        let response = app
+            .post(
+                "/api/auth/resend-verification",
+                json!({ "user_email": email }),
+            )
+            .await;
+        assert_eq!(response.status, StatusCode::OK, "{}", response.body);
+        assert_eq!(response.body["data"], generic);
+    }
+    assert_eq!(app.deliver_emails().await, 0);
+
+    // after the cooldown a new link goes out and the old one stops working
+    end_cooldown(&app).await;
+    let response = app
+        .post(
+            "/api/auth/resend-verification",
+            json!({ "user_email": EMAIL }),
+        )
+        .await;
+    assert_eq!(response.body["data"], generic);
+    assert_eq!(app.deliver_emails().await, 1);
+    let response = app
+        .post(
+            "/api/auth/validate-email",
+            json!({ "token_id": first_token }),
+        )
+        .await;
+    assert_eq!(response.body["data"]["code"], "USER_TOKEN_USED");
+
+    // up to the daily cap, after which resends are skipped just as quietly
+    for _ in 2..VERIFICATION_DAILY_CAP {
+        end_cooldown(&app).await;
+        app.post(
+            "/api/auth/resend-verification",
+            json!({ "user_email": EMAIL }),
+        )
+        .await;
+        assert_eq!(app.deliver_emails().await, 1);
+    }
+    end_cooldown(&app).await;
+    let response = app
+        .post(
+            "/api/auth/resend-verification",
+            json!({ "user_email": EMAIL }),
+        )
+        .await;
+    assert_eq!(response.status, StatusCode::OK, "{}", response.body);
+    assert_eq!(response.body["data"], generic);
+    assert_eq!(app.deliver_emails().await, 0);
+
+    // the latest link still verifies the account
+    let sent = app.mailer.sent_to(EMAIL);
+    let token_id = uuid_param(&sent.last().unwrap().text_body, "email_token");
+    let response = app
+        .post("/api/auth/validate-email", json!({ "token_id": token_id }))
+        .await;
+    assert_eq!(response.status, StatusCode::OK, "{}", response.body);
+}
@@ -0,0 +1,194 @@
+use axum::http::{header::SET_COOKIE, Method, StatusCode};
+use serde_json::json;
+
+use super::harness::TestApp;
+
+const EMAIL: &str = "session.user@example.com";
+const PASSWORD: &str = "Sup3r$ecret";
+
+fn token(data: &serde_json::Value, name: &str) -> String {
+    data[name].as_str().unwrap().to_owned()
+}
+
+#[tokio::test]
+async fn test_logout_ends_only_the_current_session() {
+    let app = TestApp::spawn().await;
+    app.create_verified_user(EMAIL, "session_user", PASSWORD)
+        .await;
+    let phone = app.login(EMAIL, PASSWORD).await.body["data"].clone();
+    let laptop = app.login(EMAIL, PASSWORD).await.body["data"].clone();
+
+    let response = app
+        .request_as(
+            Method::POST,
+            &token(&phone, "access_token"),
+            "/api/auth/logout",
+            json!({}),
+        )
+        .await;
+    assert_eq!(response.status, StatusCode::OK, "{}", response.body);
+    assert_eq!(response.body["data"]["revoked_sessions"], 1);
+    assert!(response.headers[SET_COOKIE]
+        .to_str()
+        .unwrap()
+        .contains("Max-Age=0"));
+
+    // the session's access token stops working, and its refresh token is refused without being taken for a
+    // stolen one
+    let response = app
+        .request_as(
+            Method::GET,
+            &token(&phone, "access_token"),
+            "/api/auth/me",
+            json!({}),
+        )
+        .await;
+    assert_eq!(response.status, StatusCode::UNAUTHORIZED);
+    assert_eq!(response.body["data"]["code"], "USER_SESSION_INVALID");
+    let response = app
+        .post(
+            "/api/auth/refresh",
+            json!({ "refresh_token": phone["refresh_token"] }),
+        )
+        .await;
+    assert_eq!(response.body["data"]["code"], "USER_SESSION_INVALID");
+
+    // so the other session carries on
+    let response = app
+        .request_as(
+            Method::GET,
+            &token(&laptop, "access_token"),
+            "/api/auth/me",
+            json!({}),
+        )
+        .await;
+    assert_eq!(response.status, StatusCode::OK, "{}", response.body);
+    let response = app
+        .post(
+            "/api/auth/refresh",
+            json!({ "refresh_token": laptop["refresh_token"] }),
+        )
+        .await;
+    assert_eq!(response.status, StatusCode::OK, "{}", response.body);
+}
+
+#[tokio::test]
+async fn test_logout_all_ends_every_session() {
+    let app = TestApp::spawn().await;
+    app.create_verified_user(EMAIL, "session_user", PASSWORD)
+        .await;
+    let phone = app.login(EMAIL, PASSWORD).await.body["data"].clone();
+    let laptop = app.login(EMAIL, PASSWORD).await.body["data"].clone();
+
+    let response = app
+        .request_as(
+            Method::POST,
+            &token(&phone, "access_token"),
+            "/api/auth/logout-all",
+            json!({}),
+        )
+        .await;
+    assert_eq!(response.status, StatusCode::OK, "{}", response.body);
+    assert_eq!(response.body["data"]["revoked_sessions"], 2);
+
+    for session in [&phone, &laptop] {
+        let response = app
+            .request_as(
+                Method::GET,
+                &token(session, "access_token"),
+                "/api/auth/me",
+                json!({}),
+            )
+            .await;
+        assert_eq!(response.body["data"]["code"], "USER_SESSION_INVALID");
+        let response = app
+            .post(
+                "/api/auth/refresh",
+                json!({ "refresh_token": session["refresh_token"] }),
+            )
+            .await;
+        assert_eq!(response.body["data"]["code"], "USER_SESSION_INVALID");
+    }
+}
+
+#[tokio::test]
+async fn test_sessions_are_listed_and_revoked_individually() {
+    let app = TestApp::spawn().await;
+    app.create_verified_user(EMAIL, "session_user", PASSWORD)
+        .await;
+    app.create_verified_user("someone.else@example.com", "someone_else", PASSWORD)
+        .await;
+    let phone = app.login(EMAIL, PASSWORD).await.body["data"].clone();
+    let laptop = app.login(EMAIL, PASSWORD).await.body["data"].clone();
+    let stranger = app.access_token("someone.else@example.com", PASSWORD).await;
+
+    let response = app
+        .request_as(
+            Method::GET,
+            &token(&phone, "access_token"),
+            "/api/auth/sessions",
+            json!({}),
+        )
+        .await;
+    assert_eq!(response.status, StatusCode::OK, "{}", response.body);
+    let sessions = response.body["data"]["sessions"].as_array().unwrap();
+    assert_eq!(sessions.len(), 2);
+    let current_session_id = response.body["data"]["current_session_id"].clone();
+    let laptop_session_id = sessions
+        .iter()
+        .map(|session| session["user_session_id"].clone())
+        .find(|session_id| *session_id != current_session_id)
+        .unwrap();
+    let laptop_session_uri = format!("/api/auth/sessions/{}", laptop_session_id.as_str().unwrap());
+
+    // another user's session looks like it doesn't exist
+    let response = app
+        .request_as(Method::DELETE, &stranger, &laptop_session_uri, json!({}))
+        .await;
+    assert_eq!(response.status, StatusCode::NOT_FOUND);
+    assert_eq!(response.body["data"]["code"], "USER_SESSION_NOT_FOUND");
+
+    let response = app
+        .request_as(
+            Method::DELETE,
+            &token(&phone, "access_token"),
+            &laptop_session_uri,
+            json!({}),
+        )
+        .await;
+    assert_eq!(response.status, StatusCode::OK, "{}", response.body);
+    assert_eq!(
+        response.body["data"]["revoked_session_id"],
+        laptop_session_id
+    );
+
+    let response = app
+        .request_as(
+            Method::GET,
+            &token(&laptop, "access_token"),
+            "/api/auth/me",
+            json!({}),
+        )
+        .await;
+    assert_eq!(response.body["data"]["code"], "USER_SESSION_INVALID");
+    let response = app
+        .post(
+            "/api/auth/refresh",
+            json!({ "refresh_token": laptop["refresh_token"] }),
+        )
+        .await;
+    assert_eq!(response.body["data"]["code"], "USER_SESSION_INVALID");
+
+    let response = app
+        .request_as(
+            Method::GET,
+            &token(&phone, "access_token"),
+            "/api/auth/sessions",
+            json!({}),
+        )
+        .await;
+    assert_eq!(
+        response.body["data"]["sessions"].as_array().unwrap().len(),
+        1
+    );
+}
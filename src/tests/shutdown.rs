@@ -0,0 +1,31 @@
+use std::{sync::Arc, time::Duration};
+
+use tokio_util::sync::CancellationToken;
+
+use crate::utils::workers::email_outbox_worker::run_email_outbox_worker;
+
+use super::harness::TestApp;
+
+#[tokio::test]
+async fn test_outbox_worker_stops_when_signalled() {
+    let app = TestApp::spawn().await;
+
+    let shutdown = CancellationToken::new();
+    let worker = tokio::spawn(run_email_outbox_worker(
+        Arc::clone(&app.state),
+        shutdown.clone(),
+    ));
+
+    // let it get through its first poll
+    tokio::time::sleep(Duration::from_millis(200)).await;
+    assert!(!worker.is_finished());
+
+    shutdown.cancel();
+    tokio::time::timeout(Duration::from_secs(5), worker)
+        .await
+        .expect("worker did not stop after shutdown was signalled")
+        .unwrap();
+
+    app.state.close_pool();
+    assert!(app.state.get_conn().await.is_err());
+}
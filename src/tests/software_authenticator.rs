@@ -0,0 +1,145 @@
+use aws_lc_rs::{
+    digest,
+    rand::{self, SystemRandom},
+    signature::{EcdsaKeyPair, KeyPair, ECDSA_P256_SHA256_ASN1_SIGNING},
+};
+use ciborium::value::Value;
+use data_encoding::BASE64URL_NOPAD;
+use serde_json::json;
+
+/// a P-256 passkey held in memory that answers WebAuthn options the way a browser and platform
+/// authenticator would, so the passkey endpoints can be driven without hardware
+pub struct SoftwareAuthenticator {
+    key_pair: EcdsaKeyPair,
+    credential_id: Vec<u8>,
+    rp_id: String,
+    /// the page the ceremony appears to run on
+    pub origin: String,
+    /// whether the authenticator reports checking a PIN or biometric, not only a touch
+    pub user_verified: bool,
+    /// incremented before every assertion; set it back to mimic a cloned authenticator
+    pub sign_count: u32,
+    user_handle: Option<String>,
+}
+
+impl SoftwareAuthenticator {
+    pub fn new(rp_id: &str, origin: &str) -> Self {
+        let mut credential_id = vec![0u8; 16];
+        rand::fill(&mut credential_id).unwrap();
+        SoftwareAuthenticator {
+            key_pair: EcdsaKeyPair::generate(&ECDSA_P256_SHA256_ASN1_SIGNING).unwrap(),
+            credential_id,
+            rp_id: rp_id.to_owned(),
+            origin: origin.to_owned(),
+            user_verified: true,
+            sign_count: 0,
+            user_handle: None,
+        }
+    }
+
+    pub fn credential_id(&self) -> Vec<u8> {
+        self.credential_id.clone()
+    }
+
+    /// answers creation options with a "none" attestation, as RegistrationResponseJSON
+    pub fn register(&mut self, options: &serde_json::Value) -> serde_json::Value {
+        self.user_handle = options["user"]["id"].as_str().map(str::to_owned);
+        let client_data_json = self.client_data_json("webauthn.create", options);
+
+        let mut auth_data = self.auth_data_header(0x40);
+        auth_data.extend_from_slice(&[0u8; 16]);
+        auth_data.extend_from_slice(&(self.credential_id.len() as u16).to_be_bytes());
+        auth_data.extend_from_slice(&self.credential_id);
+        auth_data.extend_from_slice(&cose_es256_key(self.key_pair.public_key().as_ref()));
+
+        let mut attestation_object = Vec::new();
+        ciborium::ser::into_writer(
+            &Value::Map(vec![
+                (Value::from("fmt"), Value::from("none")),
+                (Value::from("attStmt"), Value::Map(Vec::new())),
+                (Value::from("authData"), Value::Bytes(auth_data)),
+            ]),
+            &mut attestation_object,
+        )
+        .unwrap();
+
+        json!({
+            "id": BASE64URL_NOPAD.encode(&self.credential_id),
+            "rawId": BASE64URL_NOPAD.encode(&self.credential_id),
+            "type": "public-key",
+            "response": {
+                "clientDataJSON": BASE64URL_NOPAD.encode(&client_data_json),
+                "attestationObject": BASE64URL_NOPAD.encode(&attestation_object),
+                "transports": ["internal"],
+            },
+            "clientExtensionResults": {},
+        })
+    }
+
+    /// answers request options with a signed assertion, as AuthenticationResponseJSON
+    pub fn authenticate(&mut self, options: &serde_json::Value) -> serde_json::Value {
+        self.sign_count += 1;
+        let client_data_json = self.client_data_json("webauthn.get", options);
+        let auth_data = self.auth_data_header(0);
+
+        let mut signed = auth_data.clone();
+        signed.extend_from_slice(digest::digest(&digest::SHA256, &client_data_json).as_ref());
+        let signature = self.key_pair.sign(&SystemRandom::new(), &signed).unwrap();
+
+        json!({
+            "id": BASE64URL_NOPAD.encode(&self.credential_id),
+            "rawId": BASE64URL_NOPAD.encode(&self.credential_id),
+            "type": "public-key",
+            "response": {
+                "clientDataJSON": BASE64URL_NOPAD.encode(&client_data_json),
+                "authenticatorData": BASE64URL_NOPAD.encode(&auth_data),
+                "signature": BASE64URL_NOPAD.encode(signature.as_ref()),
+                "userHandle": self.user_handle,
+            },
+            "clientExtensionResults": {},
+        })
+    }
+
+    fn client_data_json(&self, ceremony_type: &str, options: &serde_json::Value) -> Vec<u8> {
+        json!({
+            "type": ceremony_type,
+            "challenge": options["challenge"],
+            "origin": self.origin,
+            "crossOrigin": false,
+        })
+        .to_string()
+        .into_bytes()
+    }
+
+    /// rpIdHash, flags (always user present) and the sign counter
+    fn auth_data_header(&self, extra_flags: u8) -> Vec<u8> {
+        let mut flags = 0x01 | extra_flags;
+        if self.user_verified {
+            flags |= 0x04;
+        }
+
+        let mut auth_data = digest::digest(&digest::SHA256, self.rp_id.as_bytes())
+            .as_ref()
+            .to_vec();
+        auth_data.push(flags);
+        auth_data.extend_from_slice(&self.sign_count.to_be_bytes());
+        auth_data
+    }
+}
+
+/// a P-256 public key in uncompressed SEC1 form as an ES256 COSE_Key
+pub fn cose_es256_key(public_key: &[u8]) -> Vec<u8> {
+    let mut cose = Vec::new();
+    ciborium::ser::into_writer(
+        &Value::Map(vec![
+            (Value::from(1), Value::from(2)),
+            (Value::from(3), Value::from(-7)),
+            (Value::from(-1), Value::from(1)),
+            (Value::from(-2), Value::Bytes(public_key[1..33].to_vec())),
+            (Value::from(-3), Value::Bytes(public_key[33..65].to_vec())),
+        ]),
+        &mut cose,
+    )
+    .unwrap();
+    cose
+}
@@ -0,0 +1,67 @@
+use axum::{
+    body::Body,
+    http::{Method, Request, StatusCode},
+};
+use opentelemetry::{global, trace::TracerProvider};
+use opentelemetry_sdk::{
+    propagation::TraceContextPropagator,
+    trace::{InMemorySpanExporter, SdkTracerProvider},
+};
+use tracing_subscriber::layer::SubscriberExt;
+
+use super::harness::TestApp;
+
+const TRACE_ID: &str = "4bf92f3577b34da6a3ce929d0e0e4736";
+const PARENT_SPAN_ID: &str = "00f067aa0ba902b7";
+
+#[tokio::test]
+async fn test_request_id_is_echoed_or_generated() {
+    let app = TestApp::spawn().await;
+
+    let response = app.request(Method::GET, "/healthz", None).await;
+    let generated = response.headers["x-request-id"].to_str().unwrap();
+    assert!(uuid::Uuid::parse_str(generated).is_ok());
+
+    let request = Request::builder()
+        .method(Method::GET)
+        .uri("/healthz")
+        .header("x-request-id", "lb-1234")
+        .body(Body::empty())
+        .unwrap();
+    let response = app.router_request(request).await;
+    assert_eq!(response.headers["x-request-id"], "lb-1234");
+}
+
+#[tokio::test]
+async fn test_request_span_continues_the_incoming_trace() {
+    let app = TestApp::spawn().await;
+
+    // the same propagator and layer init_trace_export installs, exporting into memory instead of over OTLP
+    let exporter = InMemorySpanExporter::default();
+    let provider = SdkTracerProvider::builder()
+        .with_simple_exporter(exporter.clone())
+        .build();
+    global::set_text_map_propagator(TraceContextPropagator::new());
+    let _guard = tracing::subscriber::set_default(
+        tracing_subscriber::registry()
+            .with(tracing_opentelemetry::layer().with_tracer(provider.tracer("test"))),
+    );
+
+    let request = Request::builder()
+        .method(Method::GET)
+        .uri("/healthz")
+        .header("traceparent", format!("00-{TRACE_ID}-{PARENT_SPAN_ID}-01"))
+        .body(Body::empty())
+        .unwrap();
+    let response = app.router_request(request).await;
+    assert_eq!(response.status, StatusCode::OK);
+
+    provider.force_flush().unwrap();
+    let spans = exporter.get_finished_spans().unwrap();
+    let span = spans
+        .iter()
+        .find(|span| span.name == "GET /healthz")
+        .expect("no span was exported for the request");
+    assert_eq!(span.span_context.trace_id().to_string(), TRACE_ID);
+    assert_eq!(span.parent_span_id.to_string(), PARENT_SPAN_ID);
+}
@@ -0,0 +1,225 @@
+use axum::http::{Method, StatusCode};
+use chrono::Utc;
+use data_encoding::{BASE32_NOPAD, BASE64};
+use serde_json::json;
+
+use crate::utils::two_factor::totp::{code_at, step_at};
+
+use super::harness::TestApp;
+
+const EMAIL: &str = "two.factor@example.com";
+const PASSWORD: &str = "Sup3r$ecret";
+
+/// logs in with the password and returns the 2FA challenge
+async fn challenge(app: &TestApp) -> String {
+    let response = app.login(EMAIL, PASSWORD).await;
+    assert_eq!(response.status, StatusCode::OK, "{}", response.body);
+    assert_eq!(response.body["data"]["two_factor_required"], true);
+    assert!(response.body["data"]["session_token"].is_null());
+    response.body["data"]["challenge_token"]
+        .as_str()
+        .unwrap()
+        .to_owned()
+}
+
+#[tokio::test]
+async fn test_enroll_confirm_and_log_in_with_a_code() {
+    let app = TestApp::spawn().await;
+    app.create_verified_user(EMAIL, "two_factor_user", PASSWORD)
+        .await;
+    let access_token = app.access_token(EMAIL, PASSWORD).await;
+
+    let response = app
+        .request_as(
+            Method::POST,
+            &access_token,
+            "/api/auth/2fa/confirm",
+            json!({ "code": "000000" }),
+        )
+        .await;
+    assert_eq!(response.body["data"]["code"], "TWO_FACTOR_NOT_ENROLLED");
+
+    let response = app
+        .request_as(
+            Method::POST,
+            &access_token,
+            "/api/auth/2fa/enroll",
+            json!({}),
+        )
+        .await;
+    assert_eq!(response.status, StatusCode::OK, "{}", response.body);
+    let data = &response.body["data"];
+    let secret = BASE32_NOPAD
+        .decode(data["secret"].as_str().unwrap().as_bytes())
+        .unwrap();
+    assert!(data["otpauth_uri"]
+        .as_str()
+        .unwrap()
+        .starts_with("otpauth://totp/cyhdev.com:two.factor%40example.com?secret="));
+    let png = BASE64
+        .decode(data["qr_code_png"].as_str().unwrap().as_bytes())
+        .unwrap();
+    assert!(png.starts_with(b"\x89PNG"));
+
+    // the secret is only stored encrypted
+    let conn = app.state.get_conn().await.unwrap();
+    let stored: Vec<u8> = conn
+        .query_one("SELECT user_totp_secret_encrypted FROM v1.user_totp", &[])
+        .await
+        .unwrap()
+        .get(0);
+    assert!(!stored.windows(secret.len()).any(|window| window == secret));
+
+    // enrolling isn't enough; the password still opens a session until the code is confirmed
+    let response = app.login(EMAIL, PASSWORD).await;
+    assert_eq!(response.body["data"]["two_factor_required"], false);
+
+    let step = step_at(Utc::now().timestamp());
+    let response = app
+        .request_as(
+            Method::POST,
+            &access_token,
+            "/api/auth/2fa/confirm",
+            json!({ "code": code_at(&secret, step + 5) }),
+        )
+        .await;
+    assert_eq!(response.body["data"]["code"], "INVALID_TWO_FACTOR_CODE");
+
+    let response = app
+        .request_as(
+            Method::POST,
+            &access_token,
+            "/api/auth/2fa/confirm",
+            json!({ "code": code_at(&secret, step) }),
+        )
+        .await;
+    assert_eq!(response.status, StatusCode::OK, "{}", response.body);
+    assert_eq!(
+        response.body["data"]["recovery_codes"]
+            .as_array()
+            .unwrap()
+            .len(),
+        10
+    );
+
+    let response = app
+        .request_as(
+            Method::POST,
+            &access_token,
+            "/api/auth/2fa/enroll",
+            json!({}),
+        )
+        .await;
+    assert_eq!(response.body["data"]["code"], "TWO_FACTOR_ALREADY_ENABLED");
+
+    let challenge_token = challenge(&app).await;
+    let response = app
+        .post(
+            "/api/auth/login/2fa",
+            json!({ "challenge_token": challenge_token, "code": code_at(&secret, step + 5) }),
+        )
+        .await;
+    assert_eq!(response.status, StatusCode::UNAUTHORIZED);
+    assert_eq!(response.body["data"]["code"], "INVALID_TWO_FACTOR_CODE");
+
+    // the confirming code's step is spent; the next one is within the allowed skew
+    let response = app
+        .post(
+            "/api/auth/login/2fa",
+            json!({ "challenge_token": challenge_token, "code": code_at(&secret, step) }),
+        )
+        .await;
+    assert_eq!(response.body["data"]["code"], "INVALID_TWO_FACTOR_CODE");
+
+    let next_code = code_at(&secret, step + 1);
+    let response = app
+        .post(
+            "/api/auth/login/2fa",
+            json!({ "challenge_token": challenge_token, "code": next_code }),
+        )
+        .await;
+    assert_eq!(response.status, StatusCode::OK, "{}", response.body);
+    assert!(response.body["data"]["access_token"].is_string());
+    assert!(response.body["data"]["session_token"].is_string());
+
+    let response = app
+        .post(
+            "/api/auth/login/2fa",
+            json!({ "challenge_token": challenge_token, "code": next_code }),
+        )
+        .await;
+    assert_eq!(response.body["data"]["code"], "USER_TOKEN_USED");
+
+    // a fresh challenge can't replay the code either
+    let challenge_token = challenge(&app).await;
+    let response = app
+        .post(
+            "/api/auth/login/2fa",
+            json!({ "challenge_token": challenge_token, "code": next_code }),
+        )
+        .await;
+    assert_eq!(response.body["data"]["code"], "INVALID_TWO_FACTOR_CODE");
+}
+
+#[tokio::test]
+async fn test_recovery_codes_work_once() {
+    let app = TestApp::spawn().await;
+    app.create_verified_user(EMAIL, "two_factor_user", PASSWORD)
+        .await;
+    let access_token = app.access_token(EMAIL, PASSWORD).await;
+
+    let response = app
+        .request_as(
+            Method::POST,
+            &access_token,
+            "/api/auth/2fa/enroll",
+            json!({}),
+        )
+        .await;
+    let secret = BASE32_NOPAD
+        .decode(response.body["data"]["secret"].as_str().unwrap().as_bytes())
+        .unwrap();
+    let response = app
+        .request_as(
+            Method::POST,
+            &access_token,
+            "/api/auth/2fa/confirm",
+            json!({ "code": code_at(&secret, step_at(Utc::now().timestamp())) }),
+        )
+        .await;
+    assert_eq!(response.status, StatusCode::OK, "{}", response.body);
+    let recovery_code = response.body["data"]["recovery_codes"][3]
+        .as_str()
+        .unwrap()
+        .to_owned();
+
+    // typed back without the dash and in capitals
+    let challenge_token = challenge(&app).await;
+    let response = app
+        .post(
+            "/api/auth/login/2fa",
+            json!({
+                "challenge_token": challenge_token,
+                "recovery_code": recovery_code.replace('-', "").to_uppercase(),
+            }),
+        )
+        .await;
+    assert_eq!(response.status, StatusCode::OK, "{}", response.body);
+
+    let challenge_token = challenge(&app).await;
+    let response = app
+        .post(
+            "/api/auth/login/2fa",
+            json!({ "challenge_token": challenge_token, "recovery_code": recovery_code }),
+        )
+        .await;
+    assert_eq!(response.body["data"]["code"], "INVALID_TWO_FACTOR_CODE");
+
+    let response = app
+        .post(
+            "/api/auth/login/2fa",
+            json!({ "challenge_token": challenge_token }),
+        )
+        .await;
+    assert_eq!(response.body["data"]["code"], "INVALID_TWO_FACTOR_CODE");
+}
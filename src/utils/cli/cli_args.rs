@@ -0,0 +1,86 @@
+use std::path::PathBuf;
+
+use anyhow::anyhow;
+
+/// command line flags; with none given the server starts normally
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct CliArgs {
+    /// `--preview-email <template>`: print a rendered email template and exit
+    pub preview_email: Option<String>,
+    /// `--locale <locale>`: locale used by --preview-email
+    pub locale: Option<String>,
+    /// `--config <path>`: TOML config file; defaults to CONFIG_FILE, then ./config.toml if it exists
+    pub config: Option<PathBuf>,
+    /// `--print-config`: print the effective configuration with secrets redacted, validate it and exit
+    pub print_config: bool,
+    /// `--migrate-only`: apply pending database migrations and exit
+    pub migrate_only: bool,
+    /// `--dry-run`: with --migrate-only, run pending migrations in a transaction that is rolled back
+    pub dry_run: bool,
+}
+
+impl CliArgs {
+    pub fn from_env() -> anyhow::Result<Self> {
+        CliArgs::parse(std::env::args().skip(1))
+    }
+
+    pub fn parse(args: impl IntoIterator<Item = String>) -> anyhow::Result<Self> {
+        let mut cli_args = CliArgs::default();
+        let mut args = args.into_iter();
+
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--preview-email" => cli_args.preview_email = Some(expect_value(&mut args, &arg)?),
+                "--locale" => cli_args.locale = Some(expect_value(&mut args, &arg)?),
+                "--config" => cli_args.config = Some(expect_value(&mut args, &arg)?.into()),
+                "--print-config" => cli_args.print_config = true,
+                "--migrate-only" => cli_args.migrate_only = true,
+                "--dry-run" => cli_args.dry_run = true,
+                _ => return Err(anyhow!("Unknown argument {:?}", arg)),
+            }
+        }
+
+        if cli_args.dry_run && !cli_args.migrate_only {
+            return Err(anyhow!("--dry-run can only be used with --migrate-only"));
+        }
+
+        Ok(cli_args)
+    }
+}
+
+fn expect_value(args: &mut impl Iterator<Item = String>, flag: &str) -> anyhow::Result<String> {
+    args.next()
+        .ok_or_else(|| anyhow!("Missing value for {}", flag))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(args: &[&str]) -> anyhow::Result<CliArgs> {
+        CliArgs::parse(args.iter().map(|arg| arg.to_string()))
+    }
+
+    #[test]
+    fn test_parse_args() {
+        assert_eq!(parse(&[]).unwrap(), CliArgs::default());
+
+        let cli_args = parse(&["--preview-email", "verify_email", "--locale", "ko"]).unwrap();
+        assert_eq!(cli_args.preview_email.as_deref(), Some("verify_email"));
+        assert_eq!(cli_args.locale.as_deref(), Some("ko"));
+
+        assert!(parse(&["--preview-email"]).is_err());
+        assert!(parse(&["--bogus"]).is_err());
+
+        let cli_args = parse(&["--migrate-only", "--dry-run"]).unwrap();
+        assert!(cli_args.migrate_only && cli_args.dry_run);
+        assert!(parse(&["--dry-run"]).is_err());
+
+        let cli_args = parse(&["--config", "/etc/cyhdev/config.toml", "--print-config"]).unwrap();
+        assert_eq!(
+            cli_args.config.as_deref(),
+            Some(std::path::Path::new("/etc/cyhdev/config.toml"))
+        );
+        assert!(cli_args.print_config);
+    }
+}
@@ -0,0 +1,32 @@
+use std::path::Path;
+
+use crate::utils::{
+    config::app_config::ConfigSources,
+    mail::templates::{render, EmailTemplate, DEFAULT_LOCALE},
+};
+
+/// `--preview-email <template> [--locale <locale>]`: renders a template with sample data to stdout
+pub fn print_email_preview(
+    template: &str,
+    locale: Option<&str>,
+    config_path: Option<&Path>,
+) -> anyhow::Result<()> {
+    let template = template.parse::<EmailTemplate>()?;
+    // only the base URL is needed, so the rest of the config doesn't have to be valid
+    let sources = ConfigSources::load(config_path);
+    let public_base_url = sources
+        .get("server.public_base_url")
+        .unwrap_or_default()
+        .trim_end_matches('/');
+    let rendered = render(
+        template,
+        locale.unwrap_or(DEFAULT_LOCALE),
+        &template.sample_vars(public_base_url),
+    )?;
+
+    println!("Subject: {}", rendered.subject);
+    println!("\n--- text/plain ---\n{}", rendered.text_body);
+    println!("--- text/html ---\n{}", rendered.html_body);
+
+    Ok(())
+}
@@ -0,0 +1,12 @@
+use std::path::Path;
+
+use crate::utils::config::app_config::ConfigSources;
+
+/// `--print-config [--config <path>]`: prints the effective configuration, then fails if it is invalid
+pub fn print_config(config_path: Option<&Path>) -> anyhow::Result<()> {
+    let sources = ConfigSources::load(config_path);
+    print!("{}", sources.render());
+    sources.build()?;
+
+    Ok(())
+}
@@ -0,0 +1,1242 @@
+use std::{
+    collections::BTreeMap,
+    fmt::{self, Display, Write},
+    net::{IpAddr, SocketAddr},
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+
+use anyhow::anyhow;
+
+use crate::{
+    models::jwt::JWT,
+    utils::{
+        mail::smtp_transport::SmtpTlsMode, net::trusted_proxies::TrustedProxies,
+        rate_limit::rate_limit_store::RateLimit, two_factor::secret_cipher::SecretCipher,
+        webauthn::relying_party::RelyingParty,
+    },
+};
+
+/// read when neither --config nor CONFIG_FILE names a file; a missing default file is not an error
+pub const DEFAULT_CONFIG_PATH: &str = "config.toml";
+pub const REDACTED: &str = "<redacted>";
+
+/// a value that must never be logged; Debug prints a placeholder and the value is only reachable through expose()
+#[derive(Clone, PartialEq, Eq)]
+pub struct Secret(String);
+
+impl Secret {
+    pub fn new(value: impl Into<String>) -> Self {
+        Secret(value.into())
+    }
+
+    pub fn expose(&self) -> &str {
+        &self.0
+    }
+}
+
+impl fmt::Debug for Secret {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(REDACTED)
+    }
+}
+
+impl FromStr for Secret {
+    type Err = std::convert::Infallible;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(Secret::new(s))
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MailTransportKind {
+    Smtp,
+    File,
+    Memory,
+}
+
+impl FromStr for MailTransportKind {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "smtp" => Ok(MailTransportKind::Smtp),
+            "file" => Ok(MailTransportKind::File),
+            "memory" => Ok(MailTransportKind::Memory),
+            _ => Err(anyhow!("expected smtp, file or memory")),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LogFormat {
+    Text,
+    Json,
+}
+
+impl FromStr for LogFormat {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "text" => Ok(LogFormat::Text),
+            "json" => Ok(LogFormat::Json),
+            _ => Err(anyhow!("expected text or json")),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LogRotation {
+    Hourly,
+    Daily,
+    /// only by size
+    Never,
+}
+
+impl FromStr for LogRotation {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "hourly" => Ok(LogRotation::Hourly),
+            "daily" => Ok(LogRotation::Daily),
+            "never" => Ok(LogRotation::Never),
+            _ => Err(anyhow!("expected hourly, daily or never")),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RateLimitStoreKind {
+    Memory,
+    Postgres,
+}
+
+impl FromStr for RateLimitStoreKind {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "memory" => Ok(RateLimitStoreKind::Memory),
+            "postgres" => Ok(RateLimitStoreKind::Postgres),
+            _ => Err(anyhow!("expected memory or postgres")),
+        }
+    }
+}
+
+/// the whole effective configuration, validated; built by ConfigSources::build
+#[derive(Clone, Debug)]
+pub struct AppConfig {
+    pub server: ServerConfig,
+    pub database: DatabaseConfig,
+    pub mail: MailConfig,
+    pub jwt: JwtConfig,
+    pub telemetry: TelemetryConfig,
+    pub logging: LoggingConfig,
+    pub rate_limit: RateLimitConfig,
+    pub lockout: LockoutConfig,
+    pub two_factor: TwoFactorConfig,
+    pub webauthn: WebauthnConfig,
+}
+
+#[derive(Clone, Debug)]
+pub struct ServerConfig {
+    pub host_addr: IpAddr,
+    pub host_port: u16,
+    pub http_redirect_port: u16,
+    /// plain-HTTP listener for /metrics; keep it on a private interface
+    pub admin_addr: SocketAddr,
+    /// how long in-flight requests and background work get to finish after SIGTERM/SIGINT
+    pub shutdown_drain_seconds: u64,
+    /// origin of the frontend that links in emails point to, without a trailing slash
+    pub public_base_url: String,
+    pub cert_path: PathBuf,
+    pub key_path: PathBuf,
+    /// peers whose X-Forwarded-For, Forwarded and PROXY protocol headers are believed
+    pub trusted_proxies: TrustedProxies,
+    /// whether every connection to the HTTPS listener starts with a PROXY protocol v1/v2 header
+    pub proxy_protocol: bool,
+}
+
+#[derive(Clone, Debug)]
+pub struct DatabaseConfig {
+    pub host: String,
+    pub port: u16,
+    pub user: String,
+    pub password: Secret,
+    pub name: String,
+}
+
+#[derive(Clone, Debug)]
+pub struct MailConfig {
+    pub transport: MailTransportKind,
+    pub file_dir: PathBuf,
+    pub smtp_host: String,
+    pub smtp_port: Option<u16>,
+    pub smtp_tls: SmtpTlsMode,
+    pub smtp_credentials: Option<(String, Secret)>,
+    /// whether /readyz also requires the mail transport to be reachable
+    pub readiness_check: bool,
+}
+
+#[derive(Clone, Debug)]
+pub struct JwtConfig {
+    pub active_kid: String,
+    pub secrets: Vec<(String, Secret)>,
+}
+
+#[derive(Clone, Debug)]
+pub struct TelemetryConfig {
+    /// base URL of an OTLP/HTTP collector, e.g. http://localhost:4318; spans are only exported when set
+    pub otlp_endpoint: Option<String>,
+    pub service_name: String,
+    /// fraction of new traces to record; traces started upstream follow the caller's sampling decision
+    pub sample_ratio: f64,
+}
+
+#[derive(Clone, Debug)]
+pub struct LoggingConfig {
+    /// EnvFilter directives, e.g. `info` or `info,cyhdev_back=debug`; can be changed at runtime on the admin listener
+    pub level: String,
+    pub format: LogFormat,
+    /// log to rotated files in this directory instead of stdout
+    pub dir: Option<PathBuf>,
+    pub rotation: LogRotation,
+    /// a file is also rotated once it reaches this size
+    pub max_file_size_mb: u64,
+    /// rotated files kept besides the current one; older ones are deleted
+    pub max_files: usize,
+}
+
+#[derive(Clone, Debug)]
+pub struct RateLimitConfig {
+    pub enabled: bool,
+    /// memory limits each instance on its own; postgres shares buckets between instances
+    pub store: RateLimitStoreKind,
+    /// per client IP across every API route
+    pub default: RateLimit,
+    /// per client IP on signup and login
+    pub credentials: RateLimit,
+    /// per client IP on routes that redeem emailed or refresh tokens
+    pub tokens: RateLimit,
+    /// per client IP on routes that send email
+    pub email: RateLimit,
+    /// per account on login, forgot-password and resend-verification, whatever the IP
+    pub account: RateLimit,
+}
+
+#[derive(Clone, Debug)]
+pub struct LockoutConfig {
+    pub enabled: bool,
+    /// failed logins against one account, from any IP, before it is locked and its owner emailed
+    pub account_threshold: u32,
+    /// failed logins from one client IP, against any account, before it is locked
+    pub ip_threshold: u32,
+    /// failures allowed before each further attempt has to wait
+    pub free_attempts: u32,
+    /// the wait doubles with every failure past the free attempts, up to this
+    pub max_delay_seconds: u64,
+    /// a counter without failures for this long starts over
+    pub window_minutes: u64,
+    /// how long a lock lasts unless the owner lifts it from the unlock email
+    pub duration_minutes: u64,
+}
+
+#[derive(Clone, Debug)]
+pub struct TwoFactorConfig {
+    /// shown as the account's label in authenticator apps
+    pub issuer: String,
+    /// base64 of 32 bytes; TOTP secrets are encrypted with it, so changing it voids every enrollment
+    pub encryption_key: Secret,
+}
+
+#[derive(Clone, Debug)]
+pub struct WebauthnConfig {
+    /// the domain passkeys are scoped to; changing it orphans every registered passkey
+    pub rp_id: String,
+    /// shown by the browser and authenticator during a ceremony
+    pub rp_name: String,
+    /// origins ceremonies may run on, each the RP ID or a subdomain of it
+    pub origins: Vec<String>,
+}
+
+impl AppConfig {
+    /// defaults, then the TOML file, then env vars; every problem is reported in one error
+    pub fn load(path: Option<&Path>) -> anyhow::Result<Self> {
+        ConfigSources::load(path).build()
+    }
+}
+
+enum SettingDefault {
+    Required,
+    Optional,
+    Value(&'static str),
+}
+
+struct Setting {
+    key: &'static str,
+    env: &'static str,
+    default: SettingDefault,
+    secret: bool,
+}
+
+const fn setting(key: &'static str, env: &'static str, default: SettingDefault) -> Setting {
+    Setting {
+        key,
+        env,
+        default,
+        secret: false,
+    }
+}
+
+const fn secret(key: &'static str, env: &'static str, default: SettingDefault) -> Setting {
+    Setting {
+        key,
+        env,
+        default,
+        secret: true,
+    }
+}
+
+/// every setting as `section.name` in the TOML file and the env var that overrides it;
+/// the env names are the ones the server has always read, so existing .env files keep working
+const SETTINGS: &[Setting] = &[
+    setting(
+        "server.host_addr",
+        "HOST_ADDR",
+        SettingDefault::Value("0.0.0.0"),
+    ),
+    setting(
+        "server.host_port",
+        "HOST_PORT",
+        SettingDefault::Value("443"),
+    ),
+    setting(
+        "server.http_redirect_port",
+        "HTTP_REDIRECT_PORT",
+        SettingDefault::Value("80"),
+    ),
+    setting(
+        "server.admin_addr",
+        "ADMIN_ADDR",
+        SettingDefault::Value("127.0.0.1:9464"),
+    ),
+    setting(
+        "server.shutdown_drain_seconds",
+        "SHUTDOWN_DRAIN_SECONDS",
+        SettingDefault::Value("30"),
+    ),
+    setting(
+        "server.public_base_url",
+        "PUBLIC_BASE_URL",
+        SettingDefault::Value("https://www.cyhdev.com"),
+    ),
+    setting("server.cert_path", "CERT_DIR", SettingDefault::Required),
+    setting("server.key_path", "KEY_DIR", SettingDefault::Required),
+    // comma-separated CIDRs or addresses; forwarding headers from anyone else are ignored
+    setting(
+        "server.trusted_proxies",
+        "TRUSTED_PROXIES",
+        SettingDefault::Optional,
+    ),
+    setting(
+        "server.proxy_protocol",
+        "PROXY_PROTOCOL",
+        SettingDefault::Value("false"),
+    ),
+    setting("database.host", "DB_HOST", SettingDefault::Required),
+    setting("database.port", "DB_PORT", SettingDefault::Value("5432")),
+    setting("database.user", "DB_USER", SettingDefault::Required),
+    secret("database.password", "DB_PASSWORD", SettingDefault::Required),
+    setting("database.name", "DB_NAME", SettingDefault::Required),
+    setting(
+        "mail.transport",
+        "MAIL_TRANSPORT",
+        SettingDefault::Value("smtp"),
+    ),
+    setting(
+        "mail.file_dir",
+        "MAIL_FILE_DIR",
+        SettingDefault::Value("./mail"),
+    ),
+    setting(
+        "mail.smtp_host",
+        "SMTP_HOST",
+        SettingDefault::Value("email-smtp.ap-northeast-2.amazonaws.com"),
+    ),
+    setting("mail.smtp_port", "SMTP_PORT", SettingDefault::Optional),
+    setting("mail.smtp_tls", "SMTP_TLS", SettingDefault::Value("tls")),
+    setting(
+        "mail.smtp_username",
+        "SMTP_USERNAME",
+        SettingDefault::Optional,
+    ),
+    secret(
+        "mail.smtp_password",
+        "SMTP_PASSWORD",
+        SettingDefault::Optional,
+    ),
+    // whether /readyz also requires the mail server to answer
+    setting(
+        "mail.readiness_check",
+        "MAIL_READINESS_CHECK",
+        SettingDefault::Value("false"),
+    ),
+    setting("jwt.active_kid", "JWT_ACTIVE_KID", SettingDefault::Required),
+    // comma-separated `kid:secret` pairs
+    secret("jwt.secrets", "JWT_SECRETS", SettingDefault::Required),
+    setting(
+        "telemetry.otlp_endpoint",
+        "OTEL_EXPORTER_OTLP_ENDPOINT",
+        SettingDefault::Optional,
+    ),
+    setting(
+        "telemetry.service_name",
+        "OTEL_SERVICE_NAME",
+        SettingDefault::Value(env!("CARGO_PKG_NAME")),
+    ),
+    setting(
+        "telemetry.sample_ratio",
+        "OTEL_TRACES_SAMPLER_ARG",
+        SettingDefault::Value("1.0"),
+    ),
+    setting("logging.level", "RUST_LOG", SettingDefault::Value("info")),
+    setting(
+        "logging.format",
+        "LOG_FORMAT",
+        SettingDefault::Value("text"),
+    ),
+    setting("logging.dir", "LOG_DIR", SettingDefault::Optional),
+    setting(
+        "logging.rotation",
+        "LOG_ROTATION",
+        SettingDefault::Value("daily"),
+    ),
+    setting(
+        "logging.max_file_size_mb",
+        "LOG_MAX_FILE_SIZE_MB",
+        SettingDefault::Value("100"),
+    ),
+    setting(
+        "logging.max_files",
+        "LOG_MAX_FILES",
+        SettingDefault::Value("14"),
+    ),
+    setting(
+        "rate_limit.enabled",
+        "RATE_LIMIT_ENABLED",
+        SettingDefault::Value("true"),
+    ),
+    setting(
+        "rate_limit.store",
+        "RATE_LIMIT_STORE",
+        SettingDefault::Value("memory"),
+    ),
+    // limits are `<requests>/<second|min|hour|day>`; the count is also the burst
+    setting(
+        "rate_limit.default",
+        "RATE_LIMIT_DEFAULT",
+        SettingDefault::Value("300/min"),
+    ),
+    setting(
+        "rate_limit.credentials",
+        "RATE_LIMIT_CREDENTIALS",
+        SettingDefault::Value("10/min"),
+    ),
+    setting(
+        "rate_limit.tokens",
+        "RATE_LIMIT_TOKENS",
+        SettingDefault::Value("30/min"),
+    ),
+    setting(
+        "rate_limit.email",
+        "RATE_LIMIT_EMAIL",
+        SettingDefault::Value("5/min"),
+    ),
+    setting(
+        "rate_limit.account",
+        "RATE_LIMIT_ACCOUNT",
+        SettingDefault::Value("20/hour"),
+    ),
+    setting(
+        "lockout.enabled",
+        "LOCKOUT_ENABLED",
+        SettingDefault::Value("true"),
+    ),
+    setting(
+        "lockout.account_threshold",
+        "LOCKOUT_ACCOUNT_THRESHOLD",
+        SettingDefault::Value("10"),
+    ),
+    setting(
+        "lockout.ip_threshold",
+        "LOCKOUT_IP_THRESHOLD",
+        SettingDefault::Value("50"),
+    ),
+    setting(
+        "lockout.free_attempts",
+        "LOCKOUT_FREE_ATTEMPTS",
+        SettingDefault::Value("3"),
+    ),
+    setting(
+        "lockout.max_delay_seconds",
+        "LOCKOUT_MAX_DELAY_SECONDS",
+        SettingDefault::Value("60"),
+    ),
+    setting(
+        "lockout.window_minutes",
+        "LOCKOUT_WINDOW_MINUTES",
+        SettingDefault::Value("15"),
+    ),
+    setting(
+        "lockout.duration_minutes",
+        "LOCKOUT_DURATION_MINUTES",
+        SettingDefault::Value("30"),
+    ),
+    setting(
+        "two_factor.issuer",
+        "TWO_FACTOR_ISSUER",
+        SettingDefault::Value("cyhdev.com"),
+    ),
+    // e.g. from `openssl rand -base64 32`
+    secret(
+        "two_factor.encryption_key",
+        "TWO_FACTOR_ENCRYPTION_KEY",
+        SettingDefault::Required,
+    ),
+    setting(
+        "webauthn.rp_id",
+        "WEBAUTHN_RP_ID",
+        SettingDefault::Value("cyhdev.com"),
+    ),
+    setting(
+        "webauthn.rp_name",
+        "WEBAUTHN_RP_NAME",
+        SettingDefault::Value("cyhdev.com"),
+    ),
+    // comma-separated; server.public_base_url when unset
+    setting(
+        "webauthn.origins",
+        "WEBAUTHN_ORIGINS",
+        SettingDefault::Optional,
+    ),
+];
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum ValueSource {
+    Default,
+    File,
+    Env(&'static str),
+}
+
+/// raw setting values from every layer, before validation
+pub struct ConfigSources {
+    file: Option<PathBuf>,
+    values: BTreeMap<&'static str, (String, ValueSource)>,
+    errors: Vec<String>,
+}
+
+impl ConfigSources {
+    /// reads the file at `path`, else CONFIG_FILE, else config.toml if present, then the process environment
+    pub fn load(path: Option<&Path>) -> Self {
+        let (path, explicit) = match path {
+            Some(path) => (path.to_path_buf(), true),
+            None => match std::env::var("CONFIG_FILE") {
+                Ok(path) => (PathBuf::from(path), true),
+                Err(_) => (PathBuf::from(DEFAULT_CONFIG_PATH), false),
+            },
+        };
+
+        let file = match std::fs::read_to_string(&path) {
+            Ok(contents) => Some((path, Ok(contents))),
+            Err(e) if explicit || e.kind() != std::io::ErrorKind::NotFound => {
+                Some((path, Err(e.to_string())))
+            }
+            Err(_) => None,
+        };
+
+        ConfigSources::from_layers(file, |name| std::env::var(name).ok())
+    }
+
+    fn from_layers(
+        file: Option<(PathBuf, Result<String, String>)>,
+        env: impl Fn(&str) -> Option<String>,
+    ) -> Self {
+        let mut sources = ConfigSources {
+            file: None,
+            values: BTreeMap::new(),
+            errors: Vec::new(),
+        };
+
+        for setting in SETTINGS {
+            if let SettingDefault::Value(value) = setting.default {
+                sources
+                    .values
+                    .insert(setting.key, (value.to_owned(), ValueSource::Default));
+            }
+        }
+
+        if let Some((path, contents)) = file {
+            match contents {
+                Ok(contents) => sources.read_toml(&path, &contents),
+                Err(e) => sources
+                    .errors
+                    .push(format!("could not read {}: {}", path.display(), e)),
+            }
+            sources.file = Some(path);
+        }
+
+        for setting in SETTINGS {
+            if let Some(value) = env(setting.env) {
+                sources
+                    .values
+                    .insert(setting.key, (value, ValueSource::Env(setting.env)));
+            }
+        }
+
+        sources
+    }
+
+    fn read_toml(&mut self, path: &Path, contents: &str) {
+        let table = match contents.parse::<toml::Table>() {
+            Ok(table) => table,
+            Err(e) => {
+                self.errors
+                    .push(format!("could not parse {}: {}", path.display(), e));
+                return;
+            }
+        };
+
+        for (section, entries) in table {
+            let entries = match entries {
+                toml::Value::Table(entries) => entries,
+                _ => {
+                    self.errors.push(format!("{}: unknown setting", section));
+                    continue;
+                }
+            };
+
+            for (name, value) in entries {
+                let key = format!("{}.{}", section, name);
+                let setting = match SETTINGS.iter().find(|setting| setting.key == key) {
+                    Some(setting) => setting,
+                    None => {
+                        self.errors.push(format!("{}: unknown setting", key));
+                        continue;
+                    }
+                };
+
+                let value = match value {
+                    toml::Value::String(value) => value,
+                    toml::Value::Integer(value) => value.to_string(),
+                    toml::Value::Float(value) => value.to_string(),
+                    toml::Value::Boolean(value) => value.to_string(),
+                    _ => {
+                        self.errors
+                            .push(format!("{}: expected a string or a number", key));
+                        continue;
+                    }
+                };
+
+                self.values.insert(setting.key, (value, ValueSource::File));
+            }
+        }
+    }
+
+    pub fn get(&self, key: &str) -> Option<&str> {
+        self.values.get(key).map(|(value, _)| value.as_str())
+    }
+
+    /// validates every setting, collecting all problems instead of stopping at the first
+    pub fn build(&self) -> anyhow::Result<AppConfig> {
+        let mut errors = self.errors.clone();
+        let host_addr = self.required("server.host_addr", &mut errors);
+        let host_port = self.required("server.host_port", &mut errors);
+        let http_redirect_port = self.required("server.http_redirect_port", &mut errors);
+        let admin_addr = self.required("server.admin_addr", &mut errors);
+        let shutdown_drain_seconds = self.required("server.shutdown_drain_seconds", &mut errors);
+        let public_base_url: Option<String> = self.required("server.public_base_url", &mut errors);
+        let cert_path: Option<PathBuf> = self.required("server.cert_path", &mut errors);
+        let key_path: Option<PathBuf> = self.required("server.key_path", &mut errors);
+        let trusted_proxies: Option<TrustedProxies> =
+            self.optional("server.trusted_proxies", &mut errors);
+        let proxy_protocol = self.required("server.proxy_protocol", &mut errors);
+        let db_host = self.required("database.host", &mut errors);
+        let db_port = self.required("database.port", &mut errors);
+        let db_user = self.required("database.user", &mut errors);
+        let db_password = self.required("database.password", &mut errors);
+        let db_name = self.required("database.name", &mut errors);
+        let transport = self.required("mail.transport", &mut errors);
+        let file_dir = self.required("mail.file_dir", &mut errors);
+        let smtp_host = self.required("mail.smtp_host", &mut errors);
+        let smtp_tls = self.required("mail.smtp_tls", &mut errors);
+        let mail_readiness_check = self.required("mail.readiness_check", &mut errors);
+        let active_kid: Option<String> = self.required("jwt.active_kid", &mut errors);
+        let raw_jwt_secrets: Option<String> = self.required("jwt.secrets", &mut errors);
+        let service_name = self.required("telemetry.service_name", &mut errors);
+        let sample_ratio: Option<f64> = self.required("telemetry.sample_ratio", &mut errors);
+        let otlp_endpoint: Option<String> = self.optional("telemetry.otlp_endpoint", &mut errors);
+        let log_level: Option<String> = self.required("logging.level", &mut errors);
+        let log_format = self.required("logging.format", &mut errors);
+        let log_dir = self.optional("logging.dir", &mut errors);
+        let log_rotation = self.required("logging.rotation", &mut errors);
+        let log_max_file_size_mb: Option<u64> =
+            self.required("logging.max_file_size_mb", &mut errors);
+        let log_max_files = self.required("logging.max_files", &mut errors);
+        let rate_limit_enabled = self.required("rate_limit.enabled", &mut errors);
+        let rate_limit_store = self.required("rate_limit.store", &mut errors);
+        let rate_limit_default = self.required("rate_limit.default", &mut errors);
+        let rate_limit_credentials = self.required("rate_limit.credentials", &mut errors);
+        let rate_limit_tokens = self.required("rate_limit.tokens", &mut errors);
+        let rate_limit_email = self.required("rate_limit.email", &mut errors);
+        let rate_limit_account = self.required("rate_limit.account", &mut errors);
+        let lockout_enabled = self.required("lockout.enabled", &mut errors);
+        let lockout_account_threshold: Option<u32> =
+            self.required("lockout.account_threshold", &mut errors);
+        let lockout_ip_threshold: Option<u32> = self.required("lockout.ip_threshold", &mut errors);
+        let lockout_free_attempts = self.required("lockout.free_attempts", &mut errors);
+        let lockout_max_delay_seconds = self.required("lockout.max_delay_seconds", &mut errors);
+        let lockout_window_minutes: Option<u64> =
+            self.required("lockout.window_minutes", &mut errors);
+        let lockout_duration_minutes: Option<u64> =
+            self.required("lockout.duration_minutes", &mut errors);
+        let two_factor_issuer = self.required("two_factor.issuer", &mut errors);
+        let two_factor_encryption_key: Option<Secret> =
+            self.required("two_factor.encryption_key", &mut errors);
+        let webauthn_rp_id: Option<String> = self.required("webauthn.rp_id", &mut errors);
+        let webauthn_rp_name: Option<String> = self.required("webauthn.rp_name", &mut errors);
+        let webauthn_origins: Option<String> = self.optional("webauthn.origins", &mut errors);
+
+        let smtp_port = self.optional("mail.smtp_port", &mut errors);
+        let smtp_username: Option<String> = self.optional("mail.smtp_username", &mut errors);
+        let smtp_password: Option<Secret> = self.optional("mail.smtp_password", &mut errors);
+
+        if let Some(url) = public_base_url.as_deref() {
+            if !(url.starts_with("https://") || url.starts_with("http://")) {
+                errors.push(format!(
+                    "server.public_base_url ({}): must start with http:// or https://",
+                    self.describe_source("server.public_base_url")
+                ));
+            }
+        }
+
+        for (key, path) in [
+            ("server.cert_path", &cert_path),
+            ("server.key_path", &key_path),
+        ] {
+            if let Some(path) = path {
+                if !path.is_file() {
+                    errors.push(format!(
+                        "{} ({}): no file at {}",
+                        key,
+                        self.describe_source(key),
+                        path.display()
+                    ));
+                }
+            }
+        }
+
+        let smtp_credentials = match (smtp_username, smtp_password) {
+            (Some(username), Some(password)) => Some((username, password)),
+            (None, None) => None,
+            _ => {
+                errors.push(
+                    "mail.smtp_username and mail.smtp_password must be set together".to_owned(),
+                );
+                None
+            }
+        };
+
+        if let Some(ratio) = sample_ratio {
+            if !(0.0..=1.0).contains(&ratio) {
+                errors.push(format!(
+                    "telemetry.sample_ratio ({}): must be between 0 and 1",
+                    self.describe_source("telemetry.sample_ratio")
+                ));
+            }
+        }
+
+        if let Some(endpoint) = otlp_endpoint.as_deref() {
+            if !(endpoint.starts_with("https://") || endpoint.starts_with("http://")) {
+                errors.push(format!(
+                    "telemetry.otlp_endpoint ({}): must start with http:// or https://",
+                    self.describe_source("telemetry.otlp_endpoint")
+                ));
+            }
+        }
+
+        if let Some(level) = log_level.as_deref() {
+            if let Err(e) = tracing_subscriber::EnvFilter::try_new(level) {
+                errors.push(format!(
+                    "logging.level ({}): {}",
+                    self.describe_source("logging.level"),
+                    e
+                ));
+            }
+        }
+
+        if log_max_file_size_mb == Some(0) {
+            errors.push(format!(
+                "logging.max_file_size_mb ({}): must be at least 1",
+                self.describe_source("logging.max_file_size_mb")
+            ));
+        }
+
+        for (key, value) in [
+            (
+                "lockout.account_threshold",
+                lockout_account_threshold.map(u64::from),
+            ),
+            ("lockout.ip_threshold", lockout_ip_threshold.map(u64::from)),
+            ("lockout.window_minutes", lockout_window_minutes),
+            ("lockout.duration_minutes", lockout_duration_minutes),
+        ] {
+            if value == Some(0) {
+                errors.push(format!(
+                    "{} ({}): must be at least 1",
+                    key,
+                    self.describe_source(key)
+                ));
+            }
+        }
+
+        let jwt_secrets = raw_jwt_secrets.and_then(|raw| match parse_jwt_secrets(&raw) {
+            Ok(secrets) => Some(secrets),
+            Err(e) => {
+                errors.push(format!(
+                    "jwt.secrets ({}): {}",
+                    self.describe_source("jwt.secrets"),
+                    e
+                ));
+                None
+            }
+        });
+
+        if let (Some(active_kid), Some(secrets)) = (active_kid.as_deref(), jwt_secrets.as_ref()) {
+            let exposed: Vec<(String, String)> = secrets
+                .iter()
+                .map(|(kid, secret)| (kid.clone(), secret.expose().to_owned()))
+                .collect();
+            if let Err(e) = JWT::from_secrets(active_kid, &exposed) {
+                errors.push(format!("jwt: {}", e));
+            }
+        }
+
+        if let Some(key) = two_factor_encryption_key.as_ref() {
+            if let Err(e) = SecretCipher::from_base64(key.expose()) {
+                errors.push(format!(
+                    "two_factor.encryption_key ({}): {}",
+                    self.describe_source("two_factor.encryption_key"),
+                    e
+                ));
+            }
+        }
+
+        let webauthn_origins: Option<Vec<String>> = webauthn_origins
+            .map(|origins| {
+                origins
+                    .split(',')
+                    .map(|origin| origin.trim().trim_end_matches('/').to_owned())
+                    .filter(|origin| !origin.is_empty())
+                    .collect()
+            })
+            .or_else(|| {
+                public_base_url
+                    .as_deref()
+                    .map(|url| vec![url.trim_end_matches('/').to_owned()])
+            });
+
+        if let (Some(rp_id), Some(rp_name), Some(origins)) = (
+            webauthn_rp_id.as_deref(),
+            webauthn_rp_name.as_deref(),
+            webauthn_origins.as_ref(),
+        ) {
+            if let Err(e) = RelyingParty::new(rp_id, rp_name, origins.clone()) {
+                let key = match self.values.contains_key("webauthn.origins") {
+                    true => "webauthn.origins",
+                    false => "webauthn.rp_id",
+                };
+                errors.push(format!("{} ({}): {}", key, self.describe_source(key), e));
+            }
+        }
+
+        if !errors.is_empty() {
+            return Err(anyhow!(
+                "invalid configuration{}:\n  - {}",
+                match &self.file {
+                    Some(path) => format!(" (file: {})", path.display()),
+                    None => String::new(),
+                },
+                errors.join("\n  - ")
+            ));
+        }
+
+        // every required value is Some once no errors were recorded
+        (|| {
+            Some(AppConfig {
+                server: ServerConfig {
+                    host_addr: host_addr?,
+                    host_port: host_port?,
+                    http_redirect_port: http_redirect_port?,
+                    admin_addr: admin_addr?,
+                    shutdown_drain_seconds: shutdown_drain_seconds?,
+                    public_base_url: public_base_url?.trim_end_matches('/').to_owned(),
+                    cert_path: cert_path?,
+                    key_path: key_path?,
+                    trusted_proxies: trusted_proxies.unwrap_or_default(),
+                    proxy_protocol: proxy_protocol?,
+                },
+                database: DatabaseConfig {
+                    host: db_host?,
+                    port: db_port?,
+                    user: db_user?,
+                    password: db_password?,
+                    name: db_name?,
+                },
+                mail: MailConfig {
+                    transport: transport?,
+                    file_dir: file_dir?,
+                    smtp_host: smtp_host?,
+                    smtp_port,
+                    smtp_tls: smtp_tls?,
+                    smtp_credentials,
+                    readiness_check: mail_readiness_check?,
+                },
+                jwt: JwtConfig {
+                    active_kid: active_kid?,
+                    secrets: jwt_secrets?,
+                },
+                telemetry: TelemetryConfig {
+                    otlp_endpoint: otlp_endpoint
+                        .map(|endpoint| endpoint.trim_end_matches('/').to_owned()),
+                    service_name: service_name?,
+                    sample_ratio: sample_ratio?,
+                },
+                logging: LoggingConfig {
+                    level: log_level?,
+                    format: log_format?,
+                    dir: log_dir,
+                    rotation: log_rotation?,
+                    max_file_size_mb: log_max_file_size_mb?,
+                    max_files: log_max_files?,
+                },
+                rate_limit: RateLimitConfig {
+                    enabled: rate_limit_enabled?,
+                    store: rate_limit_store?,
+                    default: rate_limit_default?,
+                    credentials: rate_limit_credentials?,
+                    tokens: rate_limit_tokens?,
+                    email: rate_limit_email?,
+                    account: rate_limit_account?,
+                },
+                lockout: LockoutConfig {
+                    enabled: lockout_enabled?,
+                    account_threshold: lockout_account_threshold?,
+                    ip_threshold: lockout_ip_threshold?,
+                    free_attempts: lockout_free_attempts?,
+                    max_delay_seconds: lockout_max_delay_seconds?,
+                    window_minutes: lockout_window_minutes?,
+                    duration_minutes: lockout_duration_minutes?,
+                },
+                two_factor: TwoFactorConfig {
+                    issuer: two_factor_issuer?,
+                    encryption_key: two_factor_encryption_key?,
+                },
+                webauthn: WebauthnConfig {
+                    rp_id: webauthn_rp_id?,
+                    rp_name: webauthn_rp_name?,
+                    origins: webauthn_origins?,
+                },
+            })
+        })()
+        .ok_or_else(|| anyhow!("invalid configuration"))
+    }
+
+    fn required<T>(&self, key: &str, errors: &mut Vec<String>) -> Option<T>
+    where
+        T: FromStr,
+        T::Err: Display,
+    {
+        self.parse(key, true, errors)
+    }
+
+    fn optional<T>(&self, key: &str, errors: &mut Vec<String>) -> Option<T>
+    where
+        T: FromStr,
+        T::Err: Display,
+    {
+        self.parse(key, false, errors)
+    }
+
+    fn parse<T>(&self, key: &str, required: bool, errors: &mut Vec<String>) -> Option<T>
+    where
+        T: FromStr,
+        T::Err: Display,
+    {
+        let (value, _) = match self.values.get(key) {
+            Some(entry) => entry,
+            None => {
+                if required {
+                    errors.push(format!(
+                        "{} ({}): required but not set",
+                        key,
+                        self.describe_source(key)
+                    ));
+                }
+                return None;
+            }
+        };
+
+        match value.parse::<T>() {
+            Ok(parsed) => Some(parsed),
+            Err(e) => {
+                let shown = match self.setting(key).secret {
+                    true => REDACTED.to_owned(),
+                    false => format!("{:?}", value),
+                };
+                errors.push(format!(
+                    "{} ({}): invalid value {}: {}",
+                    key,
+                    self.describe_source(key),
+                    shown,
+                    e
+                ));
+                None
+            }
+        }
+    }
+
+    fn setting(&self, key: &str) -> &'static Setting {
+        SETTINGS
+            .iter()
+            .find(|setting| setting.key == key)
+            .unwrap_or_else(|| panic!("unknown setting {}", key))
+    }
+
+    /// where a value came from, or how it can be set when it is missing
+    fn describe_source(&self, key: &str) -> String {
+        let setting = self.setting(key);
+        match self.values.get(key) {
+            Some((_, ValueSource::Default)) => "default".to_owned(),
+            Some((_, ValueSource::File)) => match &self.file {
+                Some(path) => path.display().to_string(),
+                None => "file".to_owned(),
+            },
+            Some((_, ValueSource::Env(name))) => format!("env {}", name),
+            None => format!("set in the config file or env {}", setting.env),
+        }
+    }
+
+    /// the effective configuration as TOML, secrets redacted, each value annotated with its source
+    pub fn render(&self) -> String {
+        let mut output = String::new();
+        let _ = writeln!(
+            output,
+            "# effective configuration; file: {}",
+            match &self.file {
+                Some(path) => path.display().to_string(),
+                None => "none".to_owned(),
+            }
+        );
+
+        let mut current_section = "";
+        for setting in SETTINGS {
+            let (section, name) = setting.key.split_once('.').unwrap_or(("", setting.key));
+            if section != current_section {
+                let _ = writeln!(output, "\n[{}]", section);
+                current_section = section;
+            }
+
+            match self.values.get(setting.key) {
+                Some((value, _)) => {
+                    let shown = match setting.secret {
+                        true => REDACTED,
+                        false => value.as_str(),
+                    };
+                    let _ = writeln!(
+                        output,
+                        "{} = {:?} # {}",
+                        name,
+                        shown,
+                        self.describe_source(setting.key)
+                    );
+                }
+                None => {
+                    let status = match setting.default {
+                        SettingDefault::Required => "is required but not set",
+                        _ => "is not set",
+                    };
+                    let _ = writeln!(output, "# {} {} (env {})", name, status, setting.env);
+                }
+            }
+        }
+
+        output
+    }
+}
+
+fn parse_jwt_secrets(raw: &str) -> anyhow::Result<Vec<(String, Secret)>> {
+    raw.split(',')
+        .map(|pair| match pair.trim().split_once(':') {
+            Some((kid, secret)) if !kid.is_empty() => Ok((kid.to_owned(), Secret::new(secret))),
+            _ => Err(anyhow!(
+                "malformed entry; expected comma-separated kid:secret pairs"
+            )),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const JWT_SECRET: &str = "0123456789abcdef0123456789abcdef";
+    const TWO_FACTOR_KEY: &str = "AAECAwQFBgcICQoLDA0ODxAREhMUFRYXGBkaGxwdHh8=";
+
+    fn sources(toml: Option<&str>, env: &[(&str, &str)]) -> ConfigSources {
+        let env: Vec<(String, String)> = env
+            .iter()
+            .map(|(name, value)| (name.to_string(), value.to_string()))
+            .collect();
+        ConfigSources::from_layers(
+            toml.map(|toml| (PathBuf::from("test.toml"), Ok(toml.to_owned()))),
+            move |name| {
+                env.iter()
+                    .find(|(key, _)| key == name)
+                    .map(|(_, value)| value.clone())
+            },
+        )
+    }
+
+    fn complete_toml() -> String {
+        format!(
+            r#"
+[server]
+host_port = 8443
+cert_path = "Cargo.toml"
+key_path = "Cargo.toml"
+
+[database]
+host = "localhost"
+user = "cyhdev"
+password = "db-password"
+name = "cyhdev"
+
+[jwt]
+active_kid = "k1"
+secrets = "k1:{}"
+
+[two_factor]
+encryption_key = "{}"
+"#,
+            JWT_SECRET, TWO_FACTOR_KEY
+        )
+    }
+
+    #[test]
+    fn test_layers_apply_in_order() {
+        let sources = sources(Some(&complete_toml()), &[("DB_HOST", "db.internal")]);
+        let config = sources.build().unwrap();
+
+        // default, file, env
+        assert_eq!(config.server.http_redirect_port, 80);
+        assert_eq!(config.server.host_port, 8443);
+        assert_eq!(config.database.host, "db.internal");
+        assert_eq!(config.database.password.expose(), "db-password");
+        assert_eq!(config.mail.transport, MailTransportKind::Smtp);
+        assert_eq!(config.jwt.secrets[0].0, "k1");
+    }
+
+    #[test]
+    fn test_all_errors_are_reported_together() {
+        let sources = sources(
+            Some("[server]\nhost_port = \"not-a-port\"\nbogus = 1\n"),
+            &[
+                ("SMTP_USERNAME", "mailer"),
+                ("RATE_LIMIT_EMAIL", "5/fortnight"),
+                ("LOCKOUT_ACCOUNT_THRESHOLD", "0"),
+            ],
+        );
+        let message = sources.build().unwrap_err().to_string();
+
+        for expected in [
+            "server.host_port (test.toml): invalid value \"not-a-port\"",
+            "server.bogus: unknown setting",
+            "database.password (set in the config file or env DB_PASSWORD): required but not set",
+            "jwt.active_kid",
+            "mail.smtp_username and mail.smtp_password must be set together",
+            "rate_limit.email (env RATE_LIMIT_EMAIL): invalid value \"5/fortnight\"",
+            "lockout.account_threshold (env LOCKOUT_ACCOUNT_THRESHOLD): must be at least 1",
+        ] {
+            assert!(
+                message.contains(expected),
+                "{:?} not in {}",
+                expected,
+                message
+            );
+        }
+    }
+
+    #[test]
+    fn test_secrets_are_redacted() {
+        let sources = sources(
+            Some(&complete_toml()),
+            &[
+                ("SMTP_USERNAME", "mailer"),
+                ("SMTP_PASSWORD", "smtp-password"),
+            ],
+        );
+        let rendered = sources.render();
+        let config = sources.build().unwrap();
+        let debug = format!("{:?}", config);
+
+        for output in [&rendered, &debug] {
+            for secret in ["db-password", "smtp-password", JWT_SECRET, TWO_FACTOR_KEY] {
+                assert!(!output.contains(secret), "{} leaked", secret);
+            }
+            assert!(output.contains(REDACTED));
+        }
+        assert!(rendered.contains("user = \"cyhdev\" # test.toml"));
+        assert!(rendered.contains("smtp_username = \"mailer\" # env SMTP_USERNAME"));
+        assert!(rendered.contains("# smtp_port is not set (env SMTP_PORT)"));
+    }
+
+    #[test]
+    fn test_webauthn_origins_default_to_the_public_base_url() {
+        let config = sources(Some(&complete_toml()), &[]).build().unwrap();
+        assert_eq!(config.webauthn.origins, ["https://www.cyhdev.com"]);
+
+        let sources = sources(
+            Some(&complete_toml()),
+            &[(
+                "WEBAUTHN_ORIGINS",
+                "https://cyhdev.com, https://evil.example",
+            )],
+        );
+        let message = sources.build().unwrap_err().to_string();
+        assert!(message.contains(
+            "webauthn.origins (env WEBAUTHN_ORIGINS): https://evil.example is not cyhdev.com or a subdomain of it"
+        ));
+    }
+
+    #[test]
+    fn test_invalid_secret_values_are_not_echoed() {
+        let sources = sources(
+            Some(&complete_toml()),
+            &[
+                ("JWT_SECRETS", "k1:too-short-secret"),
+                ("TWO_FACTOR_ENCRYPTION_KEY", "c2hvcnQta2V5"),
+            ],
+        );
+        let message = sources.build().unwrap_err().to_string();
+        assert!(message.contains("jwt:"));
+        assert!(message.contains(
+            "two_factor.encryption_key (env TWO_FACTOR_ENCRYPTION_KEY): must decode to 32 bytes"
+        ));
+        for secret in ["too-short-secret", "c2hvcnQta2V5"] {
+            assert!(!message.contains(secret), "{} leaked", secret);
+        }
+    }
+}
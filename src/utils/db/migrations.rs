@@ -0,0 +1,277 @@
+use std::{collections::HashMap, time::Instant};
+
+use anyhow::{anyhow, Context};
+use deadpool_postgres::{Object, Transaction};
+use sha2::{Digest, Sha256};
+use tracing::{error, info, warn};
+
+/// a versioned SQL file from migrations/, compiled into the binary
+#[derive(Debug)]
+pub struct Migration {
+    pub version: i64,
+    pub name: &'static str,
+    pub sql: &'static str,
+}
+
+impl Migration {
+    /// hex SHA-256 of the SQL; an applied migration whose file changes afterwards is refused at startup
+    pub fn checksum(&self) -> String {
+        Sha256::digest(self.sql.as_bytes())
+            .iter()
+            .map(|byte| format!("{:02x}", byte))
+            .collect()
+    }
+}
+
+macro_rules! migration {
+    ($version:literal, $name:literal) => {
+        Migration {
+            version: $version,
+            name: $name,
+            sql: include_str!(concat!("../../../migrations/", $name, ".sql")),
+        }
+    };
+}
+
+/// every migration in the order it is applied; append new files here, never edit or reorder applied ones
+pub static MIGRATIONS: &[Migration] = &[
+    migration!(1, "0001_baseline_users_and_user_tokens"),
+    migration!(2, "0002_user_sessions"),
+    migration!(3, "0003_user_roles"),
+    migration!(4, "0004_user_token_sessions"),
+    migration!(5, "0005_email_outbox"),
+    migration!(6, "0006_rate_limit_buckets"),
+    migration!(7, "0007_login_lockouts"),
+    migration!(8, "0008_user_totp"),
+    migration!(9, "0009_passkeys"),
+];
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MigrationMode {
+    /// apply pending migrations, each in its own transaction
+    Apply,
+    /// run pending migrations in a single transaction and roll it back
+    DryRun,
+}
+
+/// key for pg_advisory_lock, so instances starting together apply migrations one at a time
+const MIGRATION_LOCK_KEY: i64 = 0x6379_6864_6576; // "cyhdev"
+
+const MIGRATIONS_TABLE_EXISTS: &str = "SELECT to_regclass('public.schema_migrations') IS NOT NULL";
+
+const CREATE_MIGRATIONS_TABLE: &str = "CREATE TABLE public.schema_migrations (
+    schema_migration_version bigint PRIMARY KEY,
+    schema_migration_name text NOT NULL,
+    schema_migration_checksum text NOT NULL,
+    schema_migration_applied_at timestamptz NOT NULL DEFAULT now(),
+    schema_migration_execution_ms bigint NOT NULL
+)";
+
+/// brings the schema up to date and returns the migrations that were (or in DryRun, would be) applied.
+/// The connection is consumed so it can be discarded if the advisory lock cannot be released.
+pub async fn run_migrations(
+    mut conn: Object,
+    mode: MigrationMode,
+) -> anyhow::Result<Vec<&'static Migration>> {
+    conn.execute("SELECT pg_advisory_lock($1)", &[&MIGRATION_LOCK_KEY])
+        .await
+        .context("Could not acquire the migration lock")?;
+
+    let result = match mode {
+        MigrationMode::Apply => apply_pending(&mut conn).await,
+        MigrationMode::DryRun => dry_run_pending(&mut conn).await,
+    };
+
+    if let Err(e) = conn
+        .execute("SELECT pg_advisory_unlock($1)", &[&MIGRATION_LOCK_KEY])
+        .await
+    {
+        // the lock is tied to the session, so closing the connection releases it
+        error!("Could not release the migration lock: {:?}", e);
+        drop(Object::take(conn));
+    }
+
+    result
+}
+
+/// versions in MIGRATIONS that the database has not recorded yet, without taking the lock or changing anything
+pub async fn unapplied_migrations(conn: &Object) -> anyhow::Result<Vec<i64>> {
+    let exists: bool = conn.query_one(MIGRATIONS_TABLE_EXISTS, &[]).await?.get(0);
+    if !exists {
+        return Ok(MIGRATIONS
+            .iter()
+            .map(|migration| migration.version)
+            .collect());
+    }
+
+    let applied: Vec<i64> = conn
+        .query(
+            "SELECT schema_migration_version FROM public.schema_migrations",
+            &[],
+        )
+        .await?
+        .into_iter()
+        .map(|row| row.get(0))
+        .collect();
+
+    Ok(MIGRATIONS
+        .iter()
+        .map(|migration| migration.version)
+        .filter(|version| !applied.contains(version))
+        .collect())
+}
+
+async fn apply_pending(conn: &mut Object) -> anyhow::Result<Vec<&'static Migration>> {
+    let transaction = conn.transaction().await?;
+    ensure_migrations_table(&transaction).await?;
+    let pending = pending_migrations(&transaction).await?;
+    transaction.commit().await?;
+
+    for migration in pending.iter() {
+        let transaction = conn.transaction().await?;
+        apply_one(&transaction, migration).await?;
+        transaction
+            .commit()
+            .await
+            .with_context(|| format!("Could not commit migration {}", migration.name))?;
+        info!("Applied migration {}", migration.name);
+    }
+
+    Ok(pending)
+}
+
+async fn dry_run_pending(conn: &mut Object) -> anyhow::Result<Vec<&'static Migration>> {
+    let transaction = conn.transaction().await?;
+    ensure_migrations_table(&transaction).await?;
+    let pending = pending_migrations(&transaction).await?;
+
+    for migration in pending.iter() {
+        apply_one(&transaction, migration).await?;
+        info!("Migration {} would apply cleanly", migration.name);
+    }
+
+    transaction.rollback().await?;
+
+    Ok(pending)
+}
+
+async fn ensure_migrations_table(transaction: &Transaction<'_>) -> anyhow::Result<()> {
+    let exists: bool = transaction
+        .query_one(MIGRATIONS_TABLE_EXISTS, &[])
+        .await?
+        .get(0);
+
+    if !exists {
+        transaction.batch_execute(CREATE_MIGRATIONS_TABLE).await?;
+    }
+
+    Ok(())
+}
+
+async fn apply_one(transaction: &Transaction<'_>, migration: &Migration) -> anyhow::Result<()> {
+    let start = Instant::now();
+    transaction
+        .batch_execute(migration.sql)
+        .await
+        .with_context(|| format!("Migration {} failed", migration.name))?;
+    let execution_ms = start.elapsed().as_millis() as i64;
+
+    transaction
+        .execute(
+            "INSERT INTO public.schema_migrations (schema_migration_version, schema_migration_name, schema_migration_checksum, schema_migration_execution_ms) VALUES ($1, $2, $3, $4)",
+            &[&migration.version, &migration.name, &migration.checksum(), &execution_ms],
+        )
+        .await?;
+
+    Ok(())
+}
+
+/// compares the recorded history against MIGRATIONS; an applied migration whose checksum changed is an error
+async fn pending_migrations(
+    transaction: &Transaction<'_>,
+) -> anyhow::Result<Vec<&'static Migration>> {
+    let applied: HashMap<i64, String> = transaction
+        .query(
+            "SELECT schema_migration_version, schema_migration_checksum FROM public.schema_migrations",
+            &[],
+        )
+        .await?
+        .into_iter()
+        .map(|row| (row.get(0), row.get(1)))
+        .collect();
+
+    let mut pending = Vec::new();
+    for migration in MIGRATIONS {
+        match applied.get(&migration.version) {
+            Some(checksum) if *checksum == migration.checksum() => (),
+            Some(checksum) => {
+                return Err(anyhow!(
+                    "Migration {} was modified after it was applied (recorded checksum {}, embedded {})",
+                    migration.name,
+                    checksum,
+                    migration.checksum()
+                ))
+            }
+            None => pending.push(migration),
+        }
+    }
+
+    let known_latest = MIGRATIONS
+        .last()
+        .map(|migration| migration.version)
+        .unwrap_or(0);
+    if let Some(latest) = applied
+        .keys()
+        .copied()
+        .filter(|version| *version > known_latest)
+        .max()
+    {
+        // a newer build has already migrated this database; keep serving with the schema we know
+        warn!(
+            "Database is at migration version {}, newer than the latest this build knows ({})",
+            latest, known_latest
+        );
+    }
+
+    Ok(pending)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_migrations_are_ordered_and_named_by_version() {
+        for (index, migration) in MIGRATIONS.iter().enumerate() {
+            assert_eq!(migration.version, index as i64 + 1, "{}", migration.name);
+            assert!(
+                migration
+                    .name
+                    .starts_with(&format!("{:04}_", migration.version)),
+                "{}",
+                migration.name
+            );
+            assert!(!migration.sql.trim().is_empty(), "{}", migration.name);
+        }
+    }
+
+    #[test]
+    fn test_baseline_creates_users_and_user_tokens() {
+        let baseline = MIGRATIONS[0].sql;
+        assert!(baseline.contains("CREATE SCHEMA IF NOT EXISTS v1"));
+        assert!(baseline.contains("CREATE TABLE IF NOT EXISTS v1.users"));
+        assert!(baseline.contains("CREATE TABLE IF NOT EXISTS v1.user_tokens"));
+    }
+
+    #[test]
+    fn test_checksum_is_stable_hex_sha256() {
+        let migration = Migration {
+            version: 0,
+            name: "0000_test",
+            sql: "SELECT 1;",
+        };
+        assert_eq!(migration.checksum().len(), 64);
+        assert_eq!(migration.checksum(), migration.checksum());
+        assert_ne!(migration.checksum(), MIGRATIONS[0].checksum());
+    }
+}
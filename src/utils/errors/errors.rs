@@ -1,26 +1,32 @@
-use axum::response::IntoResponse;
+use std::{borrow::Cow, time::Duration};
+
+use axum::{
+    http::{header::RETRY_AFTER, HeaderValue, StatusCode},
+    response::IntoResponse,
+};
 use chrono::Utc;
 use serde_derive::{Deserialize, Serialize};
+use tracing::{error, warn};
 
-use crate::utils::gadgets::stopwatch::Stopwatch;
+use crate::{
+    controllers::middleware::request_response_info::request_elapsed,
+    utils::{
+        rate_limit::rate_limit_store::{RATELIMIT_LIMIT, RATELIMIT_REMAINING, RATELIMIT_RESET},
+        serde::content_format::ContentFormat,
+    },
+};
 
+/// error envelope sent to clients; built from an AppError
 #[derive(Serialize, Deserialize, Clone, Debug)]
 pub struct ErrResp {
     success: bool,
-    data: ErrRespDatFin,
+    data: ErrRespData,
     meta: SvrErrorRespMeta,
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug)]
-pub struct ErrRespDat {
-    code: u8,
-    message: &'static str,
-    status_code: u16,
-}
-
-#[derive(Serialize, Deserialize, Clone, Debug)]
-pub struct ErrRespDatFin {
-    code: u8,
+pub struct ErrRespData {
+    code: String,
     message: String,
     status_code: u16,
 }
@@ -31,123 +37,690 @@ pub struct SvrErrorRespMeta {
     timestamp: String,
 }
 
-impl IntoResponse for ErrResp {
-    fn into_response(self) -> axum::response::Response {
-        let serialized_body =
-            bincode::serialize(&self).expect("Failed to serialize ErrResp with bincode");
-        
-        match axum::response::Response::builder()
-            .status(self.data.status_code)
-            .header("Content-Type", "application/octet-stream")
-            .body(axum::body::Body::from(serialized_body))
-        {
-            Ok(resp) => resp,
-            Err(e) => panic!("Failed to build Axum response: {:?}", e),
-        }
+impl ErrResp {
+    pub fn get_code(&self) -> &str {
+        &self.data.code
     }
 }
 
-impl ErrResp {
-    pub fn from(dat: ErrRespDat, start: &Stopwatch, error: anyhow::Error) -> Self {
+/// every error a handler can return to a client.
+///
+/// `code()` is part of the API contract: once shipped a code never changes meaning, and a removed
+/// variant's code goes into RETIRED_CODES so it is never handed out again. Variants carrying an
+/// `anyhow::Error` hold internal detail that is logged but never sent to the client.
+#[derive(Debug)]
+pub enum AppError {
+    // 5xx; the payload is logged, the client only sees the public message
+    CouldNotGetConnFromPool(anyhow::Error),
+    CouldNotBuildTransactionFromConn(anyhow::Error),
+    CouldNotCommitTransaction(anyhow::Error),
+    Database(anyhow::Error),
+    CouldNotInsertUser(anyhow::Error),
+    CouldNotInsertUserToken(anyhow::Error),
+    CouldNotInsertUserSession(anyhow::Error),
+    CouldNotGetUser(anyhow::Error),
+    CouldNotGetUserSessions(anyhow::Error),
+    CouldNotUpdateUser(anyhow::Error),
+    CouldNotUpdateUserToken(anyhow::Error),
+    CouldNotRevokeUserSession(anyhow::Error),
+    CouldNotSignJwt(anyhow::Error),
+    CouldNotEnqueueEmail(anyhow::Error),
+    CouldNotGetEmailOutbox(anyhow::Error),
+    CouldNotRenderEmailTemplate(anyhow::Error),
+    CouldNotSetUpTwoFactor(anyhow::Error),
+    CouldNotVerifyTwoFactor(anyhow::Error),
+    CouldNotVerifyPassword(anyhow::Error),
+    CouldNotHashPassword(anyhow::Error),
+    CouldNotStartPasskeyCeremony(anyhow::Error),
+    CouldNotSerializeResponse(anyhow::Error),
+    CouldNotBuildResponse(anyhow::Error),
+
+    // request validation
+    WrongEmailFormat,
+    WrongPwFormat,
+    UnsupportedMediaType,
+    /// the deserializer's message is safe to show and tells the client which field is wrong
+    CouldNotDeserializeBody(String),
+
+    // authentication and authorization
+    InvalidCredentials,
+    UserEmailNotVerified,
+    UserInactive,
+    AuthRequired,
+    AdminRequired,
+    JwtExpired,
+    JwtInvalidSignature,
+    JwtMalformed,
+    UserSessionInvalid,
+    RefreshTokenReused,
+    InvalidTwoFactorCode,
+
+    // users, tokens and sessions
+    UserAlreadyExists,
+    UserAlreadyVerified,
+    UserTokenInvalid,
+    UserTokenUsed,
+    UserTokenExpired,
+    UserSessionNotFound,
+
+    // two-factor enrollment
+    TwoFactorAlreadyEnabled,
+    TwoFactorNotEnrolled,
+
+    // passkeys
+    PasskeyChallengeInvalid,
+    /// the authenticator's response failed verification; the reason is logged, not sent
+    PasskeyRejected,
+    PasskeyAlreadyRegistered,
+    PasskeyNotFound,
+
+    // rate limiting
+    RateLimited {
+        limit: u32,
+        retry_after_seconds: u64,
+    },
+    LoginBackoff {
+        retry_after_seconds: u64,
+    },
+    AccountLocked {
+        retry_after_seconds: u64,
+    },
+
+    // admin
+    EmailOutboxEntryNotFound,
+    EmailTemplateNotFound,
+}
+
+impl AppError {
+    /// stable, machine-readable identifier sent as `data.code`
+    pub fn code(&self) -> &'static str {
+        match self {
+            AppError::CouldNotGetConnFromPool(_) => "COULD_NOT_GET_CONN_FROM_POOL",
+            AppError::CouldNotBuildTransactionFromConn(_) => {
+                "COULD_NOT_BUILD_TRANSACTION_FROM_CONN"
+            }
+            AppError::CouldNotCommitTransaction(_) => "COULD_NOT_COMMIT_TRANSACTION",
+            AppError::Database(_) => "DATABASE_ERROR",
+            AppError::CouldNotInsertUser(_) => "COULD_NOT_INSERT_USER",
+            AppError::CouldNotInsertUserToken(_) => "COULD_NOT_INSERT_USER_TOKEN",
+            AppError::CouldNotInsertUserSession(_) => "COULD_NOT_INSERT_USER_SESSION",
+            AppError::CouldNotGetUser(_) => "COULD_NOT_GET_USER",
+            AppError::CouldNotGetUserSessions(_) => "COULD_NOT_GET_USER_SESSIONS",
+            AppError::CouldNotUpdateUser(_) => "COULD_NOT_UPDATE_USER",
+            AppError::CouldNotUpdateUserToken(_) => "COULD_NOT_UPDATE_USER_TOKEN",
+            AppError::CouldNotRevokeUserSession(_) => "COULD_NOT_REVOKE_USER_SESSION",
+            AppError::CouldNotSignJwt(_) => "COULD_NOT_SIGN_JWT",
+            AppError::CouldNotEnqueueEmail(_) => "COULD_NOT_ENQUEUE_EMAIL",
+            AppError::CouldNotGetEmailOutbox(_) => "COULD_NOT_GET_EMAIL_OUTBOX",
+            AppError::CouldNotRenderEmailTemplate(_) => "COULD_NOT_RENDER_EMAIL_TEMPLATE",
+            AppError::CouldNotSetUpTwoFactor(_) => "COULD_NOT_SET_UP_TWO_FACTOR",
+            AppError::CouldNotVerifyTwoFactor(_) => "COULD_NOT_VERIFY_TWO_FACTOR",
+            AppError::CouldNotVerifyPassword(_) => "COULD_NOT_VERIFY_PASSWORD",
+            AppError::CouldNotHashPassword(_) => "COULD_NOT_HASH_PASSWORD",
+            AppError::CouldNotStartPasskeyCeremony(_) => "COULD_NOT_START_PASSKEY_CEREMONY",
+            AppError::CouldNotSerializeResponse(_) => "COULD_NOT_SERIALIZE_RESPONSE",
+            AppError::CouldNotBuildResponse(_) => "COULD_NOT_BUILD_RESPONSE",
+            AppError::WrongEmailFormat => "WRONG_EMAIL_FORMAT",
+            AppError::WrongPwFormat => "WRONG_PW_FORMAT",
+            AppError::UnsupportedMediaType => "UNSUPPORTED_MEDIA_TYPE",
+            AppError::CouldNotDeserializeBody(_) => "COULD_NOT_DESERIALIZE_BODY",
+            AppError::InvalidCredentials => "INVALID_CREDENTIALS",
+            AppError::UserEmailNotVerified => "USER_EMAIL_NOT_VERIFIED",
+            AppError::UserInactive => "USER_INACTIVE",
+            AppError::AuthRequired => "AUTH_REQUIRED",
+            AppError::AdminRequired => "ADMIN_REQUIRED",
+            AppError::JwtExpired => "JWT_EXPIRED",
+            AppError::JwtInvalidSignature => "JWT_INVALID_SIGNATURE",
+            AppError::JwtMalformed => "JWT_MALFORMED",
+            AppError::UserSessionInvalid => "USER_SESSION_INVALID",
+            AppError::RefreshTokenReused => "REFRESH_TOKEN_REUSED",
+            AppError::InvalidTwoFactorCode => "INVALID_TWO_FACTOR_CODE",
+            AppError::UserAlreadyExists => "USER_ALREADY_EXISTS",
+            AppError::UserAlreadyVerified => "USER_ALREADY_VERIFIED",
+            AppError::UserTokenInvalid => "USER_TOKEN_INVALID",
+            AppError::UserTokenUsed => "USER_TOKEN_USED",
+            AppError::UserTokenExpired => "USER_TOKEN_EXPIRED",
+            AppError::UserSessionNotFound => "USER_SESSION_NOT_FOUND",
+            AppError::TwoFactorAlreadyEnabled => "TWO_FACTOR_ALREADY_ENABLED",
+            AppError::TwoFactorNotEnrolled => "TWO_FACTOR_NOT_ENROLLED",
+            AppError::PasskeyChallengeInvalid => "PASSKEY_CHALLENGE_INVALID",
+            AppError::PasskeyRejected => "PASSKEY_REJECTED",
+            AppError::PasskeyAlreadyRegistered => "PASSKEY_ALREADY_REGISTERED",
+            AppError::PasskeyNotFound => "PASSKEY_NOT_FOUND",
+            AppError::RateLimited { .. } => "RATE_LIMITED",
+            AppError::LoginBackoff { .. } => "LOGIN_BACKOFF",
+            AppError::AccountLocked { .. } => "ACCOUNT_LOCKED",
+            AppError::EmailOutboxEntryNotFound => "EMAIL_OUTBOX_ENTRY_NOT_FOUND",
+            AppError::EmailTemplateNotFound => "EMAIL_TEMPLATE_NOT_FOUND",
+        }
+    }
+
+    pub fn status_code(&self) -> StatusCode {
+        match self {
+            AppError::CouldNotGetConnFromPool(_) => StatusCode::SERVICE_UNAVAILABLE,
+            AppError::CouldNotBuildTransactionFromConn(_)
+            | AppError::CouldNotCommitTransaction(_)
+            | AppError::Database(_)
+            | AppError::CouldNotInsertUser(_)
+            | AppError::CouldNotInsertUserToken(_)
+            | AppError::CouldNotInsertUserSession(_)
+            | AppError::CouldNotGetUser(_)
+            | AppError::CouldNotGetUserSessions(_)
+            | AppError::CouldNotUpdateUser(_)
+            | AppError::CouldNotUpdateUserToken(_)
+            | AppError::CouldNotRevokeUserSession(_)
+            | AppError::CouldNotSignJwt(_)
+            | AppError::CouldNotEnqueueEmail(_)
+            | AppError::CouldNotGetEmailOutbox(_)
+            | AppError::CouldNotRenderEmailTemplate(_)
+            | AppError::CouldNotSetUpTwoFactor(_)
+            | AppError::CouldNotVerifyTwoFactor(_)
+            | AppError::CouldNotVerifyPassword(_)
+            | AppError::CouldNotHashPassword(_)
+            | AppError::CouldNotStartPasskeyCeremony(_)
+            | AppError::CouldNotSerializeResponse(_)
+            | AppError::CouldNotBuildResponse(_) => StatusCode::INTERNAL_SERVER_ERROR,
+            AppError::WrongEmailFormat
+            | AppError::WrongPwFormat
+            | AppError::PasskeyRejected
+            | AppError::CouldNotDeserializeBody(_) => StatusCode::BAD_REQUEST,
+            AppError::UnsupportedMediaType => StatusCode::UNSUPPORTED_MEDIA_TYPE,
+            AppError::InvalidCredentials
+            | AppError::AuthRequired
+            | AppError::JwtExpired
+            | AppError::JwtInvalidSignature
+            | AppError::JwtMalformed
+            | AppError::UserSessionInvalid
+            | AppError::RefreshTokenReused
+            | AppError::InvalidTwoFactorCode
+            | AppError::PasskeyChallengeInvalid
+            | AppError::UserTokenInvalid
+            | AppError::UserTokenExpired => StatusCode::UNAUTHORIZED,
+            AppError::UserEmailNotVerified
+            | AppError::UserInactive
+            | AppError::AdminRequired
+            | AppError::UserTokenUsed => StatusCode::FORBIDDEN,
+            AppError::UserSessionNotFound
+            | AppError::PasskeyNotFound
+            | AppError::EmailOutboxEntryNotFound
+            | AppError::EmailTemplateNotFound => StatusCode::NOT_FOUND,
+            AppError::UserAlreadyExists
+            | AppError::UserAlreadyVerified
+            | AppError::TwoFactorAlreadyEnabled
+            | AppError::TwoFactorNotEnrolled
+            | AppError::PasskeyAlreadyRegistered => StatusCode::CONFLICT,
+            AppError::RateLimited { .. } | AppError::LoginBackoff { .. } => {
+                StatusCode::TOO_MANY_REQUESTS
+            }
+            AppError::AccountLocked { .. } => StatusCode::LOCKED,
+        }
+    }
+
+    /// text shown to the client; never includes internal detail
+    pub fn public_message(&self) -> Cow<'static, str> {
+        Cow::Borrowed(match self {
+            AppError::CouldNotGetConnFromPool(_) => {
+                "The server is busy; please try again shortly."
+            }
+            AppError::CouldNotBuildTransactionFromConn(_)
+            | AppError::CouldNotCommitTransaction(_)
+            | AppError::Database(_) => "A database error occurred.",
+            AppError::CouldNotInsertUser(_) => "Could not create the user.",
+            AppError::CouldNotInsertUserToken(_) => "Could not create the token.",
+            AppError::CouldNotInsertUserSession(_) => "Could not create the session.",
+            AppError::CouldNotGetUser(_) => "Could not look up the user.",
+            AppError::CouldNotGetUserSessions(_) => "Could not look up sessions.",
+            AppError::CouldNotUpdateUser(_) => "Could not update the user.",
+            AppError::CouldNotUpdateUserToken(_) => "Could not update the token.",
+            AppError::CouldNotRevokeUserSession(_) => "Could not revoke the session.",
+            AppError::CouldNotSignJwt(_) => "Could not issue an access token.",
+            AppError::CouldNotEnqueueEmail(_) => "Could not queue the email for delivery.",
+            AppError::CouldNotGetEmailOutbox(_) => "Could not look up the email outbox.",
+            AppError::CouldNotRenderEmailTemplate(_) => "Could not render the email template.",
+            AppError::CouldNotSetUpTwoFactor(_) => "Could not set up two-factor authentication.",
+            AppError::CouldNotVerifyTwoFactor(_) => "Could not verify the two-factor code.",
+            AppError::CouldNotVerifyPassword(_) => "Could not verify the password.",
+            AppError::CouldNotHashPassword(_) => "Could not set the password.",
+            AppError::CouldNotStartPasskeyCeremony(_) => "Could not start the passkey ceremony.",
+            AppError::CouldNotSerializeResponse(_) | AppError::CouldNotBuildResponse(_) => {
+                "Could not build the response."
+            }
+            AppError::WrongEmailFormat => "The provided email format is incorrect.",
+            AppError::WrongPwFormat => "Password format is incorrect. Must be at least 8 characters and include uppercase, lowercase, number, and special characters among: [@, $, !, %, *, ?, &, #].",
+            AppError::UnsupportedMediaType => "Unsupported request body Content-Type. Use application/json, application/octet-stream (bincode), application/msgpack or application/cbor.",
+            AppError::CouldNotDeserializeBody(detail) => {
+                return Cow::Owned(format!("Could not deserialize request body: {}", detail))
+            }
+            AppError::InvalidCredentials => "The provided login credentials are invalid.",
+            AppError::UserEmailNotVerified => "The user's email has not been verified yet.",
+            AppError::UserInactive => "This account has been deactivated.",
+            AppError::AuthRequired => "Authentication is required to access this resource.",
+            AppError::AdminRequired => {
+                "Administrator privileges are required to access this resource."
+            }
+            AppError::JwtExpired => "The provided access token has expired.",
+            AppError::JwtInvalidSignature => "The provided access token has an invalid signature.",
+            AppError::JwtMalformed => "The provided access token is malformed.",
+            AppError::UserSessionInvalid => "The session is invalid, expired or revoked.",
+            AppError::RefreshTokenReused => {
+                "The provided refresh token was already used; the session has been revoked."
+            }
+            AppError::InvalidTwoFactorCode => "The provided two-factor or recovery code is invalid.",
+            AppError::UserAlreadyExists => {
+                "User already exists! Please use another email and screen name."
+            }
+            AppError::UserAlreadyVerified => "The user is already verified.",
+            AppError::UserTokenInvalid => "The provided token is invalid.",
+            AppError::UserTokenUsed => "The provided token has already been used.",
+            AppError::UserTokenExpired => "The provided token has expired.",
+            AppError::UserSessionNotFound => "The requested session does not exist.",
+            AppError::TwoFactorAlreadyEnabled => {
+                "Two-factor authentication is already enabled for this account."
+            }
+            AppError::TwoFactorNotEnrolled => {
+                "Two-factor enrollment has not been started; request a new secret first."
+            }
+            AppError::PasskeyChallengeInvalid => {
+                "The passkey challenge is invalid, expired or already used; start again."
+            }
+            AppError::PasskeyRejected => "The authenticator's response could not be verified.",
+            AppError::PasskeyAlreadyRegistered => "This passkey is already registered.",
+            AppError::PasskeyNotFound => "The requested passkey does not exist.",
+            AppError::RateLimited {
+                retry_after_seconds,
+                ..
+            } => {
+                return Cow::Owned(format!(
+                    "Too many requests; try again in {} seconds.",
+                    retry_after_seconds
+                ))
+            }
+            AppError::LoginBackoff {
+                retry_after_seconds,
+            } => {
+                return Cow::Owned(format!(
+                    "Too many failed login attempts; try again in {} seconds.",
+                    retry_after_seconds
+                ))
+            }
+            AppError::AccountLocked {
+                retry_after_seconds,
+            } => {
+                return Cow::Owned(format!(
+                    "This account is locked after too many failed login attempts; try again in {} minutes or use the unlock link sent by email.",
+                    retry_after_seconds.div_ceil(60)
+                ))
+            }
+            AppError::EmailOutboxEntryNotFound => {
+                "The requested email does not exist or is not dead-lettered."
+            }
+            AppError::EmailTemplateNotFound => "The requested email template does not exist.",
+        })
+    }
+
+    /// detail that only goes to the logs
+    pub fn internal_detail(&self) -> Option<&anyhow::Error> {
+        match self {
+            AppError::CouldNotGetConnFromPool(e)
+            | AppError::CouldNotBuildTransactionFromConn(e)
+            | AppError::CouldNotCommitTransaction(e)
+            | AppError::Database(e)
+            | AppError::CouldNotInsertUser(e)
+            | AppError::CouldNotInsertUserToken(e)
+            | AppError::CouldNotInsertUserSession(e)
+            | AppError::CouldNotGetUser(e)
+            | AppError::CouldNotGetUserSessions(e)
+            | AppError::CouldNotUpdateUser(e)
+            | AppError::CouldNotUpdateUserToken(e)
+            | AppError::CouldNotRevokeUserSession(e)
+            | AppError::CouldNotSignJwt(e)
+            | AppError::CouldNotEnqueueEmail(e)
+            | AppError::CouldNotGetEmailOutbox(e)
+            | AppError::CouldNotRenderEmailTemplate(e)
+            | AppError::CouldNotSetUpTwoFactor(e)
+            | AppError::CouldNotVerifyTwoFactor(e)
+            | AppError::CouldNotVerifyPassword(e)
+            | AppError::CouldNotHashPassword(e)
+            | AppError::CouldNotStartPasskeyCeremony(e)
+            | AppError::CouldNotSerializeResponse(e)
+            | AppError::CouldNotBuildResponse(e) => Some(e),
+            _ => None,
+        }
+    }
+
+    fn retry_after(&self) -> Option<Duration> {
+        match self {
+            AppError::RateLimited {
+                retry_after_seconds,
+                ..
+            }
+            | AppError::LoginBackoff {
+                retry_after_seconds,
+            }
+            | AppError::AccountLocked {
+                retry_after_seconds,
+            } => Some(Duration::from_secs((*retry_after_seconds).max(1))),
+            _ => None,
+        }
+    }
+
+    pub fn to_err_resp(&self) -> ErrResp {
         ErrResp {
             success: false,
-            data: ErrRespDatFin {
-                code: dat.code,
-                message: {
-                    let mut nstr = dat.message.to_owned();
-                    nstr.push_str(&error.to_string());
-
-                    nstr
-                },
-                status_code: dat.status_code,
+            data: ErrRespData {
+                code: self.code().to_owned(),
+                message: self.public_message().into_owned(),
+                status_code: self.status_code().as_u16(),
             },
             meta: SvrErrorRespMeta {
-                time_taken: format!("{:?}", start.get_original_start().elapsed()),
+                time_taken: format!("{:?}", request_elapsed()),
                 timestamp: Utc::now().to_rfc3339(),
             },
         }
     }
 }
 
-impl ErrRespDat {
-    pub const COULD_NOT_GET_CONN_FROM_POOL: ErrRespDat = ErrRespDat {
-        code: 1,
-        message: "Could not get connection from pool; ",
-        status_code: 500, // INTERNAL SERVER ERROR
-    };
-    pub const COULD_NOT_BUILD_TRANSACTION_FROM_CONN: ErrRespDat = ErrRespDat {
-        code: 2,
-        message: "Could not build transaction from connection; ",
-        status_code: 500, // INTERNAL SERVER ERROR
-    };
-    pub const WRONG_EMAIL_FORMAT: ErrRespDat = ErrRespDat {
-        code: 3,
-        message: "The provided email format is incorrect.",
-        status_code: 400, // BAD IN
-    };
-    pub const WRONG_PW_FORMAT: ErrRespDat = ErrRespDat {
-        code: 4,
-        message: "Password format is incorrect. Must be at least 8 characters and include uppercase, lowercase, number, and special characters among: [@, $, !, %, *, ?, &, #]",
-        status_code: 400, // BAD REQUEST
-    };
-    pub const COULD_NOT_INSERT_USER: ErrRespDat = ErrRespDat {
-        code: 5,
-        message: "Could not insert user into database; ",
-        status_code: 500, // INTERNAL SERVER ERROR
-    };
-    pub const COULD_NOT_INSERT_USER_TOKEN: ErrRespDat = ErrRespDat {
-        code: 6,
-        message: "Could not insert user token into database; ",
-        status_code: 500, // INTERNAL SERVER ERROR
-    };
-    pub const COULD_NOT_CONSTRUCT_EMAIL: ErrRespDat = ErrRespDat {
-        code: 7,
-        message: "Could not construct email content; ",
-        status_code: 500, // INTERNAL SERVER ERROR
-    };
-    pub const COULD_NOT_SEND_MAIL: ErrRespDat = ErrRespDat {
-        code: 8,
-        message: "Could not send email; ",
-        status_code: 500, // INTERNAL SERVER ERROR
-    };
-    pub const COULD_NOT_SERIALIZE_BINCODE: ErrRespDat = ErrRespDat {
-        code: 9,
-        message: "Failed to serialize data with bincode; ",
-        status_code: 500, // INTERNAL SERVER ERROR
-    };
-    pub const COULD_NOT_COMMIT_TRANSACTION: ErrRespDat = ErrRespDat {
-        code: 10,
-        message: "Could not commit transaction; ",
-        status_code: 500, // INTERNAL SERVER ERROR
-    };
-    pub const USER_ALREADY_EXISTS: ErrRespDat = ErrRespDat {
-        code: 11,
-        message: "User already exists in the database; ",
-        status_code: 409, // CONFLICT
-    };
-    pub const USER_TOKEN_INVALID: ErrRespDat = ErrRespDat {
-        code: 12,
-        message: "The provided user token is invalid; ",
-        status_code: 401, // UNAUTHORIZED
-    };
-    pub const USER_TOKEN_USED: ErrRespDat = ErrRespDat {
-        code: 13,
-        message: "The provided user token has already been used; ",
-        status_code: 403, // FORBIDDEN
-    };
-    pub const USER_TOKEN_EXPIRED: ErrRespDat = ErrRespDat {
-        code: 14,
-        message: "The provided user token has expired; ",
-        status_code: 401, // UNAUTHORIZED
-    };
-    pub const USER_ALREADY_VERIFIED: ErrRespDat = ErrRespDat {
-        code: 15,
-        message: "The user is already verified; ",
-        status_code: 409, // CONFLICT
-    };
-    pub const COULD_NOT_BUILD_RESPONSE: ErrRespDat = ErrRespDat {
-        code: 16,
-        message: "Could not build response; ",
-        status_code: 500, // INTERNAL SERVER ERROR
-    };
+impl std::fmt::Display for AppError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.internal_detail() {
+            Some(detail) => write!(f, "{}: {:#}", self.code(), detail),
+            None => write!(f, "{}: {}", self.code(), self.public_message()),
+        }
+    }
+}
+
+impl IntoResponse for AppError {
+    fn into_response(self) -> axum::response::Response {
+        match self.internal_detail() {
+            Some(detail) => error!(code = self.code(), "{:?}", detail),
+            None if self.status_code().is_server_error() => error!(code = self.code()),
+            None => warn!(code = self.code(), "{}", self.public_message()),
+        }
+
+        let format = ContentFormat::current();
+        let body = self.to_err_resp();
+        let serialized_body = match format.serialize(&body) {
+            Ok(serialized_body) => serialized_body,
+            Err(e) => {
+                error!("Failed to serialize ErrResp: {:?}", e);
+                return self.status_code().into_response();
+            }
+        };
+
+        let mut response = (
+            self.status_code(),
+            [(axum::http::header::CONTENT_TYPE, format.mime())],
+            serialized_body,
+        )
+            .into_response();
+
+        if let Some(retry_after) = self.retry_after() {
+            if let Ok(value) = HeaderValue::from_str(&retry_after.as_secs().to_string()) {
+                response.headers_mut().insert(RETRY_AFTER, value);
+            }
+        }
+
+        // the bucket that refused the request is empty until the next token
+        if let AppError::RateLimited {
+            limit,
+            retry_after_seconds,
+        } = self
+        {
+            let headers = response.headers_mut();
+            headers.insert(RATELIMIT_LIMIT.clone(), HeaderValue::from(limit));
+            headers.insert(RATELIMIT_REMAINING.clone(), HeaderValue::from(0));
+            headers.insert(
+                RATELIMIT_RESET.clone(),
+                HeaderValue::from(retry_after_seconds.max(1)),
+            );
+        }
+
+        response
+    }
+}
+
+impl From<tokio_postgres::Error> for AppError {
+    fn from(e: tokio_postgres::Error) -> Self {
+        AppError::Database(e.into())
+    }
+}
+
+impl From<deadpool_postgres::PoolError> for AppError {
+    fn from(e: deadpool_postgres::PoolError) -> Self {
+        AppError::CouldNotGetConnFromPool(e.into())
+    }
+}
+
+/// codes that were shipped once and removed; they must never be given to a new variant
+pub const RETIRED_CODES: &[&str] = &[
+    "COULD_NOT_CONSTRUCT_EMAIL", // mail is rendered by the outbox, not in handlers
+    "COULD_NOT_SEND_MAIL",       // delivery failures stay in the outbox worker
+    "COULD_NOT_SERIALIZE_BINCODE", // became COULD_NOT_SERIALIZE_RESPONSE
+    "VERIFICATION_RESEND_COOLDOWN", // told callers an unverified account exists; now a silent no-op
+    "VERIFICATION_RESEND_DAILY_CAP", // likewise
+];
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+
+    use anyhow::anyhow;
+
+    use super::*;
+
+    /// one of every variant; the exhaustive match below stops this list from going stale
+    fn every_variant() -> Vec<AppError> {
+        let all = vec![
+            AppError::CouldNotGetConnFromPool(anyhow!("")),
+            AppError::CouldNotBuildTransactionFromConn(anyhow!("")),
+            AppError::CouldNotCommitTransaction(anyhow!("")),
+            AppError::Database(anyhow!("")),
+            AppError::CouldNotInsertUser(anyhow!("")),
+            AppError::CouldNotInsertUserToken(anyhow!("")),
+            AppError::CouldNotInsertUserSession(anyhow!("")),
+            AppError::CouldNotGetUser(anyhow!("")),
+            AppError::CouldNotGetUserSessions(anyhow!("")),
+            AppError::CouldNotUpdateUser(anyhow!("")),
+            AppError::CouldNotUpdateUserToken(anyhow!("")),
+            AppError::CouldNotRevokeUserSession(anyhow!("")),
+            AppError::CouldNotSignJwt(anyhow!("")),
+            AppError::CouldNotEnqueueEmail(anyhow!("")),
+            AppError::CouldNotGetEmailOutbox(anyhow!("")),
+            AppError::CouldNotRenderEmailTemplate(anyhow!("")),
+            AppError::CouldNotSetUpTwoFactor(anyhow!("")),
+            AppError::CouldNotVerifyTwoFactor(anyhow!("")),
+            AppError::CouldNotVerifyPassword(anyhow!("")),
+            AppError::CouldNotHashPassword(anyhow!("")),
+            AppError::CouldNotStartPasskeyCeremony(anyhow!("")),
+            AppError::CouldNotSerializeResponse(anyhow!("")),
+            AppError::CouldNotBuildResponse(anyhow!("")),
+            AppError::WrongEmailFormat,
+            AppError::WrongPwFormat,
+            AppError::UnsupportedMediaType,
+            AppError::CouldNotDeserializeBody(String::new()),
+            AppError::InvalidCredentials,
+            AppError::UserEmailNotVerified,
+            AppError::UserInactive,
+            AppError::AuthRequired,
+            AppError::AdminRequired,
+            AppError::JwtExpired,
+            AppError::JwtInvalidSignature,
+            AppError::JwtMalformed,
+            AppError::UserSessionInvalid,
+            AppError::RefreshTokenReused,
+            AppError::InvalidTwoFactorCode,
+            AppError::UserAlreadyExists,
+            AppError::UserAlreadyVerified,
+            AppError::UserTokenInvalid,
+            AppError::UserTokenUsed,
+            AppError::UserTokenExpired,
+            AppError::UserSessionNotFound,
+            AppError::TwoFactorAlreadyEnabled,
+            AppError::TwoFactorNotEnrolled,
+            AppError::PasskeyChallengeInvalid,
+            AppError::PasskeyRejected,
+            AppError::PasskeyAlreadyRegistered,
+            AppError::PasskeyNotFound,
+            AppError::RateLimited {
+                limit: 1,
+                retry_after_seconds: 1,
+            },
+            AppError::LoginBackoff {
+                retry_after_seconds: 1,
+            },
+            AppError::AccountLocked {
+                retry_after_seconds: 1,
+            },
+            AppError::EmailOutboxEntryNotFound,
+            AppError::EmailTemplateNotFound,
+        ];
+
+        // adding a variant without listing it above fails to compile here
+        for e in all.iter() {
+            match e {
+                AppError::CouldNotGetConnFromPool(_)
+                | AppError::CouldNotBuildTransactionFromConn(_)
+                | AppError::CouldNotCommitTransaction(_)
+                | AppError::Database(_)
+                | AppError::CouldNotInsertUser(_)
+                | AppError::CouldNotInsertUserToken(_)
+                | AppError::CouldNotInsertUserSession(_)
+                | AppError::CouldNotGetUser(_)
+                | AppError::CouldNotGetUserSessions(_)
+                | AppError::CouldNotUpdateUser(_)
+                | AppError::CouldNotUpdateUserToken(_)
+                | AppError::CouldNotRevokeUserSession(_)
+                | AppError::CouldNotSignJwt(_)
+                | AppError::CouldNotEnqueueEmail(_)
+                | AppError::CouldNotGetEmailOutbox(_)
+                | AppError::CouldNotRenderEmailTemplate(_)
+                | AppError::CouldNotSetUpTwoFactor(_)
+                | AppError::CouldNotVerifyTwoFactor(_)
+                | AppError::CouldNotVerifyPassword(_)
+                | AppError::CouldNotHashPassword(_)
+                | AppError::CouldNotStartPasskeyCeremony(_)
+                | AppError::CouldNotSerializeResponse(_)
+                | AppError::CouldNotBuildResponse(_)
+                | AppError::WrongEmailFormat
+                | AppError::WrongPwFormat
+                | AppError::UnsupportedMediaType
+                | AppError::CouldNotDeserializeBody(_)
+                | AppError::InvalidCredentials
+                | AppError::UserEmailNotVerified
+                | AppError::UserInactive
+                | AppError::AuthRequired
+                | AppError::AdminRequired
+                | AppError::JwtExpired
+                | AppError::JwtInvalidSignature
+                | AppError::JwtMalformed
+                | AppError::UserSessionInvalid
+                | AppError::RefreshTokenReused
+                | AppError::InvalidTwoFactorCode
+                | AppError::UserAlreadyExists
+                | AppError::UserAlreadyVerified
+                | AppError::UserTokenInvalid
+                | AppError::UserTokenUsed
+                | AppError::UserTokenExpired
+                | AppError::UserSessionNotFound
+                | AppError::TwoFactorAlreadyEnabled
+                | AppError::TwoFactorNotEnrolled
+                | AppError::PasskeyChallengeInvalid
+                | AppError::PasskeyRejected
+                | AppError::PasskeyAlreadyRegistered
+                | AppError::PasskeyNotFound
+                | AppError::RateLimited { .. }
+                | AppError::LoginBackoff { .. }
+                | AppError::AccountLocked { .. }
+                | AppError::EmailOutboxEntryNotFound
+                | AppError::EmailTemplateNotFound => (),
+            }
+        }
+
+        all
+    }
+
+    #[test]
+    fn test_codes_are_unique() {
+        let mut seen = HashSet::new();
+        for e in every_variant() {
+            assert!(seen.insert(e.code()), "duplicate error code {}", e.code());
+        }
+    }
+
+    #[test]
+    fn test_retired_codes_are_never_reused() {
+        let retired: HashSet<&str> = RETIRED_CODES.iter().copied().collect();
+        assert_eq!(
+            retired.len(),
+            RETIRED_CODES.len(),
+            "RETIRED_CODES has duplicates"
+        );
+
+        for e in every_variant() {
+            assert!(
+                !retired.contains(e.code()),
+                "error code {} was retired and may not be reused",
+                e.code()
+            );
+        }
+    }
+
+    #[test]
+    fn test_codes_are_screaming_snake_case() {
+        for e in every_variant() {
+            assert!(
+                e.code()
+                    .chars()
+                    .all(|c| c.is_ascii_uppercase() || c.is_ascii_digit() || c == '_'),
+                "{}",
+                e.code()
+            );
+        }
+    }
+
+    #[test]
+    fn test_internal_detail_is_not_public() {
+        let e = AppError::CouldNotInsertUser(anyhow!("duplicate key value violates constraint"));
+        let body = e.to_err_resp();
+        assert_eq!(body.get_code(), "COULD_NOT_INSERT_USER");
+        assert!(!body.data.message.contains("duplicate key"));
+        assert_eq!(body.data.status_code, 500);
+    }
+
+    #[test]
+    fn test_cooldown_sets_retry_after() {
+        let response = AppError::LoginBackoff {
+            retry_after_seconds: 42,
+        }
+        .into_response();
+        assert_eq!(response.status(), StatusCode::TOO_MANY_REQUESTS);
+        assert_eq!(response.headers().get(RETRY_AFTER).unwrap(), "42");
+    }
+
+    #[test]
+    fn test_rate_limited_sets_ratelimit_headers() {
+        let response = AppError::RateLimited {
+            limit: 10,
+            retry_after_seconds: 6,
+        }
+        .into_response();
+        assert_eq!(response.status(), StatusCode::TOO_MANY_REQUESTS);
+        assert_eq!(response.headers().get(RETRY_AFTER).unwrap(), "6");
+        assert_eq!(response.headers()[&RATELIMIT_LIMIT], "10");
+        assert_eq!(response.headers()[&RATELIMIT_REMAINING], "0");
+        assert_eq!(response.headers()[&RATELIMIT_RESET], "6");
+    }
+
+    #[test]
+    fn test_account_locked_reports_minutes_left() {
+        let e = AppError::AccountLocked {
+            retry_after_seconds: 1799,
+        };
+        assert!(e.public_message().contains("30 minutes"));
+
+        let response = e.into_response();
+        assert_eq!(response.status(), StatusCode::LOCKED);
+        assert_eq!(response.headers().get(RETRY_AFTER).unwrap(), "1799");
+        assert!(!response.headers().contains_key(&RATELIMIT_LIMIT));
+    }
 }
@@ -1,23 +1,54 @@
+use std::{sync::OnceLock, time::Instant};
+
 use anyhow::Result;
 use argon2::{
     password_hash::{rand_core::OsRng, PasswordHash, PasswordHasher, PasswordVerifier, SaltString},
     Argon2,
 };
 
+use tracing::info_span;
+use uuid::Uuid;
+
+use crate::utils::server_init::server_init_funcs::initialize_metrics::PASSWORD_HASH_DURATION_SECONDS;
+
 pub fn hash_password(password: &str) -> String {
+    try_hash_password(password).unwrap_or_else(|e| panic!("Failed to hash password: {:?}", e))
+}
+
+/// hash_password for callers that answer a request and would rather report the failure than panic
+pub fn try_hash_password(password: &str) -> Result<String> {
+    let _span = info_span!("argon2.hash").entered();
+    let start = Instant::now();
     let salt = SaltString::generate(&mut OsRng);
     let argon2 = Argon2::default();
     let password_hash = argon2
         .hash_password(password.as_bytes(), &salt)
-        .unwrap_or_else(|e| panic!("Failed to hash password: {:?}", e))
+        .map_err(|e| anyhow::anyhow!(e))?
         .to_string();
+    metrics::histogram!(PASSWORD_HASH_DURATION_SECONDS, "operation" => "hash")
+        .record(start.elapsed());
 
-    password_hash
+    Ok(password_hash)
 }
 
 pub fn verify_password(hash: String, password: String) -> Result<bool> {
+    let _span = info_span!("argon2.verify").entered();
     let parsed_hash = PasswordHash::new(&hash).map_err(|e| anyhow::anyhow!(e))?;
-    Ok(Argon2::default()
+    let start = Instant::now();
+    let verified = Argon2::default()
         .verify_password(password.as_bytes(), &parsed_hash)
-        .is_ok())
+        .is_ok();
+    metrics::histogram!(PASSWORD_HASH_DURATION_SECONDS, "operation" => "verify")
+        .record(start.elapsed());
+
+    Ok(verified)
+}
+
+/// a hash of a password nobody knows; logins naming an unknown user are verified against it so they take as
+/// long as a wrong password for a real one
+pub fn dummy_password_hash() -> String {
+    static DUMMY_PASSWORD_HASH: OnceLock<String> = OnceLock::new();
+    DUMMY_PASSWORD_HASH
+        .get_or_init(|| hash_password(&Uuid::new_v4().to_string()))
+        .clone()
 }
@@ -10,24 +10,14 @@ pub struct Stopwatch {
 }
 
 impl Stopwatch {
-    /// takes in a displayable and as_ref<str> type as reference to initiate a new stopwatch instance
+    /// takes in a displayable and as_ref<str> type as reference to initiate a new stopwatch instance;
+    /// a non-empty message is logged as it starts
     pub fn new<T>(message: &T) -> Self
     where
         T: Display + AsRef<str> + ?Sized,
     {
         if !message.as_ref().is_empty() {
-            println!(
-                "{}  INFO {}: {}",
-                chrono::Utc::now().to_rfc3339_opts(chrono::SecondsFormat::Micros, true),
-                env!("CARGO_PKG_NAME"),
-                message
-            );
-        } else {
-            println!(
-                "{}  INFO {}",
-                chrono::Utc::now().to_rfc3339_opts(chrono::SecondsFormat::Micros, true),
-                env!("CARGO_PKG_NAME"),
-            );
+            info!("{}", message);
         }
 
         let now = tokio::time::Instant::now();
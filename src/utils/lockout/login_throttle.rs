@@ -0,0 +1,167 @@
+use std::{
+    sync::atomic::{AtomicU64, Ordering},
+    time::Duration,
+};
+
+use chrono::Utc;
+use deadpool_postgres::Pool;
+use tracing::{debug, warn};
+
+use crate::{
+    models::login_failures::LoginFailure,
+    utils::{config::app_config::LockoutConfig, errors::errors::AppError},
+};
+
+/// how often each instance deletes failure counters that have gone idle
+const PRUNE_INTERVAL_SECONDS: u64 = 60;
+
+/// the longest back-off exponent; 2^20 seconds is far past any sensible max_delay_seconds
+const MAX_DOUBLINGS: u32 = 20;
+
+/// decides whether a login attempt may go ahead, given the failures recorded against its account and client IP
+pub struct LoginThrottle {
+    config: LockoutConfig,
+    pool: Pool,
+    /// unix seconds of the last prune
+    pruned_at: AtomicU64,
+}
+
+impl LoginThrottle {
+    pub fn new(config: LockoutConfig, pool: Pool) -> Self {
+        LoginThrottle {
+            config,
+            pool,
+            pruned_at: AtomicU64::new(Utc::now().timestamp() as u64),
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.config.enabled
+    }
+
+    pub fn get_config(&self) -> &LockoutConfig {
+        &self.config
+    }
+
+    pub fn window_seconds(&self) -> f64 {
+        (self.config.window_minutes * 60) as f64
+    }
+
+    pub fn duration_seconds(&self) -> f64 {
+        (self.config.duration_minutes * 60) as f64
+    }
+
+    /// how long the next attempt has to wait after this many failures: nothing for the free attempts,
+    /// then one second doubling with each further failure, capped at max_delay_seconds
+    pub fn backoff_delay(&self, failures: u32) -> Duration {
+        if failures < self.config.free_attempts {
+            return Duration::ZERO;
+        }
+
+        let doublings = (failures - self.config.free_attempts).min(MAX_DOUBLINGS);
+        Duration::from_secs((1u64 << doublings).min(self.config.max_delay_seconds))
+    }
+
+    /// refuses the attempt while a lock is in place or a back-off hasn't elapsed.
+    /// A locked account is reported as such; a locked IP looks like a long back-off
+    pub fn check(&self, failures: &[LoginFailure]) -> Result<(), AppError> {
+        if !self.config.enabled {
+            return Ok(());
+        }
+
+        let mut wait_seconds = 0.0f64;
+        for failure in failures {
+            if failure.is_locked() {
+                if failure.get_key().starts_with("account:") {
+                    return Err(AppError::AccountLocked {
+                        retry_after_seconds: failure.get_locked_seconds().ceil() as u64,
+                    });
+                }
+                wait_seconds = wait_seconds.max(failure.get_locked_seconds());
+                continue;
+            }
+
+            let delay = self.backoff_delay(failure.get_count().max(0) as u32);
+            wait_seconds = wait_seconds.max(delay.as_secs_f64() - failure.get_elapsed_seconds());
+        }
+
+        match wait_seconds > 0.0 {
+            true => Err(AppError::LoginBackoff {
+                retry_after_seconds: wait_seconds.ceil() as u64,
+            }),
+            false => Ok(()),
+        }
+    }
+
+    /// deletes idle counters at most once per interval; the caller's request does not wait for it
+    pub fn prune_if_due(&self) {
+        let now = Utc::now().timestamp() as u64;
+        let pruned_at = self.pruned_at.load(Ordering::Relaxed);
+        if now < pruned_at + PRUNE_INTERVAL_SECONDS
+            || self
+                .pruned_at
+                .compare_exchange(pruned_at, now, Ordering::Relaxed, Ordering::Relaxed)
+                .is_err()
+        {
+            return;
+        }
+
+        let pool = self.pool.clone();
+        let window_seconds = self.window_seconds();
+        tokio::spawn(async move {
+            let deleted = match pool.get().await {
+                Ok(conn) => LoginFailure::delete_stale(&conn, window_seconds).await,
+                Err(e) => Err(e.into()),
+            };
+            match deleted {
+                Ok(deleted) => debug!("Pruned {} idle login failure counter(s)", deleted),
+                Err(e) => warn!("Could not prune login failure counters: {:?}", e),
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use deadpool_postgres::{Manager, Pool};
+    use tokio_postgres::NoTls;
+
+    use super::*;
+
+    fn throttle(free_attempts: u32, max_delay_seconds: u64) -> LoginThrottle {
+        let pool = Pool::builder(Manager::new(tokio_postgres::Config::new(), NoTls))
+            .build()
+            .unwrap();
+        LoginThrottle::new(
+            LockoutConfig {
+                enabled: true,
+                account_threshold: 10,
+                ip_threshold: 50,
+                free_attempts,
+                max_delay_seconds,
+                window_minutes: 15,
+                duration_minutes: 30,
+            },
+            pool,
+        )
+    }
+
+    #[test]
+    fn test_backoff_doubles_after_the_free_attempts() {
+        let throttle = throttle(3, 60);
+        let delays: Vec<u64> = (0..12)
+            .map(|failures| throttle.backoff_delay(failures).as_secs())
+            .collect();
+        assert_eq!(delays, [0, 0, 0, 1, 2, 4, 8, 16, 32, 60, 60, 60]);
+
+        // no overflow however many failures pile up
+        assert_eq!(throttle.backoff_delay(u32::MAX).as_secs(), 60);
+    }
+
+    #[test]
+    fn test_zero_max_delay_disables_backoff() {
+        let throttle = throttle(0, 0);
+        assert_eq!(throttle.backoff_delay(0), Duration::ZERO);
+        assert_eq!(throttle.backoff_delay(100), Duration::ZERO);
+    }
+}
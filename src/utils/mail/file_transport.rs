@@ -0,0 +1,66 @@
+use std::path::PathBuf;
+
+use anyhow::anyhow;
+use axum::async_trait;
+use uuid::Uuid;
+
+use super::mail_transport::{MailTransport, OutgoingEmail};
+
+/// writes every email as a `.eml` file into a directory instead of sending it
+pub struct FileMailTransport {
+    dir: PathBuf,
+}
+
+impl FileMailTransport {
+    pub fn new(dir: impl Into<PathBuf>) -> anyhow::Result<Self> {
+        let dir = dir.into();
+        std::fs::create_dir_all(&dir)
+            .map_err(|e| anyhow!("Could not create mail directory {:?}: {:?}", dir, e))?;
+        Ok(FileMailTransport { dir })
+    }
+}
+
+#[async_trait]
+impl MailTransport for FileMailTransport {
+    async fn send(&self, email: &OutgoingEmail) -> anyhow::Result<()> {
+        let path = self.dir.join(format!("{}.eml", Uuid::new_v4()));
+        match tokio::fs::write(&path, email.to_message()?.formatted()).await {
+            Ok(_) => Ok(()),
+            Err(e) => Err(anyhow!("Could not write email to {:?}: {:?}", path, e)),
+        }
+    }
+
+    fn name(&self) -> &'static str {
+        "file"
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_writes_eml_file() {
+        let dir = std::env::temp_dir().join(format!("cyhdev_mail_{}", Uuid::new_v4()));
+        let transport = FileMailTransport::new(&dir).unwrap();
+
+        transport
+            .send(&OutgoingEmail {
+                to: "someone@example.com".to_owned(),
+                subject: "Hello".to_owned(),
+                text_body: "Hi there".to_owned(),
+                html_body: Some("<p>Hi there</p>".to_owned()),
+            })
+            .await
+            .unwrap();
+
+        let files: Vec<_> = std::fs::read_dir(&dir).unwrap().collect();
+        assert_eq!(files.len(), 1);
+        let raw = std::fs::read_to_string(files[0].as_ref().unwrap().path()).unwrap();
+        assert!(raw.contains("To: someone@example.com"));
+        assert!(raw.contains("Subject: Hello"));
+        assert!(raw.contains("multipart/alternative"));
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,53 @@
+use anyhow::anyhow;
+use axum::async_trait;
+use lettre::{
+    message::{Mailbox, MultiPart},
+    Message,
+};
+
+use crate::models::consts::SMTP_EMAIL;
+
+/// an email ready to be handed to a transport; kept apart from lettre's Message so tests can read it back
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct OutgoingEmail {
+    pub to: String,
+    pub subject: String,
+    pub text_body: String,
+    pub html_body: Option<String>,
+}
+
+impl OutgoingEmail {
+    /// builds the RFC 5322 message sent by the SMTP and file transports; multipart/alternative when there is an HTML part
+    pub fn to_message(&self) -> anyhow::Result<Message> {
+        let builder = Message::builder()
+            .from(unsafe { SMTP_EMAIL.parse().unwrap_unchecked() })
+            .to(self
+                .to
+                .parse::<Mailbox>()
+                .map_err(|e| anyhow!("Could not parse recipient email: {:?}", e))?)
+            .subject(&self.subject);
+
+        match &self.html_body {
+            Some(html_body) => builder.multipart(MultiPart::alternative_plain_html(
+                self.text_body.clone(),
+                html_body.clone(),
+            )),
+            None => builder.body(self.text_body.clone()),
+        }
+        .map_err(|e| anyhow!("Could not construct email: {:?}", e))
+    }
+}
+
+/// anything that can deliver an OutgoingEmail; held in ServerResources as `Arc<dyn MailTransport>`
+#[async_trait]
+pub trait MailTransport: Send + Sync {
+    async fn send(&self, email: &OutgoingEmail) -> anyhow::Result<()>;
+
+    /// whether the transport could send right now; used by the readiness probe
+    async fn check(&self) -> anyhow::Result<()> {
+        Ok(())
+    }
+
+    /// short name for logs, e.g. "smtp"
+    fn name(&self) -> &'static str;
+}
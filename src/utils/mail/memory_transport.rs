@@ -0,0 +1,55 @@
+use std::sync::Mutex;
+
+use axum::async_trait;
+
+use super::mail_transport::{MailTransport, OutgoingEmail};
+
+/// keeps every email in memory so tests can assert on what would have been sent
+#[derive(Default)]
+pub struct InMemoryMailTransport {
+    sent: Mutex<Vec<OutgoingEmail>>,
+}
+
+impl InMemoryMailTransport {
+    pub fn new() -> Self {
+        InMemoryMailTransport::default()
+    }
+
+    /// every email sent so far, oldest first
+    pub fn sent(&self) -> Vec<OutgoingEmail> {
+        self.sent
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            .clone()
+    }
+
+    /// emails sent to one recipient, oldest first
+    pub fn sent_to(&self, to: &str) -> Vec<OutgoingEmail> {
+        self.sent()
+            .into_iter()
+            .filter(|email| email.to == to)
+            .collect()
+    }
+
+    pub fn clear(&self) {
+        self.sent
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            .clear();
+    }
+}
+
+#[async_trait]
+impl MailTransport for InMemoryMailTransport {
+    async fn send(&self, email: &OutgoingEmail) -> anyhow::Result<()> {
+        self.sent
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            .push(email.clone());
+        Ok(())
+    }
+
+    fn name(&self) -> &'static str {
+        "memory"
+    }
+}
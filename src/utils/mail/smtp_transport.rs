@@ -0,0 +1,121 @@
+use std::{str::FromStr, time::Duration};
+
+use anyhow::anyhow;
+use axum::async_trait;
+use lettre::{
+    transport::smtp::authentication::Credentials, AsyncSmtpTransport, AsyncTransport,
+    Tokio1Executor,
+};
+
+use super::mail_transport::{MailTransport, OutgoingEmail};
+
+/// the longest one send may take, connecting included; the outbox lease is sized against it
+pub const SMTP_SEND_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// how the SMTP connection is secured
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SmtpTlsMode {
+    /// plaintext connection upgraded with STARTTLS (usually port 587)
+    StartTls,
+    /// TLS from the first byte (usually port 465)
+    Implicit,
+    /// no TLS at all; only for local relays such as MailHog
+    None,
+}
+
+impl FromStr for SmtpTlsMode {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "starttls" => Ok(SmtpTlsMode::StartTls),
+            "tls" | "implicit" => Ok(SmtpTlsMode::Implicit),
+            "none" => Ok(SmtpTlsMode::None),
+            _ => Err(anyhow!(
+                "Unknown SMTP TLS mode {:?}; expected starttls, tls or none",
+                s
+            )),
+        }
+    }
+}
+
+pub struct SmtpMailTransport {
+    transport: AsyncSmtpTransport<Tokio1Executor>,
+}
+
+impl SmtpMailTransport {
+    pub fn new(
+        host: &str,
+        port: Option<u16>,
+        tls_mode: SmtpTlsMode,
+        credentials: Option<Credentials>,
+    ) -> anyhow::Result<Self> {
+        let mut builder = match tls_mode {
+            SmtpTlsMode::StartTls => AsyncSmtpTransport::<Tokio1Executor>::starttls_relay(host)
+                .map_err(|e| anyhow!("Could not build SMTP relay: {:?}", e))?,
+            SmtpTlsMode::Implicit => AsyncSmtpTransport::<Tokio1Executor>::relay(host)
+                .map_err(|e| anyhow!("Could not build SMTP relay: {:?}", e))?,
+            SmtpTlsMode::None => AsyncSmtpTransport::<Tokio1Executor>::builder_dangerous(host),
+        };
+
+        if let Some(port) = port {
+            builder = builder.port(port);
+        }
+
+        if let Some(credentials) = credentials {
+            builder = builder.credentials(credentials);
+        }
+
+        builder = builder.timeout(Some(SMTP_SEND_TIMEOUT));
+
+        Ok(SmtpMailTransport {
+            transport: builder.build(),
+        })
+    }
+}
+
+#[async_trait]
+impl MailTransport for SmtpMailTransport {
+    async fn send(&self, email: &OutgoingEmail) -> anyhow::Result<()> {
+        // lettre's timeout applies to each step of the conversation, so the whole send is bounded as well
+        match tokio::time::timeout(SMTP_SEND_TIMEOUT, self.transport.send(email.to_message()?))
+            .await
+        {
+            Ok(Ok(_)) => Ok(()),
+            Ok(Err(e)) => Err(anyhow::Error::from(e)),
+            Err(_) => Err(anyhow!("SMTP send timed out after {:?}", SMTP_SEND_TIMEOUT)),
+        }
+    }
+
+    async fn check(&self) -> anyhow::Result<()> {
+        match self.transport.test_connection().await {
+            Ok(true) => Ok(()),
+            Ok(false) => Err(anyhow!("SMTP server did not accept the connection")),
+            Err(e) => Err(anyhow::Error::from(e)),
+        }
+    }
+
+    fn name(&self) -> &'static str {
+        "smtp"
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_tls_mode_parsing() {
+        assert_eq!(
+            "STARTTLS".parse::<SmtpTlsMode>().unwrap(),
+            SmtpTlsMode::StartTls
+        );
+        assert_eq!("tls".parse::<SmtpTlsMode>().unwrap(), SmtpTlsMode::Implicit);
+        assert_eq!(
+            "implicit".parse::<SmtpTlsMode>().unwrap(),
+            SmtpTlsMode::Implicit
+        );
+        assert_eq!("none".parse::<SmtpTlsMode>().unwrap(), SmtpTlsMode::None);
+        assert!("ssl3".parse::<SmtpTlsMode>().is_err());
+    }
+}
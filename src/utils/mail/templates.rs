@@ -0,0 +1,386 @@
+use std::str::FromStr;
+
+use anyhow::anyhow;
+use axum::http::{header::ACCEPT_LANGUAGE, HeaderMap};
+use serde_derive::Serialize;
+
+use crate::models::user_tokens::{PASSWORD_RESET_DURATION_MINUTES, VERIFICATION_DURATION_HOURS};
+
+/// templates live in templates/email/{locale}/{name}.{txt,html} and are compiled into the binary;
+/// the first line of the .txt file is the subject, separated from the body by a blank line
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum EmailTemplate {
+    VerifyEmail,
+    PasswordReset,
+    EmailChange,
+    Notification,
+    AccountLocked,
+}
+
+impl EmailTemplate {
+    pub const ALL: [EmailTemplate; 5] = [
+        EmailTemplate::VerifyEmail,
+        EmailTemplate::PasswordReset,
+        EmailTemplate::EmailChange,
+        EmailTemplate::Notification,
+        EmailTemplate::AccountLocked,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            EmailTemplate::VerifyEmail => "verify_email",
+            EmailTemplate::PasswordReset => "password_reset",
+            EmailTemplate::EmailChange => "email_change",
+            EmailTemplate::Notification => "notification",
+            EmailTemplate::AccountLocked => "account_locked",
+        }
+    }
+
+    /// placeholder values for previews
+    pub fn sample_vars(&self, public_base_url: &str) -> Vec<(&'static str, String)> {
+        match self {
+            EmailTemplate::VerifyEmail => vec![
+                (
+                    "link",
+                    verify_email_link(public_base_url, uuid::Uuid::nil()),
+                ),
+                ("expires_hours", VERIFICATION_DURATION_HOURS.to_string()),
+            ],
+            EmailTemplate::PasswordReset => vec![
+                (
+                    "link",
+                    password_reset_link(public_base_url, uuid::Uuid::nil()),
+                ),
+                (
+                    "expires_minutes",
+                    PASSWORD_RESET_DURATION_MINUTES.to_string(),
+                ),
+            ],
+            EmailTemplate::EmailChange => vec![
+                (
+                    "link",
+                    email_change_link(public_base_url, uuid::Uuid::nil()),
+                ),
+                ("new_email", "new.address@example.com".to_owned()),
+                ("expires_hours", VERIFICATION_DURATION_HOURS.to_string()),
+            ],
+            EmailTemplate::Notification => vec![
+                ("title", "You have a new reply".to_owned()),
+                (
+                    "message",
+                    "Someone replied to your post <Hello, world!>.".to_owned(),
+                ),
+                ("link", public_base_url.trim_end_matches('/').to_owned()),
+            ],
+            EmailTemplate::AccountLocked => vec![
+                (
+                    "link",
+                    account_unlock_link(public_base_url, uuid::Uuid::nil()),
+                ),
+                ("locked_minutes", "30".to_owned()),
+            ],
+        }
+    }
+
+    fn sources(&self, locale: &str) -> (&'static str, &'static str) {
+        macro_rules! template_sources {
+            ($locale:literal, $name:literal) => {
+                (
+                    include_str!(concat!(
+                        "../../../templates/email/",
+                        $locale,
+                        "/",
+                        $name,
+                        ".txt"
+                    )),
+                    include_str!(concat!(
+                        "../../../templates/email/",
+                        $locale,
+                        "/",
+                        $name,
+                        ".html"
+                    )),
+                )
+            };
+        }
+
+        match (locale, self) {
+            ("ko", EmailTemplate::VerifyEmail) => template_sources!("ko", "verify_email"),
+            ("ko", EmailTemplate::PasswordReset) => template_sources!("ko", "password_reset"),
+            ("ko", EmailTemplate::EmailChange) => template_sources!("ko", "email_change"),
+            ("ko", EmailTemplate::Notification) => template_sources!("ko", "notification"),
+            ("ko", EmailTemplate::AccountLocked) => template_sources!("ko", "account_locked"),
+            (_, EmailTemplate::VerifyEmail) => template_sources!("en", "verify_email"),
+            (_, EmailTemplate::PasswordReset) => template_sources!("en", "password_reset"),
+            (_, EmailTemplate::EmailChange) => template_sources!("en", "email_change"),
+            (_, EmailTemplate::Notification) => template_sources!("en", "notification"),
+            (_, EmailTemplate::AccountLocked) => template_sources!("en", "account_locked"),
+        }
+    }
+}
+
+impl FromStr for EmailTemplate {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        EmailTemplate::ALL
+            .into_iter()
+            .find(|template| template.name() == s)
+            .ok_or_else(|| anyhow!("Unknown email template {:?}", s))
+    }
+}
+
+#[derive(Serialize, Clone, Debug)]
+pub struct RenderedEmail {
+    pub subject: String,
+    pub text_body: String,
+    pub html_body: String,
+}
+
+/// renders both parts of a template; every `{{var}}` must be supplied, and values are HTML-escaped in the HTML part
+pub fn render(
+    template: EmailTemplate,
+    locale: &str,
+    vars: &[(&str, String)],
+) -> anyhow::Result<RenderedEmail> {
+    let locale = if SUPPORTED_LOCALES.contains(&locale) {
+        locale
+    } else {
+        DEFAULT_LOCALE
+    };
+
+    let mut vars: Vec<(&str, String)> = vars.to_vec();
+    vars.push(("app_name", APP_NAME.to_owned()));
+
+    let (text_source, html_source) = template.sources(locale);
+
+    let text = substitute(text_source, &vars, false)?;
+    let (subject, text_body) = match text.split_once("\n\n") {
+        Some((subject, body)) => (subject.trim().to_owned(), body.to_owned()),
+        None => {
+            return Err(anyhow!(
+                "Template {}/{} has no subject line",
+                locale,
+                template.name()
+            ))
+        }
+    };
+
+    let content = substitute(html_source, &vars, true)?;
+    vars.push(("lang", locale.to_owned()));
+    vars.push(("subject", subject.clone()));
+    let mut html_body = substitute(LAYOUT_HTML, &vars, true)?;
+    // content is already escaped, so it is spliced in after the layout's own substitution
+    html_body = html_body.replace(CONTENT_MARKER, &content);
+
+    Ok(RenderedEmail {
+        subject,
+        text_body,
+        html_body,
+    })
+}
+
+/// picks the best supported locale from an Accept-Language header, falling back to DEFAULT_LOCALE
+pub fn negotiate_locale(headers: &HeaderMap) -> &'static str {
+    let header = match headers
+        .get(ACCEPT_LANGUAGE)
+        .and_then(|value| value.to_str().ok())
+    {
+        Some(header) => header,
+        None => return DEFAULT_LOCALE,
+    };
+
+    let mut candidates: Vec<(&str, f32)> = header
+        .split(',')
+        .filter_map(|entry| {
+            let mut parts = entry.trim().split(';');
+            let tag = parts.next()?.trim();
+            let quality = parts
+                .find_map(|param| param.trim().strip_prefix("q="))
+                .and_then(|q| q.parse::<f32>().ok())
+                .unwrap_or(1.0);
+            Some((tag, quality))
+        })
+        .filter(|(_, quality)| *quality > 0.0)
+        .collect();
+
+    // stable sort keeps header order among equal weights
+    candidates.sort_by(|a, b| b.1.total_cmp(&a.1));
+
+    candidates
+        .into_iter()
+        .find_map(|(tag, _)| {
+            let primary = tag.split('-').next().unwrap_or_default();
+            SUPPORTED_LOCALES
+                .into_iter()
+                .find(|locale| locale.eq_ignore_ascii_case(primary))
+        })
+        .unwrap_or(DEFAULT_LOCALE)
+}
+
+pub fn verify_email_link(public_base_url: &str, user_token_id: uuid::Uuid) -> String {
+    format!(
+        "{}/auth/verify_email?email_token={}",
+        public_base_url.trim_end_matches('/'),
+        user_token_id
+    )
+}
+
+pub fn password_reset_link(public_base_url: &str, user_token_id: uuid::Uuid) -> String {
+    format!(
+        "{}/auth/reset_password?reset_token={}",
+        public_base_url.trim_end_matches('/'),
+        user_token_id
+    )
+}
+
+pub fn email_change_link(public_base_url: &str, user_token_id: uuid::Uuid) -> String {
+    format!(
+        "{}/auth/confirm_email_change?email_token={}",
+        public_base_url.trim_end_matches('/'),
+        user_token_id
+    )
+}
+
+pub fn account_unlock_link(public_base_url: &str, user_token_id: uuid::Uuid) -> String {
+    format!(
+        "{}/auth/unlock_account?unlock_token={}",
+        public_base_url.trim_end_matches('/'),
+        user_token_id
+    )
+}
+
+fn substitute(source: &str, vars: &[(&str, String)], escape: bool) -> anyhow::Result<String> {
+    let mut output = String::with_capacity(source.len());
+    let mut rest = source;
+
+    while let Some(start) = rest.find("{{") {
+        output.push_str(&rest[..start]);
+        let after = &rest[start + 2..];
+        let end = after
+            .find("}}")
+            .ok_or_else(|| anyhow!("Unclosed placeholder in email template"))?;
+        let name = after[..end].trim();
+
+        if name == "content" {
+            output.push_str(CONTENT_MARKER);
+        } else {
+            let value = vars
+                .iter()
+                .find(|(key, _)| *key == name)
+                .map(|(_, value)| value)
+                .ok_or_else(|| anyhow!("Missing email template variable {:?}", name))?;
+            if escape {
+                output.push_str(&escape_html(value));
+            } else {
+                output.push_str(value);
+            }
+        }
+
+        rest = &after[end + 2..];
+    }
+    output.push_str(rest);
+
+    Ok(output)
+}
+
+fn escape_html(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for c in value.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            _ => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+const LAYOUT_HTML: &str = include_str!("../../../templates/email/layout.html");
+const CONTENT_MARKER: &str = "\u{0}content\u{0}";
+
+pub const APP_NAME: &str = "cyhdev.com";
+pub const SUPPORTED_LOCALES: [&str; 2] = ["en", "ko"];
+pub const DEFAULT_LOCALE: &str = "en";
+
+#[cfg(test)]
+mod tests {
+    use axum::http::HeaderValue;
+
+    use super::*;
+
+    #[test]
+    fn test_every_template_renders_in_every_locale() {
+        for template in EmailTemplate::ALL {
+            for locale in SUPPORTED_LOCALES {
+                let rendered = render(
+                    template,
+                    locale,
+                    &template.sample_vars("https://example.com/"),
+                )
+                .unwrap();
+                assert!(!rendered.subject.is_empty());
+                assert!(!rendered.subject.contains('\n'));
+                for part in [&rendered.text_body, &rendered.html_body] {
+                    assert!(!part.contains("{{"), "{}/{}", locale, template.name());
+                    assert!(!part.contains(CONTENT_MARKER));
+                }
+                assert!(rendered.html_body.contains(&format!("lang=\"{}\"", locale)));
+            }
+        }
+    }
+
+    #[test]
+    fn test_links_use_public_base_url() {
+        let rendered = render(
+            EmailTemplate::VerifyEmail,
+            "en",
+            &EmailTemplate::VerifyEmail.sample_vars("http://localhost:3000/"),
+        )
+        .unwrap();
+        let link = verify_email_link("http://localhost:3000", uuid::Uuid::nil());
+        assert!(rendered.text_body.contains(&link));
+        assert!(rendered.html_body.contains(&link));
+    }
+
+    #[test]
+    fn test_html_values_are_escaped() {
+        let rendered = render(
+            EmailTemplate::Notification,
+            "en",
+            &EmailTemplate::Notification.sample_vars("https://example.com"),
+        )
+        .unwrap();
+        assert!(rendered.html_body.contains("&lt;Hello, world!&gt;"));
+        assert!(rendered.text_body.contains("<Hello, world!>"));
+    }
+
+    #[test]
+    fn test_missing_variable_is_an_error() {
+        assert!(render(EmailTemplate::PasswordReset, "en", &[]).is_err());
+    }
+
+    #[test]
+    fn test_negotiate_locale() {
+        let mut headers = HeaderMap::new();
+        assert_eq!(negotiate_locale(&headers), "en");
+
+        headers.insert(
+            ACCEPT_LANGUAGE,
+            HeaderValue::from_static("fr-CH, ko-KR;q=0.9, en;q=0.8"),
+        );
+        assert_eq!(negotiate_locale(&headers), "ko");
+
+        headers.insert(
+            ACCEPT_LANGUAGE,
+            HeaderValue::from_static("ko;q=0.5, en-US;q=0.7"),
+        );
+        assert_eq!(negotiate_locale(&headers), "en");
+
+        headers.insert(ACCEPT_LANGUAGE, HeaderValue::from_static("de, ko;q=0"));
+        assert_eq!(negotiate_locale(&headers), "en");
+    }
+}
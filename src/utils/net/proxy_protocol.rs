@@ -0,0 +1,245 @@
+use std::{
+    future::Future,
+    io,
+    net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr},
+    pin::Pin,
+    time::Duration,
+};
+
+use axum_server::accept::Accept;
+use tokio::{
+    io::{AsyncRead, AsyncReadExt},
+    net::TcpStream,
+};
+use tower_http::add_extension::AddExtension;
+use tracing::debug;
+
+/// how long a new connection gets to send its PROXY header
+const HEADER_TIMEOUT: Duration = Duration::from_secs(5);
+/// the longest v1 line the spec allows, CRLF included
+const V1_MAX_LENGTH: usize = 107;
+const V2_SIGNATURE: &[u8; 12] = b"\r\n\r\n\0\r\nQUIT\n";
+
+/// the source address from the connection's PROXY protocol header; None when the listener doesn't
+/// expect one or the proxy sent a LOCAL/UNKNOWN header (e.g. its own health checks).
+/// Added to every request on the connection.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct ProxiedPeer(pub Option<SocketAddr>);
+
+/// reads a PROXY protocol v1 or v2 header from the start of a connection, consuming exactly the header
+pub async fn read_proxy_header<R: AsyncRead + Unpin>(
+    stream: &mut R,
+) -> io::Result<Option<SocketAddr>> {
+    match stream.read_u8().await? {
+        b'P' => {
+            // one byte at a time so nothing past the CRLF is consumed
+            let mut line = vec![b'P'];
+            while !line.ends_with(b"\r\n") {
+                if line.len() >= V1_MAX_LENGTH {
+                    return Err(invalid("PROXY v1 header is too long"));
+                }
+                line.push(stream.read_u8().await?);
+            }
+            parse_v1(&line)
+        }
+        b'\r' => {
+            let mut header = [0u8; 16];
+            header[0] = b'\r';
+            stream.read_exact(&mut header[1..]).await?;
+            let length = u16::from_be_bytes([header[14], header[15]]) as usize;
+            let mut body = vec![0u8; length];
+            stream.read_exact(&mut body).await?;
+            parse_v2(&header, &body)
+        }
+        _ => Err(invalid("connection did not start with a PROXY header")),
+    }
+}
+
+/// `PROXY TCP4 <src> <dst> <sport> <dport>\r\n`, or `PROXY UNKNOWN ...\r\n`
+fn parse_v1(line: &[u8]) -> io::Result<Option<SocketAddr>> {
+    let line = std::str::from_utf8(line)
+        .map_err(|_| invalid("PROXY v1 header is not ASCII"))?
+        .trim_end_matches("\r\n");
+    let fields: Vec<&str> = line.split(' ').collect();
+
+    match fields.as_slice() {
+        ["PROXY", "UNKNOWN", ..] => Ok(None),
+        ["PROXY", protocol @ ("TCP4" | "TCP6"), source, _, source_port, _] => {
+            let ip: IpAddr = source
+                .parse()
+                .map_err(|_| invalid("PROXY v1 header has an invalid source address"))?;
+            if (*protocol == "TCP4") != ip.is_ipv4() {
+                return Err(invalid(
+                    "PROXY v1 source address does not match the protocol",
+                ));
+            }
+            let port: u16 = source_port
+                .parse()
+                .map_err(|_| invalid("PROXY v1 header has an invalid source port"))?;
+            Ok(Some(SocketAddr::new(ip, port)))
+        }
+        _ => Err(invalid("malformed PROXY v1 header")),
+    }
+}
+
+/// the 16-byte binary header and the address block that follows it; TLVs are ignored
+fn parse_v2(header: &[u8; 16], body: &[u8]) -> io::Result<Option<SocketAddr>> {
+    if &header[..12] != V2_SIGNATURE {
+        return Err(invalid("malformed PROXY v2 signature"));
+    }
+    if header[12] >> 4 != 2 {
+        return Err(invalid("unsupported PROXY protocol version"));
+    }
+
+    match header[12] & 0x0f {
+        // LOCAL: the proxy's own connection, no client behind it
+        0x0 => return Ok(None),
+        0x1 => (),
+        _ => return Err(invalid("unsupported PROXY v2 command")),
+    }
+
+    let port = |offset: usize| u16::from_be_bytes([body[offset], body[offset + 1]]);
+    match header[13] >> 4 {
+        // AF_INET: source, destination, source port, destination port
+        0x1 if body.len() >= 12 => {
+            let ip = Ipv4Addr::new(body[0], body[1], body[2], body[3]);
+            Ok(Some(SocketAddr::new(ip.into(), port(8))))
+        }
+        // AF_INET6
+        0x2 if body.len() >= 36 => {
+            let octets: [u8; 16] = body[..16].try_into().unwrap_or_default();
+            Ok(Some(SocketAddr::new(
+                Ipv6Addr::from(octets).into(),
+                port(32),
+            )))
+        }
+        // AF_UNSPEC and AF_UNIX carry no usable address
+        0x0 | 0x3 => Ok(None),
+        _ => Err(invalid("PROXY v2 address block is too short")),
+    }
+}
+
+fn invalid(message: &'static str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+/// reads the PROXY header, when the listener expects one, before handing the connection to `inner`
+/// (the TLS acceptor); the source address reaches handlers as a ProxiedPeer extension
+#[derive(Clone, Debug)]
+pub struct ProxyProtocolAcceptor<A> {
+    inner: A,
+    enabled: bool,
+}
+
+impl<A> ProxyProtocolAcceptor<A> {
+    pub fn new(inner: A, enabled: bool) -> Self {
+        ProxyProtocolAcceptor { inner, enabled }
+    }
+}
+
+impl<A, S> Accept<TcpStream, S> for ProxyProtocolAcceptor<A>
+where
+    A: Accept<TcpStream, AddExtension<S, ProxiedPeer>> + Clone + Send + 'static,
+    A::Future: Send,
+    S: Send + 'static,
+{
+    type Stream = A::Stream;
+    type Service = A::Service;
+    type Future = Pin<Box<dyn Future<Output = io::Result<(Self::Stream, Self::Service)>> + Send>>;
+
+    fn accept(&self, mut stream: TcpStream, service: S) -> Self::Future {
+        let inner = self.inner.clone();
+        let enabled = self.enabled;
+
+        Box::pin(async move {
+            let proxied = match enabled {
+                true => match tokio::time::timeout(HEADER_TIMEOUT, read_proxy_header(&mut stream))
+                    .await
+                {
+                    Ok(Ok(proxied)) => proxied,
+                    Ok(Err(e)) => {
+                        debug!("Dropping connection without a valid PROXY header: {}", e);
+                        return Err(e);
+                    }
+                    Err(_) => {
+                        debug!("Dropping connection that sent no PROXY header in time");
+                        return Err(io::ErrorKind::TimedOut.into());
+                    }
+                },
+                false => None,
+            };
+
+            inner
+                .accept(stream, AddExtension::new(service, ProxiedPeer(proxied)))
+                .await
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// reads the header and returns it with whatever the stream still holds
+    async fn read(bytes: &[u8]) -> (io::Result<Option<SocketAddr>>, Vec<u8>) {
+        let mut stream = bytes;
+        let proxied = read_proxy_header(&mut stream).await;
+        (proxied, stream.to_vec())
+    }
+
+    #[tokio::test]
+    async fn test_v1_header_is_consumed_exactly() {
+        let (proxied, rest) =
+            read(b"PROXY TCP4 198.51.100.7 10.0.0.1 56324 443\r\n\x16\x03\x01").await;
+        assert_eq!(
+            proxied.unwrap(),
+            Some("198.51.100.7:56324".parse().unwrap())
+        );
+        assert_eq!(rest, b"\x16\x03\x01");
+
+        let (proxied, _) = read(b"PROXY TCP6 2001:db8::1 2001:db8::2 4711 443\r\n").await;
+        assert_eq!(
+            proxied.unwrap(),
+            Some("[2001:db8::1]:4711".parse().unwrap())
+        );
+
+        let (proxied, _) = read(b"PROXY UNKNOWN\r\n").await;
+        assert_eq!(proxied.unwrap(), None);
+
+        for malformed in [
+            &b"PROXY TCP4 198.51.100.7 10.0.0.1 56324\r\n"[..],
+            b"PROXY TCP4 2001:db8::1 10.0.0.1 1 443\r\n",
+            b"GET / HTTP/1.1\r\n",
+        ] {
+            assert!(read(malformed).await.0.is_err());
+        }
+        assert!(read(&[b'P'; 200]).await.0.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_v2_header_is_consumed_exactly() {
+        let mut bytes = V2_SIGNATURE.to_vec();
+        // PROXY command, TCP over IPv4, 12 address bytes plus a 3-byte TLV
+        bytes.extend_from_slice(&[0x21, 0x11, 0x00, 15]);
+        bytes.extend_from_slice(&[198, 51, 100, 7, 10, 0, 0, 1, 0xdc, 0x04, 0x01, 0xbb]);
+        bytes.extend_from_slice(&[0x04, 0x00, 0x00]);
+        bytes.extend_from_slice(b"\x16\x03\x01");
+
+        let (proxied, rest) = read(&bytes).await;
+        assert_eq!(
+            proxied.unwrap(),
+            Some("198.51.100.7:56324".parse().unwrap())
+        );
+        assert_eq!(rest, b"\x16\x03\x01");
+
+        // LOCAL command: a health check from the proxy itself
+        let mut local = V2_SIGNATURE.to_vec();
+        local.extend_from_slice(&[0x20, 0x00, 0x00, 0x00]);
+        assert_eq!(read(&local).await.0.unwrap(), None);
+
+        let mut short = V2_SIGNATURE.to_vec();
+        short.extend_from_slice(&[0x21, 0x21, 0x00, 12]);
+        short.extend_from_slice(&[0; 12]);
+        assert!(read(&short).await.0.is_err());
+    }
+}
@@ -0,0 +1,244 @@
+use std::{net::IpAddr, str::FromStr};
+
+use anyhow::anyhow;
+use axum::http::{header::FORWARDED, HeaderMap};
+use ipnet::IpNet;
+
+/// proxies (CIDRs or single addresses) whose forwarding headers and PROXY protocol headers are believed
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct TrustedProxies(Vec<IpNet>);
+
+impl FromStr for TrustedProxies {
+    type Err = anyhow::Error;
+
+    /// comma-separated, e.g. `10.0.0.0/8, 192.0.2.1, fd00::/8`; empty trusts nobody
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        s.split(',')
+            .map(str::trim)
+            .filter(|entry| !entry.is_empty())
+            .map(|entry| {
+                entry
+                    .parse::<IpNet>()
+                    .or_else(|_| entry.parse::<IpAddr>().map(IpNet::from))
+                    .map_err(|_| anyhow!("{:?} is not a CIDR or IP address", entry))
+            })
+            .collect::<anyhow::Result<Vec<IpNet>>>()
+            .map(TrustedProxies)
+    }
+}
+
+impl TrustedProxies {
+    pub fn contains(&self, ip: IpAddr) -> bool {
+        let ip = ip.to_canonical();
+        self.0.iter().any(|net| net.contains(&ip))
+    }
+
+    /// the client's address: starting from the socket peer, each hop is replaced by the address it reports
+    /// (PROXY protocol source, then the forwarding headers from right to left) for as long as the hop is a
+    /// trusted proxy. Anything a client sends itself is therefore ignored.
+    pub fn resolve(&self, peer: IpAddr, proxied: Option<IpAddr>, headers: &HeaderMap) -> IpAddr {
+        let mut client = peer.to_canonical();
+        if !self.contains(client) {
+            return client;
+        }
+
+        if let Some(proxied) = proxied {
+            client = proxied.to_canonical();
+            if !self.contains(client) {
+                return client;
+            }
+        }
+
+        for hop in forwarded_chain(headers).into_iter().rev() {
+            match hop {
+                Some(hop) => {
+                    client = hop.to_canonical();
+                    if !self.contains(client) {
+                        return client;
+                    }
+                }
+                // an obfuscated or malformed entry; the proxy that recorded it is as far as we can see
+                None => return client,
+            }
+        }
+
+        client
+    }
+}
+
+/// addresses recorded by proxies, client first: RFC 7239 `Forwarded` when present, else `X-Forwarded-For`,
+/// else `X-Real-IP`. Entries that aren't IP addresses (`unknown`, `_hidden`, garbage) are None.
+fn forwarded_chain(headers: &HeaderMap) -> Vec<Option<IpAddr>> {
+    let values = |name: &str| -> Vec<&str> {
+        headers
+            .get_all(name)
+            .iter()
+            .filter_map(|header_value| header_value.to_str().ok())
+            .flat_map(|value| value.split(','))
+            .map(str::trim)
+            .filter(|entry| !entry.is_empty())
+            .collect()
+    };
+
+    let forwarded = values(FORWARDED.as_str());
+    if !forwarded.is_empty() {
+        return forwarded
+            .into_iter()
+            .map(|element| {
+                element
+                    .split(';')
+                    .filter_map(|pair| pair.trim().split_once('='))
+                    .find(|(name, _)| name.trim().eq_ignore_ascii_case("for"))
+                    .and_then(|(_, node)| parse_node(node))
+            })
+            .collect();
+    }
+
+    let forwarded_for = values("x-forwarded-for");
+    if !forwarded_for.is_empty() {
+        return forwarded_for.into_iter().map(parse_node).collect();
+    }
+
+    values("x-real-ip").into_iter().map(parse_node).collect()
+}
+
+/// `192.0.2.1`, `192.0.2.1:4711`, `2001:db8::1` or `[2001:db8::1]:4711`, optionally quoted
+fn parse_node(node: &str) -> Option<IpAddr> {
+    let node = node.trim().trim_matches('"');
+
+    if let Ok(ip) = node.parse::<IpAddr>() {
+        return Some(ip);
+    }
+
+    match node.strip_prefix('[') {
+        Some(bracketed) => bracketed.split_once(']')?.0.parse().ok(),
+        None => node.split_once(':')?.0.parse().ok(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::http::HeaderValue;
+
+    use super::*;
+
+    fn headers(entries: &[(&'static str, &'static str)]) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        for (name, value) in entries {
+            headers.append(*name, HeaderValue::from_static(value));
+        }
+        headers
+    }
+
+    fn ip(s: &str) -> IpAddr {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn test_parses_cidrs_and_single_addresses() {
+        let trusted: TrustedProxies = "10.0.0.0/8, 192.0.2.1 ,fd00::/8,".parse().unwrap();
+        assert!(trusted.contains(ip("10.1.2.3")));
+        assert!(trusted.contains(ip("192.0.2.1")));
+        assert!(!trusted.contains(ip("192.0.2.2")));
+        assert!(trusted.contains(ip("fd00::1")));
+        // IPv4 peers on a dual-stack socket show up as mapped IPv6 addresses
+        assert!(trusted.contains(ip("::ffff:10.0.0.1")));
+
+        assert_eq!(
+            "".parse::<TrustedProxies>().unwrap(),
+            TrustedProxies::default()
+        );
+        assert!("10.0.0.0/33".parse::<TrustedProxies>().is_err());
+        assert!("proxy.internal".parse::<TrustedProxies>().is_err());
+    }
+
+    #[test]
+    fn test_headers_from_untrusted_peers_are_ignored() {
+        let trusted: TrustedProxies = "10.0.0.0/8".parse().unwrap();
+        let spoofed = headers(&[
+            ("x-forwarded-for", "198.51.100.7"),
+            ("x-real-ip", "198.51.100.8"),
+        ]);
+
+        assert_eq!(
+            trusted.resolve(ip("203.0.113.9"), Some(ip("198.51.100.9")), &spoofed),
+            ip("203.0.113.9")
+        );
+        assert_eq!(
+            TrustedProxies::default().resolve(ip("10.0.0.1"), None, &spoofed),
+            ip("10.0.0.1")
+        );
+    }
+
+    #[test]
+    fn test_rightmost_untrusted_hop_is_the_client() {
+        let trusted: TrustedProxies = "10.0.0.0/8".parse().unwrap();
+
+        // the client prepended its own entry; only what the trusted proxies appended counts
+        let chain = headers(&[
+            ("x-forwarded-for", "1.1.1.1, 198.51.100.7"),
+            ("x-forwarded-for", "10.0.0.2"),
+        ]);
+        assert_eq!(
+            trusted.resolve(ip("10.0.0.1"), None, &chain),
+            ip("198.51.100.7")
+        );
+
+        // every hop trusted: the leftmost is as close to the client as we get
+        let internal = headers(&[("x-forwarded-for", "10.0.0.3, 10.0.0.2")]);
+        assert_eq!(
+            trusted.resolve(ip("10.0.0.1"), None, &internal),
+            ip("10.0.0.3")
+        );
+
+        // an unparseable hop stops the walk at the proxy that recorded it
+        let garbage = headers(&[("x-forwarded-for", "198.51.100.7, not-an-ip, 10.0.0.2")]);
+        assert_eq!(
+            trusted.resolve(ip("10.0.0.1"), None, &garbage),
+            ip("10.0.0.2")
+        );
+    }
+
+    #[test]
+    fn test_forwarded_header_takes_precedence() {
+        let trusted: TrustedProxies = "10.0.0.0/8".parse().unwrap();
+        let chain = headers(&[
+            (
+                "forwarded",
+                r#"for="[2001:db8:cafe::17]:4711";proto=https, For=10.0.0.2"#,
+            ),
+            ("x-forwarded-for", "198.51.100.7"),
+        ]);
+        assert_eq!(
+            trusted.resolve(ip("10.0.0.1"), None, &chain),
+            ip("2001:db8:cafe::17")
+        );
+
+        let hidden = headers(&[("forwarded", "for=_hidden, for=10.0.0.2")]);
+        assert_eq!(
+            trusted.resolve(ip("10.0.0.1"), None, &hidden),
+            ip("10.0.0.2")
+        );
+    }
+
+    #[test]
+    fn test_proxy_protocol_source_is_one_more_hop() {
+        let trusted: TrustedProxies = "10.0.0.0/8".parse().unwrap();
+        let none = HeaderMap::new();
+        assert_eq!(
+            trusted.resolve(ip("10.0.0.1"), Some(ip("198.51.100.7")), &none),
+            ip("198.51.100.7")
+        );
+
+        // headers are only believed if the PROXY source is itself trusted
+        let chain = headers(&[("x-real-ip", "198.51.100.8:5000")]);
+        assert_eq!(
+            trusted.resolve(ip("10.0.0.1"), Some(ip("198.51.100.7")), &chain),
+            ip("198.51.100.7")
+        );
+        assert_eq!(
+            trusted.resolve(ip("10.0.0.1"), Some(ip("10.0.0.5")), &chain),
+            ip("198.51.100.8")
+        );
+    }
+}
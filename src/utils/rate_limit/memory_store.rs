@@ -0,0 +1,108 @@
+use std::{
+    collections::HashMap,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+use axum::async_trait;
+
+use super::rate_limit_store::{take_token, RateLimit, RateLimitDecision, RateLimitStore};
+
+/// how often buckets that have refilled completely are dropped
+const PRUNE_INTERVAL: Duration = Duration::from_secs(60);
+
+struct Bucket {
+    tokens: f64,
+    updated_at: Instant,
+    /// a full bucket is the same as no bucket, so it can be forgotten after this
+    full_at: Instant,
+}
+
+struct Buckets {
+    buckets: HashMap<String, Bucket>,
+    pruned_at: Instant,
+}
+
+/// buckets in this process only; each instance of a multi-instance deployment limits on its own
+pub struct InMemoryRateLimitStore {
+    state: Mutex<Buckets>,
+}
+
+impl InMemoryRateLimitStore {
+    pub fn new() -> Self {
+        InMemoryRateLimitStore {
+            state: Mutex::new(Buckets {
+                buckets: HashMap::new(),
+                pruned_at: Instant::now(),
+            }),
+        }
+    }
+
+    fn take_at(&self, key: &str, limit: &RateLimit, now: Instant) -> RateLimitDecision {
+        let mut state = self
+            .state
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+
+        if now.duration_since(state.pruned_at) >= PRUNE_INTERVAL {
+            state.buckets.retain(|_, bucket| bucket.full_at > now);
+            state.pruned_at = now;
+        }
+
+        let (tokens, elapsed) = match state.buckets.get(key) {
+            Some(bucket) => (bucket.tokens, now.duration_since(bucket.updated_at)),
+            None => (limit.burst as f64, Duration::ZERO),
+        };
+        let (tokens, decision) = take_token(tokens, elapsed, limit);
+
+        state.buckets.insert(
+            key.to_owned(),
+            Bucket {
+                tokens,
+                updated_at: now,
+                full_at: now + decision.reset,
+            },
+        );
+
+        decision
+    }
+}
+
+impl Default for InMemoryRateLimitStore {
+    fn default() -> Self {
+        InMemoryRateLimitStore::new()
+    }
+}
+
+#[async_trait]
+impl RateLimitStore for InMemoryRateLimitStore {
+    async fn take(&self, key: &str, limit: &RateLimit) -> anyhow::Result<RateLimitDecision> {
+        Ok(self.take_at(key, limit, Instant::now()))
+    }
+
+    fn name(&self) -> &'static str {
+        "memory"
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_buckets_are_per_key_and_pruned_once_full() {
+        let store = InMemoryRateLimitStore::new();
+        let limit: RateLimit = "1/min".parse().unwrap();
+        let now = Instant::now();
+
+        assert!(store.take_at("a", &limit, now).allowed);
+        assert!(!store.take_at("a", &limit, now).allowed);
+        assert!(store.take_at("b", &limit, now).allowed);
+        assert_eq!(store.state.lock().unwrap().buckets.len(), 2);
+
+        let later = now + Duration::from_secs(61);
+        assert!(store.take_at("a", &limit, later).allowed);
+        // "b" refilled and was dropped; "a" was just used again
+        assert_eq!(store.state.lock().unwrap().buckets.len(), 1);
+    }
+}
@@ -0,0 +1,91 @@
+use std::{
+    sync::atomic::{AtomicU64, Ordering},
+    time::Duration,
+};
+
+use anyhow::Context;
+use axum::async_trait;
+use chrono::Utc;
+use deadpool_postgres::Pool;
+use tracing::{debug, warn};
+
+use crate::models::rate_limit_buckets::RateLimitBucket;
+
+use super::rate_limit_store::{take_token, RateLimit, RateLimitDecision, RateLimitStore};
+
+/// how often each instance deletes buckets that have refilled completely
+const PRUNE_INTERVAL_SECONDS: u64 = 60;
+
+/// buckets in v1.rate_limit_buckets, shared by every instance on the same database;
+/// each take is one short transaction holding the bucket's row lock
+pub struct PostgresRateLimitStore {
+    pool: Pool,
+    /// unix seconds of the last prune
+    pruned_at: AtomicU64,
+}
+
+impl PostgresRateLimitStore {
+    pub fn new(pool: Pool) -> Self {
+        PostgresRateLimitStore {
+            pool,
+            pruned_at: AtomicU64::new(Utc::now().timestamp() as u64),
+        }
+    }
+
+    /// deletes full buckets at most once per interval; the caller's request does not wait for it
+    fn prune_if_due(&self) {
+        let now = Utc::now().timestamp() as u64;
+        let pruned_at = self.pruned_at.load(Ordering::Relaxed);
+        if now < pruned_at + PRUNE_INTERVAL_SECONDS
+            || self
+                .pruned_at
+                .compare_exchange(pruned_at, now, Ordering::Relaxed, Ordering::Relaxed)
+                .is_err()
+        {
+            return;
+        }
+
+        let pool = self.pool.clone();
+        tokio::spawn(async move {
+            let deleted = match pool.get().await {
+                Ok(conn) => RateLimitBucket::delete_full(&conn).await,
+                Err(e) => Err(e.into()),
+            };
+            match deleted {
+                Ok(deleted) => debug!("Pruned {} full rate limit bucket(s)", deleted),
+                Err(e) => warn!("Could not prune rate limit buckets: {:?}", e),
+            }
+        });
+    }
+}
+
+#[async_trait]
+impl RateLimitStore for PostgresRateLimitStore {
+    async fn take(&self, key: &str, limit: &RateLimit) -> anyhow::Result<RateLimitDecision> {
+        self.prune_if_due();
+
+        let mut conn = self
+            .pool
+            .get()
+            .await
+            .context("Could not get a connection for the rate limit store")?;
+        let transaction = conn.transaction().await?;
+
+        let bucket = RateLimitBucket::lock_or_create(&transaction, key, limit.burst as f64).await?;
+        let (tokens, decision) = take_token(
+            bucket.get_tokens(),
+            Duration::from_secs_f64(bucket.get_elapsed_seconds()),
+            limit,
+        );
+        bucket
+            .save(&transaction, tokens, decision.reset.as_secs_f64())
+            .await?;
+
+        transaction.commit().await?;
+        Ok(decision)
+    }
+
+    fn name(&self) -> &'static str {
+        "postgres"
+    }
+}
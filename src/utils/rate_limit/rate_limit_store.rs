@@ -0,0 +1,274 @@
+use std::{collections::HashMap, str::FromStr, sync::Arc, time::Duration};
+
+use anyhow::anyhow;
+use axum::{
+    async_trait,
+    http::{HeaderMap, HeaderName, HeaderValue},
+};
+use tracing::error;
+
+use crate::utils::{
+    errors::errors::AppError,
+    server_init::server_init_funcs::initialize_metrics::RATE_LIMITED_TOTAL,
+};
+
+pub static RATELIMIT_LIMIT: HeaderName = HeaderName::from_static("ratelimit-limit");
+pub static RATELIMIT_REMAINING: HeaderName = HeaderName::from_static("ratelimit-remaining");
+pub static RATELIMIT_RESET: HeaderName = HeaderName::from_static("ratelimit-reset");
+
+/// a token bucket holding up to `burst` requests, refilled at `burst` per `period`; written as e.g. `10/min`
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct RateLimit {
+    pub burst: u32,
+    pub period: Duration,
+}
+
+impl RateLimit {
+    fn tokens_per_second(&self) -> f64 {
+        self.burst as f64 / self.period.as_secs_f64()
+    }
+}
+
+impl FromStr for RateLimit {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let format_error = || anyhow!("expected <requests>/<second|min|hour|day>, e.g. 10/min");
+
+        let (burst, unit) = s.split_once('/').ok_or_else(format_error)?;
+        let burst: u32 = burst.trim().parse().map_err(|_| format_error())?;
+        let seconds = match unit.trim().to_ascii_lowercase().as_str() {
+            "s" | "sec" | "second" => 1,
+            "m" | "min" | "minute" => 60,
+            "h" | "hour" => 60 * 60,
+            "d" | "day" => 24 * 60 * 60,
+            _ => return Err(format_error()),
+        };
+
+        if burst == 0 {
+            return Err(anyhow!("the request count must be at least 1"));
+        }
+
+        Ok(RateLimit {
+            burst,
+            period: Duration::from_secs(seconds),
+        })
+    }
+}
+
+/// which bucket a request draws from; each group has its own limit
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum RateLimitGroup {
+    /// every rate-limited route, per client IP
+    Default,
+    /// signup and login, which hash passwords with argon2
+    Credentials,
+    /// routes that redeem emailed or refresh tokens
+    Tokens,
+    /// routes that send email
+    Email,
+    /// login and password reset attempts against one account, from any IP
+    Account,
+}
+
+impl RateLimitGroup {
+    pub fn name(&self) -> &'static str {
+        match self {
+            RateLimitGroup::Default => "default",
+            RateLimitGroup::Credentials => "credentials",
+            RateLimitGroup::Tokens => "tokens",
+            RateLimitGroup::Email => "email",
+            RateLimitGroup::Account => "account",
+        }
+    }
+}
+
+/// outcome of taking a token, with what the RateLimit-* headers report
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct RateLimitDecision {
+    pub allowed: bool,
+    pub limit: u32,
+    pub remaining: u32,
+    /// until the bucket is full again
+    pub reset: Duration,
+    /// until the next token; zero when allowed
+    pub retry_after: Duration,
+}
+
+impl RateLimitDecision {
+    /// sets RateLimit-Limit/Remaining/Reset unless a stricter bucket already set them
+    pub fn apply_headers(&self, headers: &mut HeaderMap) {
+        let stricter_already_set = headers
+            .get(&RATELIMIT_REMAINING)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.parse::<u32>().ok())
+            .is_some_and(|remaining| remaining <= self.remaining);
+        if stricter_already_set {
+            return;
+        }
+
+        for (name, value) in [
+            (&RATELIMIT_LIMIT, self.limit as u64),
+            (&RATELIMIT_REMAINING, self.remaining as u64),
+            (&RATELIMIT_RESET, self.reset.as_secs_f64().ceil() as u64),
+        ] {
+            headers.insert(name.clone(), HeaderValue::from(value));
+        }
+    }
+}
+
+/// refills a bucket that held `tokens` for `elapsed` and takes one token if there is one;
+/// returns the tokens left and the decision
+pub fn take_token(tokens: f64, elapsed: Duration, limit: &RateLimit) -> (f64, RateLimitDecision) {
+    let rate = limit.tokens_per_second();
+    let burst = limit.burst as f64;
+    let refilled = (tokens + elapsed.as_secs_f64() * rate).min(burst);
+
+    let allowed = refilled >= 1.0;
+    let left = if allowed { refilled - 1.0 } else { refilled };
+
+    let decision = RateLimitDecision {
+        allowed,
+        limit: limit.burst,
+        remaining: left.floor() as u32,
+        reset: Duration::from_secs_f64((burst - left) / rate),
+        retry_after: match allowed {
+            true => Duration::ZERO,
+            false => Duration::from_secs_f64((1.0 - left) / rate),
+        },
+    };
+
+    (left, decision)
+}
+
+/// where buckets live; held by RateLimiter as `Arc<dyn RateLimitStore>`
+#[async_trait]
+pub trait RateLimitStore: Send + Sync {
+    /// takes one token from the bucket under `key`, starting a full bucket for a new key
+    async fn take(&self, key: &str, limit: &RateLimit) -> anyhow::Result<RateLimitDecision>;
+
+    /// short name for logs, e.g. "memory"
+    fn name(&self) -> &'static str;
+}
+
+/// checks requests against the configured limit of their group
+#[derive(Clone)]
+pub struct RateLimiter {
+    store: Arc<dyn RateLimitStore>,
+    limits: HashMap<RateLimitGroup, RateLimit>,
+    enabled: bool,
+}
+
+impl RateLimiter {
+    pub fn new(
+        store: Arc<dyn RateLimitStore>,
+        limits: HashMap<RateLimitGroup, RateLimit>,
+        enabled: bool,
+    ) -> Self {
+        RateLimiter {
+            store,
+            limits,
+            enabled,
+        }
+    }
+
+    pub fn store_name(&self) -> &'static str {
+        self.store.name()
+    }
+
+    /// takes a token for `subject` (e.g. `ip:203.0.113.7`) in `group`; Ok(None) when the group is not limited.
+    /// A store that fails lets the request through rather than taking the site down with it.
+    pub async fn check(
+        &self,
+        group: RateLimitGroup,
+        subject: &str,
+    ) -> Result<Option<RateLimitDecision>, AppError> {
+        let Some(limit) = self.limits.get(&group).filter(|_| self.enabled) else {
+            return Ok(None);
+        };
+
+        let key = format!("{}:{}", group.name(), subject);
+        let decision = match self.store.take(&key, limit).await {
+            Ok(decision) => decision,
+            Err(e) => {
+                error!("Rate limit store {} failed: {:?}", self.store.name(), e);
+                return Ok(None);
+            }
+        };
+
+        if !decision.allowed {
+            metrics::counter!(RATE_LIMITED_TOTAL, "group" => group.name()).increment(1);
+            return Err(AppError::RateLimited {
+                limit: decision.limit,
+                retry_after_seconds: decision.retry_after.as_secs_f64().ceil().max(1.0) as u64,
+            });
+        }
+
+        Ok(Some(decision))
+    }
+
+    /// per-account check for handlers that know which account a request targets; the identifier is case-folded
+    pub async fn check_account(&self, account: &str) -> Result<(), AppError> {
+        self.check(
+            RateLimitGroup::Account,
+            &format!("account:{}", account.trim().to_lowercase()),
+        )
+        .await
+        .map(|_| ())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rate_limit_parses_count_and_period() {
+        let limit: RateLimit = "10/min".parse().unwrap();
+        assert_eq!(limit.burst, 10);
+        assert_eq!(limit.period, Duration::from_secs(60));
+        assert_eq!(
+            "5 / hour".parse::<RateLimit>().unwrap().period,
+            Duration::from_secs(3600)
+        );
+        assert!("0/min".parse::<RateLimit>().is_err());
+        assert!("10".parse::<RateLimit>().is_err());
+        assert!("10/fortnight".parse::<RateLimit>().is_err());
+    }
+
+    #[test]
+    fn test_take_token_drains_then_refills() {
+        let limit: RateLimit = "2/min".parse().unwrap();
+
+        let (tokens, decision) = take_token(2.0, Duration::ZERO, &limit);
+        assert!(decision.allowed);
+        assert_eq!(decision.remaining, 1);
+        let (tokens, decision) = take_token(tokens, Duration::ZERO, &limit);
+        assert!(decision.allowed);
+        assert_eq!(decision.remaining, 0);
+        assert_eq!(decision.reset, Duration::from_secs(60));
+
+        let (tokens, decision) = take_token(tokens, Duration::ZERO, &limit);
+        assert!(!decision.allowed);
+        assert_eq!(decision.retry_after, Duration::from_secs(30));
+
+        // one token comes back every 30 seconds, and never more than the burst
+        let (_, decision) = take_token(tokens, Duration::from_secs(30), &limit);
+        assert!(decision.allowed);
+        let (_, decision) = take_token(tokens, Duration::from_secs(3600), &limit);
+        assert_eq!(decision.remaining, 1);
+    }
+
+    #[test]
+    fn test_headers_keep_the_stricter_bucket() {
+        let limit: RateLimit = "10/min".parse().unwrap();
+        let (_, strict) = take_token(2.0, Duration::ZERO, &limit);
+        let (_, loose) = take_token(10.0, Duration::ZERO, &limit);
+
+        let mut headers = HeaderMap::new();
+        strict.apply_headers(&mut headers);
+        loose.apply_headers(&mut headers);
+        assert_eq!(headers[&RATELIMIT_REMAINING], "1");
+        assert_eq!(headers[&RATELIMIT_LIMIT], "10");
+    }
+}
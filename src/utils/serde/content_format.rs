@@ -0,0 +1,217 @@
+use anyhow::anyhow;
+use axum::http::{
+    header::{ACCEPT, CONTENT_TYPE},
+    HeaderMap,
+};
+use serde::{de::DeserializeOwned, Serialize};
+
+tokio::task_local! {
+    /// format picked for the current request by the content negotiation middleware
+    pub static RESPONSE_FORMAT: ContentFormat;
+}
+
+/// wire formats the API speaks; bincode stays the default so existing clients are unaffected
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ContentFormat {
+    Bincode,
+    Json,
+    MessagePack,
+    Cbor,
+}
+
+impl ContentFormat {
+    pub const DEFAULT: ContentFormat = ContentFormat::Bincode;
+
+    pub fn mime(&self) -> &'static str {
+        match self {
+            ContentFormat::Bincode => "application/octet-stream",
+            ContentFormat::Json => "application/json",
+            ContentFormat::MessagePack => "application/msgpack",
+            ContentFormat::Cbor => "application/cbor",
+        }
+    }
+
+    /// maps a media type (parameters and case ignored) to a format
+    pub fn from_mime(mime: &str) -> Option<Self> {
+        let essence = mime
+            .split(';')
+            .next()
+            .unwrap_or_default()
+            .trim()
+            .to_ascii_lowercase();
+
+        match essence.as_str() {
+            "application/octet-stream" | "application/x-bincode" => Some(ContentFormat::Bincode),
+            "application/json" => Some(ContentFormat::Json),
+            "application/msgpack" | "application/x-msgpack" | "application/vnd.msgpack" => {
+                Some(ContentFormat::MessagePack)
+            }
+            "application/cbor" => Some(ContentFormat::Cbor),
+            _ => None,
+        }
+    }
+
+    /// best format for the Accept header; a missing header, wildcards or nothing we support fall back to DEFAULT
+    pub fn from_accept(headers: &HeaderMap) -> Self {
+        let header = match headers.get(ACCEPT).and_then(|value| value.to_str().ok()) {
+            Some(header) => header,
+            None => return ContentFormat::DEFAULT,
+        };
+
+        let mut candidates: Vec<(Option<ContentFormat>, f32)> = header
+            .split(',')
+            .filter_map(|entry| {
+                let mut parts = entry.trim().split(';');
+                let mime = parts.next()?.trim();
+                let quality = parts
+                    .find_map(|param| param.trim().strip_prefix("q="))
+                    .and_then(|q| q.parse::<f32>().ok())
+                    .unwrap_or(1.0);
+                let format = match mime {
+                    "*/*" | "application/*" => Some(ContentFormat::DEFAULT),
+                    _ => ContentFormat::from_mime(mime),
+                };
+                Some((format, quality))
+            })
+            .filter(|(format, quality)| format.is_some() && *quality > 0.0)
+            .collect();
+
+        // stable sort keeps header order among equal weights
+        candidates.sort_by(|a, b| b.1.total_cmp(&a.1));
+
+        candidates
+            .into_iter()
+            .find_map(|(format, _)| format)
+            .unwrap_or(ContentFormat::DEFAULT)
+    }
+
+    /// request body format from Content-Type; None when the header names a format we can't read.
+    /// A missing Content-Type is read as JSON, which is what the API accepted before.
+    pub fn from_content_type(headers: &HeaderMap) -> Option<Self> {
+        match headers.get(CONTENT_TYPE) {
+            Some(value) => value.to_str().ok().and_then(ContentFormat::from_mime),
+            None => Some(ContentFormat::Json),
+        }
+    }
+
+    /// format of the request currently being handled, or DEFAULT outside of one
+    pub fn current() -> Self {
+        RESPONSE_FORMAT
+            .try_with(|format| *format)
+            .unwrap_or(ContentFormat::DEFAULT)
+    }
+
+    pub fn serialize<T: Serialize>(&self, value: &T) -> anyhow::Result<Vec<u8>> {
+        match self {
+            ContentFormat::Bincode => bincode::serialize(value).map_err(|e| anyhow!(e)),
+            ContentFormat::Json => serde_json::to_vec(value).map_err(|e| anyhow!(e)),
+            ContentFormat::MessagePack => rmp_serde::to_vec_named(value).map_err(|e| anyhow!(e)),
+            ContentFormat::Cbor => {
+                let mut buf = Vec::new();
+                ciborium::into_writer(value, &mut buf).map_err(|e| anyhow!("{}", e))?;
+                Ok(buf)
+            }
+        }
+    }
+
+    pub fn deserialize<T: DeserializeOwned>(&self, bytes: &[u8]) -> anyhow::Result<T> {
+        match self {
+            ContentFormat::Bincode => bincode::deserialize(bytes).map_err(|e| anyhow!(e)),
+            ContentFormat::Json => serde_json::from_slice(bytes).map_err(|e| anyhow!(e)),
+            ContentFormat::MessagePack => rmp_serde::from_slice(bytes).map_err(|e| anyhow!(e)),
+            ContentFormat::Cbor => ciborium::from_reader(bytes).map_err(|e| anyhow!("{}", e)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::http::HeaderValue;
+    use chrono::{DateTime, Utc};
+    use serde_derive::Deserialize;
+
+    use super::*;
+
+    #[derive(serde_derive::Serialize, Deserialize, Debug, PartialEq)]
+    struct Sample {
+        name: String,
+        count: u32,
+        tags: Vec<String>,
+        maybe: Option<i64>,
+        at: DateTime<Utc>,
+    }
+
+    fn accept(value: &'static str) -> ContentFormat {
+        let mut headers = HeaderMap::new();
+        headers.insert(ACCEPT, HeaderValue::from_static(value));
+        ContentFormat::from_accept(&headers)
+    }
+
+    #[test]
+    fn test_from_accept() {
+        assert_eq!(
+            ContentFormat::from_accept(&HeaderMap::new()),
+            ContentFormat::Bincode
+        );
+        assert_eq!(accept("application/json"), ContentFormat::Json);
+        assert_eq!(accept("*/*"), ContentFormat::Bincode);
+        assert_eq!(
+            accept("text/html, application/json;q=0.9, */*;q=0.8"),
+            ContentFormat::Json
+        );
+        assert_eq!(
+            accept("application/json;q=0.5, application/cbor"),
+            ContentFormat::Cbor
+        );
+        assert_eq!(accept("application/x-msgpack"), ContentFormat::MessagePack);
+        assert_eq!(accept("text/html"), ContentFormat::Bincode);
+        assert_eq!(accept("application/json;q=0"), ContentFormat::Bincode);
+    }
+
+    #[test]
+    fn test_from_content_type() {
+        let mut headers = HeaderMap::new();
+        assert_eq!(
+            ContentFormat::from_content_type(&headers),
+            Some(ContentFormat::Json)
+        );
+
+        headers.insert(
+            CONTENT_TYPE,
+            HeaderValue::from_static("application/json; charset=utf-8"),
+        );
+        assert_eq!(
+            ContentFormat::from_content_type(&headers),
+            Some(ContentFormat::Json)
+        );
+
+        headers.insert(CONTENT_TYPE, HeaderValue::from_static("text/plain"));
+        assert_eq!(ContentFormat::from_content_type(&headers), None);
+    }
+
+    #[test]
+    fn test_round_trip_every_format() {
+        let sample = Sample {
+            name: "cyhdev".to_owned(),
+            count: 3,
+            tags: vec!["a".to_owned(), "b".to_owned()],
+            maybe: None,
+            at: Utc::now(),
+        };
+
+        for format in [
+            ContentFormat::Bincode,
+            ContentFormat::Json,
+            ContentFormat::MessagePack,
+            ContentFormat::Cbor,
+        ] {
+            let bytes = format.serialize(&sample).unwrap();
+            assert_eq!(
+                format.deserialize::<Sample>(&bytes).unwrap(),
+                sample,
+                "{:?}",
+                format
+            );
+        }
+    }
+}
@@ -0,0 +1,41 @@
+use axum::{
+    async_trait,
+    body::Bytes,
+    extract::{FromRequest, Request},
+    response::{IntoResponse, Response},
+};
+use serde::de::DeserializeOwned;
+
+use crate::utils::errors::errors::AppError;
+
+use super::content_format::ContentFormat;
+
+/// request body extractor that reads JSON, bincode, MessagePack or CBOR depending on Content-Type;
+/// use it wherever a handler would otherwise take `Json<T>`
+pub struct Payload<T>(pub T);
+
+#[async_trait]
+impl<T, S> FromRequest<S> for Payload<T>
+where
+    T: DeserializeOwned,
+    S: Send + Sync,
+{
+    type Rejection = Response;
+
+    async fn from_request(request: Request, state: &S) -> Result<Self, Self::Rejection> {
+        let format = match ContentFormat::from_content_type(request.headers()) {
+            Some(format) => format,
+            None => return Err(AppError::UnsupportedMediaType.into_response()),
+        };
+
+        let bytes = match Bytes::from_request(request, state).await {
+            Ok(bytes) => bytes,
+            Err(e) => return Err(AppError::CouldNotDeserializeBody(e.body_text()).into_response()),
+        };
+
+        match format.deserialize::<T>(&bytes) {
+            Ok(value) => Ok(Payload(value)),
+            Err(e) => Err(AppError::CouldNotDeserializeBody(e.to_string()).into_response()),
+        }
+    }
+}
@@ -1,38 +1,25 @@
-use crate::utils::{
-    errors::errors::{ErrResp, ErrRespDat},
-    gadgets::stopwatch::Stopwatch,
-};
-
-use anyhow::anyhow;
 use axum::response::IntoResponse;
-use tracing::error;
 
+use crate::utils::errors::errors::AppError;
+
+use super::content_format::ContentFormat;
+
+/// serializes in the format negotiated for the current request (bincode unless the client asked otherwise)
 pub fn serialize_to_response<T: serde::Serialize>(
     value: &T,
-    stopwatch: &Stopwatch,
 ) -> axum::http::Response<axum::body::Body> {
-    let serialized_data = match bincode::serialize(value) {
+    let format = ContentFormat::current();
+
+    let serialized_data = match format.serialize(value) {
         Ok(data) => data,
-        Err(e) => {
-            error!("Could not serialize value: {:?}", e);
-            return ErrResp::from(
-                ErrRespDat::COULD_NOT_SERIALIZE_BINCODE,
-                stopwatch,
-                anyhow!("Failed to serialize value!"),
-            )
-            .into_response();
-        }
+        Err(e) => return AppError::CouldNotSerializeResponse(e).into_response(),
     };
 
     match axum::response::Response::builder()
-        .header(axum::http::header::CONTENT_TYPE, "application/octet-stream")
+        .header(axum::http::header::CONTENT_TYPE, format.mime())
         .body(axum::body::Body::from(serialized_data))
     {
         Ok(response) => response,
-        Err(e) => {
-            error!("Failed to build response: {:?}", e);
-            ErrResp::from(ErrRespDat::COULD_NOT_BUILD_RESPONSE, stopwatch, anyhow!(e))
-                .into_response()
-        }
+        Err(e) => AppError::CouldNotBuildResponse(e.into()).into_response(),
     }
 }
@@ -1,14 +1,28 @@
-use std::sync::Arc;
+use std::{net::SocketAddr, sync::Arc, time::Duration};
 
 use anyhow::anyhow;
+use axum_server::{tls_rustls::RustlsAcceptor, Handle};
 use chrono::{DateTime, Utc};
+use tokio_util::{sync::CancellationToken, task::TaskTracker};
+use tracing::warn;
 
-use crate::{controllers::router::generate_router, utils::gadgets::stopwatch::Stopwatch};
+use crate::{
+    controllers::router::generate_router,
+    utils::{
+        config::app_config::AppConfig,
+        db::migrations::{run_migrations, MigrationMode},
+        gadgets::stopwatch::Stopwatch,
+        net::proxy_protocol::ProxyProtocolAcceptor,
+        workers::email_outbox_worker::run_email_outbox_worker,
+    },
+};
 
 use super::{
     server_init_funcs::{
-        https_redirector::redirect_http_to_https, initialize_crypto::init_crypto,
-        load_cert_config::load_certs,
+        admin_server::serve_admin, https_redirector::redirect_http_to_https,
+        initialize_crypto::init_crypto, initialize_logger::LogFilterHandle,
+        initialize_metrics::init_metrics, load_cert_config::load_certs,
+        shutdown_signal::shutdown_on_signal,
     },
     server_state_def::ServerState,
 };
@@ -16,20 +30,39 @@ use super::{
 pub async fn init_server(
     stopwatch: &mut Stopwatch,
     server_start_time: DateTime<Utc>,
+    config: AppConfig,
+    log_filter: LogFilterHandle,
 ) -> anyhow::Result<()> {
     // initialize crypto
     init_crypto()?;
     // load certs
-    let cert_config = load_certs().await?;
+    let cert_config = load_certs(&config.server).await?;
     stopwatch.click("crypto initialized, certs loaded");
 
+    // install the metrics recorder before anything records
+    let metrics = init_metrics()?;
+    stopwatch.click("metrics recorder installed");
+
+    // cancelled on SIGTERM/SIGINT; everything spawned below is tracked so shutdown can wait for it
+    let shutdown = CancellationToken::new();
+    let background_tasks = TaskTracker::new();
+    let drain_timeout = Duration::from_secs(config.server.shutdown_drain_seconds);
+
     // set up http -> https redirect server
-    tokio::spawn(redirect_http_to_https());
+    background_tasks.spawn(redirect_http_to_https(
+        config.server.http_redirect_port,
+        config.server.host_port,
+        shutdown.clone(),
+    ));
     stopwatch.click("HTTPS redirection server online.");
 
     // initialize server state
-    let state = Arc::new(ServerState::new(stopwatch, server_start_time)?);
-    stopwatch.click("server state initialized");
+    let state = Arc::new(ServerState::new(stopwatch, server_start_time, &config)?);
+    stopwatch.click(&format!(
+        "server state initialized; mail transport: {}, rate limit store: {}",
+        state.get_mailer().name(),
+        state.get_rate_limiter().store_name()
+    ));
 
     // test connection pool
     let conn = state.get_conn().await?;
@@ -45,22 +78,76 @@ pub async fn init_server(
     stopwatch.click(&format!("DB connection verified: {}; latency", ver_string));
     drop(ver_string);
 
+    // bring the schema up to date before anything queries it
+    let applied = run_migrations(state.get_conn().await?, MigrationMode::Apply).await?;
+    stopwatch.click(&format!("{} migration(s) applied", applied.len()));
+
+    // start delivering queued emails
+    background_tasks.spawn(run_email_outbox_worker(
+        Arc::clone(&state),
+        shutdown.clone(),
+    ));
+    stopwatch.click("email outbox worker started");
+
+    // serve /metrics and /log-level on the internal admin listener
+    background_tasks.spawn(serve_admin(
+        config.server.admin_addr,
+        Arc::clone(&state),
+        metrics,
+        log_filter,
+        shutdown.clone(),
+    ));
+    stopwatch.click(&format!(
+        "admin listener online at {}",
+        config.server.admin_addr
+    ));
+
     // define router
     let router = generate_router(&state);
     stopwatch.click("routers defined");
 
     stopwatch.total("server started in");
 
-    // serve server
-    match axum_server::bind_rustls(state.get_socket_addr(), cert_config)
-        .serve(router.into_make_service())
+    // stop accepting and drain on SIGTERM/SIGINT
+    let handle = Handle::new();
+    tokio::spawn(shutdown_on_signal(
+        handle.clone(),
+        shutdown.clone(),
+        drain_timeout,
+    ));
+
+    // serve server; returns once connections have drained after a shutdown signal.
+    // The socket peer (ConnectInfo) and any PROXY header feed the client IP resolution.
+    let served = axum_server::bind(state.get_socket_addr())
+        .acceptor(ProxyProtocolAcceptor::new(
+            RustlsAcceptor::new(cert_config),
+            config.server.proxy_protocol,
+        ))
+        .handle(handle)
+        .serve(router.into_make_service_with_connect_info::<SocketAddr>())
+        .await;
+    stopwatch.click("HTTPS server stopped");
+
+    // also reached when serving failed, so background work is wound down either way
+    shutdown.cancel();
+    background_tasks.close();
+    if tokio::time::timeout(drain_timeout, background_tasks.wait())
         .await
+        .is_err()
     {
-        Ok(_) => (),
-        Err(e) => {
-            return Err(anyhow!("Axum could not serve app: {:?}", e));
-        }
-    };
+        warn!(
+            "Background tasks did not stop within {:?}; abandoning them",
+            drain_timeout
+        );
+    }
+    stopwatch.click("redirector, admin listener and background workers stopped");
+
+    state.close_pool();
+    stopwatch.click("database pool closed");
+
+    if let Err(e) = served {
+        return Err(anyhow!("Axum could not serve app: {:?}", e));
+    }
 
     Ok(())
 }
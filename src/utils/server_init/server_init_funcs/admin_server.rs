@@ -0,0 +1,125 @@
+use std::{net::SocketAddr, sync::Arc};
+
+use axum::{
+    extract::State,
+    http::{header::CONTENT_TYPE, StatusCode},
+    response::IntoResponse,
+    routing::get,
+    Json, Router,
+};
+use metrics_exporter_prometheus::PrometheusHandle;
+use serde_derive::{Deserialize, Serialize};
+use serde_json::json;
+use tokio_util::sync::CancellationToken;
+use tracing::{error, info};
+
+use crate::utils::server_init::server_state_def::ServerState;
+
+use super::{
+    initialize_logger::LogFilterHandle,
+    initialize_metrics::{DB_POOL_AVAILABLE, DB_POOL_MAX_SIZE, DB_POOL_SIZE, DB_POOL_WAITING},
+};
+
+#[derive(Clone)]
+struct AdminState {
+    state: Arc<ServerState>,
+    metrics: PrometheusHandle,
+    log_filter: LogFilterHandle,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct LogLevel {
+    /// EnvFilter directives, e.g. `debug` or `info,cyhdev_back=trace`
+    filter: String,
+}
+
+/// routes for the internal listener; nothing here is reachable through the public HTTPS server
+pub fn admin_router(
+    state: Arc<ServerState>,
+    metrics: PrometheusHandle,
+    log_filter: LogFilterHandle,
+) -> Router {
+    Router::new()
+        .route("/metrics", get(render_metrics))
+        .route("/log-level", get(get_log_level).put(set_log_level))
+        .with_state(AdminState {
+            state,
+            metrics,
+            log_filter,
+        })
+}
+
+// GET /metrics
+async fn render_metrics(State(admin): State<AdminState>) -> impl IntoResponse {
+    // pool gauges are sampled at scrape time rather than tracked on every checkout
+    let status = admin.state.get_pool_status();
+    metrics::gauge!(DB_POOL_MAX_SIZE).set(status.max_size as f64);
+    metrics::gauge!(DB_POOL_SIZE).set(status.size as f64);
+    metrics::gauge!(DB_POOL_AVAILABLE).set(status.available as f64);
+    metrics::gauge!(DB_POOL_WAITING).set(status.waiting as f64);
+
+    admin.metrics.run_upkeep();
+
+    (
+        [(CONTENT_TYPE, "text/plain; version=0.0.4; charset=utf-8")],
+        admin.metrics.render(),
+    )
+}
+
+// GET /log-level
+async fn get_log_level(State(admin): State<AdminState>) -> impl IntoResponse {
+    match admin.log_filter.current() {
+        Ok(filter) => (StatusCode::OK, Json(json!(LogLevel { filter }))),
+        Err(e) => {
+            error!("{:?}", e);
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(json!({ "error": "could not read the log filter" })),
+            )
+        }
+    }
+}
+
+// PUT /log-level
+// lasts until the next restart; the configured logging.level applies again after that
+async fn set_log_level(
+    State(admin): State<AdminState>,
+    Json(body): Json<LogLevel>,
+) -> impl IntoResponse {
+    match admin.log_filter.set(&body.filter) {
+        Ok(filter) => {
+            info!("Log filter changed to {}", filter);
+            (StatusCode::OK, Json(json!(LogLevel { filter })))
+        }
+        Err(e) => (
+            StatusCode::BAD_REQUEST,
+            Json(json!({ "error": e.to_string() })),
+        ),
+    }
+}
+
+/// serves the admin router over plain HTTP until `shutdown` is cancelled; bind it to a private address
+pub async fn serve_admin(
+    addr: SocketAddr,
+    state: Arc<ServerState>,
+    metrics: PrometheusHandle,
+    log_filter: LogFilterHandle,
+    shutdown: CancellationToken,
+) {
+    match tokio::net::TcpListener::bind(addr).await {
+        Ok(listener) => {
+            match listener.local_addr() {
+                Ok(local_addr) => tracing::debug!("admin listener on {}", local_addr),
+                Err(e) => tracing::error!("failed to get local address: {}", e),
+            }
+
+            if let Err(e) = axum::serve(listener, admin_router(state, metrics, log_filter))
+                .with_graceful_shutdown(shutdown.cancelled_owned())
+                .await
+            {
+                tracing::error!("admin server error: {}", e);
+            }
+        }
+        Err(e) => tracing::error!("failed to bind admin listener to {}: {}", addr, e),
+    }
+}
@@ -7,7 +7,7 @@ use axum::{
     response::Redirect,
     BoxError,
 };
-use dotenvy::var;
+use tokio_util::sync::CancellationToken;
 
 #[derive(Clone, Copy)]
 pub struct Ports {
@@ -24,13 +24,9 @@ impl Ports {
     }
 }
 
-pub async fn redirect_http_to_https() {
-    let https_port = var("HOST_PORT")
-        .ok()
-        .and_then(|port_str| port_str.parse::<u16>().ok())
-        .unwrap_or(443u16);
-
-    let ports = Ports::new(80u16, https_port);
+/// serves permanent redirects to HTTPS until `shutdown` is cancelled
+pub async fn redirect_http_to_https(http_port: u16, https_port: u16, shutdown: CancellationToken) {
+    let ports = Ports::new(http_port, https_port);
 
     fn make_https(host: String, uri: Uri, ports: Ports) -> Result<Uri, BoxError> {
         let mut parts = uri.into_parts();
@@ -65,7 +61,10 @@ pub async fn redirect_http_to_https() {
                 Err(e) => tracing::error!("failed to get local address: {}", e),
             }
 
-            if let Err(e) = axum::serve(listener, redirect.into_make_service()).await {
+            if let Err(e) = axum::serve(listener, redirect.into_make_service())
+                .with_graceful_shutdown(shutdown.cancelled_owned())
+                .await
+            {
                 tracing::error!("axum server error: {}", e);
             }
         }
@@ -3,16 +3,15 @@ use deadpool_postgres::{Config, ManagerConfig, Pool};
 use tokio_postgres::NoTls;
 use tracing::error;
 
-use super::load_env_vars::get_env_var;
+use crate::utils::config::app_config::DatabaseConfig;
 
-pub fn init_db_conn_pool() -> anyhow::Result<Pool> {
-    // db configuration from env
+pub fn init_db_conn_pool(database: &DatabaseConfig) -> anyhow::Result<Pool> {
     let mut db_config: Config = Config::new();
-    db_config.user = Some(get_env_var("DB_USER")?);
-    db_config.host = Some(get_env_var("DB_HOST")?);
-    db_config.dbname = Some(get_env_var("DB_NAME")?);
-    db_config.password = Some(get_env_var("DB_PASSWORD")?);
-    db_config.port = Some(get_env_var("DB_PORT")?.parse()?);
+    db_config.user = Some(database.user.clone());
+    db_config.host = Some(database.host.clone());
+    db_config.dbname = Some(database.name.clone());
+    db_config.password = Some(database.password.expose().to_owned());
+    db_config.port = Some(database.port);
     db_config.manager = Some(ManagerConfig {
         recycling_method: deadpool_postgres::RecyclingMethod::Fast, // look into more later
     });
@@ -1,10 +1,20 @@
-use tracing_subscriber::EnvFilter;
+use anyhow::anyhow;
+use opentelemetry::trace::TracerProvider;
+use opentelemetry_sdk::trace::SdkTracerProvider;
+use rolling_file::{BasicRollingFileAppender, RollingConditionBasic};
+use tracing_appender::non_blocking::{NonBlockingBuilder, WorkerGuard};
+use tracing_subscriber::{
+    layer::SubscriberExt, reload, util::SubscriberInitExt, EnvFilter, Layer, Registry,
+};
 
-/// initialize logger using 'tracing' crate
-pub fn init_logger() -> anyhow::Result<()> {
-    // adjust logging levels here
-    let mut filter: EnvFilter =
-        EnvFilter::try_from_default_env().or_else(|_| EnvFilter::try_new("info"))?; //for prod
+use crate::utils::config::app_config::{LogFormat, LogRotation, LoggingConfig};
+
+/// file name inside logging.dir; rotated files get .1, .2, ... appended
+pub const LOG_FILE_NAME: &str = concat!(env!("CARGO_PKG_NAME"), ".log");
+
+/// the configured directives plus the exclusions for noisy external crates
+pub fn build_log_filter(directives: &str) -> anyhow::Result<EnvFilter> {
+    let mut filter: EnvFilter = EnvFilter::try_new(directives)?;
 
     // exclude output from external crates here
     filter = filter
@@ -12,14 +22,100 @@ pub fn init_logger() -> anyhow::Result<()> {
         .add_directive("axum-template=info".parse()?)
         .add_directive("rustls=off".parse()?)
         // .add_directive("tokio_postgres=debug".parse()?)
-        .add_directive("aws_config=off".parse()?);
+        .add_directive("aws_config=off".parse()?)
+        // the exporter's own HTTP client would otherwise trace its exports
+        .add_directive("opentelemetry=warn".parse()?)
+        .add_directive("reqwest=warn".parse()?)
+        .add_directive("hyper_util=warn".parse()?);
+
+    Ok(filter)
+}
+
+/// swaps the level filter of the running logger; handed to the admin listener
+#[derive(Clone)]
+pub struct LogFilterHandle(reload::Handle<EnvFilter, Registry>);
+
+impl LogFilterHandle {
+    pub fn new(handle: reload::Handle<EnvFilter, Registry>) -> Self {
+        LogFilterHandle(handle)
+    }
+
+    /// the filter in effect, in EnvFilter directive syntax
+    pub fn current(&self) -> anyhow::Result<String> {
+        self.0
+            .with_current(|filter| filter.to_string())
+            .map_err(|e| anyhow!("Could not read the log filter: {}", e))
+    }
+
+    /// replaces the filter; invalid directives leave the current one in place
+    pub fn set(&self, directives: &str) -> anyhow::Result<String> {
+        let filter = build_log_filter(directives)?;
+        self.0
+            .reload(filter)
+            .map_err(|e| anyhow!("Could not change the log filter: {}", e))?;
+        self.current()
+    }
+}
+
+/// initialize logger using 'tracing' crate; spans also go to `tracer_provider` when trace export is configured.
+/// Keep the returned guard alive until exit: dropping it flushes the log lines still queued for the writer.
+pub fn init_logger(
+    config: &LoggingConfig,
+    tracer_provider: Option<&SdkTracerProvider>,
+) -> anyhow::Result<(LogFilterHandle, WorkerGuard)> {
+    let (filter, filter_handle) = reload::Layer::new(build_log_filter(&config.level)?);
+
+    // lines are handed to a writer thread; lossy(false) makes callers wait rather than drop lines when it falls behind
+    let (writer, guard) = match config.dir.as_deref() {
+        Some(dir) => {
+            std::fs::create_dir_all(dir)
+                .map_err(|e| anyhow!("Could not create log directory {:?}: {}", dir, e))?;
+
+            let mut condition = RollingConditionBasic::new()
+                .max_size(config.max_file_size_mb.saturating_mul(1024 * 1024));
+            condition = match config.rotation {
+                LogRotation::Hourly => condition.hourly(),
+                LogRotation::Daily => condition.daily(),
+                LogRotation::Never => condition,
+            };
+
+            let appender =
+                BasicRollingFileAppender::new(dir.join(LOG_FILE_NAME), condition, config.max_files)
+                    .map_err(|e| anyhow!("Could not open log file in {:?}: {}", dir, e))?;
+
+            NonBlockingBuilder::default().lossy(false).finish(appender)
+        }
+        None => NonBlockingBuilder::default()
+            .lossy(false)
+            .finish(std::io::stdout()),
+    };
+
+    let trace_export = tracer_provider.map(|provider| {
+        tracing_opentelemetry::layer().with_tracer(provider.tracer(env!("CARGO_PKG_NAME")))
+    });
+
+    let output = match config.format {
+        LogFormat::Text => tracing_subscriber::fmt::layer()
+            .with_ansi(false) // disable colored output; advisable if persisting logs to external
+            .with_target(false) // disable target display
+            .with_writer(writer)
+            .boxed(),
+        // one object per line, with the fields of the enclosing spans (request_id, user.id, ...)
+        LogFormat::Json => tracing_subscriber::fmt::layer()
+            .json()
+            .with_target(false)
+            .with_current_span(true)
+            .with_span_list(false)
+            .with_writer(writer)
+            .boxed(),
+    };
 
-    tracing_subscriber::fmt()
-        .with_ansi(false) // disable colored output; advisable if persisting logs to external
-        .with_target(false) // disable target display
-        .with_env_filter(filter)
-        // .with_writer(non_blocking) // output to a log file
-        .init();
+    tracing_subscriber::registry()
+        .with(filter)
+        .with(trace_export)
+        .with(output)
+        .try_init()
+        .map_err(|e| anyhow!("Could not install logger: {}", e))?;
 
-    Ok(())
+    Ok((LogFilterHandle::new(filter_handle), guard))
 }
@@ -1,22 +1,30 @@
-use anyhow::{anyhow, Result};
-use lettre::{transport::smtp::authentication::Credentials, AsyncSmtpTransport, Tokio1Executor};
+use std::sync::Arc;
 
-use super::load_env_vars::get_env_var;
+use anyhow::Result;
+use lettre::transport::smtp::authentication::Credentials;
 
-pub fn init_mailer() -> Result<AsyncSmtpTransport<Tokio1Executor>> {
-    let creds = Credentials::new(
-        get_env_var("SMTP_USERNAME")?.to_owned(),
-        get_env_var("SMTP_PASSWORD")?.to_owned(),
-    );
+use crate::utils::{
+    config::app_config::{MailConfig, MailTransportKind},
+    mail::{
+        file_transport::FileMailTransport, mail_transport::MailTransport,
+        memory_transport::InMemoryMailTransport, smtp_transport::SmtpMailTransport,
+    },
+};
 
-    let mailer: AsyncSmtpTransport<Tokio1Executor> = match AsyncSmtpTransport::<Tokio1Executor>::relay("email-smtp.ap-northeast-2.amazonaws.com") {
-        Ok(relay) => relay,
-        Err(e) => {
-            return Err(anyhow!("Could not build SMTP relay: {:?}", e));
-        }
+/// builds the configured mail transport (smtp, file or memory)
+pub fn init_mailer(mail: &MailConfig) -> Result<Arc<dyn MailTransport>> {
+    match mail.transport {
+        MailTransportKind::Smtp => Ok(Arc::new(init_smtp_mailer(mail)?)),
+        MailTransportKind::File => Ok(Arc::new(FileMailTransport::new(mail.file_dir.clone())?)),
+        MailTransportKind::Memory => Ok(Arc::new(InMemoryMailTransport::new())),
     }
-    .credentials(creds)
-    .build();
+}
+
+/// credentials are used when configured; relays such as MailHog need none
+fn init_smtp_mailer(mail: &MailConfig) -> Result<SmtpMailTransport> {
+    let credentials = mail.smtp_credentials.as_ref().map(|(username, password)| {
+        Credentials::new(username.clone(), password.expose().to_owned())
+    });
 
-    Ok(mailer)
+    SmtpMailTransport::new(&mail.smtp_host, mail.smtp_port, mail.smtp_tls, credentials)
 }
@@ -0,0 +1,76 @@
+use std::sync::OnceLock;
+
+use anyhow::anyhow;
+use metrics::{describe_counter, describe_gauge, describe_histogram, Unit};
+use metrics_exporter_prometheus::{PrometheusBuilder, PrometheusHandle};
+
+pub const HTTP_REQUESTS_TOTAL: &str = "http_requests_total";
+pub const HTTP_REQUEST_DURATION_SECONDS: &str = "http_request_duration_seconds";
+pub const DB_POOL_MAX_SIZE: &str = "db_pool_max_size";
+pub const DB_POOL_SIZE: &str = "db_pool_size";
+pub const DB_POOL_AVAILABLE: &str = "db_pool_available";
+pub const DB_POOL_WAITING: &str = "db_pool_waiting";
+pub const EMAILS_SENT_TOTAL: &str = "emails_sent_total";
+pub const EMAIL_SEND_FAILURES_TOTAL: &str = "email_send_failures_total";
+pub const PASSWORD_HASH_DURATION_SECONDS: &str = "password_hash_duration_seconds";
+pub const RATE_LIMITED_TOTAL: &str = "rate_limited_total";
+pub const LOGIN_LOCKOUTS_TOTAL: &str = "login_lockouts_total";
+
+/// histogram buckets in seconds, shared by request latency and argon2 timings
+const DURATION_BUCKETS: &[f64] = &[
+    0.001, 0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0,
+];
+
+static METRICS_HANDLE: OnceLock<Result<PrometheusHandle, String>> = OnceLock::new();
+
+/// installs the global Prometheus recorder once and returns the handle that renders it;
+/// metrics recorded before the first call are dropped
+pub fn init_metrics() -> anyhow::Result<PrometheusHandle> {
+    METRICS_HANDLE
+        .get_or_init(|| install_recorder().map_err(|e| format!("{:?}", e)))
+        .clone()
+        .map_err(|e| anyhow!("Could not install metrics recorder: {}", e))
+}
+
+fn install_recorder() -> anyhow::Result<PrometheusHandle> {
+    let handle = PrometheusBuilder::new()
+        .set_buckets(DURATION_BUCKETS)?
+        .install_recorder()?;
+
+    describe_counter!(
+        HTTP_REQUESTS_TOTAL,
+        "HTTP requests by route, method and status"
+    );
+    describe_histogram!(
+        HTTP_REQUEST_DURATION_SECONDS,
+        Unit::Seconds,
+        "HTTP request latency by route, method and status"
+    );
+    describe_gauge!(DB_POOL_MAX_SIZE, "Maximum connections in the database pool");
+    describe_gauge!(
+        DB_POOL_SIZE,
+        "Connections currently open in the database pool"
+    );
+    describe_gauge!(DB_POOL_AVAILABLE, "Idle connections in the database pool");
+    describe_gauge!(DB_POOL_WAITING, "Tasks waiting for a database connection");
+    describe_counter!(EMAILS_SENT_TOTAL, "Emails handed to the mail transport");
+    describe_counter!(
+        EMAIL_SEND_FAILURES_TOTAL,
+        "Emails the mail transport rejected or that could not be built"
+    );
+    describe_histogram!(
+        PASSWORD_HASH_DURATION_SECONDS,
+        Unit::Seconds,
+        "Time spent in argon2 by operation (hash or verify)"
+    );
+    describe_counter!(
+        RATE_LIMITED_TOTAL,
+        "Requests refused with 429 by rate limit group"
+    );
+    describe_counter!(
+        LOGIN_LOCKOUTS_TOTAL,
+        "Accounts and client IPs locked after repeated failed logins, by kind"
+    );
+
+    Ok(handle)
+}
@@ -0,0 +1,30 @@
+use std::{collections::HashMap, sync::Arc};
+
+use deadpool_postgres::Pool;
+
+use crate::utils::{
+    config::app_config::{RateLimitConfig, RateLimitStoreKind},
+    rate_limit::{
+        memory_store::InMemoryRateLimitStore,
+        postgres_store::PostgresRateLimitStore,
+        rate_limit_store::{RateLimitGroup, RateLimitStore, RateLimiter},
+    },
+};
+
+/// builds the rate limiter over the configured store (memory or postgres)
+pub fn init_rate_limiter(config: &RateLimitConfig, pool: &Pool) -> RateLimiter {
+    let store: Arc<dyn RateLimitStore> = match config.store {
+        RateLimitStoreKind::Memory => Arc::new(InMemoryRateLimitStore::new()),
+        RateLimitStoreKind::Postgres => Arc::new(PostgresRateLimitStore::new(pool.clone())),
+    };
+
+    let limits = HashMap::from([
+        (RateLimitGroup::Default, config.default),
+        (RateLimitGroup::Credentials, config.credentials),
+        (RateLimitGroup::Tokens, config.tokens),
+        (RateLimitGroup::Email, config.email),
+        (RateLimitGroup::Account, config.account),
+    ]);
+
+    RateLimiter::new(store, limits, config.enabled)
+}
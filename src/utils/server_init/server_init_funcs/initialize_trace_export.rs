@@ -0,0 +1,40 @@
+use anyhow::anyhow;
+use opentelemetry::global;
+use opentelemetry_otlp::{SpanExporter, WithExportConfig};
+use opentelemetry_sdk::{
+    propagation::TraceContextPropagator,
+    trace::{Sampler, SdkTracerProvider},
+    Resource,
+};
+
+use crate::utils::config::app_config::TelemetryConfig;
+
+/// accepts W3C `traceparent` headers and, when an OTLP endpoint is configured, returns a provider that
+/// exports spans to it in batches; hand it to init_logger, and shut it down on exit so buffered spans are flushed
+pub fn init_trace_export(config: &TelemetryConfig) -> anyhow::Result<Option<SdkTracerProvider>> {
+    global::set_text_map_propagator(TraceContextPropagator::new());
+
+    let Some(endpoint) = config.otlp_endpoint.as_deref() else {
+        return Ok(None);
+    };
+
+    let exporter = SpanExporter::builder()
+        .with_http()
+        .with_endpoint(format!("{}/v1/traces", endpoint))
+        .build()
+        .map_err(|e| anyhow!("Could not build OTLP span exporter: {:?}", e))?;
+
+    let provider = SdkTracerProvider::builder()
+        .with_batch_exporter(exporter)
+        .with_sampler(Sampler::ParentBased(Box::new(Sampler::TraceIdRatioBased(
+            config.sample_ratio,
+        ))))
+        .with_resource(
+            Resource::builder()
+                .with_service_name(config.service_name.clone())
+                .build(),
+        )
+        .build();
+
+    Ok(Some(provider))
+}
@@ -1,22 +1,14 @@
 use std::path::PathBuf;
 
 use anyhow::anyhow;
-use dotenvy::{dotenv, var};
+use dotenvy::dotenv;
 
-/// load environment variables from .env using 'dotenvy' crate
-pub fn load_env() -> anyhow::Result<PathBuf> {
+/// load environment variables from .env using 'dotenvy' crate; they override the config file.
+/// A missing .env is fine since everything can come from the config file instead.
+pub fn load_env() -> anyhow::Result<Option<PathBuf>> {
     match dotenv() {
-        Ok(path_buf) => Ok(path_buf),
-        Err(e) => Err(anyhow!(
-            "Dotenvy could not load .env file: {}",
-            e.to_string()
-        )),
-    }
-}
-
-pub fn get_env_var(key: &str) -> anyhow::Result<String> {
-    match var(key) {
-        Ok(value) => Ok(value),
-        Err(e) => Err(anyhow!("Could not find var {}: {:?}", key, e)),
+        Ok(path_buf) => Ok(Some(path_buf)),
+        Err(e) if e.not_found() => Ok(None),
+        Err(e) => Err(anyhow!("Dotenvy could not load .env file: {}", e)),
     }
 }
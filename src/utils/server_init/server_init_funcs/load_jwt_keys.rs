@@ -0,0 +1,14 @@
+use anyhow::Result;
+
+use crate::{models::jwt::JWT, utils::config::app_config::JwtConfig};
+
+/// builds the JWT key ring; `active_kid` picks the signing key, the rest only verify
+pub fn load_jwt_keys(jwt: &JwtConfig) -> Result<JWT> {
+    let secrets: Vec<(String, String)> = jwt
+        .secrets
+        .iter()
+        .map(|(kid, secret)| (kid.clone(), secret.expose().to_owned()))
+        .collect();
+
+    JWT::from_secrets(&jwt.active_kid, &secrets)
+}
@@ -0,0 +1,50 @@
+use std::time::Duration;
+
+use axum_server::Handle;
+use tokio::signal::unix::{signal, SignalKind};
+use tokio_util::sync::CancellationToken;
+use tracing::{error, info};
+
+/// resolves on SIGTERM (sent by deploy.sh and container runtimes) or SIGINT (Ctrl+C)
+pub async fn wait_for_shutdown_signal() -> &'static str {
+    let sigterm = async {
+        match signal(SignalKind::terminate()) {
+            Ok(mut sigterm) => {
+                sigterm.recv().await;
+            }
+            Err(e) => {
+                error!("Could not install SIGTERM handler: {:?}", e);
+                std::future::pending::<()>().await;
+            }
+        }
+    };
+
+    let sigint = async {
+        if let Err(e) = tokio::signal::ctrl_c().await {
+            error!("Could not install SIGINT handler: {:?}", e);
+            std::future::pending::<()>().await;
+        }
+    };
+
+    tokio::select! {
+        _ = sigterm => "SIGTERM",
+        _ = sigint => "SIGINT",
+    }
+}
+
+/// on SIGTERM/SIGINT: stops accepting connections, gives in-flight requests `drain_timeout` to finish,
+/// and cancels `shutdown` so the redirector and background workers wind down alongside
+pub async fn shutdown_on_signal(
+    handle: Handle,
+    shutdown: CancellationToken,
+    drain_timeout: Duration,
+) {
+    let signal = wait_for_shutdown_signal().await;
+    info!(
+        "{} received; draining connections for up to {:?}",
+        signal, drain_timeout
+    );
+
+    shutdown.cancel();
+    handle.graceful_shutdown(Some(drain_timeout));
+}
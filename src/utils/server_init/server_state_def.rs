@@ -1,32 +1,111 @@
-use std::net::{IpAddr, Ipv4Addr, SocketAddr, SocketAddrV4, SocketAddrV6};
+use std::{
+    net::{IpAddr, SocketAddr, SocketAddrV4, SocketAddrV6},
+    sync::Arc,
+};
 
 use anyhow::Result;
 use chrono::{DateTime, Utc};
-use deadpool_postgres::{Object, Pool};
-use lettre::{AsyncSmtpTransport, Tokio1Executor};
+use deadpool_postgres::{Object, Pool, PoolError, Status};
 use regex::Regex;
 
-use crate::utils::gadgets::{
-    regex::{compile_regex, EMAIL_VALIDATION_REGEX},
-    stopwatch::Stopwatch,
+use crate::{
+    models::jwt::JWT,
+    utils::{
+        config::app_config::{AppConfig, ServerConfig},
+        gadgets::{
+            regex::{compile_regex, EMAIL_VALIDATION_REGEX},
+            stopwatch::Stopwatch,
+        },
+        lockout::login_throttle::LoginThrottle,
+        mail::mail_transport::MailTransport,
+        net::trusted_proxies::TrustedProxies,
+        rate_limit::rate_limit_store::RateLimiter,
+        two_factor::secret_cipher::SecretCipher,
+        webauthn::relying_party::RelyingParty,
+    },
 };
 
 use super::server_init_funcs::{
     initialize_db_conn_pool::init_db_conn_pool, initialize_mailer::init_mailer,
-    load_env_vars::get_env_var,
+    initialize_rate_limiter::init_rate_limiter, load_jwt_keys::load_jwt_keys,
 };
 
 #[derive(Clone)]
 pub struct ServerState {
+    #[allow(dead_code)]
     cache: Cache,
     server_resources: ServerResources,
 }
 
 impl ServerState {
-    pub fn new(stopwatch: &mut Stopwatch, server_start_time: DateTime<Utc>) -> Result<Self> {
+    pub fn new(
+        _stopwatch: &mut Stopwatch,
+        server_start_time: DateTime<Utc>,
+        config: &AppConfig,
+    ) -> Result<Self> {
         Ok(ServerState {
             cache: Cache::new()?,
-            server_resources: ServerResources::new(server_start_time)?,
+            server_resources: ServerResources::new(server_start_time, config)?,
+        })
+    }
+}
+
+#[cfg(test)]
+impl ServerState {
+    /// state for integration tests: the given pool, mailer, rate limiter and login throttle, fixed JWT and
+    /// two-factor keys and a localhost base URL
+    pub fn for_tests(
+        pool: Pool,
+        mailer: Arc<dyn MailTransport>,
+        rate_limiter: RateLimiter,
+        login_throttle: LoginThrottle,
+    ) -> Result<Self> {
+        Ok(ServerState {
+            cache: Cache::new()?,
+            server_resources: ServerResources {
+                server_config: ServerConfig {
+                    host_addr: IpAddr::V4(std::net::Ipv4Addr::LOCALHOST),
+                    host_port: 0,
+                    http_redirect_port: 0,
+                    admin_addr: SocketAddr::from(([127, 0, 0, 1], 0)),
+                    shutdown_drain_seconds: 0,
+                    public_base_url: TEST_PUBLIC_BASE_URL.to_owned(),
+                    cert_path: Default::default(),
+                    key_path: Default::default(),
+                    // the harness connects from 127.0.0.1, so tests pick clients with X-Forwarded-For
+                    trusted_proxies: "127.0.0.0/8".parse()?,
+                    proxy_protocol: false,
+                },
+                regexes: CompiledRegexes::compile()?,
+                server_start_time: Utc::now(),
+                app_name_version: format!(
+                    "{} {}",
+                    env!("CARGO_PKG_NAME"),
+                    env!("CARGO_PKG_VERSION")
+                ),
+                pool,
+                request_client: reqwest::Client::new(),
+                mailer,
+                check_mail_when_ready: false,
+                rate_limiter,
+                login_throttle: Arc::new(login_throttle),
+                jwt: JWT::from_secrets(
+                    "test",
+                    &[(
+                        "test".to_owned(),
+                        "integration-test-secret-not-for-production".to_owned(),
+                    )],
+                )?,
+                two_factor_issuer: "cyhdev.com".to_owned(),
+                secret_cipher: Arc::new(SecretCipher::from_base64(
+                    "dGVzdC10d28tZmFjdG9yLWtleS1ub3QtZm9yLXByb2Q=",
+                )?),
+                relying_party: RelyingParty::new(
+                    "localhost",
+                    "cyhdev.com",
+                    vec![TEST_PUBLIC_BASE_URL.to_owned()],
+                )?,
+            },
         })
     }
 }
@@ -40,21 +119,72 @@ impl ServerState {
         self.server_resources.app_name_version.clone()
     }
 
-    pub async fn get_conn(&self) -> Result<Object> {
-        match self.server_resources.pool.get().await {
-            Ok(conn) => Ok(conn),
-            Err(e) => Err(anyhow::Error::from(e)),
-        }
+    pub fn get_server_start_time(&self) -> DateTime<Utc> {
+        self.server_resources.server_start_time
+    }
+
+    pub async fn get_conn(&self) -> Result<Object, PoolError> {
+        self.server_resources.pool.get().await
+    }
+
+    /// connection counts for the pool gauges on /metrics
+    pub fn get_pool_status(&self) -> Status {
+        self.server_resources.pool.status()
+    }
+
+    /// closes idle connections and makes further get_conn calls fail; used on shutdown
+    pub fn close_pool(&self) {
+        self.server_resources.pool.close();
     }
 
     pub fn get_request(&self) -> &reqwest::Client {
         &self.server_resources.request_client
     }
 
-    pub fn get_mailer(&self) -> &AsyncSmtpTransport<Tokio1Executor> {
+    pub fn get_mailer(&self) -> &Arc<dyn MailTransport> {
         &self.server_resources.mailer
     }
 
+    /// whether /readyz should also check the mail transport
+    pub fn get_check_mail_when_ready(&self) -> bool {
+        self.server_resources.check_mail_when_ready
+    }
+
+    pub fn get_rate_limiter(&self) -> &RateLimiter {
+        &self.server_resources.rate_limiter
+    }
+
+    pub fn get_login_throttle(&self) -> &LoginThrottle {
+        &self.server_resources.login_throttle
+    }
+
+    pub fn get_jwt(&self) -> &JWT {
+        &self.server_resources.jwt
+    }
+
+    /// shown as the account's label in authenticator apps
+    pub fn get_two_factor_issuer(&self) -> &str {
+        &self.server_resources.two_factor_issuer
+    }
+
+    pub fn get_secret_cipher(&self) -> &SecretCipher {
+        &self.server_resources.secret_cipher
+    }
+
+    pub fn get_relying_party(&self) -> &RelyingParty {
+        &self.server_resources.relying_party
+    }
+
+    /// origin of the frontend that links in emails point to, without a trailing slash
+    pub fn get_public_base_url(&self) -> &str {
+        &self.server_resources.server_config.public_base_url
+    }
+
+    /// proxies whose forwarding headers are believed when resolving the client IP
+    pub fn get_trusted_proxies(&self) -> &TrustedProxies {
+        &self.server_resources.server_config.trusted_proxies
+    }
+
     pub fn get_socket_addr(&self) -> SocketAddr {
         match self.server_resources.server_config.host_addr {
             IpAddr::V4(ipv4_addr) => SocketAddr::V4(SocketAddrV4::new(
@@ -88,36 +218,41 @@ pub struct ServerResources {
     app_name_version: String,
     pool: Pool,
     request_client: reqwest::Client,
-    mailer: AsyncSmtpTransport<Tokio1Executor>,
+    mailer: Arc<dyn MailTransport>,
+    check_mail_when_ready: bool,
+    rate_limiter: RateLimiter,
+    login_throttle: Arc<LoginThrottle>,
+    jwt: JWT,
+    two_factor_issuer: String,
+    secret_cipher: Arc<SecretCipher>,
+    relying_party: RelyingParty,
 }
 
 impl ServerResources {
-    pub fn new(server_start_time: DateTime<Utc>) -> anyhow::Result<Self> {
+    pub fn new(server_start_time: DateTime<Utc>, config: &AppConfig) -> anyhow::Result<Self> {
+        let pool = init_db_conn_pool(&config.database)?;
+
         Ok(ServerResources {
-            server_config: ServerConfig::new()?,
+            server_config: config.server.clone(),
             regexes: CompiledRegexes::compile()?,
             server_start_time,
             app_name_version: format!("{} {}", env!("CARGO_PKG_NAME"), env!("CARGO_PKG_VERSION")),
-            pool: init_db_conn_pool()?,
+            rate_limiter: init_rate_limiter(&config.rate_limit, &pool),
+            login_throttle: Arc::new(LoginThrottle::new(config.lockout.clone(), pool.clone())),
+            pool,
             request_client: reqwest::Client::new(),
-            mailer: init_mailer()?,
-        })
-    }
-}
-
-#[derive(Clone)]
-pub struct ServerConfig {
-    host_port: u16,
-    host_addr: IpAddr,
-}
-
-impl ServerConfig {
-    pub fn new() -> anyhow::Result<Self> {
-        Ok(ServerConfig {
-            host_port: get_env_var("HOST_PORT")?.parse::<u16>().unwrap_or(443u16),
-            host_addr: get_env_var("HOST_ADDR")?
-                .parse::<IpAddr>()
-                .unwrap_or(IpAddr::V4(Ipv4Addr::UNSPECIFIED)),
+            mailer: init_mailer(&config.mail)?,
+            check_mail_when_ready: config.mail.readiness_check,
+            jwt: load_jwt_keys(&config.jwt)?,
+            two_factor_issuer: config.two_factor.issuer.clone(),
+            secret_cipher: Arc::new(SecretCipher::from_base64(
+                config.two_factor.encryption_key.expose(),
+            )?),
+            relying_party: RelyingParty::new(
+                &config.webauthn.rp_id,
+                &config.webauthn.rp_name,
+                config.webauthn.origins.clone(),
+            )?,
         })
     }
 }
@@ -134,3 +269,6 @@ impl CompiledRegexes {
         })
     }
 }
+
+#[cfg(test)]
+pub const TEST_PUBLIC_BASE_URL: &str = "http://localhost:3000";
@@ -0,0 +1,62 @@
+use anyhow::anyhow;
+use qrcodegen::{QrCode, QrCodeEcc};
+
+/// pixels per QR module; big enough for a phone camera to read off a screen
+const MODULE_PIXELS: u32 = 8;
+
+/// the quiet zone the QR spec asks for around the symbol, in modules
+const BORDER_MODULES: u32 = 4;
+
+/// the text as a black-on-white QR code PNG
+pub fn render_qr_png(text: &str) -> anyhow::Result<Vec<u8>> {
+    let qr = QrCode::encode_text(text, QrCodeEcc::Medium)
+        .map_err(|e| anyhow!("Could not encode QR code: {}", e))?;
+    let side = (qr.size() as u32 + 2 * BORDER_MODULES) * MODULE_PIXELS;
+
+    let mut pixels = Vec::with_capacity((side * side) as usize);
+    for y in 0..side {
+        for x in 0..side {
+            let module_x = (x / MODULE_PIXELS) as i32 - BORDER_MODULES as i32;
+            let module_y = (y / MODULE_PIXELS) as i32 - BORDER_MODULES as i32;
+            // get_module is false outside the symbol, which paints the quiet zone white
+            pixels.push(match qr.get_module(module_x, module_y) {
+                true => 0x00,
+                false => 0xff,
+            });
+        }
+    }
+
+    let mut png = Vec::new();
+    let mut encoder = png::Encoder::new(&mut png, side, side);
+    encoder.set_color(png::ColorType::Grayscale);
+    encoder.set_depth(png::BitDepth::Eight);
+    let mut writer = encoder.write_header()?;
+    writer.write_image_data(&pixels)?;
+    writer.finish()?;
+
+    Ok(png)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_renders_a_png_with_a_quiet_zone() {
+        let png_bytes = render_qr_png("otpauth://totp/cyhdev.com:user?secret=GEZDGNBV").unwrap();
+
+        let mut reader = png::Decoder::new(std::io::Cursor::new(png_bytes))
+            .read_info()
+            .unwrap();
+        let mut pixels = vec![0; reader.output_buffer_size().unwrap()];
+        let info = reader.next_frame(&mut pixels).unwrap();
+        assert_eq!(info.width, info.height);
+        assert_eq!(info.width % MODULE_PIXELS, 0);
+
+        // white quiet zone, then the dark corner of the top-left finder pattern
+        let corner = (BORDER_MODULES * MODULE_PIXELS) as usize;
+        assert_eq!(pixels[0], 0xff);
+        assert_eq!(pixels[corner - 1], 0xff);
+        assert_eq!(pixels[corner * info.width as usize + corner], 0x00);
+    }
+}
@@ -0,0 +1,73 @@
+use anyhow::anyhow;
+use aws_lc_rs::rand;
+
+/// codes handed out per enrollment; confirming again replaces the whole set
+pub const RECOVERY_CODE_COUNT: usize = 10;
+
+/// characters per code; past the selector, 10 from a 32-letter alphabet is 50 secret bits
+const RECOVERY_CODE_LEN: usize = 14;
+
+/// leading characters stored in the clear, so a login verifies the one hash they pick out instead of every one
+const RECOVERY_CODE_SELECTOR_LEN: usize = 4;
+
+/// Crockford's base32 letters: no i, l, o or u to misread
+const RECOVERY_CODE_ALPHABET: &[u8; 32] = b"0123456789abcdefghjkmnpqrstvwxyz";
+
+/// a fresh code, shown to the user as `xxxxxxx-xxxxxxx`
+pub fn generate_recovery_code() -> anyhow::Result<String> {
+    let mut bytes = [0u8; RECOVERY_CODE_LEN];
+    rand::fill(&mut bytes).map_err(|_| anyhow!("Could not generate a recovery code"))?;
+
+    // 256 is a multiple of 32, so masking keeps every letter equally likely
+    let letters: String = bytes
+        .iter()
+        .map(|b| RECOVERY_CODE_ALPHABET[(b & 0x1f) as usize] as char)
+        .collect();
+    let (first, second) = letters.split_at(RECOVERY_CODE_LEN / 2);
+    Ok(format!("{}-{}", first, second))
+}
+
+/// the form codes are hashed in, so case, dashes and spaces don't matter when one is typed back
+pub fn normalize_recovery_code(code: &str) -> String {
+    code.chars()
+        .filter(|c| c.is_ascii_alphanumeric())
+        .map(|c| c.to_ascii_lowercase())
+        .collect()
+}
+
+/// the part of a normalized code its hash is looked up by
+pub fn recovery_code_selector(normalized: &str) -> &str {
+    normalized
+        .get(..RECOVERY_CODE_SELECTOR_LEN)
+        .unwrap_or(normalized)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_codes_are_formatted_and_normalize_back() {
+        let code = generate_recovery_code().unwrap();
+        assert_eq!(code.len(), RECOVERY_CODE_LEN + 1);
+        assert_eq!(code.as_bytes()[RECOVERY_CODE_LEN / 2], b'-');
+
+        let normalized = normalize_recovery_code(&code);
+        assert_eq!(normalized.len(), RECOVERY_CODE_LEN);
+        assert!(normalized
+            .bytes()
+            .all(|b| RECOVERY_CODE_ALPHABET.contains(&b)));
+        assert_eq!(
+            normalize_recovery_code(&format!(" {} ", code.to_uppercase())),
+            normalized
+        );
+
+        assert_eq!(
+            recovery_code_selector(&normalized),
+            &code[..RECOVERY_CODE_SELECTOR_LEN]
+        );
+        assert_eq!(recovery_code_selector("ab"), "ab");
+
+        assert_ne!(code, generate_recovery_code().unwrap());
+    }
+}
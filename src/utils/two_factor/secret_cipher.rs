@@ -0,0 +1,112 @@
+use anyhow::anyhow;
+use aws_lc_rs::{
+    aead::{Aad, LessSafeKey, Nonce, UnboundKey, AES_256_GCM, NONCE_LEN},
+    rand,
+};
+use data_encoding::BASE64;
+use uuid::Uuid;
+
+/// AES-256 takes exactly this many key bytes
+pub const SECRET_CIPHER_KEY_LEN: usize = 32;
+
+/// encrypts TOTP secrets at rest with the server key. Each ciphertext is bound to its user, so a row copied
+/// onto another account does not decrypt
+pub struct SecretCipher {
+    key: LessSafeKey,
+}
+
+impl SecretCipher {
+    /// the key as standard base64, e.g. from `openssl rand -base64 32`
+    pub fn from_base64(encoded: &str) -> anyhow::Result<Self> {
+        let key_bytes = BASE64
+            .decode(encoded.trim().as_bytes())
+            .map_err(|_| anyhow!("must be base64"))?;
+        if key_bytes.len() != SECRET_CIPHER_KEY_LEN {
+            return Err(anyhow!(
+                "must decode to {} bytes, not {}",
+                SECRET_CIPHER_KEY_LEN,
+                key_bytes.len()
+            ));
+        }
+
+        let key = UnboundKey::new(&AES_256_GCM, &key_bytes)
+            .map_err(|_| anyhow!("could not build an AES-256-GCM key"))?;
+        Ok(SecretCipher {
+            key: LessSafeKey::new(key),
+        })
+    }
+
+    /// a random nonce followed by the ciphertext and its tag
+    pub fn encrypt(&self, user_id: Uuid, plaintext: &[u8]) -> anyhow::Result<Vec<u8>> {
+        let mut nonce = [0u8; NONCE_LEN];
+        rand::fill(&mut nonce).map_err(|_| anyhow!("Could not generate a nonce"))?;
+
+        let mut in_out = plaintext.to_vec();
+        self.key
+            .seal_in_place_append_tag(
+                Nonce::assume_unique_for_key(nonce),
+                Aad::from(user_id.as_bytes()),
+                &mut in_out,
+            )
+            .map_err(|_| anyhow!("Could not encrypt secret"))?;
+
+        let mut sealed = nonce.to_vec();
+        sealed.extend_from_slice(&in_out);
+        Ok(sealed)
+    }
+
+    /// fails if the ciphertext was tampered with, belongs to another user or was sealed with another key
+    pub fn decrypt(&self, user_id: Uuid, sealed: &[u8]) -> anyhow::Result<Vec<u8>> {
+        if sealed.len() < NONCE_LEN {
+            return Err(anyhow!("Sealed secret is too short"));
+        }
+        let (nonce, ciphertext) = sealed.split_at(NONCE_LEN);
+        let nonce = Nonce::try_assume_unique_for_key(nonce)
+            .map_err(|_| anyhow!("Sealed secret has a malformed nonce"))?;
+
+        let mut in_out = ciphertext.to_vec();
+        let plaintext = self
+            .key
+            .open_in_place(nonce, Aad::from(user_id.as_bytes()), &mut in_out)
+            .map_err(|_| anyhow!("Could not decrypt secret"))?;
+        Ok(plaintext.to_vec())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const KEY: &str = "AAECAwQFBgcICQoLDA0ODxAREhMUFRYXGBkaGxwdHh8=";
+
+    #[test]
+    fn test_round_trip_is_bound_to_the_user() {
+        let cipher = SecretCipher::from_base64(KEY).unwrap();
+        let (alice, bob) = (Uuid::new_v4(), Uuid::new_v4());
+
+        let sealed = cipher.encrypt(alice, b"totp secret").unwrap();
+        assert_ne!(&sealed[NONCE_LEN..], b"totp secret");
+        assert_eq!(cipher.decrypt(alice, &sealed).unwrap(), b"totp secret");
+        assert!(cipher.decrypt(bob, &sealed).is_err());
+
+        // fresh nonce every time
+        assert_ne!(sealed, cipher.encrypt(alice, b"totp secret").unwrap());
+
+        let mut tampered = sealed.clone();
+        *tampered.last_mut().unwrap() ^= 1;
+        assert!(cipher.decrypt(alice, &tampered).is_err());
+    }
+
+    #[test]
+    fn test_key_must_be_32_base64_bytes() {
+        assert!(SecretCipher::from_base64("not base64!").is_err());
+        assert!(SecretCipher::from_base64("AAECAwQFBgcICQoLDA0ODw==").is_err());
+
+        let other = SecretCipher::from_base64(&BASE64.encode(&[7u8; 32])).unwrap();
+        let sealed = SecretCipher::from_base64(KEY)
+            .unwrap()
+            .encrypt(Uuid::nil(), b"secret")
+            .unwrap();
+        assert!(other.decrypt(Uuid::nil(), &sealed).is_err());
+    }
+}
@@ -0,0 +1,148 @@
+use anyhow::{anyhow, Context};
+use ciborium::value::Value;
+
+// flag bits of authenticator data
+const FLAG_USER_PRESENT: u8 = 0x01;
+const FLAG_USER_VERIFIED: u8 = 0x04;
+const FLAG_BACKUP_ELIGIBLE: u8 = 0x08;
+const FLAG_BACKED_UP: u8 = 0x10;
+const FLAG_ATTESTED_CREDENTIAL_DATA: u8 = 0x40;
+const FLAG_EXTENSION_DATA: u8 = 0x80;
+
+/// rpIdHash, flags and the sign counter
+const FIXED_LEN: usize = 32 + 1 + 4;
+
+/// the credential an authenticator created, present in authenticator data during registration
+#[derive(Debug)]
+pub struct AttestedCredential {
+    pub aaguid: [u8; 16],
+    pub credential_id: Vec<u8>,
+    /// COSE_Key, kept as the authenticator encoded it
+    pub public_key: Vec<u8>,
+}
+
+/// the authenticator's signed statement about a ceremony (WebAuthn §6.1)
+#[derive(Debug)]
+pub struct AuthenticatorData {
+    pub rp_id_hash: [u8; 32],
+    pub flags: u8,
+    pub sign_count: u32,
+    pub attested_credential: Option<AttestedCredential>,
+}
+
+impl AuthenticatorData {
+    pub fn parse(bytes: &[u8]) -> anyhow::Result<Self> {
+        if bytes.len() < FIXED_LEN {
+            return Err(anyhow!("authenticator data is {} bytes", bytes.len()));
+        }
+
+        let mut rp_id_hash = [0u8; 32];
+        rp_id_hash.copy_from_slice(&bytes[..32]);
+        let flags = bytes[32];
+        let sign_count = u32::from_be_bytes([bytes[33], bytes[34], bytes[35], bytes[36]]);
+        let mut rest = &bytes[FIXED_LEN..];
+
+        let attested_credential = match flags & FLAG_ATTESTED_CREDENTIAL_DATA != 0 {
+            true => Some(parse_attested_credential(&mut rest)?),
+            false => None,
+        };
+
+        // extension outputs are not used, but anything else left over means the data is malformed
+        if flags & FLAG_EXTENSION_DATA != 0 {
+            let _: Value =
+                ciborium::de::from_reader(&mut rest).context("extension data is not CBOR")?;
+        }
+        if !rest.is_empty() {
+            return Err(anyhow!(
+                "{} trailing bytes after authenticator data",
+                rest.len()
+            ));
+        }
+
+        Ok(AuthenticatorData {
+            rp_id_hash,
+            flags,
+            sign_count,
+            attested_credential,
+        })
+    }
+
+    pub fn user_present(&self) -> bool {
+        self.flags & FLAG_USER_PRESENT != 0
+    }
+
+    pub fn user_verified(&self) -> bool {
+        self.flags & FLAG_USER_VERIFIED != 0
+    }
+
+    /// a synced (multi-device) credential, e.g. one kept in a password manager
+    pub fn backup_eligible(&self) -> bool {
+        self.flags & FLAG_BACKUP_ELIGIBLE != 0
+    }
+
+    pub fn backed_up(&self) -> bool {
+        self.flags & FLAG_BACKED_UP != 0
+    }
+}
+
+/// AAGUID, a length-prefixed credential ID, then a COSE key whose length is only known by decoding it
+fn parse_attested_credential(rest: &mut &[u8]) -> anyhow::Result<AttestedCredential> {
+    if rest.len() < 18 {
+        return Err(anyhow!("attested credential data is truncated"));
+    }
+
+    let mut aaguid = [0u8; 16];
+    aaguid.copy_from_slice(&rest[..16]);
+    let id_len = u16::from_be_bytes([rest[16], rest[17]]) as usize;
+    *rest = &rest[18..];
+
+    if rest.len() < id_len {
+        return Err(anyhow!("credential ID is truncated"));
+    }
+    let credential_id = rest[..id_len].to_vec();
+    *rest = &rest[id_len..];
+
+    let key_start = *rest;
+    let _: Value =
+        ciborium::de::from_reader(&mut *rest).context("credential public key is not CBOR")?;
+    let public_key = key_start[..key_start.len() - rest.len()].to_vec();
+
+    Ok(AttestedCredential {
+        aaguid,
+        credential_id,
+        public_key,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_attested_credential_is_split_from_extensions() {
+        let public_key = vec![0xa1, 0x01, 0x02]; // {1: 2}
+        let mut bytes = vec![7u8; 32];
+        bytes.push(FLAG_USER_PRESENT | FLAG_ATTESTED_CREDENTIAL_DATA | FLAG_EXTENSION_DATA);
+        bytes.extend_from_slice(&42u32.to_be_bytes());
+        bytes.extend_from_slice(&[9u8; 16]);
+        bytes.extend_from_slice(&3u16.to_be_bytes());
+        bytes.extend_from_slice(b"abc");
+        bytes.extend_from_slice(&public_key);
+        bytes.extend_from_slice(&[0xa1, 0x61, 0x78, 0xf5]); // {"x": true}
+
+        let parsed = AuthenticatorData::parse(&bytes).unwrap();
+        assert!(parsed.user_present());
+        assert!(!parsed.user_verified());
+        assert_eq!(parsed.sign_count, 42);
+
+        let credential = parsed.attested_credential.unwrap();
+        assert_eq!(credential.aaguid, [9u8; 16]);
+        assert_eq!(credential.credential_id, b"abc");
+        assert_eq!(credential.public_key, public_key);
+
+        // without the extension flag the same bytes are trailing garbage
+        bytes[32] &= !FLAG_EXTENSION_DATA;
+        assert!(AuthenticatorData::parse(&bytes).is_err());
+        assert!(AuthenticatorData::parse(&bytes[..36]).is_err());
+    }
+}
@@ -0,0 +1,186 @@
+use anyhow::{anyhow, Context};
+use aws_lc_rs::signature::{
+    RsaPublicKeyComponents, UnparsedPublicKey, ECDSA_P256_SHA256_ASN1, ED25519,
+    RSA_PKCS1_2048_8192_SHA256,
+};
+use ciborium::value::Value;
+
+/// COSE algorithm identifiers (RFC 9053) offered to authenticators, most preferred first
+pub const COSE_ALG_ES256: i64 = -7;
+pub const COSE_ALG_EDDSA: i64 = -8;
+pub const COSE_ALG_RS256: i64 = -257;
+pub const SUPPORTED_ALGORITHMS: [i64; 3] = [COSE_ALG_ES256, COSE_ALG_EDDSA, COSE_ALG_RS256];
+
+// COSE_Key map labels and values
+const LABEL_KTY: i128 = 1;
+const LABEL_ALG: i128 = 3;
+const LABEL_CRV: i128 = -1;
+const LABEL_X: i128 = -2;
+const LABEL_Y: i128 = -3;
+const LABEL_RSA_N: i128 = -1;
+const LABEL_RSA_E: i128 = -2;
+const KTY_OKP: i128 = 1;
+const KTY_EC2: i128 = 2;
+const KTY_RSA: i128 = 3;
+const CRV_P256: i128 = 1;
+const CRV_ED25519: i128 = 6;
+
+/// a credential public key as the authenticator sent it; stored in its CBOR form and parsed per ceremony
+#[derive(Debug)]
+pub enum CoseKey {
+    Es256 { x: Vec<u8>, y: Vec<u8> },
+    EdDsa { x: Vec<u8> },
+    Rs256 { n: Vec<u8>, e: Vec<u8> },
+}
+
+impl CoseKey {
+    /// only the key types and curves behind SUPPORTED_ALGORITHMS are accepted
+    pub fn from_cbor(bytes: &[u8]) -> anyhow::Result<Self> {
+        let value: Value =
+            ciborium::de::from_reader(bytes).context("credential public key is not CBOR")?;
+        let map = value
+            .as_map()
+            .ok_or_else(|| anyhow!("credential public key is not a CBOR map"))?;
+
+        let kty = integer_label(map, LABEL_KTY)?;
+        let alg = integer_label(map, LABEL_ALG)?;
+
+        match (kty, alg as i64) {
+            (KTY_EC2, COSE_ALG_ES256) => {
+                if integer_label(map, LABEL_CRV)? != CRV_P256 {
+                    return Err(anyhow!("ES256 key is not on P-256"));
+                }
+                let x = bytes_label(map, LABEL_X)?;
+                let y = bytes_label(map, LABEL_Y)?;
+                if x.len() != 32 || y.len() != 32 {
+                    return Err(anyhow!("P-256 coordinates must be 32 bytes"));
+                }
+                Ok(CoseKey::Es256 { x, y })
+            }
+            (KTY_OKP, COSE_ALG_EDDSA) => {
+                if integer_label(map, LABEL_CRV)? != CRV_ED25519 {
+                    return Err(anyhow!("EdDSA key is not on Ed25519"));
+                }
+                let x = bytes_label(map, LABEL_X)?;
+                if x.len() != 32 {
+                    return Err(anyhow!("Ed25519 public keys must be 32 bytes"));
+                }
+                Ok(CoseKey::EdDsa { x })
+            }
+            (KTY_RSA, COSE_ALG_RS256) => Ok(CoseKey::Rs256 {
+                n: bytes_label(map, LABEL_RSA_N)?,
+                e: bytes_label(map, LABEL_RSA_E)?,
+            }),
+            (kty, alg) => Err(anyhow!(
+                "unsupported credential key type {} with algorithm {}",
+                kty,
+                alg
+            )),
+        }
+    }
+
+    pub fn algorithm(&self) -> i64 {
+        match self {
+            CoseKey::Es256 { .. } => COSE_ALG_ES256,
+            CoseKey::EdDsa { .. } => COSE_ALG_EDDSA,
+            CoseKey::Rs256 { .. } => COSE_ALG_RS256,
+        }
+    }
+
+    /// checks a signature in the format WebAuthn uses for the algorithm: ASN.1 DER for ECDSA, raw otherwise
+    pub fn verify(&self, message: &[u8], signature: &[u8]) -> anyhow::Result<()> {
+        let verified = match self {
+            CoseKey::Es256 { x, y } => {
+                let mut point = Vec::with_capacity(65);
+                point.push(0x04);
+                point.extend_from_slice(x);
+                point.extend_from_slice(y);
+                UnparsedPublicKey::new(&ECDSA_P256_SHA256_ASN1, point).verify(message, signature)
+            }
+            CoseKey::EdDsa { x } => UnparsedPublicKey::new(&ED25519, x).verify(message, signature),
+            CoseKey::Rs256 { n, e } => RsaPublicKeyComponents { n, e }.verify(
+                &RSA_PKCS1_2048_8192_SHA256,
+                message,
+                signature,
+            ),
+        };
+        verified.map_err(|_| anyhow!("signature does not verify"))
+    }
+}
+
+fn label(map: &[(Value, Value)], label: i128) -> anyhow::Result<&Value> {
+    map.iter()
+        .find(|(key, _)| key.as_integer().map(i128::from) == Some(label))
+        .map(|(_, value)| value)
+        .ok_or_else(|| anyhow!("credential public key has no label {}", label))
+}
+
+fn integer_label(map: &[(Value, Value)], key: i128) -> anyhow::Result<i128> {
+    label(map, key)?
+        .as_integer()
+        .map(i128::from)
+        .ok_or_else(|| anyhow!("credential public key label {} is not an integer", key))
+}
+
+fn bytes_label(map: &[(Value, Value)], key: i128) -> anyhow::Result<Vec<u8>> {
+    label(map, key)?
+        .as_bytes()
+        .cloned()
+        .ok_or_else(|| anyhow!("credential public key label {} is not a byte string", key))
+}
+
+#[cfg(test)]
+mod tests {
+    use aws_lc_rs::{
+        rand::SystemRandom,
+        signature::{EcdsaKeyPair, Ed25519KeyPair, KeyPair, ECDSA_P256_SHA256_ASN1_SIGNING},
+    };
+
+    use super::*;
+    use crate::tests::software_authenticator::cose_es256_key;
+
+    fn to_cbor(value: &Value) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        ciborium::ser::into_writer(value, &mut bytes).unwrap();
+        bytes
+    }
+
+    #[test]
+    fn test_es256_and_eddsa_keys_verify_their_signatures() {
+        let p256 = EcdsaKeyPair::generate(&ECDSA_P256_SHA256_ASN1_SIGNING).unwrap();
+        let key = CoseKey::from_cbor(&cose_es256_key(p256.public_key().as_ref())).unwrap();
+        let signature = p256.sign(&SystemRandom::new(), b"signed data").unwrap();
+        assert_eq!(key.algorithm(), COSE_ALG_ES256);
+        assert!(key.verify(b"signed data", signature.as_ref()).is_ok());
+        assert!(key.verify(b"other data", signature.as_ref()).is_err());
+
+        let ed25519 = Ed25519KeyPair::generate().unwrap();
+        let cose = to_cbor(&Value::Map(vec![
+            (Value::from(1), Value::from(1)),
+            (Value::from(3), Value::from(-8)),
+            (Value::from(-1), Value::from(6)),
+            (
+                Value::from(-2),
+                Value::Bytes(ed25519.public_key().as_ref().to_vec()),
+            ),
+        ]));
+        let key = CoseKey::from_cbor(&cose).unwrap();
+        let signature = ed25519.sign(b"signed data");
+        assert!(key.verify(b"signed data", signature.as_ref()).is_ok());
+        assert!(key.verify(b"other data", signature.as_ref()).is_err());
+    }
+
+    #[test]
+    fn test_unsupported_keys_are_refused() {
+        // ES384 on P-384
+        let cose = to_cbor(&Value::Map(vec![
+            (Value::from(1), Value::from(2)),
+            (Value::from(3), Value::from(-35)),
+            (Value::from(-1), Value::from(2)),
+            (Value::from(-2), Value::Bytes(vec![0; 48])),
+            (Value::from(-3), Value::Bytes(vec![0; 48])),
+        ]));
+        assert!(CoseKey::from_cbor(&cose).is_err());
+        assert!(CoseKey::from_cbor(b"not cbor").is_err());
+    }
+}
@@ -0,0 +1,607 @@
+use anyhow::{anyhow, Context};
+use aws_lc_rs::{digest, rand};
+use ciborium::value::Value;
+use data_encoding::BASE64URL_NOPAD;
+use serde_derive::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use super::{
+    authenticator_data::AuthenticatorData,
+    cose_key::{CoseKey, SUPPORTED_ALGORITHMS},
+};
+
+/// how long the browser shows its prompt, and how long the challenge behind it stays redeemable
+pub const CEREMONY_TIMEOUT_MINUTES: i64 = 5;
+
+/// WebAuthn asks for at least 16 random bytes
+pub const CHALLENGE_LEN: usize = 32;
+
+// request
+
+/// PublicKeyCredential.toJSON() of a credential from navigator.credentials.create()
+#[derive(Deserialize, Debug)]
+pub struct RegistrationCredential {
+    id: String,
+    response: AttestationResponse,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct AttestationResponse {
+    #[serde(rename = "clientDataJSON")]
+    client_data_json: String,
+    #[serde(rename = "attestationObject")]
+    attestation_object: String,
+}
+
+/// PublicKeyCredential.toJSON() of a credential from navigator.credentials.get()
+#[derive(Deserialize, Debug)]
+pub struct AuthenticationCredential {
+    id: String,
+    response: AssertionResponse,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct AssertionResponse {
+    #[serde(rename = "clientDataJSON")]
+    client_data_json: String,
+    #[serde(rename = "authenticatorData")]
+    authenticator_data: String,
+    signature: String,
+    #[serde(rename = "userHandle")]
+    user_handle: Option<String>,
+}
+
+impl AuthenticationCredential {
+    pub fn credential_id(&self) -> anyhow::Result<Vec<u8>> {
+        decode_base64url(&self.id).context("credential id")
+    }
+
+    /// the user.id given at registration, which discoverable credentials send back
+    pub fn user_handle(&self) -> anyhow::Result<Option<Vec<u8>>> {
+        match self.response.user_handle.as_deref() {
+            Some(handle) if !handle.is_empty() => {
+                Ok(Some(decode_base64url(handle).context("userHandle")?))
+            }
+            _ => Ok(None),
+        }
+    }
+}
+
+// response; field names follow the WebAuthn JSON shapes so the browser can take them as they are
+
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct CreationOptions {
+    rp: RelyingPartyEntity,
+    user: UserEntity,
+    challenge: String,
+    pub_key_cred_params: Vec<CredentialParameters>,
+    timeout: u64,
+    exclude_credentials: Vec<CredentialDescriptor>,
+    authenticator_selection: AuthenticatorSelection,
+    attestation: &'static str,
+}
+
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct RequestOptions {
+    challenge: String,
+    timeout: u64,
+    rp_id: String,
+    allow_credentials: Vec<CredentialDescriptor>,
+    user_verification: &'static str,
+}
+
+#[derive(Serialize, Debug)]
+pub struct RelyingPartyEntity {
+    id: String,
+    name: String,
+}
+
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct UserEntity {
+    id: String,
+    name: String,
+    display_name: String,
+}
+
+#[derive(Serialize, Debug)]
+pub struct CredentialParameters {
+    #[serde(rename = "type")]
+    credential_type: &'static str,
+    alg: i64,
+}
+
+#[derive(Serialize, Debug)]
+pub struct CredentialDescriptor {
+    #[serde(rename = "type")]
+    credential_type: &'static str,
+    id: String,
+}
+
+impl CredentialDescriptor {
+    pub fn new(credential_id: &[u8]) -> Self {
+        CredentialDescriptor {
+            credential_type: "public-key",
+            id: BASE64URL_NOPAD.encode(credential_id),
+        }
+    }
+}
+
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct AuthenticatorSelection {
+    resident_key: &'static str,
+    require_resident_key: bool,
+    user_verification: &'static str,
+}
+
+/// whether the authenticator has to check who is holding it (PIN, biometrics) or only that someone is
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum UserVerification {
+    /// passkeys as a primary login stand in for the password, so they have to verify the user
+    Required,
+    /// as a second factor the password already identified the user
+    Discouraged,
+}
+
+impl UserVerification {
+    fn as_str(self) -> &'static str {
+        match self {
+            UserVerification::Required => "required",
+            UserVerification::Discouraged => "discouraged",
+        }
+    }
+}
+
+/// a credential that passed registration, ready to be stored
+#[derive(Debug)]
+pub struct RegisteredCredential {
+    pub credential_id: Vec<u8>,
+    /// COSE_Key
+    pub public_key: Vec<u8>,
+    pub algorithm: i64,
+    pub sign_count: u32,
+    pub backup_eligible: bool,
+}
+
+#[derive(Deserialize)]
+struct CollectedClientData {
+    #[serde(rename = "type")]
+    ceremony_type: String,
+    challenge: String,
+    origin: String,
+    #[serde(rename = "crossOrigin", default)]
+    cross_origin: bool,
+}
+
+/// this server as a WebAuthn relying party: builds ceremony options and verifies what authenticators send back
+#[derive(Clone, Debug)]
+pub struct RelyingParty {
+    id: String,
+    name: String,
+    origins: Vec<String>,
+    id_hash: [u8; 32],
+}
+
+impl RelyingParty {
+    /// every origin has to be the RP ID or a subdomain of it, over https unless it is localhost
+    pub fn new(id: &str, name: &str, origins: Vec<String>) -> anyhow::Result<Self> {
+        if id.is_empty() || id.contains(['/', ':']) {
+            return Err(anyhow!("RP ID must be a bare domain, not {:?}", id));
+        }
+        if origins.is_empty() {
+            return Err(anyhow!("at least one origin is required"));
+        }
+        for origin in &origins {
+            check_origin(id, origin)?;
+        }
+
+        let mut id_hash = [0u8; 32];
+        id_hash.copy_from_slice(digest::digest(&digest::SHA256, id.as_bytes()).as_ref());
+
+        Ok(RelyingParty {
+            id: id.to_owned(),
+            name: name.to_owned(),
+            origins,
+            id_hash,
+        })
+    }
+
+    pub fn generate_challenge() -> anyhow::Result<Vec<u8>> {
+        let mut challenge = vec![0u8; CHALLENGE_LEN];
+        rand::fill(&mut challenge).map_err(|_| anyhow!("Could not generate a challenge"))?;
+        Ok(challenge)
+    }
+
+    /// options for navigator.credentials.create(). Discoverable credentials are preferred so the passkey
+    /// can later sign in without a username; existing ones are excluded so an authenticator isn't added twice
+    pub fn creation_options(
+        &self,
+        challenge: &[u8],
+        user_id: Uuid,
+        user_name: &str,
+        user_display_name: &str,
+        exclude_credentials: Vec<CredentialDescriptor>,
+    ) -> CreationOptions {
+        CreationOptions {
+            rp: RelyingPartyEntity {
+                id: self.id.clone(),
+                name: self.name.clone(),
+            },
+            user: UserEntity {
+                id: BASE64URL_NOPAD.encode(user_id.as_bytes()),
+                name: user_name.to_owned(),
+                display_name: user_display_name.to_owned(),
+            },
+            challenge: BASE64URL_NOPAD.encode(challenge),
+            pub_key_cred_params: SUPPORTED_ALGORITHMS
+                .iter()
+                .map(|alg| CredentialParameters {
+                    credential_type: "public-key",
+                    alg: *alg,
+                })
+                .collect(),
+            timeout: timeout_ms(),
+            exclude_credentials,
+            authenticator_selection: AuthenticatorSelection {
+                resident_key: "preferred",
+                require_resident_key: false,
+                user_verification: "preferred",
+            },
+            attestation: "none",
+        }
+    }
+
+    /// options for navigator.credentials.get(); an empty allow list lets the user pick any discoverable passkey
+    pub fn request_options(
+        &self,
+        challenge: &[u8],
+        allow_credentials: Vec<CredentialDescriptor>,
+        user_verification: UserVerification,
+    ) -> RequestOptions {
+        RequestOptions {
+            challenge: BASE64URL_NOPAD.encode(challenge),
+            timeout: timeout_ms(),
+            rp_id: self.id.clone(),
+            allow_credentials,
+            user_verification: user_verification.as_str(),
+        }
+    }
+
+    /// checks a new credential against the challenge it answers (WebAuthn §7.1). Attestation is not
+    /// checked against vendor roots: "none" and packed self-attestation are accepted, anything with a
+    /// certificate chain is refused
+    pub fn verify_registration(
+        &self,
+        challenge: &[u8],
+        credential: &RegistrationCredential,
+    ) -> anyhow::Result<RegisteredCredential> {
+        let client_data_json =
+            decode_base64url(&credential.response.client_data_json).context("clientDataJSON")?;
+        self.check_client_data(&client_data_json, "webauthn.create", challenge)?;
+
+        let attestation_object = decode_base64url(&credential.response.attestation_object)
+            .context("attestationObject")?;
+        let attestation: Value = ciborium::de::from_reader(attestation_object.as_slice())
+            .context("attestationObject is not CBOR")?;
+        let attestation = attestation
+            .as_map()
+            .ok_or_else(|| anyhow!("attestationObject is not a CBOR map"))?;
+
+        let fmt = text_field(attestation, "fmt")?
+            .as_text()
+            .ok_or_else(|| anyhow!("attestation fmt is not text"))?;
+        let statement = text_field(attestation, "attStmt")?
+            .as_map()
+            .ok_or_else(|| anyhow!("attStmt is not a map"))?;
+        let auth_data_bytes = text_field(attestation, "authData")?
+            .as_bytes()
+            .ok_or_else(|| anyhow!("authData is not a byte string"))?;
+
+        let auth_data = AuthenticatorData::parse(auth_data_bytes)?;
+        self.check_authenticator_data(&auth_data, UserVerification::Discouraged)?;
+
+        let attested = auth_data
+            .attested_credential
+            .as_ref()
+            .ok_or_else(|| anyhow!("no attested credential data"))?;
+        if decode_base64url(&credential.id).context("credential id")? != attested.credential_id {
+            return Err(anyhow!("credential id does not match authenticator data"));
+        }
+
+        let public_key = CoseKey::from_cbor(&attested.public_key)?;
+
+        match fmt {
+            "none" if statement.is_empty() => (),
+            "none" => return Err(anyhow!("\"none\" attestation has a statement")),
+            "packed" => {
+                if text_field(statement, "x5c").is_ok() {
+                    return Err(anyhow!("attestation certificates are not accepted"));
+                }
+                let alg = text_field(statement, "alg")?
+                    .as_integer()
+                    .map(i128::from)
+                    .ok_or_else(|| anyhow!("packed alg is not an integer"))?;
+                if alg != public_key.algorithm() as i128 {
+                    return Err(anyhow!("self attestation uses another algorithm"));
+                }
+                let signature = text_field(statement, "sig")?
+                    .as_bytes()
+                    .ok_or_else(|| anyhow!("packed sig is not a byte string"))?;
+                public_key
+                    .verify(&signed_data(auth_data_bytes, &client_data_json), signature)
+                    .context("self attestation")?;
+            }
+            other => return Err(anyhow!("unsupported attestation format {:?}", other)),
+        }
+
+        Ok(RegisteredCredential {
+            credential_id: attested.credential_id.clone(),
+            public_key: attested.public_key.clone(),
+            algorithm: public_key.algorithm(),
+            sign_count: auth_data.sign_count,
+            backup_eligible: auth_data.backup_eligible(),
+        })
+    }
+
+    /// checks an assertion by a stored credential against the challenge it answers (WebAuthn §7.2) and
+    /// returns the authenticator's sign counter, which the caller still has to compare with the stored one
+    pub fn verify_assertion(
+        &self,
+        challenge: &[u8],
+        public_key: &[u8],
+        credential: &AuthenticationCredential,
+        user_verification: UserVerification,
+    ) -> anyhow::Result<u32> {
+        let client_data_json =
+            decode_base64url(&credential.response.client_data_json).context("clientDataJSON")?;
+        self.check_client_data(&client_data_json, "webauthn.get", challenge)?;
+
+        let auth_data_bytes = decode_base64url(&credential.response.authenticator_data)
+            .context("authenticatorData")?;
+        let auth_data = AuthenticatorData::parse(&auth_data_bytes)?;
+        self.check_authenticator_data(&auth_data, user_verification)?;
+
+        let signature = decode_base64url(&credential.response.signature).context("signature")?;
+        CoseKey::from_cbor(public_key)?.verify(
+            &signed_data(&auth_data_bytes, &client_data_json),
+            &signature,
+        )?;
+
+        Ok(auth_data.sign_count)
+    }
+
+    fn check_client_data(
+        &self,
+        client_data_json: &[u8],
+        ceremony_type: &str,
+        challenge: &[u8],
+    ) -> anyhow::Result<()> {
+        let client_data: CollectedClientData =
+            serde_json::from_slice(client_data_json).context("clientDataJSON is not JSON")?;
+
+        if client_data.ceremony_type != ceremony_type {
+            return Err(anyhow!(
+                "expected a {} ceremony, got {:?}",
+                ceremony_type,
+                client_data.ceremony_type
+            ));
+        }
+        if decode_base64url(&client_data.challenge).ok().as_deref() != Some(challenge) {
+            return Err(anyhow!("challenge does not match"));
+        }
+        if !self.origins.contains(&client_data.origin) {
+            return Err(anyhow!("unexpected origin {:?}", client_data.origin));
+        }
+        if client_data.cross_origin {
+            return Err(anyhow!("ceremony ran in a cross-origin iframe"));
+        }
+
+        Ok(())
+    }
+
+    fn check_authenticator_data(
+        &self,
+        auth_data: &AuthenticatorData,
+        user_verification: UserVerification,
+    ) -> anyhow::Result<()> {
+        if auth_data.rp_id_hash != self.id_hash {
+            return Err(anyhow!("credential is scoped to another RP ID"));
+        }
+        if !auth_data.user_present() {
+            return Err(anyhow!("user was not present"));
+        }
+        if user_verification == UserVerification::Required && !auth_data.user_verified() {
+            return Err(anyhow!("user was not verified"));
+        }
+        Ok(())
+    }
+}
+
+fn timeout_ms() -> u64 {
+    (CEREMONY_TIMEOUT_MINUTES * 60 * 1000) as u64
+}
+
+/// what assertion and packed attestation signatures cover: authenticator data, then the client data's hash
+fn signed_data(auth_data: &[u8], client_data_json: &[u8]) -> Vec<u8> {
+    let mut data = auth_data.to_vec();
+    data.extend_from_slice(digest::digest(&digest::SHA256, client_data_json).as_ref());
+    data
+}
+
+/// browsers send unpadded base64url, but some client libraries pad it
+fn decode_base64url(encoded: &str) -> anyhow::Result<Vec<u8>> {
+    BASE64URL_NOPAD
+        .decode(encoded.trim_end_matches('=').as_bytes())
+        .map_err(|_| anyhow!("not base64url"))
+}
+
+fn text_field<'a>(map: &'a [(Value, Value)], key: &str) -> anyhow::Result<&'a Value> {
+    map.iter()
+        .find(|(name, _)| name.as_text() == Some(key))
+        .map(|(_, value)| value)
+        .ok_or_else(|| anyhow!("missing {}", key))
+}
+
+fn check_origin(rp_id: &str, origin: &str) -> anyhow::Result<()> {
+    let (scheme, authority) = origin
+        .split_once("://")
+        .ok_or_else(|| anyhow!("{} is not an origin", origin))?;
+    if authority.is_empty() || authority.contains('/') {
+        return Err(anyhow!("{} is not an origin; leave out any path", origin));
+    }
+    let host = authority
+        .rsplit_once(':')
+        .map_or(authority, |(host, _port)| host);
+
+    match scheme {
+        "https" => (),
+        "http" if host == "localhost" => (),
+        _ => return Err(anyhow!("{} must use https", origin)),
+    }
+    if host != rp_id && !host.ends_with(&format!(".{}", rp_id)) {
+        return Err(anyhow!("{} is not {} or a subdomain of it", origin, rp_id));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::software_authenticator::SoftwareAuthenticator;
+
+    const ORIGIN: &str = "https://www.cyhdev.com";
+
+    fn relying_party() -> RelyingParty {
+        RelyingParty::new("cyhdev.com", "cyhdev.com", vec![ORIGIN.to_owned()]).unwrap()
+    }
+
+    fn register(
+        relying_party: &RelyingParty,
+        authenticator: &mut SoftwareAuthenticator,
+    ) -> RegisteredCredential {
+        let challenge = RelyingParty::generate_challenge().unwrap();
+        let options = relying_party.creation_options(
+            &challenge,
+            Uuid::new_v4(),
+            "user@example.com",
+            "user",
+            Vec::new(),
+        );
+        let credential = authenticator.register(&serde_json::to_value(&options).unwrap());
+        relying_party
+            .verify_registration(&challenge, &serde_json::from_value(credential).unwrap())
+            .unwrap()
+    }
+
+    #[test]
+    fn test_origins_must_be_on_the_rp_id() {
+        for origin in [
+            "https://cyhdev.com",
+            "https://www.cyhdev.com:8443",
+            "http://localhost:3000",
+        ] {
+            let rp_id = if origin.contains("localhost") {
+                "localhost"
+            } else {
+                "cyhdev.com"
+            };
+            assert!(check_origin(rp_id, origin).is_ok(), "{}", origin);
+        }
+
+        for origin in [
+            "http://www.cyhdev.com",
+            "https://evilcyhdev.com",
+            "https://www.cyhdev.com/login",
+            "www.cyhdev.com",
+        ] {
+            assert!(check_origin("cyhdev.com", origin).is_err(), "{}", origin);
+        }
+    }
+
+    #[test]
+    fn test_registration_and_assertion_round_trip() {
+        let relying_party = relying_party();
+        let mut authenticator = SoftwareAuthenticator::new("cyhdev.com", ORIGIN);
+        let registered = register(&relying_party, &mut authenticator);
+        assert_eq!(registered.credential_id, authenticator.credential_id());
+        assert_eq!(registered.algorithm, -7);
+
+        let challenge = RelyingParty::generate_challenge().unwrap();
+        let options = relying_party.request_options(
+            &challenge,
+            vec![CredentialDescriptor::new(&registered.credential_id)],
+            UserVerification::Required,
+        );
+        let assertion = authenticator.authenticate(&serde_json::to_value(&options).unwrap());
+        let assertion: AuthenticationCredential = serde_json::from_value(assertion).unwrap();
+        let sign_count = relying_party
+            .verify_assertion(
+                &challenge,
+                &registered.public_key,
+                &assertion,
+                UserVerification::Required,
+            )
+            .unwrap();
+        assert!(sign_count > registered.sign_count);
+
+        // the same assertion does not answer another challenge
+        let other = RelyingParty::generate_challenge().unwrap();
+        assert!(relying_party
+            .verify_assertion(
+                &other,
+                &registered.public_key,
+                &assertion,
+                UserVerification::Required
+            )
+            .is_err());
+    }
+
+    #[test]
+    fn test_assertions_from_elsewhere_are_refused() {
+        let relying_party = relying_party();
+        let mut authenticator = SoftwareAuthenticator::new("cyhdev.com", ORIGIN);
+        let registered = register(&relying_party, &mut authenticator);
+
+        let challenge = RelyingParty::generate_challenge().unwrap();
+        let options = serde_json::to_value(relying_party.request_options(
+            &challenge,
+            Vec::new(),
+            UserVerification::Required,
+        ))
+        .unwrap();
+        let verify = |assertion: serde_json::Value, user_verification| {
+            relying_party.verify_assertion(
+                &challenge,
+                &registered.public_key,
+                &serde_json::from_value(assertion).unwrap(),
+                user_verification,
+            )
+        };
+
+        // a phishing page on another origin
+        authenticator.origin = "https://cyhdev.com.evil.example".to_owned();
+        assert!(verify(
+            authenticator.authenticate(&options),
+            UserVerification::Required
+        )
+        .is_err());
+        authenticator.origin = ORIGIN.to_owned();
+
+        // only user presence, which is enough for a second factor but not a primary login
+        authenticator.user_verified = false;
+        let assertion = authenticator.authenticate(&options);
+        assert!(verify(assertion.clone(), UserVerification::Required).is_err());
+        assert!(verify(assertion, UserVerification::Discouraged).is_ok());
+
+        // a signature by some other key
+        let mut impostor = SoftwareAuthenticator::new("cyhdev.com", ORIGIN);
+        assert!(verify(
+            impostor.authenticate(&options),
+            UserVerification::Discouraged
+        )
+        .is_err());
+    }
+}
@@ -0,0 +1,229 @@
+use std::{sync::Arc, time::Duration};
+
+use chrono::{DateTime, Utc};
+use deadpool_postgres::Object;
+use tokio_util::sync::CancellationToken;
+use tracing::{error, info, info_span, warn, Instrument};
+
+use crate::{
+    models::email_outbox::{EmailOutbox, EMAIL_OUTBOX_MAX_ATTEMPTS},
+    utils::{
+        mail::mail_transport::OutgoingEmail,
+        server_init::{
+            server_init_funcs::initialize_metrics::{EMAILS_SENT_TOTAL, EMAIL_SEND_FAILURES_TOTAL},
+            server_state_def::ServerState,
+        },
+    },
+};
+
+pub const EMAIL_OUTBOX_POLL_INTERVAL: Duration = Duration::from_secs(5);
+/// entries sent per drain; each is leased on its own just before it is sent
+pub const EMAIL_OUTBOX_BATCH_SIZE: i64 = 20;
+/// how long an entry being sent is kept from other instances; several times SMTP_SEND_TIMEOUT, which bounds
+/// one send
+pub const EMAIL_OUTBOX_LEASE: chrono::Duration = chrono::Duration::minutes(5);
+
+const BACKOFF_BASE_SECONDS: i64 = 30;
+const BACKOFF_MAX_SECONDS: i64 = 60 * 60;
+
+/// delay before the next attempt after `attempts` failed ones: 30s, 1m, 2m, ... capped at an hour
+pub fn backoff_delay(attempts: i32) -> chrono::Duration {
+    let exponent = attempts.saturating_sub(1).clamp(0, 16) as u32;
+    chrono::Duration::seconds(
+        BACKOFF_BASE_SECONDS
+            .saturating_mul(2i64.pow(exponent))
+            .min(BACKOFF_MAX_SECONDS),
+    )
+}
+
+fn build_email(entry: &EmailOutbox) -> OutgoingEmail {
+    OutgoingEmail {
+        to: entry.get_to().to_owned(),
+        subject: entry.get_subject().to_owned(),
+        text_body: entry.get_body().to_owned(),
+        html_body: entry.get_html_body().map(str::to_owned),
+    }
+}
+
+/// sends up to a batch of due emails; returns how many entries were processed. Each entry is leased in a
+/// transaction of its own just before it is sent and its result recorded as soon as it is known, so no row
+/// lock is held across SMTP and a slow relay can't outlast the lease of entries still waiting their turn
+pub async fn drain_once(state: &Arc<ServerState>) -> anyhow::Result<usize> {
+    let mut conn = state.get_conn().await?;
+
+    let mut processed = 0;
+    while processed < EMAIL_OUTBOX_BATCH_SIZE as usize {
+        let transaction = conn.transaction().await?;
+        let Some(entry) = EmailOutbox::claim_due(&transaction, 1, EMAIL_OUTBOX_LEASE)
+            .await?
+            .pop()
+        else {
+            break;
+        };
+        transaction.commit().await?;
+
+        send_entry(state, &mut conn, &entry).await;
+        processed += 1;
+    }
+
+    Ok(processed)
+}
+
+async fn send_entry(state: &Arc<ServerState>, conn: &mut Object, entry: &EmailOutbox) {
+    let email = build_email(entry);
+
+    // a message that can't even be built will never succeed, so it is dead-lettered right away
+    if let Err(e) = email.to_message() {
+        error!("Dead-lettering email {}: {:?}", entry.get_id(), e);
+        metrics::counter!(EMAIL_SEND_FAILURES_TOTAL).increment(1);
+        record_failure(conn, entry, &e.to_string(), None).await;
+        return;
+    }
+
+    let send_span = info_span!(
+        "mail.send",
+        mail.transport = state.get_mailer().name(),
+        email_outbox_id = %entry.get_id()
+    );
+    match state.get_mailer().send(&email).instrument(send_span).await {
+        Ok(_) => {
+            metrics::counter!(EMAILS_SENT_TOTAL).increment(1);
+            record_sent(conn, entry).await;
+        }
+        Err(e) => {
+            metrics::counter!(EMAIL_SEND_FAILURES_TOTAL).increment(1);
+            let attempts = entry.get_attempts() + 1;
+            let next_attempt_at = if attempts >= EMAIL_OUTBOX_MAX_ATTEMPTS {
+                error!(
+                    "Dead-lettering email {} after {} attempts: {:?}",
+                    entry.get_id(),
+                    attempts,
+                    e
+                );
+                None
+            } else {
+                warn!(
+                    "Could not send email {} (attempt {}): {:?}",
+                    entry.get_id(),
+                    attempts,
+                    e
+                );
+                Some(Utc::now() + backoff_delay(attempts))
+            };
+
+            record_failure(conn, entry, &format!("{:?}", e), next_attempt_at).await;
+        }
+    }
+}
+
+/// an email that went out but couldn't be marked sent will go out again once its lease runs out
+async fn record_sent(conn: &mut Object, entry: &EmailOutbox) {
+    let recorded = match conn.transaction().await {
+        Ok(transaction) => match entry.mark_sent(&transaction).await {
+            Ok(held) => transaction
+                .commit()
+                .await
+                .map(|_| held)
+                .map_err(anyhow::Error::from),
+            Err(e) => Err(e),
+        },
+        Err(e) => Err(e.into()),
+    };
+
+    match recorded {
+        Ok(true) => (),
+        Ok(false) => warn!(
+            "Sent email {} after its lease ran out and another instance claimed it; it may go out twice",
+            entry.get_id()
+        ),
+        Err(e) => error!(
+            "Could not mark email {} as sent; it will be resent after its lease: {:?}",
+            entry.get_id(),
+            e
+        ),
+    }
+}
+
+/// a failure that can't be recorded is retried once the lease runs out, without using up an attempt
+async fn record_failure(
+    conn: &mut Object,
+    entry: &EmailOutbox,
+    error: &str,
+    next_attempt_at: Option<DateTime<Utc>>,
+) {
+    let recorded = match conn.transaction().await {
+        Ok(transaction) => match entry
+            .mark_failed(&transaction, error, next_attempt_at)
+            .await
+        {
+            Ok(held) => transaction
+                .commit()
+                .await
+                .map(|_| held)
+                .map_err(anyhow::Error::from),
+            Err(e) => Err(e),
+        },
+        Err(e) => Err(e.into()),
+    };
+
+    match recorded {
+        Ok(true) => (),
+        // whoever holds the lease now records its own attempt
+        Ok(false) => warn!(
+            "Not recording failed attempt for email {}; another instance claimed it",
+            entry.get_id()
+        ),
+        Err(e) => error!(
+            "Could not record failed attempt for email {}: {:?}",
+            entry.get_id(),
+            e
+        ),
+    }
+}
+
+/// drains v1.email_outbox through the shared mailer until `shutdown` is cancelled;
+/// a batch that is already being sent is finished first so no claimed entry is left half-done
+pub async fn run_email_outbox_worker(state: Arc<ServerState>, shutdown: CancellationToken) {
+    let mut interval = tokio::time::interval(EMAIL_OUTBOX_POLL_INTERVAL);
+
+    loop {
+        tokio::select! {
+            _ = shutdown.cancelled() => break,
+            _ = interval.tick() => (),
+        }
+
+        // keep going while full batches come back so a backlog clears without waiting on the interval
+        while !shutdown.is_cancelled() {
+            match drain_once(&state).await {
+                Ok(count) => {
+                    if count > 0 {
+                        info!("Processed {} outbox emails", count);
+                    }
+                    if (count as i64) < EMAIL_OUTBOX_BATCH_SIZE {
+                        break;
+                    }
+                }
+                Err(e) => {
+                    error!("Email outbox worker failed: {:?}", e);
+                    break;
+                }
+            }
+        }
+    }
+
+    info!("Email outbox worker stopped");
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_backoff_delay_doubles_and_caps() {
+        assert_eq!(backoff_delay(1), chrono::Duration::seconds(30));
+        assert_eq!(backoff_delay(2), chrono::Duration::seconds(60));
+        assert_eq!(backoff_delay(3), chrono::Duration::seconds(120));
+        assert_eq!(backoff_delay(8), chrono::Duration::seconds(3600));
+        assert_eq!(backoff_delay(i32::MAX), chrono::Duration::seconds(3600));
+    }
+}